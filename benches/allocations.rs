@@ -0,0 +1,55 @@
+//! Count the allocations made logging a static message with set_message and with set_text
+//!
+//! Run with `cargo bench --bench allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::sink;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ymlog::prelude::*;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    System.alloc(layout)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+const RECORDS: u64 = 10_000;
+
+/// The average allocations for each record, building the block and writing it
+fn per_record(build: impl Fn() -> Block) -> f64 {
+  let logger = YmLog::new();
+  logger.set_output(sink());
+  let before = ALLOCATIONS.load(Ordering::Relaxed);
+  for _ in 0..RECORDS {
+    logger.log(&mut build(), Some("r")).unwrap();
+  }
+  (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / RECORDS as f64
+}
+
+fn main() {
+  let message = per_record(|| {
+    let mut block = Block::new();
+    block.set_message("Polling the queue").unwrap();
+    block
+  });
+  let text = per_record(|| {
+    let mut block = Block::new();
+    block.set_text("Polling the queue");
+    block
+  });
+  println!("set_message: {:.2} allocations per record", message);
+  println!("set_text:    {:.2} allocations per record", text);
+}
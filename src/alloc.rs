@@ -0,0 +1,124 @@
+//! An allocator that keeps count of the memory in use, so it can be logged alongside everything
+//! else
+//!
+//! Only available with the `alloc-stats` feature. Wrap the allocator the program already uses:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: ymlog::alloc::TrackingAllocator = ymlog::alloc::TrackingAllocator::new(std::alloc::System);
+//!
+//! // Write the numbers to the global log every 10 seconds until the reporter is dropped
+//! let _reporter = ymlog::alloc::report_every(std::time::Duration::from_secs(10));
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::prelude::*;
+use crate::reporter::{log_global, Reporter};
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Passes every request on to the inner allocator while counting the bytes
+pub struct TrackingAllocator<A = System> {
+  inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+  pub const fn new(inner: A) -> TrackingAllocator<A> {
+    TrackingAllocator { inner }
+  }
+}
+
+fn grow(size: usize) {
+  let in_use = IN_USE.fetch_add(size, Ordering::Relaxed) + size;
+  PEAK.fetch_max(in_use, Ordering::Relaxed);
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = self.inner.alloc(layout);
+    if !ptr.is_null() {
+      ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+      grow(layout.size());
+    }
+    ptr
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    let ptr = self.inner.alloc_zeroed(layout);
+    if !ptr.is_null() {
+      ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+      grow(layout.size());
+    }
+    ptr
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    self.inner.dealloc(ptr, layout);
+    IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let new_ptr = self.inner.realloc(ptr, layout, new_size);
+    if !new_ptr.is_null() {
+      ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+      match new_size >= layout.size() {
+        true => grow(new_size - layout.size()),
+        false => {
+          IN_USE.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+        }
+      }
+    }
+    new_ptr
+  }
+}
+
+/// The allocation counts at a moment in time
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AllocStats {
+  /// Bytes currently allocated
+  pub in_use: usize,
+
+  /// The most bytes that have been allocated at once
+  pub peak: usize,
+
+  /// The number of allocations made, including reallocations
+  pub allocations: u64,
+}
+
+impl AllocStats {
+  /// Make a metric record of the stats
+  pub fn to_block(&self) -> Block {
+    let mut block = Block::new();
+    let _ = block.set_message("Allocation stats");
+    let _ = block.add_field("bytes_in_use", self.in_use);
+    let _ = block.add_field("peak_bytes", self.peak);
+    let _ = block.add_field("allocations", self.allocations);
+    block
+  }
+}
+
+/// Read the current counts
+///
+/// These stay at zero unless the [`TrackingAllocator`] is the global allocator.
+pub fn stats() -> AllocStats {
+  AllocStats {
+    in_use: IN_USE.load(Ordering::Relaxed),
+    peak: PEAK.load(Ordering::Relaxed),
+    allocations: ALLOCATIONS.load(Ordering::Relaxed),
+  }
+}
+
+/// Write the current counts to the global log, at its current depth
+pub fn log_stats() {
+  log_global(stats().to_block());
+}
+
+/// Start writing the stats to the global log on an interval
+pub fn report_every(interval: Duration) -> Reporter {
+  Reporter::spawn("ymlog-alloc", interval, log_stats)
+}
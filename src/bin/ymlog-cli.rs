@@ -0,0 +1,138 @@
+//! Filter and print ymlog files
+//!
+//! Only built with the `cli` feature. Matching records are written to stdout with everything
+//! nested under them, or on their own with `--flatten`:
+//!
+//! ```text
+//! ymlog-cli filter --level warn --tag db app.yml
+//! ymlog-cli filter --where 'level>=warn AND msg~"timeout"' app.yml
+//! ```
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::process::ExitCode;
+
+use ymlog::prelude::*;
+use ymlog::query::Query;
+use ymlog::reader::{self, Opener};
+use ymlog::Timestamp;
+
+const USAGE: &str = "\
+Usage: ymlog-cli filter [OPTIONS] FILE...
+
+Print the records from the logs matching every option given
+
+Options:
+  --level LEVEL   Only records at or above the level, where records without one are info
+  --tag TAG       Only records with the tag or YAML tag
+  --since TIME    Only records stamped at or after the RFC 3339 time
+  --until TIME    Only records stamped at or before the RFC 3339 time
+  --where QUERY   Only records matching the query, such as 'level>=warn AND tag:db'
+  --flatten       Print each matching record on its own, without its children
+  --json          Print JSON lines instead of YAML
+  -h, --help      Print this message";
+
+/// What to print, from the command line
+struct Filter {
+  query: Query,
+  flatten: bool,
+  format: OutputFormat,
+  files: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> IoResult<Filter> {
+  let usage = |msg: String| IoError::new(ErrorKind::InvalidInput, msg);
+  match args.next().as_deref() {
+    Some("filter") => (),
+    Some(command) => return Err(usage(format!("Unknown command {:?}", command))),
+    None => return Err(usage("Missing the command".to_string())),
+  }
+
+  let mut filter = Filter {
+    query: Query::new(),
+    flatten: false,
+    format: OutputFormat::Yaml,
+    files: vec![],
+  };
+  while let Some(arg) = args.next() {
+    let mut value = || {
+      args
+        .next()
+        .ok_or_else(|| usage(format!("{} needs a value", arg)))
+    };
+
+    filter.query = match arg.as_str() {
+      "--level" => filter.query.level(value()?.parse()?),
+      "--tag" => filter.query.tag(value()?),
+      "--since" => filter.query.since(value()?.parse::<Timestamp>()?),
+      "--until" => filter.query.until(value()?.parse::<Timestamp>()?),
+      "--where" => filter.query.refine(&value()?)?,
+      "--flatten" => {
+        filter.flatten = true;
+        continue;
+      }
+      "--json" => {
+        filter.format = OutputFormat::JsonLines;
+        continue;
+      }
+      option if option.starts_with('-') => {
+        return Err(usage(format!("Unknown option {:?}", option)))
+      }
+      _ => {
+        filter.files.push(arg);
+        continue;
+      }
+    };
+  }
+
+  match filter.files.is_empty() {
+    true => Err(usage("No log files were given".to_string())),
+    false => Ok(filter),
+  }
+}
+
+fn run(filter: Filter) -> IoResult<()> {
+  // The query picks the records, so the logger writes every one it is given
+  let logger = YmLog::new();
+  logger.set_level(Level::Trace);
+  logger.set_output(std::io::stdout());
+  logger.set_format(filter.format.clone());
+
+  let opener = Opener::new();
+  for path in &filter.files {
+    let records = reader::parse(opener.open(path)?)
+      .collect::<IoResult<Vec<_>>>()
+      .map_err(|err| IoError::new(err.kind(), format!("{}: {}", path, err)))?;
+    let selected = match filter.flatten {
+      true => filter.query.flatten(&records),
+      false => filter.query.subtrees(&records),
+    };
+    for mut block in selected {
+      logger.log(&mut block, None)?;
+    }
+  }
+  // End the last document, so the prompt doesn't end up on the last line
+  logger.close()
+}
+
+fn main() -> ExitCode {
+  let args = std::env::args().skip(1).collect::<Vec<_>>();
+  if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+    println!("{}", USAGE);
+    return ExitCode::SUCCESS;
+  }
+
+  let filter = match parse_args(args.into_iter()) {
+    Ok(filter) => filter,
+    Err(err) => {
+      eprintln!("ymlog-cli: {}\n\n{}", err, USAGE);
+      return ExitCode::from(2);
+    }
+  };
+  match run(filter) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      eprintln!("ymlog-cli: {}", err);
+      ExitCode::FAILURE
+    }
+  }
+}
@@ -0,0 +1,211 @@
+//! Color the records written to a terminal
+//!
+//! Keys are written in cyan, and the values of records other than info are colored by their
+//! level, since YAML records don't carry a level marker. JSON lines color their `log_level` value
+//! instead. Colors are only added to the text written, so the structure of the records is
+//! unchanged once the escape codes are stripped.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+use crate::prelude::*;
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[36m";
+
+/// Whether records are written with ANSI colors
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ColorChoice {
+  /// Color outputs that are terminals, unless the `NO_COLOR` variable is set
+  #[default]
+  Auto,
+
+  /// Always color, even when writing to a file or pipe
+  Always,
+
+  /// Never color
+  Never,
+}
+
+impl ColorChoice {
+  /// Check whether an output should be colored
+  pub(crate) fn enabled(&self, terminal: bool) -> bool {
+    match self {
+      ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+    }
+  }
+}
+
+impl std::str::FromStr for ColorChoice {
+  type Err = IoError;
+
+  /// Parse "auto", "always" or "never", ignoring case
+  fn from_str(name: &str) -> IoResult<ColorChoice> {
+    match name.to_ascii_lowercase().as_str() {
+      "auto" => Ok(ColorChoice::Auto),
+      "always" => Ok(ColorChoice::Always),
+      "never" => Ok(ColorChoice::Never),
+      _ => Err(IoError::new(
+        ErrorKind::InvalidInput,
+        format!("{:?} is not auto, always or never", name),
+      )),
+    }
+  }
+}
+
+/// The escape code a level's text is written with, if it has one
+fn level_color(level: &Level) -> Option<&'static str> {
+  match level {
+    Level::Trace => Some("\x1b[2m"),
+    Level::Debug => Some("\x1b[34m"),
+    Level::Info => None,
+    Level::Warn => Some("\x1b[33m"),
+    Level::Error => Some("\x1b[31m"),
+    // Custom levels take the color of the built in level they rank with
+    Level::Custom(rank, _) => {
+      let ranked = Level::iter().filter(|level| level.rank() <= *rank).last();
+      level_color(&ranked.unwrap_or(Level::Trace))
+    }
+  }
+}
+
+fn paint_into(out: &mut String, text: &str, color: Option<&str>) {
+  match color {
+    Some(color) if !text.is_empty() => {
+      out.push_str(color);
+      out.push_str(text);
+      out.push_str(RESET);
+    }
+    _ => out.push_str(text),
+  }
+}
+
+/// Add colors to a serialized record
+pub(crate) fn paint(record: &str, level: &Level, format: &OutputFormat) -> String {
+  match format {
+    OutputFormat::Yaml => paint_yaml(record, level_color(level)),
+    OutputFormat::JsonLines => paint_json(record, level_color(level).unwrap_or("\x1b[32m")),
+  }
+}
+
+/// Color the keys and values of each line, leaving the contents of block scalars as values
+fn paint_yaml(record: &str, color: Option<&str>) -> String {
+  let mut out = String::with_capacity(record.len() * 2);
+  // The indentation of the line that started the block scalar being written
+  let mut scalar_indent: Option<usize> = None;
+  for (i, line) in record.split('\n').enumerate() {
+    if i > 0 {
+      out.push('\n');
+    }
+
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if let Some(start) = scalar_indent {
+      if indent > start || line.trim().is_empty() {
+        out.push_str(&line[..indent]);
+        paint_into(&mut out, &line[indent..], color);
+        continue;
+      }
+      scalar_indent = None;
+    }
+
+    let rest = &line[indent..];
+    if rest == "---" || rest == "..." || rest.starts_with('!') || rest.starts_with('#') {
+      out.push_str(line);
+      continue;
+    }
+
+    // Keep the sequence dashes uncolored
+    let mut body = rest;
+    while let Some(item) = body
+      .strip_prefix("- ")
+      .or_else(|| body.strip_prefix('-').filter(|r| r.is_empty()))
+    {
+      body = item;
+    }
+    out.push_str(&line[..line.len() - body.len()]);
+
+    let value = match split_key(body) {
+      Some((key, separator, value)) => {
+        paint_into(&mut out, key, Some(KEY));
+        out.push_str(separator);
+        value
+      }
+      None => body,
+    };
+    paint_into(&mut out, value, color);
+
+    let value = value.trim_end();
+    if value.starts_with('|') || value.starts_with('>') {
+      scalar_indent = Some(indent);
+    }
+  }
+  out
+}
+
+/// Split a line into its key, the `: ` after it, and its value, if it is a mapping entry
+pub(crate) fn split_key(line: &str) -> Option<(&str, &str, &str)> {
+  let key_end = match line.chars().next()? {
+    quote @ ('"' | '\'') => {
+      let close = line[1..].find(quote)? + 2;
+      close + line[close..].len() - line[close..].trim_start_matches(' ').len()
+    }
+    '{' | '[' | '|' | '>' | '&' | '*' => return None,
+    _ => line
+      .find(": ")
+      .or_else(|| line.strip_suffix(':').map(str::len))?,
+  };
+  let after = &line[key_end..];
+  let separator_len = match after {
+    ":" => 1,
+    _ if after.starts_with(": ") => 2,
+    _ => return None,
+  };
+  Some((
+    line[..key_end].trim_end(),
+    &line[line[..key_end].trim_end().len()..key_end + separator_len],
+    &line[key_end + separator_len..],
+  ))
+}
+
+/// Color each key of a JSON line, and the value of its `log_level`
+fn paint_json(record: &str, level_color: &str) -> String {
+  let mut out = String::with_capacity(record.len() * 2);
+  let mut level_next = false;
+  let mut rest = record;
+  while let Some(start) = rest.find('"') {
+    out.push_str(&rest[..start]);
+    let string_len = string_end(&rest[start..]);
+    let string = &rest[start..start + string_len];
+    rest = &rest[start + string_len..];
+
+    let is_key = rest.trim_start().starts_with(':');
+    match (is_key, level_next) {
+      (true, _) => {
+        paint_into(&mut out, string, Some(KEY));
+        level_next = string == "\"log_level\"";
+      }
+      (false, true) => {
+        paint_into(&mut out, string, Some(level_color));
+        level_next = false;
+      }
+      (false, false) => out.push_str(string),
+    }
+  }
+  out.push_str(rest);
+  out
+}
+
+/// The length of the JSON string at the start of the text, including both quotes
+fn string_end(text: &str) -> usize {
+  let mut escaped = false;
+  for (i, c) in text.char_indices().skip(1) {
+    match (escaped, c) {
+      (true, _) => escaped = false,
+      (false, '\\') => escaped = true,
+      (false, '"') => return i + 1,
+      _ => (),
+    }
+  }
+  text.len()
+}
@@ -0,0 +1,212 @@
+//! Compression of very large messages
+//!
+//! Big embedded literals (dumps, payloads, backtraces) can swamp a log. Messages over a size
+//! threshold are compressed, base64 encoded, and written with a `!ymlog/<codec>` tag so the rest of
+//! the document stays human readable while its size is bounded.
+//!
+//! The `gzip` and `zstd` features ship [`Gzip`](crate::gzip::Gzip) and
+//! [`Zstd`](crate::zstd::Zstd). Any other compression library can be plugged in as a codec:
+//!
+//! ```ignore
+//! struct Lz4;
+//!
+//! impl Codec for Lz4 {
+//!   fn name(&self) -> &str {
+//!     "lz4"
+//!   }
+//!
+//!   fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+//!     Ok(lz4_flex::compress_prepend_size(data))
+//!   }
+//!
+//!   fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+//!     lz4_flex::decompress_size_prepended(data)
+//!       .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+//!   }
+//! }
+//!
+//! logger.set_compression(Compression::new(Box::new(Lz4), 64 * 1024));
+//! ```
+
+use std::io::{BufRead, Cursor, Error as IoError, ErrorKind, Read, Result as IoResult};
+
+use serde_yaml::value::{Tag, TaggedValue};
+use serde_yaml::Value as YmlValue;
+
+/// The prefix of the tags we write compressed values with
+const TAG_PREFIX: &str = "ymlog/";
+
+/// A compression algorithm that can be plugged into the logger
+pub trait Codec: Send + Sync {
+  /// The name written in the tag, so "zstd" is written as `!ymlog/zstd`
+  fn name(&self) -> &str;
+
+  /// Shrink the raw bytes of the message
+  fn compress(&self, data: &[u8]) -> IoResult<Vec<u8>>;
+
+  /// Restore the bytes created by compress
+  fn decompress(&self, data: &[u8]) -> IoResult<Vec<u8>>;
+
+  /// Read back a whole file compressed by this codec, such as a rotated log
+  ///
+  /// Codecs with a magic should decompress as the file is read, so a large one is never held in
+  /// memory. The default reads the whole file and decompresses it at once.
+  fn decoder(&self, mut compressed: Box<dyn BufRead + Send>) -> IoResult<Box<dyn Read + Send>> {
+    let mut data = vec![];
+    compressed.read_to_end(&mut data)?;
+    Ok(Box::new(Cursor::new(self.decompress(&data)?)))
+  }
+
+  /// The bytes a whole file compressed by this codec starts with, such as `[0x1f, 0x8b]` for gzip
+  ///
+  /// The reader uses this to find the codec for rotated files. Codecs that leave it empty are only
+  /// used for single messages.
+  fn magic(&self) -> &[u8] {
+    &[]
+  }
+}
+
+/// Report corrupt and cut off data from a codec's library as `InvalidData`, like the rest of the
+/// reader
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub(crate) struct Corrupt<R>(pub(crate) R);
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl<R: Read> Read for Corrupt<R> {
+  fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    self.0.read(buf).map_err(|err| match err.kind() {
+      ErrorKind::InvalidInput | ErrorKind::UnexpectedEof | ErrorKind::Other => {
+        IoError::new(ErrorKind::InvalidData, err)
+      }
+      _ => err,
+    })
+  }
+}
+
+/// When and how to compress messages
+pub struct Compression {
+  /// The algorithm used to compress the messages
+  codec: Box<dyn Codec>,
+
+  /// The smallest message (in bytes) that will be compressed
+  threshold: usize,
+}
+
+impl Compression {
+  pub fn new(codec: Box<dyn Codec>, threshold: usize) -> Compression {
+    Compression { codec, threshold }
+  }
+
+  /// The tag compressed values are written with
+  pub fn tag(&self) -> Tag {
+    tag(self.codec.as_ref())
+  }
+
+  /// Compress the value if it is a string over the threshold
+  ///
+  /// Returns None if the value should be written as is
+  pub fn pack(&self, value: &YmlValue) -> IoResult<Option<YmlValue>> {
+    match value {
+      YmlValue::String(inner) if inner.len() >= self.threshold => {
+        let packed = self.codec.compress(inner.as_bytes())?;
+        Ok(Some(YmlValue::Tagged(Box::new(TaggedValue {
+          tag: self.tag(),
+          value: YmlValue::String(base64::encode(&packed)),
+        }))))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  /// Expand a value that was compressed by this codec
+  ///
+  /// Returns None if the value wasn't tagged with our codec, so readers can pass every value
+  /// through this.
+  pub fn unpack(&self, value: &YmlValue) -> IoResult<Option<String>> {
+    unpack(self.codec.as_ref(), value)
+  }
+}
+
+/// The tag values compressed by the codec are written with
+fn tag(codec: &dyn Codec) -> Tag {
+  Tag::new(format!("{}{}", TAG_PREFIX, codec.name()))
+}
+
+/// Expand a value that was compressed by the codec, or None if it wasn't tagged with it
+pub(crate) fn unpack(codec: &dyn Codec, value: &YmlValue) -> IoResult<Option<String>> {
+  let tagged = match value {
+    YmlValue::Tagged(tagged) if tagged.tag == tag(codec) => tagged,
+    _ => return Ok(None),
+  };
+
+  let encoded = match &tagged.value {
+    YmlValue::String(encoded) => encoded,
+    other => {
+      return Err(IoError::new(
+        ErrorKind::InvalidData,
+        format!(
+          "Compressed values must be base64 strings, found {:?}",
+          other
+        ),
+      ))
+    }
+  };
+
+  let packed = base64::decode(encoded)?;
+  let raw = codec.decompress(&packed)?;
+  String::from_utf8(raw)
+    .map(Some)
+    .map_err(|err| IoError::new(ErrorKind::InvalidData, err))
+}
+
+/// The standard base64 alphabet (RFC 4648) with padding
+mod base64 {
+  use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+  const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+  pub fn encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+      let bytes = [
+        chunk[0],
+        *chunk.get(1).unwrap_or(&0),
+        *chunk.get(2).unwrap_or(&0),
+      ];
+      let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+      for i in 0..4 {
+        match i <= chunk.len() {
+          true => result.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char),
+          false => result.push('='),
+        }
+      }
+    }
+    result
+  }
+
+  pub fn decode(encoded: &str) -> IoResult<Vec<u8>> {
+    let invalid = || IoError::new(ErrorKind::InvalidData, "Invalid base64 in compressed value");
+
+    // Whitespace may have been added if the value was folded
+    let digits = encoded
+      .bytes()
+      .filter(|c| !c.is_ascii_whitespace())
+      .take_while(|c| *c != b'=')
+      .map(|c| ALPHABET.iter().position(|a| *a == c).ok_or_else(invalid))
+      .collect::<IoResult<Vec<usize>>>()?;
+
+    let mut result = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+      if chunk.len() == 1 {
+        return Err(invalid());
+      }
+      let bits = chunk.iter().enumerate().fold(0u32, |acc, (i, digit)| {
+        acc | (*digit as u32) << (18 - 6 * i)
+      });
+      for i in 0..chunk.len() - 1 {
+        result.push((bits >> (16 - 8 * i) & 0xff) as u8);
+      }
+    }
+    Ok(result)
+  }
+}
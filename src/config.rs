@@ -0,0 +1,72 @@
+//! Writing an application's configuration to the log
+//!
+//! Services log their configuration at startup, so a run can be matched to the settings it had.
+//! [`YmLog::log_config`] writes it the same way everywhere: as a tree of records under a `config`
+//! record, with the values of denied keys left out.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+
+use serde::Serialize;
+use serde_yaml::Value as YmlValue;
+
+use crate::deny;
+use crate::message::MessageType;
+use crate::prelude::*;
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Write the configuration as a tree of records nested under a `config` record
+  ///
+  /// Each mapping and sequence becomes a record with its entries nested under it, and everything
+  /// else is written as a `key: value` pair. The values of keys on the deny-list (see
+  /// [`never_log_keys`](crate::never_log_keys)) are written as `<removed>`, contents and all.
+  pub fn log_config(&self, config: &impl Serialize) -> IoResult<Option<RecordHandle>> {
+    let value =
+      serde_yaml::to_value(config).map_err(|err| IoError::new(ErrorKind::InvalidData, err))?;
+    let mut root = Block::new();
+    root.message = MessageType::Value("config".into());
+    root.set_children(entries(value));
+    self.log(&mut root, None)
+  }
+}
+
+/// The records for the entries of a value
+fn entries(value: YmlValue) -> Vec<Block> {
+  let record = |message| {
+    let mut block = Block::new();
+    block.message = message;
+    block
+  };
+  match value {
+    YmlValue::Mapping(mapping) => mapping
+      .into_iter()
+      .map(
+        |(key, value)| match is_nested(&value) && !deny::is_denied(&key) {
+          true => {
+            let mut block = record(MessageType::Value(key));
+            block.set_children(entries(value));
+            block
+          }
+          // The deny-list removes the values of pairs as they are written
+          false => record(MessageType::KeyValue(key, value)),
+        },
+      )
+      .collect(),
+    YmlValue::Sequence(items) => items
+      .into_iter()
+      .map(|item| record(MessageType::Value(item)))
+      .collect(),
+    value => vec![record(MessageType::Value(value))],
+  }
+}
+
+/// Check if a value has entries to nest, as empty ones are clearer written as `{}` or `[]`
+fn is_nested(value: &YmlValue) -> bool {
+  match value {
+    YmlValue::Mapping(mapping) => !mapping.is_empty(),
+    YmlValue::Sequence(items) => !items.is_empty(),
+    _ => false,
+  }
+}
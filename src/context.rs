@@ -0,0 +1,227 @@
+//! Tags and fields added to every block, such as a request's id
+//!
+//! A [`ScopedLogger`] borrows the logger it came from, so records logged through it share the same
+//! outputs and nesting as the rest of the log. Code without a logger handle can [`push`] fields
+//! onto the thread's context instead, which every logger adds to the blocks logged on the thread.
+//! Either way, a block's own fields win over the context's, which are written first.
+//!
+//! ```
+//! let scope = ymlog::context::scope();
+//! scope.push("job", 42).unwrap();
+//! // Everything logged on this thread has `job: 42` until the scope is dropped
+//! # drop(scope);
+//! # assert!(ymlog::context::fields().is_empty());
+//! ```
+
+use std::cell::RefCell;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+
+use serde::Serialize;
+use serde_yaml::{Error as YmlError, Mapping, Value as YmlValue};
+
+use crate::message::Tag;
+use crate::prelude::*;
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Get a handle that adds the tags and fields to every block logged through it
+  ///
+  /// The fields must serialize to a mapping, or be None. Anything else is an `InvalidInput` error.
+  pub fn with_context(
+    &self,
+    tags: Vec<impl std::fmt::Display>,
+    fields: impl Serialize,
+  ) -> IoResult<ScopedLogger<'_, T>> {
+    ScopedLogger {
+      logger: self,
+      tags: vec![],
+      fields: Mapping::new(),
+    }
+    .with_context(tags, fields)
+  }
+}
+
+/// A logger adding its context to every block, made with [`YmLog::with_context`]
+pub struct ScopedLogger<'a, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  logger: &'a YmLog<T>,
+  tags: Vec<Tag>,
+  fields: Mapping,
+}
+
+impl<'a, T> ScopedLogger<'a, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Get a handle with more context, keeping this one's
+  pub fn with_context(
+    &self,
+    tags: Vec<impl std::fmt::Display>,
+    fields: impl Serialize,
+  ) -> IoResult<ScopedLogger<'a, T>> {
+    let mut context = ScopedLogger {
+      logger: self.logger,
+      tags: self.tags.clone(),
+      fields: self.fields.clone(),
+    };
+    for tag in tags.iter().map(Tag::new) {
+      if !context.tags.contains(&tag) {
+        context.tags.push(tag);
+      }
+    }
+    match serde_yaml::to_value(fields) {
+      Ok(YmlValue::Mapping(fields)) => context.fields.extend(fields),
+      Ok(YmlValue::Null) => (),
+      Ok(_) => {
+        return Err(IoError::new(
+          ErrorKind::InvalidInput,
+          "The context fields must be a mapping",
+        ))
+      }
+      Err(err) => return Err(IoError::new(ErrorKind::InvalidInput, err)),
+    }
+    Ok(context)
+  }
+
+  /// The logger the records are written to
+  pub fn logger(&self) -> &'a YmLog<T> {
+    self.logger
+  }
+
+  /// The tags added to every block
+  pub fn tags(&self) -> &[Tag] {
+    &self.tags
+  }
+
+  /// The fields added to every block
+  pub fn fields(&self) -> &Mapping {
+    &self.fields
+  }
+
+  /// Add the context to the block and write it, like [`YmLog::log`]
+  pub fn log(&self, block: &mut Block, actions: Option<&str>) -> IoResult<Option<RecordHandle>> {
+    self.add_context(block);
+    self.logger.log(block, actions)
+  }
+
+  /// Like [`ScopedLogger::log`], but only builds the block if it could be written
+  pub fn log_lazy(
+    &self,
+    actions: Option<&str>,
+    target: &str,
+    build: impl FnOnce() -> Block,
+  ) -> IoResult<Option<RecordHandle>> {
+    self.logger.log_lazy(actions, target, || {
+      let mut block = build();
+      self.add_context(&mut block);
+      block
+    })
+  }
+
+  /// Run only the indent, dedent and reset actions, like [`YmLog::skip`]
+  pub fn skip(&self, actions: Option<&str>) -> IoResult<()> {
+    self.logger.skip(actions)
+  }
+
+  fn add_context(&self, block: &mut Block) {
+    if !self.tags.is_empty() {
+      let mut tags = self.tags.clone();
+      for tag in block.tags() {
+        if !tags.contains(tag) {
+          tags.push(tag.clone());
+        }
+      }
+      block.tags = Some(tags);
+    }
+    add_fields(block, self.fields.clone());
+  }
+}
+
+impl<T> std::fmt::Debug for ScopedLogger<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ScopedLogger")
+      .field("tags", &self.tags)
+      .field("fields", &self.fields)
+      .finish()
+  }
+}
+
+thread_local! {
+  static STACK: RefCell<Vec<(String, YmlValue)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Add a field to the context of the thread, until it is popped
+pub fn push(key: impl std::fmt::Display, value: impl Serialize) -> Result<(), YmlError> {
+  let value = serde_yaml::to_value(value)?;
+  STACK.with(|stack| stack.borrow_mut().push((key.to_string(), value)));
+  Ok(())
+}
+
+/// Remove the field pushed last onto the thread's context
+pub fn pop() -> Option<(String, YmlValue)> {
+  STACK.with(|stack| stack.borrow_mut().pop())
+}
+
+/// The fields of the thread's context, with the ones pushed later winning
+pub fn fields() -> Mapping {
+  STACK.with(|stack| {
+    stack
+      .borrow()
+      .iter()
+      .map(|(key, value)| (key.as_str().into(), value.clone()))
+      .collect()
+  })
+}
+
+/// Start a scope that pops every field pushed onto the thread's context while it was open when it
+/// is dropped
+pub fn scope() -> ContextScope {
+  ContextScope {
+    depth: STACK.with(|stack| stack.borrow().len()),
+    _thread: std::marker::PhantomData,
+  }
+}
+
+/// Pops the fields pushed onto the thread's context while it was open, made with [`scope`]
+#[derive(Debug)]
+pub struct ContextScope {
+  depth: usize,
+  // The context belongs to the thread, so the scope can't leave it
+  _thread: std::marker::PhantomData<*const ()>,
+}
+
+impl ContextScope {
+  /// Add a field to the thread's context until the scope is dropped
+  pub fn push(&self, key: impl std::fmt::Display, value: impl Serialize) -> Result<(), YmlError> {
+    push(key, value)
+  }
+}
+
+impl Drop for ContextScope {
+  fn drop(&mut self) {
+    STACK.with(|stack| stack.borrow_mut().truncate(self.depth));
+  }
+}
+
+/// Add the thread's context to a block being logged
+pub(crate) fn add_thread_context(block: &mut Block) {
+  let empty = STACK.with(|stack| stack.borrow().is_empty());
+  if !empty {
+    add_fields(block, fields());
+  }
+}
+
+/// Add the fields to the block, keeping its own values
+fn add_fields(block: &mut Block, mut fields: Mapping) {
+  if !fields.is_empty() {
+    fields.extend(block.fields.take().unwrap_or_default());
+    block.fields = Some(fields);
+  }
+}
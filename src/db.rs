@@ -0,0 +1,98 @@
+//! Log SQL statements in one shape, so slow queries are easy to pick out of the log
+//!
+//! The statement is written as a literal block and tagged `!sql`, with the bound parameters as a
+//! field and the time it took as a `duration_ms` key/value under it. Parameters often hold
+//! personal data, so they are redacted unless [`Query::reveal_params`] is used.
+
+use std::io::{Result as IoResult, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_yaml::Value as YmlValue;
+
+use crate::http::REDACTED;
+use crate::message::MessageType;
+use crate::prelude::*;
+use crate::reporter::log_global;
+
+/// The YAML tag the queries are written with
+pub const SQL_TAG: &str = "sql";
+
+/// A statement that was run
+#[derive(Debug, Clone)]
+pub struct Query {
+  statement: String,
+  params: Vec<YmlValue>,
+  duration: Duration,
+  reveal: bool,
+}
+
+impl Query {
+  pub fn new(statement: impl std::fmt::Display, duration: Duration) -> Query {
+    Query {
+      statement: statement.to_string(),
+      params: vec![],
+      duration,
+      reveal: false,
+    }
+  }
+
+  /// Add the next bound parameter
+  ///
+  /// A value that can't be serialized is written as null.
+  pub fn param(mut self, value: impl Serialize) -> Query {
+    self
+      .params
+      .push(serde_yaml::to_value(value).unwrap_or_default());
+    self
+  }
+
+  /// Write the parameter values rather than redacting them
+  pub fn reveal_params(mut self) -> Query {
+    self.reveal = true;
+    self
+  }
+
+  /// Make a `!sql` record of the query
+  ///
+  /// The statement is trimmed and ends with a newline, which keeps it a literal block even when it
+  /// fits on one line.
+  pub fn to_block(&self) -> Block {
+    let mut block = Block::new();
+    let _ = block.set_message(format!("{}\n", self.statement.trim()));
+    block.set_tag_type(SQL_TAG);
+
+    let params = match self.reveal {
+      true => self.params.clone(),
+      false => vec![YmlValue::from(REDACTED); self.params.len()],
+    };
+    let _ = block.add_field("params", params);
+
+    let mut duration = Block::new();
+    duration.message = MessageType::KeyValue(
+      "duration_ms".into(),
+      YmlValue::from(self.duration.as_secs_f64() * 1000.0),
+    );
+    block.set_children(vec![duration]);
+    block
+  }
+
+  /// Write the query to the log
+  pub fn log<T>(&self, logger: &YmLog<T>, actions: Option<&str>) -> IoResult<Option<RecordHandle>>
+  where
+    T: Write + Send + Sync + 'static,
+  {
+    logger.log(&mut self.to_block(), actions)
+  }
+}
+
+/// Write a query to the global log with its parameters redacted
+///
+/// The parameters are only counted, so values of different types can be passed as
+/// `serde_yaml::Value`s.
+pub fn log_query<P: Serialize>(sql: &str, params: &[P], duration: Duration) {
+  let query = params
+    .iter()
+    .fold(Query::new(sql, duration), |query, param| query.param(param));
+  log_global(query.to_block());
+}
@@ -0,0 +1,161 @@
+//! Keys whose values must never reach a log
+//!
+//! This is a safety net below the pipelines: it is checked as each record is written to each
+//! output, after every stage has run, so a redaction that was forgotten or broken still can't leak
+//! a password. Keys are matched ignoring case in fields, mapping messages and key/value pairs, at
+//! any depth.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use serde_yaml::Value as YmlValue;
+
+use crate::message::MessageType;
+use crate::prelude::*;
+
+/// The value written in place of a denied key's value
+pub const REMOVED: &str = "<removed>";
+
+static DENIED: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Set when the list isn't empty, so records can skip the lock when nothing is denied
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+static REMOVED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Never write the values of these keys in any logger, replacing the list set before
+pub fn never_log_keys<I>(keys: I)
+where
+  I: IntoIterator,
+  I::Item: std::fmt::Display,
+{
+  let keys = keys
+    .into_iter()
+    .map(|key| key.to_string())
+    .collect::<Vec<_>>();
+  ACTIVE.store(!keys.is_empty(), Ordering::Release);
+  *DENIED
+    .write()
+    .unwrap_or_else(|poisoned| poisoned.into_inner()) = keys;
+}
+
+/// The number of values removed so far, counted once for each output they were removed from
+pub fn removed_count() -> u64 {
+  REMOVED_COUNT.load(Ordering::Relaxed)
+}
+
+/// A copy of the block with the denied values removed and how many there were, or None if it had
+/// none
+pub(crate) fn scrub(block: &Block) -> Option<(Block, u64)> {
+  if !ACTIVE.load(Ordering::Acquire) {
+    return None;
+  }
+  let denied = DENIED
+    .read()
+    .unwrap_or_else(|poisoned| poisoned.into_inner());
+  let is_denied = |key: &YmlValue| matches(&denied, key);
+
+  if !has_denied(block, &is_denied) {
+    return None;
+  }
+  let mut scrubbed = block.clone();
+  let removed = scrub_block(&mut scrubbed, &is_denied);
+  REMOVED_COUNT.fetch_add(removed, Ordering::Relaxed);
+  Some((scrubbed, removed))
+}
+
+/// Check if the key is on the deny-list
+pub(crate) fn is_denied(key: &YmlValue) -> bool {
+  ACTIVE.load(Ordering::Acquire)
+    && matches(
+      &DENIED
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()),
+      key,
+    )
+}
+
+fn matches(denied: &[String], key: &YmlValue) -> bool {
+  key
+    .as_str()
+    .is_some_and(|key| denied.iter().any(|denied| denied.eq_ignore_ascii_case(key)))
+}
+
+fn has_denied(block: &Block, is_denied: &impl Fn(&YmlValue) -> bool) -> bool {
+  let in_value = |value: &YmlValue| value_has_denied(value, is_denied);
+  let in_message = match &block.message {
+    MessageType::Value(value) => in_value(value),
+    MessageType::KeyValue(key, value) => is_denied(key) || in_value(value),
+    _ => false,
+  };
+  in_message
+    || block.fields.iter().any(|fields| {
+      fields
+        .iter()
+        .any(|(key, value)| is_denied(key) || in_value(value))
+    })
+    || block
+      .children()
+      .iter()
+      .any(|child| has_denied(child, is_denied))
+}
+
+fn value_has_denied(value: &YmlValue, is_denied: &impl Fn(&YmlValue) -> bool) -> bool {
+  match value {
+    YmlValue::Mapping(mapping) => mapping
+      .iter()
+      .any(|(key, value)| is_denied(key) || value_has_denied(value, is_denied)),
+    YmlValue::Sequence(items) => items.iter().any(|item| value_has_denied(item, is_denied)),
+    YmlValue::Tagged(tagged) => value_has_denied(&tagged.value, is_denied),
+    _ => false,
+  }
+}
+
+/// Replace the denied values in the block, returning how many there were
+fn scrub_block(block: &mut Block, is_denied: &impl Fn(&YmlValue) -> bool) -> u64 {
+  let mut removed = match &mut block.message {
+    MessageType::Value(value) => scrub_value(value, is_denied),
+    MessageType::KeyValue(key, value) if is_denied(key) => {
+      *value = REMOVED.into();
+      1
+    }
+    MessageType::KeyValue(_, value) => scrub_value(value, is_denied),
+    _ => 0,
+  };
+  if let Some(fields) = &mut block.fields {
+    for (key, value) in fields.iter_mut() {
+      removed += match is_denied(key) {
+        true => {
+          *value = REMOVED.into();
+          1
+        }
+        false => scrub_value(value, is_denied),
+      };
+    }
+  }
+  for child in block.children.iter_mut().flatten() {
+    removed += scrub_block(child, is_denied);
+  }
+  removed
+}
+
+fn scrub_value(value: &mut YmlValue, is_denied: &impl Fn(&YmlValue) -> bool) -> u64 {
+  match value {
+    YmlValue::Mapping(mapping) => mapping
+      .iter_mut()
+      .map(|(key, value)| match is_denied(key) {
+        true => {
+          *value = REMOVED.into();
+          1
+        }
+        false => scrub_value(value, is_denied),
+      })
+      .sum(),
+    YmlValue::Sequence(items) => items
+      .iter_mut()
+      .map(|item| scrub_value(item, is_denied))
+      .sum(),
+    YmlValue::Tagged(tagged) => scrub_value(&mut tagged.value, is_denied),
+    _ => 0,
+  }
+}
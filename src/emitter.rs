@@ -0,0 +1,114 @@
+//! Indented YAML streams written without a logger
+//!
+//! The emitter writes values through the same [`Tracker`] the logger uses, so callers exporting
+//! their own trees get the same streams: each value is an item of the one it is nested under,
+//! parents become keys over their children, multiline strings become block scalars and every root
+//! item is its own document.
+//!
+//! ```
+//! use ymlog::Emitter;
+//!
+//! let mut emitter = Emitter::new(Vec::new());
+//! emitter.start_sequence_item("Orders").unwrap();
+//! emitter.scalar("First").unwrap();
+//! emitter.pair("total", 12).unwrap();
+//! emitter.end_document();
+//! emitter.scalar("Done").unwrap();
+//!
+//! let written = String::from_utf8(emitter.into_inner()).unwrap();
+//! assert_eq!(written, "---\nOrders:\n  - First\n  - total: 12\n---\nDone");
+//! ```
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+
+use serde::Serialize;
+
+use crate::logger::{TimestampFormat, Tracker};
+use crate::message::Block;
+
+/// Writes values into an indented YAML stream one at a time
+#[derive(Debug)]
+pub struct Emitter<W: Write> {
+  out: W,
+  tracker: Tracker,
+  timestamps: TimestampFormat,
+}
+
+impl<W: Write> Emitter<W> {
+  pub fn new(out: W) -> Emitter<W> {
+    Emitter {
+      out,
+      tracker: Tracker::new(),
+      timestamps: TimestampFormat::default(),
+    }
+  }
+
+  /// Write a value as the next item at the current depth
+  pub fn scalar(&mut self, value: impl Serialize) -> IoResult<()> {
+    let mut block = Block::new();
+    block.set_message(value).map_err(invalid)?;
+    self.emit(&block)
+  }
+
+  /// Write a value and indent, so the items after it are written as its children
+  pub fn start_sequence_item(&mut self, value: impl Serialize) -> IoResult<()> {
+    self.scalar(value)?;
+    self.indent();
+    Ok(())
+  }
+
+  /// Write a key that the items after it are nested under
+  ///
+  /// Parents are already written as keys over their children, so this is the same as
+  /// [`Emitter::start_sequence_item`] with the key as the value.
+  pub fn start_mapping_key(&mut self, key: impl Serialize) -> IoResult<()> {
+    self.start_sequence_item(key)
+  }
+
+  /// Write a `key: value` pair as the next item at the current depth
+  ///
+  /// Like the logger's pairs, ones written right after an indent close when a plain item follows.
+  pub fn pair(&mut self, key: impl Serialize, value: impl Serialize) -> IoResult<()> {
+    let mut block = Block::new();
+    block.set_key_value(key, value).map_err(invalid)?;
+    self.emit(&block)
+  }
+
+  /// Nest the items after this under the last one written
+  pub fn indent(&mut self) {
+    self.tracker.indent()
+  }
+
+  /// Go back out a level, staying at the root if already there
+  pub fn dedent(&mut self) {
+    self.tracker.dedent()
+  }
+
+  /// Close every level, so the next item starts a new document
+  pub fn end_document(&mut self) {
+    self.tracker.reset()
+  }
+
+  /// The depth the next item will be written at, with zero being the document root
+  pub fn depth(&self) -> usize {
+    self.tracker.next_depth()
+  }
+
+  pub fn flush(&mut self) -> IoResult<()> {
+    self.out.flush()
+  }
+
+  /// Stop emitting and get the output back
+  pub fn into_inner(self) -> W {
+    self.out
+  }
+
+  fn emit(&mut self, block: &Block) -> IoResult<()> {
+    let record = self.tracker.serialize(block, &self.timestamps);
+    self.out.write_all(record.as_bytes())
+  }
+}
+
+fn invalid(error: serde_yaml::Error) -> IoError {
+  IoError::new(ErrorKind::InvalidData, error)
+}
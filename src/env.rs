@@ -0,0 +1,96 @@
+//! Configure a logger from the `YMLOG` environment variable
+//!
+//! The variable is a comma separated list of directives, such as
+//! `YMLOG=debug,file=/tmp/app.yml,format=json,indent=4,my_app::db=trace`:
+//!
+//! - A level name sets the logger's level
+//! - `stderr` or `stdout` picks the output, and `file=<path>` writes to a new file. The default is
+//!   stderr.
+//! - `format=yaml` or `format=json` sets the output format
+//! - `indent=<spaces>` or `indent=tab` sets the indentation, with tabs written as two spaces
+//! - `color=auto`, `color=always` or `color=never` sets when records are colored
+//! - Anything else is a module directive for [`YmLog::set_filter`]
+
+use std::fs::File;
+use std::io::{Error as IoError, ErrorKind, IsTerminal, Result as IoResult};
+
+use crate::formatter::Indent;
+use crate::prelude::*;
+use crate::GlobalWriter;
+
+/// The variable read by [`YmLog::from_env`]
+pub const ENV_VAR: &str = "YMLOG";
+
+impl YmLog<GlobalWriter> {
+  /// Build a logger from the `YMLOG` environment variable
+  ///
+  /// If the variable isn't set, the logger writes YAML to stderr at the default level.
+  pub fn from_env() -> IoResult<YmLog<GlobalWriter>> {
+    match std::env::var(ENV_VAR) {
+      Ok(directives) => YmLog::from_directives(&directives),
+      Err(std::env::VarError::NotPresent) => YmLog::from_directives(""),
+      Err(err) => Err(IoError::new(ErrorKind::InvalidInput, err)),
+    }
+  }
+
+  /// Build a logger from a directive string in the same syntax as the `YMLOG` variable
+  pub fn from_directives(directives: &str) -> IoResult<YmLog<GlobalWriter>> {
+    let invalid = |directive: &str, reason: &str| {
+      Err(IoError::new(
+        ErrorKind::InvalidInput,
+        format!("The {} directive {:?} {}", ENV_VAR, directive, reason),
+      ))
+    };
+
+    let logger = YmLog::new();
+    let mut output: GlobalWriter = Box::new(std::io::stderr());
+    let mut terminal = std::io::stderr().is_terminal();
+    let mut filters = vec![];
+    for directive in directives
+      .split(',')
+      .map(str::trim)
+      .filter(|d| !d.is_empty())
+    {
+      match directive.split_once('=') {
+        Some(("file", path)) => {
+          let file = File::create(path.trim())?;
+          terminal = file.is_terminal();
+          output = Box::new(file);
+        }
+        Some(("color", choice)) => logger.set_color(choice.trim().parse()?),
+        Some(("format", format)) => match format.trim().to_ascii_lowercase().as_str() {
+          "yaml" | "yml" => logger.set_format(OutputFormat::Yaml),
+          "json" | "jsonl" | "json-lines" => logger.set_format(OutputFormat::JsonLines),
+          _ => return invalid(directive, "is not yaml or json"),
+        },
+        Some(("indent", indent)) => match indent.trim() {
+          "tab" => logger.set_indent(Indent::Tab),
+          spaces => match spaces.parse::<u8>() {
+            Ok(count) if count > 0 => logger.set_indent(Indent::Space(count)),
+            _ => return invalid(directive, "is not a number of spaces or \"tab\""),
+          },
+        },
+        Some(_) => filters.push(directive),
+        None => match directive {
+          "stderr" => {
+            output = Box::new(std::io::stderr());
+            terminal = std::io::stderr().is_terminal();
+          }
+          "stdout" => {
+            output = Box::new(std::io::stdout());
+            terminal = std::io::stdout().is_terminal();
+          }
+          _ => match Level::from_name(directive) {
+            Some(level) => logger.set_level(level),
+            None => filters.push(directive),
+          },
+        },
+      }
+    }
+
+    logger.set_filter(&filters.join(","))?;
+    logger.set_output(output);
+    logger.set_terminal(terminal);
+    Ok(logger)
+  }
+}
@@ -0,0 +1,217 @@
+//! Outputs that stand in for each other, written to one at a time
+//!
+//! The outputs of a group are ranked in the order they were added. Each record is offered to the
+//! active member, and if it is unhealthy or the write fails, to the members after it in turn, so
+//! the record only goes missing if every member fails. While a backup is active, the members
+//! ranked above it are given a record again every interval, and the first one to take it becomes
+//! active again. Each change of the active member is written as a `!ymlog/failover` record.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+
+/// The tag the changes of a group's active member are written with
+pub const FAILOVER_TAG: &str = "ymlog/failover";
+
+/// How long a failed member is left alone before it is tried again, by default
+const FAILBACK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The state of every failover group of a logger
+#[derive(Debug)]
+pub(crate) struct Failover {
+  groups: HashMap<String, Group>,
+
+  /// How long a failed member is left alone before it is tried again
+  pub interval: Duration,
+}
+
+#[derive(Debug)]
+struct Group {
+  /// The rank of the member taking the records
+  active: usize,
+
+  /// When the members ranked above the active one were last tried
+  last_retry: Instant,
+}
+
+/// Which members of each group may take a record, and what happened when they were offered it
+pub(crate) struct Attempts {
+  groups: Vec<Attempt>,
+}
+
+struct Attempt {
+  name: String,
+
+  /// The outputs in the group, by their index in the logger
+  members: Vec<usize>,
+
+  /// The rank of the first member offered the record
+  start: usize,
+
+  /// Whether the members above the active one are being tried again
+  retrying: bool,
+
+  /// The rank of the member that took the record
+  taken: Option<usize>,
+
+  /// Why the last member passed over didn't take the record
+  reason: Option<String>,
+}
+
+impl Default for Failover {
+  fn default() -> Failover {
+    Failover {
+      groups: HashMap::new(),
+      interval: FAILBACK_INTERVAL,
+    }
+  }
+}
+
+impl Failover {
+  /// Plan the next record, from the failover group of each output in order
+  pub(crate) fn attempts<'a>(
+    &mut self,
+    outputs: impl Iterator<Item = Option<&'a str>>,
+  ) -> Attempts {
+    let mut groups: Vec<Attempt> = vec![];
+    for (i, group) in outputs.enumerate() {
+      let name = match group {
+        Some(name) => name,
+        None => continue,
+      };
+      match groups.iter_mut().find(|attempt| attempt.name == name) {
+        Some(attempt) => attempt.members.push(i),
+        None => groups.push(Attempt {
+          name: name.to_string(),
+          members: vec![i],
+          start: 0,
+          retrying: false,
+          taken: None,
+          reason: None,
+        }),
+      }
+    }
+
+    for attempt in groups.iter_mut() {
+      let group = self
+        .groups
+        .entry(attempt.name.clone())
+        .or_insert_with(|| Group {
+          active: 0,
+          last_retry: Instant::now(),
+        });
+      let active = group.active.min(attempt.members.len() - 1);
+      attempt.retrying = active > 0 && group.last_retry.elapsed() >= self.interval;
+      attempt.start = match attempt.retrying {
+        true => 0,
+        false => active,
+      };
+    }
+    Attempts { groups }
+  }
+
+  /// Make the members that took the record active, returning the records noting each change
+  pub(crate) fn finish(&mut self, attempts: Attempts) -> Vec<Block> {
+    let mut changes = vec![];
+    for attempt in attempts.groups {
+      let group = match self.groups.get_mut(&attempt.name) {
+        Some(group) => group,
+        None => continue,
+      };
+      if attempt.retrying {
+        group.last_retry = Instant::now();
+      }
+      let taken = match attempt.taken {
+        Some(taken) if taken != group.active => taken,
+        _ => continue,
+      };
+
+      let mut block = Block::new();
+      let _ = match taken > group.active {
+        true => block.set_message(format!(
+          "Failed over to output {} of {}",
+          taken, attempt.name
+        )),
+        false => block.set_message(format!(
+          "Failed back to output {} of {}",
+          taken, attempt.name
+        )),
+      };
+      block.set_log_level(match taken > group.active {
+        true => Level::Warn,
+        false => Level::Info,
+      });
+      let _ = block.add_field("group", &attempt.name);
+      let _ = block.add_field("from", group.active);
+      let _ = block.add_field("to", taken);
+      if let (Some(reason), true) = (attempt.reason, taken > group.active) {
+        let _ = block.add_field("reason", reason);
+      }
+      block.set_tag_type(FAILOVER_TAG);
+      changes.push(block);
+      group.active = taken;
+      // A member that just failed is left alone for the interval
+      group.last_retry = Instant::now();
+    }
+    changes
+  }
+}
+
+impl Attempts {
+  /// Check if the output should be offered the record
+  ///
+  /// Outputs outside any group always are. The last member is offered the record even if it is
+  /// unhealthy, as is the first one being tried again, since there is nothing better to do.
+  pub(crate) fn should_offer(
+    &mut self,
+    output: usize,
+    healthy: impl FnOnce() -> Result<(), String>,
+  ) -> bool {
+    let (attempt, rank) = match self.find(output) {
+      Some(found) => found,
+      None => return true,
+    };
+    if attempt.taken.is_some() || rank < attempt.start {
+      return false;
+    }
+    let last = rank + 1 == attempt.members.len();
+    let retried = attempt.retrying && rank == attempt.start;
+    match healthy() {
+      Ok(()) => true,
+      Err(_) if last || retried => true,
+      Err(reason) => {
+        attempt.reason = Some(reason);
+        false
+      }
+    }
+  }
+
+  /// Note that the output took the record, or passed it over on purpose
+  pub(crate) fn took(&mut self, output: usize) {
+    if let Some((attempt, rank)) = self.find(output) {
+      attempt.taken = Some(rank);
+    }
+  }
+
+  /// Note that writing the record to the output failed, returning if another member can take it
+  pub(crate) fn failed(&mut self, output: usize, reason: String) -> bool {
+    match self.find(output) {
+      Some((attempt, rank)) => {
+        attempt.reason = Some(reason);
+        rank + 1 < attempt.members.len()
+      }
+      None => false,
+    }
+  }
+
+  fn find(&mut self, output: usize) -> Option<(&mut Attempt, usize)> {
+    self.groups.iter_mut().find_map(|attempt| {
+      let rank = attempt
+        .members
+        .iter()
+        .position(|member| *member == output)?;
+      Some((attempt, rank))
+    })
+  }
+}
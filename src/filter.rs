@@ -0,0 +1,113 @@
+//! Levels set per module, in the style of `env_logger` directives
+//!
+//! A filter such as `warn,my_app::db=trace,hyper=off` is a comma separated list. A bare level sets
+//! the default, a bare module path logs everything from it, and `off` silences the module. The
+//! longest module path matching a block's target wins.
+//!
+//! Blocks can also be filtered by their tags, keeping those with an included tag and dropping any
+//! with an excluded one.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+use crate::prelude::*;
+
+/// What a directive allows through. None turns the module off.
+type Threshold = Option<Level>;
+
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+  /// Replaces the logger's level for blocks not matching a directive
+  default: Option<Threshold>,
+
+  /// The module paths with their own threshold
+  directives: Vec<(String, Threshold)>,
+}
+
+impl Filter {
+  pub fn parse(spec: &str) -> IoResult<Filter> {
+    let mut filter = Filter::default();
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+      match directive.split_once('=') {
+        Some((path, level)) => {
+          let level = Filter::threshold(level.trim())
+            .ok_or_else(|| Filter::invalid(directive, "is not a level"))?;
+          filter.directives.push((path.trim().to_string(), level));
+        }
+        None => match Filter::threshold(directive) {
+          Some(level) => filter.default = Some(level),
+          None => filter
+            .directives
+            .push((directive.to_string(), Some(Level::Trace))),
+        },
+      }
+    }
+    Ok(filter)
+  }
+
+  fn threshold(name: &str) -> Option<Threshold> {
+    match name.eq_ignore_ascii_case("off") {
+      true => Some(None),
+      false => Level::from_name(name).map(Some),
+    }
+  }
+
+  fn invalid(directive: &str, reason: &str) -> IoError {
+    IoError::new(
+      ErrorKind::InvalidInput,
+      format!("The filter directive {:?} {}", directive, reason),
+    )
+  }
+
+  /// Find the threshold for the target, or None if the logger's level applies
+  pub fn level_for(&self, target: Option<&str>) -> Option<&Threshold> {
+    let matched = target.and_then(|target| {
+      self
+        .directives
+        .iter()
+        .filter(|(path, _)| {
+          target == path
+            || (target.starts_with(path.as_str()) && target[path.len()..].starts_with("::"))
+        })
+        .max_by_key(|(path, _)| path.len())
+    });
+    match matched {
+      Some((_, threshold)) => Some(threshold),
+      None => self.default.as_ref(),
+    }
+  }
+}
+
+/// Which tags a block must have, or must not have, to be written
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct TagFilter {
+  /// A block needs one of these, unless it is empty
+  include: Vec<String>,
+
+  /// A block with any of these is dropped, even if it has an included tag
+  exclude: Vec<String>,
+}
+
+impl TagFilter {
+  pub fn new<I, E>(include: I, exclude: E) -> TagFilter
+  where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+    E: IntoIterator,
+    E::Item: std::fmt::Display,
+  {
+    TagFilter {
+      include: include.into_iter().map(|tag| tag.to_string()).collect(),
+      exclude: exclude.into_iter().map(|tag| tag.to_string()).collect(),
+    }
+  }
+
+  /// Check if a block with these tags should be written
+  pub fn allows(&self, tags: &[impl AsRef<str>]) -> bool {
+    let has = |wanted: &Vec<String>| {
+      tags
+        .iter()
+        .any(|tag| wanted.iter().any(|other| other == tag.as_ref()))
+    };
+    (self.include.is_empty() || has(&self.include)) && !has(&self.exclude)
+  }
+}
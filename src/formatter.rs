@@ -3,14 +3,25 @@
 //! Because I don't have time to write a new serializer, I'm going to hack in some functionality
 //! missing from rust-yaml. I'm likely going to reuse this when I try to write my own YAML parser.
 
-use serde_yaml::{Result as YmlResult, Value as YmlValue};
+use serde_yaml::value::TaggedValue;
+use serde_yaml::{Mapping, Number, Result as YmlResult, Value as YmlValue};
+use unicode_width::UnicodeWidthStr;
+
+use crate::message::MessageType;
+use crate::node::{self, Node};
+use crate::prelude::*;
+use crate::scan;
+use crate::strict;
+
+/// The width of the readable page that blocks are folded at, unless a formatter is given another
+pub const WRAP_AT: usize = 120;
 
 /// Options used in converting a YAML Value into a string
 ///
 /// This inserts itself as a middle-man to serde_yaml so we can customize the formatting
 ///
 /// TODO: Merge this with the tracker for the new serializer
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct YamlFormatter {
   /// Similar to a buffer, this can be used when streaming to tell how to prefix the current line
   ///
@@ -25,6 +36,16 @@ pub struct YamlFormatter {
   /// How multiline scalars should be printed
   multiline_style: Style,
 
+  /// How numbers are written
+  numbers: NumberFormat,
+
+  /// Which values are written as readable durations and sizes
+  values: ValueFormat,
+
+  /// Nested mappings and sequences that fit in this many characters in flow style are written on
+  /// one line, like `{key: value, n: 3}`
+  flow_width: Option<usize>,
+
   /// Disables some options that don't work when using this in a streaming write
   ///
   /// to_flow doesn't make sense since the next write may contain another value of the same indent
@@ -40,11 +61,14 @@ pub struct YamlFormatter {
   /// preceding value
   _trailing_newline: bool,
 
-  /// The width of the readable page (default is 120 characters)
+  /// The width of the readable page (default is [`WRAP_AT`] characters)
   ///
   /// This is used to determine how to fold strings
   /// TODO: Make this a more robust filter, such as using min or max items
   wrap_at: Option<usize>,
+
+  /// Single line prose longer than this is guessed to be Folded (default is the wrap width)
+  fold_length: Option<usize>,
 }
 
 impl YamlFormatter {
@@ -58,40 +82,101 @@ impl YamlFormatter {
     self.multiline_style = style;
   }
 
+  /// Set how long a single line of prose has to be for the Guess style to fold it, or None to
+  /// use the wrap width
+  pub fn set_fold_length(&mut self, length: Option<usize>) {
+    self.fold_length = length;
+  }
+
+  /// Set how numbers are written
+  pub fn set_number_format(&mut self, numbers: NumberFormat) {
+    self.numbers = numbers;
+  }
+
+  /// Set which values are written as readable durations and sizes
+  pub fn set_value_format(&mut self, values: ValueFormat) {
+    self.values = values;
+  }
+
+  /// Write nested mappings and sequences that fit in the width in flow style, or None to always
+  /// write them as blocks
+  pub fn set_flow_width(&mut self, width: Option<usize>) {
+    self.flow_width = width;
+  }
+
+  /// What each level is indented with
+  pub(crate) fn indent(&self) -> &Indent {
+    &self.indent
+  }
+
+  /// Write a record's node, with every line starting with the base
+  ///
+  /// Nested mappings and sequences are only written in flow style if `flow` is set, as a record
+  /// with children continues in the records after it.
+  pub(crate) fn write_node(&self, node: &Node, base: &str, flow: bool) -> String {
+    let flow_width = self.flow_width.filter(|_| flow);
+    node::Writer::new(base, self.indent.width(), flow_width).document(node)
+  }
+
   /// Convert a yaml value into a string
   ///
   /// This is being designed for streaming.
   pub fn stringify(&mut self, value: YmlValue, indent: Option<u8>) -> YmlResult<String> {
+    let value = match self.values == ValueFormat::default() {
+      true => value,
+      false => self.values.apply(&value),
+    };
+    let value = match self.numbers == NumberFormat::default() {
+      true => value,
+      false => self.numbers.apply(&value),
+    };
+
     // An empty container for the string
     let mut result = String::new();
     let depth = indent.unwrap_or(0);
 
     // Get the initial indent and add one to the result
     let indent_str = self.indent.make(indent);
-    result.push_str(&indent_str);
 
     match value {
-      YmlValue::Mapping(_mapping) => {
-        unimplemented!("'stringify mapping' still needs to be implemented")
+      YmlValue::Mapping(mapping) => {
+        self.last_write = (depth, LastWriteItem::Block(ItemType::MappingValue));
+        self.block_mapping(&mapping, depth as usize)
       }
-      YmlValue::Sequence(_seq) => {
-        unimplemented!("'stringify sequence' still needs to be implemented")
+      YmlValue::Sequence(seq) => {
+        self.last_write = (depth, LastWriteItem::Block(ItemType::SequenceItem));
+        self.block_sequence(&seq, depth as usize)
       }
       YmlValue::Null => {
         self.last_write = (indent.unwrap_or(0), LastWriteItem::Flow(ItemType::Scalar));
-        Ok(String::new())
+        Ok(indent_str)
       }
       YmlValue::Number(value) => {
         self.last_write = (indent.unwrap_or(0), LastWriteItem::Flow(ItemType::Scalar));
-        serde_yaml::to_string(&value)
+        serde_yaml::to_string(&value).map(|value| format!("{}{}", indent_str, value))
       }
       YmlValue::Bool(value) => {
         self.last_write = (indent.unwrap_or(0), LastWriteItem::Flow(ItemType::Scalar));
-        serde_yaml::to_string(&value)
+        serde_yaml::to_string(&value).map(|value| format!("{}{}", indent_str, value))
       }
       YmlValue::String(value) => {
         self.last_write = (indent.unwrap_or(0), LastWriteItem::None);
-        self.stringify_string(value, depth as usize)
+        // Flow scalars are written to follow a key, so they start with a space we don't need here
+        self.stringify_string(value, depth as usize).map(|value| {
+          let value = value.strip_prefix(' ').unwrap_or(&value);
+          format!("{}{}\n", indent_str, value)
+        })
+      }
+      YmlValue::Tagged(tagged) => {
+        let TaggedValue { tag, value } = *tagged;
+        match is_block_container(&value) {
+          true => self
+            .block_container(&value, depth as usize)
+            .map(|value| format!("{}{}\n{}", indent_str, tag, value)),
+          false => self
+            .inline(&value, depth as usize)
+            .map(|value| format!("{}{}{}\n", indent_str, tag, value)),
+        }
       }
     }
     .map(|value| {
@@ -106,12 +191,118 @@ impl YamlFormatter {
     Ok(result)
   }
 
+  /// Write each pair of the mapping on its own line(s) at the depth
+  fn block_mapping(&mut self, mapping: &Mapping, depth: usize) -> YmlResult<String> {
+    if mapping.is_empty() {
+      return Ok(format!("{}{{}}\n", self.indent.make(Some(depth as u8))));
+    }
+
+    let indent = self.indent.make(Some(depth as u8));
+    let mut result = String::new();
+    for (key, value) in mapping {
+      result.push_str(&format!("{}{}:", indent, self.key(key)?));
+      result.push_str(&self.nested(value, depth)?);
+    }
+    Ok(result)
+  }
+
+  /// Write each item of the sequence on its own line(s) at the depth
+  fn block_sequence(&mut self, seq: &[YmlValue], depth: usize) -> YmlResult<String> {
+    if seq.is_empty() {
+      return Ok(format!("{}[]\n", self.indent.make(Some(depth as u8))));
+    }
+
+    let indent = self.indent.make(Some(depth as u8));
+    let mut result = String::new();
+    for item in seq {
+      if let Some(flowed) = self.flow_width.and_then(|width| flow_within(item, width)) {
+        result.push_str(&format!("{}- {}\n", indent, flowed));
+        continue;
+      }
+      match is_block_container(item) {
+        // Start the container on the dash line, replacing the first level of its indentation
+        true => {
+          let nested = self.block_container(item, depth + 1)?;
+          let skip = self.indent.make(Some(depth as u8 + 1)).len();
+          result.push_str(&format!(
+            "{}{}{}",
+            indent,
+            self.indent.dash(),
+            &nested[skip..]
+          ));
+        }
+        false => result.push_str(&format!("{}-{}", indent, self.nested(item, depth)?)),
+      }
+    }
+    Ok(result)
+  }
+
+  /// Write a value following a key or a dash, at the depth of its parent
+  ///
+  /// Containers start on the next line, one level deeper. Everything else continues the line.
+  fn nested(&mut self, value: &YmlValue, depth: usize) -> YmlResult<String> {
+    if let Some(flowed) = self.flow_width.and_then(|width| flow_within(value, width)) {
+      return Ok(format!(" {}\n", flowed));
+    }
+    match value {
+      _ if is_block_container(value) => {
+        Ok(format!("\n{}", self.block_container(value, depth + 1)?))
+      }
+      YmlValue::Tagged(tagged) if is_block_container(&tagged.value) => Ok(format!(
+        " {}\n{}",
+        tagged.tag,
+        self.block_container(&tagged.value, depth + 1)?
+      )),
+      _ => Ok(format!("{}\n", self.inline(value, depth)?)),
+    }
+  }
+
+  fn block_container(&mut self, value: &YmlValue, depth: usize) -> YmlResult<String> {
+    match value {
+      YmlValue::Mapping(mapping) => self.block_mapping(mapping, depth),
+      YmlValue::Sequence(seq) => self.block_sequence(seq, depth),
+      _ => unreachable!("Only non-empty mappings and sequences are written as blocks"),
+    }
+  }
+
+  /// Write a value that fits on the rest of the current line, starting with a space
+  ///
+  /// Block strings start their header here, continuing on the lines after.
+  fn inline(&mut self, value: &YmlValue, depth: usize) -> YmlResult<String> {
+    match value {
+      YmlValue::Null => Ok(" null".to_string()),
+      YmlValue::String(value) => self.stringify_string(value.clone(), depth),
+      YmlValue::Tagged(tagged) => Ok(format!(
+        " {}{}",
+        tagged.tag,
+        self.inline(&tagged.value, depth)?
+      )),
+      // Empty containers
+      YmlValue::Mapping(_) | YmlValue::Sequence(_) => Ok(format!(" {}", flow(value)?)),
+      _ => Ok(format!(" {}", serde_yaml::to_string(value)?.trim_end())),
+    }
+  }
+
+  /// Write a mapping key, which always has to be on a single line
+  fn key(&self, key: &YmlValue) -> YmlResult<String> {
+    match key {
+      YmlValue::String(key) if needs_quotes(key) => Ok(Style::double_quote(key)),
+      YmlValue::String(key) => Ok(key.clone()),
+      YmlValue::Mapping(_) | YmlValue::Sequence(_) | YmlValue::Tagged(_) => flow(key),
+      YmlValue::Null => Ok("null".to_string()),
+      _ => Ok(serde_yaml::to_string(key)?.trim_end().to_string()),
+    }
+  }
+
   /// Write a String scalar
   fn stringify_string(&mut self, value: String, depth: usize) -> YmlResult<String> {
     let mut result = String::new();
 
     let style = match self.multiline_style {
-      Style::Guess => Style::guess_style(&value),
+      Style::Guess => {
+        let fold_length = self.fold_length.or(self.wrap_at).unwrap_or(WRAP_AT);
+        Style::guess_style(&value, fold_length)
+      }
       _ => self.multiline_style.clone(),
     };
 
@@ -125,7 +316,7 @@ impl YamlFormatter {
           depth,
           &chomp,
           &self.indent,
-          self.wrap_at.unwrap_or(120),
+          self.wrap_at.unwrap_or(WRAP_AT),
         )?);
       }
       Style::Literal(chomp) => {
@@ -135,10 +326,22 @@ impl YamlFormatter {
           depth,
           &chomp,
           &self.indent,
-          self.wrap_at.unwrap_or(120),
+          self.wrap_at.unwrap_or(WRAP_AT),
         )?);
       }
-      _ => unimplemented!("'The Scalars' still needs to be implemented"),
+      // Flow scalars can't hold a line break without escaping it
+      Style::Plain if !needs_quotes(&value) => {
+        *last_write = LastWriteItem::Flow(ItemType::Scalar);
+        result.push_str(&format!(" {}", value));
+      }
+      Style::Single if !needs_escapes(&value) => {
+        *last_write = LastWriteItem::Flow(ItemType::Scalar);
+        result.push_str(&format!(" '{}'", value.replace('\'', "''")));
+      }
+      Style::Plain | Style::Single | Style::Double => {
+        *last_write = LastWriteItem::Flow(ItemType::Scalar);
+        result.push_str(&format!(" {}", Style::double_quote(&value)));
+      }
     }
     Ok(result)
   }
@@ -149,19 +352,13 @@ impl YamlFormatter {
 pub enum Indent {
   /// Use the number of spaces listed for each level. Default is 2
   Space(u8),
+  /// YAML doesn't allow tabs as indentation, so this falls back to the default two spaces
   Tab,
 }
 
 impl std::fmt::Display for Indent {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "{}",
-      match self {
-        Indent::Space(count) => " ".repeat(*count as usize),
-        Indent::Tab => "\t".to_string(),
-      }
-    )
+    write!(f, "{}", " ".repeat(self.width()))
   }
 }
 
@@ -172,19 +369,196 @@ impl Default for Indent {
 }
 
 impl Indent {
+  /// How many spaces each level is indented with, which is at least one
+  pub fn width(&self) -> usize {
+    match self {
+      Indent::Space(count) => (*count).max(1) as usize,
+      Indent::Tab => 2,
+    }
+  }
+
   /// Make a string containing count many indents
   pub fn make(&self, count: Option<u8>) -> String {
     self.to_string().repeat(count.unwrap_or(0).into())
   }
+
+  /// The sequence marker, padded to the width of an indent so a container can start on its line
+  pub fn dash(&self) -> String {
+    format!("-{}", " ".repeat(self.width().max(2) - 1))
+  }
+}
+
+/// Non-empty mappings and sequences are written over several lines. Empty ones are written in flow.
+fn is_block_container(value: &YmlValue) -> bool {
+  match value {
+    YmlValue::Mapping(mapping) => !mapping.is_empty(),
+    YmlValue::Sequence(seq) => !seq.is_empty(),
+    _ => false,
+  }
+}
+
+/// Check if a string has to be quoted to be read back as the same string
+///
+/// Plain scalars can't be empty, start with an indicator or whitespace, end with whitespace or a
+/// `:`, or hold `: `, ` #` or characters that have to be escaped. They also can't be anything a
+/// YAML 1.1 or 1.2 loader reads as another type, such as `yes`, `null`, `3.14` or `0x1f`.
+pub(crate) fn needs_quotes(value: &str) -> bool {
+  const INDICATORS: &[char] = &[
+    '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+  ];
+  let (first, last) = match (value.chars().next(), value.chars().last()) {
+    (Some(first), Some(last)) => (first, last),
+    _ => return true,
+  };
+  if INDICATORS.contains(&first)
+    || first.is_whitespace()
+    || last.is_whitespace()
+    || last == ':'
+    || breaks_plain(value)
+  {
+    return true;
+  }
+  is_typed(value)
+}
+
+/// Check if a string holds `: `, ` #` or a character that has to be escaped
+///
+/// Only the characters the scanner stops at can start one, so the rest of the string is skipped.
+fn breaks_plain(value: &str) -> bool {
+  let bytes = value.as_bytes();
+  let blank = |at: Option<usize>| matches!(at.and_then(|at| bytes.get(at)), Some(b' ' | b'\t'));
+  let mut start = 0;
+  while let Some(offset) = scan::find_yaml_special(&value[start..]) {
+    let at = start + offset;
+    start = match bytes[at] {
+      b':' if blank(Some(at + 1)) => return true,
+      b'#' if blank(at.checked_sub(1)) => return true,
+      b':' | b'#' => at + 1,
+      // Every other ASCII character found is a control character
+      byte if byte.is_ascii() => return true,
+      _ => match value[at..].chars().next() {
+        Some(c) if is_escaped(c) => return true,
+        Some(c) => at + c.len_utf8(),
+        None => return false,
+      },
+    };
+  }
+  false
+}
+
+/// Check if a string holds characters that can only be written escaped in double quotes
+fn needs_escapes(value: &str) -> bool {
+  value.chars().any(is_escaped)
+}
+
+/// Control characters, the byte order mark and the line and paragraph separators, which a YAML
+/// reader would break lines at
+fn is_escaped(c: char) -> bool {
+  c.is_control() || matches!(c, '\u{feff}' | '\u{2028}' | '\u{2029}')
+}
+
+/// Check if a plain scalar would be read as a number, boolean, null or timestamp
+fn is_typed(value: &str) -> bool {
+  if strict::is_ambiguous(value) {
+    return true;
+  }
+  let unsigned = value.trim_start_matches(['-', '+']);
+  let lower = unsigned.to_ascii_lowercase();
+  if matches!(lower.as_str(), ".inf" | ".nan") {
+    return true;
+  }
+  for prefix in ["0x", "0o", "0b"] {
+    if let Some(digits) = lower.strip_prefix(prefix) {
+      if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit() || c == '_') {
+        return true;
+      }
+    }
+  }
+  // Rust also reads words like inf and NaN, which YAML only does with a leading dot
+  unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.') && value.parse::<f64>().is_ok()
+}
+
+/// Write a non-empty mapping or sequence on a single line, if it takes no more than the width
+pub(crate) fn flow_within(value: &YmlValue, width: usize) -> Option<String> {
+  if !is_block_container(value) {
+    return None;
+  }
+  flow(value).ok().filter(|flowed| flowed.width() <= width)
+}
+
+/// The style a message given one is written in: a block that can't hold the string, or a flow
+/// style that would change it, is double quoted instead, and Guess leaves it to serde_yaml
+pub(crate) fn message_style(text: &str, style: &Style) -> Option<Style> {
+  // Blocks can't escape anything, and leading whitespace would need an indentation indicator
+  let is_raw = |text: &str| {
+    !text
+      .chars()
+      .any(|c| is_escaped(c) && !matches!(c, '\n' | '\t'))
+      && !text.trim_start_matches('\n').starts_with([' ', '\t'])
+  };
+  match style {
+    Style::Guess => None,
+    Style::Literal(_) | Style::Folded(_) if !is_raw(text) => Some(Style::Double),
+    Style::Plain if needs_quotes(text) => Some(Style::Double),
+    Style::Single if needs_escapes(text) => Some(Style::Double),
+    _ => Some(style.clone()),
+  }
+}
+
+/// Write the string in a style from [`message_style`], with a block's lines indented to the column
+pub(crate) fn write_styled(text: &str, style: &Style, column: usize) -> String {
+  // Blocks are indented one level deeper than the depth
+  let (depth, indent) = (column.saturating_sub(1), Indent::Space(1));
+  let written = match style {
+    Style::Literal(chomp) => {
+      Style::literal_string(text.to_string(), depth, chomp, &indent, WRAP_AT)
+    }
+    Style::Folded(chomp) => Style::fold_string(text.to_string(), depth, chomp, &indent, WRAP_AT),
+    Style::Plain => Ok(text.to_string()),
+    Style::Single => Ok(format!("'{}'", text.replace('\'', "''"))),
+    Style::Guess | Style::Double => Ok(Style::double_quote(text)),
+  };
+  // The block writers start with the space after a key
+  match written {
+    Ok(written) => written.trim_start_matches(' ').to_string(),
+    Err(_) => Style::double_quote(text),
+  }
+}
+
+/// Write the value on a single line, using flow syntax for the containers
+fn flow(value: &YmlValue) -> YmlResult<String> {
+  match value {
+    YmlValue::Mapping(mapping) => {
+      let pairs = mapping
+        .iter()
+        .map(|(key, value)| Ok(format!("{}: {}", flow(key)?, flow(value)?)))
+        .collect::<YmlResult<Vec<_>>>()?;
+      Ok(format!("{{{}}}", pairs.join(", ")))
+    }
+    YmlValue::Sequence(seq) => {
+      let items = seq.iter().map(flow).collect::<YmlResult<Vec<_>>>()?;
+      Ok(format!("[{}]", items.join(", ")))
+    }
+    YmlValue::Tagged(tagged) => Ok(format!("{} {}", tagged.tag, flow(&tagged.value)?)),
+    YmlValue::Null => Ok("null".to_string()),
+
+    // Flow indicators end a plain scalar, so anything but simple words is quoted
+    YmlValue::String(value) => match needs_quotes(value) || scan::has_flow_indicator(value) {
+      true => Ok(Style::double_quote(value)),
+      false => Ok(value.clone()),
+    },
+    _ => Ok(serde_yaml::to_string(value)?.trim_end().to_string()),
+  }
 }
 
 /// How YAML should handle formatting multiline strings
 ///
 /// FIXME: The spec for YAML is rather confusing, so this will need to be totally reworked
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub enum Style {
   /// This will guess the best style based on the contents of the message (Heaviest calculation)
+  #[default]
   Guess,
 
   /// Block Style: Folded replaces all individual newlines with a single space '>'
@@ -200,15 +574,15 @@ pub enum Style {
   Double,
 }
 
-impl Default for Style {
-  fn default() -> Self {
-    Style::Guess
-  }
-}
-
 impl Style {
-  /// When writing, this replaces a whitespace character with a wrap
+  /// Print a block with a Folded syntax, wrapping long lines at a space
   ///
+  /// A line is only wrapped at a single space between two words, once the next word would take
+  /// it past `wrap_at` columns, indent included. Wide characters such as CJK and emoji count as two
+  /// columns and combining marks as none. The reader folds a line break back into a space, so each
+  /// newline of the value is written as an empty line instead, except next to lines starting with
+  /// whitespace, which are never folded or wrapped. The chomp works like
+  /// [`Style::literal_string`]'s, and so do the line endings, which are written as `\n`.
   pub fn fold_string(
     value: String,
     depth: usize,
@@ -216,84 +590,192 @@ impl Style {
     indent: &Indent,
     wrap_at: usize,
   ) -> YmlResult<String> {
-    // How much to indent a block
-    let indent = indent.to_string().repeat(depth + 1);
+    let value = normalize_line_endings(value);
+    let padding = indent.to_string().repeat(depth + 1);
+    let content = match chomp {
+      Chomp::Keep => value.strip_suffix('\n').unwrap_or(&value),
+      Chomp::Clip | Chomp::Strip => value.trim_end_matches('\n'),
+    };
+    let indicator = match (
+      content.trim_start_matches('\n').starts_with([' ', '\t']),
+      indent,
+    ) {
+      (true, indent) => indent.width().to_string(),
+      _ => String::new(),
+    };
 
-    // Count of characters towards the wrap in the current line. Indent counts
-    let mut line_len = indent.len() as usize;
-    let mut word = String::new();
-    let mut word_is_ws = false;
+    let is_spaced = |line: &str| line.starts_with(' ') || line.starts_with('\t');
+    let lines = content.split('\n').collect::<Vec<_>>();
+    let mut result = format!(" >{}{}", indicator, chomp);
+    for (i, line) in lines.iter().enumerate() {
+      result.push('\n');
+      if line.is_empty() {
+        continue;
+      }
+      result.push_str(&padding);
+      match is_spaced(line) {
+        true => result.push_str(line),
+        false => result.push_str(&Style::wrap_line(line, &padding, wrap_at)),
+      }
 
-    let mut result = format!(" >{}\n", chomp);
-    for c in value.chars() {
-      match c {
-        ' ' => {
-          // If we've hit the wrap limit, convert the space into a newline
-          if wrap_at <= word.len() + line_len {
-            if word_is_ws {
-              // If its all whitespace, save it for the next line
-              result.push('\n');
-              word = format!("{}{}", indent, word);
-              line_len = indent.len() - 1;
-            } else {
-              // Otherwise the word fits so we print it to the next line
-              result.push_str(&format!("{}\n{}", word, indent));
-              word = indent.clone();
-              line_len = indent.len() - 1;
-              word_is_ws = false;
-            }
-          } else {
-            // Found the end of a word, so we print it
-            result.push_str(&word);
-            line_len += word.len();
-            word.clear();
+      // A single line break between two folded lines would be read back as a space
+      let next = lines[i + 1..].iter().find(|line| !line.is_empty());
+      if !is_spaced(line) && next.is_some_and(|next| !is_spaced(next)) {
+        result.push('\n');
+      }
+    }
+    Ok(result)
+  }
+
+  /// Break the line at the spaces that are read back as one, keeping it within the width
+  fn wrap_line(line: &str, padding: &str, wrap_at: usize) -> String {
+    let start = padding.width();
+    let mut column = start;
+    let mut result = String::with_capacity(line.len());
+    let mut last = None;
+    for (i, word) in line.split(' ').enumerate() {
+      let word_width = word.width();
+      if i > 0 {
+        // Only a single space between two words can be folded, or the spaces around it would
+        // change how it is read
+        let can_wrap = last.is_some_and(|last: &str| !last.is_empty()) && !word.is_empty();
+        match can_wrap && column > start && column + 1 + word_width > wrap_at {
+          true => {
+            result.push('\n');
+            result.push_str(padding);
+            column = start;
+          }
+          false => {
+            result.push(' ');
+            column += 1;
           }
-        }
-        '\n' => {
-          result.push_str(&format!("\n{}", indent));
-          line_len = indent.len() - 1;
-        }
-        _ => {
-          word.push(c);
         }
       }
+      result.push_str(word);
+      column += word_width;
+      last = Some(word);
     }
-
-    Ok(result)
+    result
   }
 
   /// Print a block with a Literal syntax (preserves newlines)
+  ///
+  /// Each line is indented one level deeper than the depth, and the result doesn't end with a
+  /// newline. The chomp decides what happens to trailing newlines: Strip and Clip drop any extra
+  /// ones, while Keep writes them as empty lines so they are read back.
+  ///
+  /// A block only holds `\n` line breaks, so `\r\n` and lone `\r` line endings are written, and
+  /// read back, as `\n`. Use a quoted style to keep them.
   pub fn literal_string(
-    _value: String,
-    _depth: usize,
+    value: String,
+    depth: usize,
     chomp: &Chomp,
-    _indent: &Indent,
+    indent: &Indent,
     _wrap_at: usize,
   ) -> YmlResult<String> {
-    let result = format!(" |{}\n", chomp);
+    let value = normalize_line_endings(value);
+    let padding = indent.to_string().repeat(depth + 1);
 
-    Ok(result)
+    // The line break after the last line is added by the reader based on the chomp
+    let content = match chomp {
+      Chomp::Keep => value.strip_suffix('\n').unwrap_or(&value),
+      Chomp::Clip | Chomp::Strip => value.trim_end_matches('\n'),
+    };
+    // Leading whitespace would be read as indentation, so the width has to be given explicitly
+    // Leading spaces would be read as indentation, so the width has to be given explicitly
+    let indicator = match (
+      content.trim_start_matches('\n').starts_with([' ', '\t']),
+      indent,
+    ) {
+      (true, indent) => indent.width().to_string(),
+      _ => String::new(),
+    };
+
+    let lines = content
+      .split('\n')
+      .map(|line| match line.is_empty() {
+        true => String::new(),
+        false => format!("{}{}", padding, line),
+      })
+      .collect::<Vec<_>>();
+    Ok(format!(" |{}{}\n{}", indicator, chomp, lines.join("\n")))
+  }
+
+  /// Write the string in double quotes, escaping anything that can't appear in them
+  pub fn double_quote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+      match c {
+        '"' => result.push_str("\\\""),
+        '\\' => result.push_str("\\\\"),
+        '\n' => result.push_str("\\n"),
+        '\r' => result.push_str("\\r"),
+        '\t' => result.push_str("\\t"),
+        c if is_escaped(c) => result.push_str(&format!("\\u{:04x}", c as u32)),
+        c => result.push(c),
+      }
+    }
+    result.push('"');
+    result
   }
 
-  pub fn guess_style(value: &str) -> Style {
-    match value.contains('\n') {
-      true => Style::Literal(Default::default()),
-      false => Style::Double,
+  /// Pick the most readable style that reads back as the same string
+  ///
+  /// - Anything with a character that has to be escaped is Double quoted, as are `\r` line
+  ///   endings, which a block would change
+  /// - Text with line breaks is taken as pre-formatted, such as code, and keeps its lines in a
+  ///   Literal block
+  /// - A single line of prose, with words to wrap at, longer than the fold length is Folded
+  /// - Other text with quotes or backslashes is Double quoted
+  /// - Anything that reads back as a string without quotes is Plain
+  /// - And the rest, such as `yes` or `- item`, is Double quoted
+  pub fn guess_style(value: &str, fold_length: usize) -> Style {
+    let is_prose =
+      || !value.starts_with([' ', '\t']) && value.contains(' ') && value.width() > fold_length;
+    // Blocks hold line breaks and tabs as they are, but nothing else that has to be escaped
+    if value
+      .chars()
+      .any(|c| is_escaped(c) && !matches!(c, '\n' | '\t'))
+    {
+      Style::Double
+    } else if scan::has_newline(value) {
+      Style::Literal(Chomp::matching(value))
+    } else if is_prose() {
+      Style::Folded(Chomp::Strip)
+    } else if value.contains(['"', '\'', '\\']) || needs_quotes(value) {
+      Style::Double
+    } else {
+      Style::Plain
     }
   }
 }
 
+/// Write every `\r\n` and lone `\r` as `\n`, the only line break a block scalar holds
+fn normalize_line_endings(value: String) -> String {
+  match value.contains('\r') {
+    true => value.replace("\r\n", "\n").replace('\r', "\n"),
+    false => value,
+  }
+}
+
 /// Whether to remove any trailing newlines
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub enum Chomp {
+  #[default]
   Clip,
   Strip,
   Keep,
 }
 
-impl Default for Chomp {
-  fn default() -> Self {
-    Chomp::Clip
+impl Chomp {
+  /// The chomp that reads a block back with the same trailing newlines as the value
+  pub fn matching(value: &str) -> Chomp {
+    match value.len() - value.trim_end_matches('\n').len() {
+      0 => Chomp::Strip,
+      1 => Chomp::Clip,
+      _ => Chomp::Keep,
+    }
   }
 }
 
@@ -311,10 +793,289 @@ impl std::fmt::Display for Chomp {
   }
 }
 
+/// How numbers in messages and fields are written
+///
+/// serde_yaml picks its own notation for floats, so only rounding keeps them numbers. Scientific
+/// notation and thousands separators turn them into strings, and YAML quotes the scientific ones
+/// as they look like numbers. Nothing here reads the locale: the decimal point is always `.` and
+/// the only separator is the one given, so a record is written the same on every platform.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NumberFormat {
+  /// Decimal places to round floats to
+  precision: Option<usize>,
+
+  /// Floats smaller than the first or at least the second are written in scientific notation
+  scientific: Option<(f64, f64)>,
+
+  /// Groups the digits before the decimal point in threes
+  thousands: Option<char>,
+}
+
+impl NumberFormat {
+  pub fn new() -> NumberFormat {
+    Default::default()
+  }
+
+  /// Round floats to this many decimal places, so `0.30000000000000004` is written as `0.3`
+  pub fn precision(mut self, places: usize) -> NumberFormat {
+    self.precision = Some(places);
+    self
+  }
+
+  /// Write floats in scientific notation when their size is below `below` or at least `above`
+  ///
+  /// Zero is always written plainly.
+  pub fn scientific(mut self, below: f64, above: f64) -> NumberFormat {
+    self.scientific = Some((below, above));
+    self
+  }
+
+  /// Separate the thousands of integers and floats, such as `1,234,567`
+  pub fn thousands(mut self, separator: char) -> NumberFormat {
+    self.thousands = Some(separator);
+    self
+  }
+
+  /// Format every number in the value. Mapping keys are left alone.
+  pub fn apply(&self, value: &YmlValue) -> YmlValue {
+    match value {
+      YmlValue::Number(number) => self.number(number),
+      YmlValue::Sequence(seq) => {
+        YmlValue::Sequence(seq.iter().map(|item| self.apply(item)).collect())
+      }
+      YmlValue::Mapping(mapping) => YmlValue::Mapping(self.mapping(mapping)),
+      YmlValue::Tagged(tagged) => YmlValue::Tagged(Box::new(TaggedValue {
+        tag: tagged.tag.clone(),
+        value: self.apply(&tagged.value),
+      })),
+      other => other.clone(),
+    }
+  }
+
+  /// Format the numbers of the block's message, fields and children
+  pub(crate) fn apply_block(&self, block: &mut Block) {
+    match &mut block.message {
+      MessageType::Value(value) | MessageType::KeyValue(_, value) => *value = self.apply(value),
+      _ => (),
+    }
+    if let Some(fields) = &mut block.fields {
+      *fields = self.mapping(fields);
+    }
+    if let Some(children) = &mut block.children {
+      children
+        .iter_mut()
+        .for_each(|child| self.apply_block(child));
+    }
+  }
+
+  fn mapping(&self, mapping: &Mapping) -> Mapping {
+    mapping
+      .iter()
+      .map(|(key, value)| (key.clone(), self.apply(value)))
+      .collect()
+  }
+
+  fn number(&self, number: &Number) -> YmlValue {
+    if !number.is_f64() {
+      return match self.thousands {
+        Some(separator) => YmlValue::String(group_thousands(&number.to_string(), separator)),
+        None => YmlValue::Number(number.clone()),
+      };
+    }
+
+    let float = number.as_f64().unwrap_or_default();
+    if !float.is_finite() {
+      return YmlValue::Number(number.clone());
+    }
+    if let Some((below, above)) = self.scientific {
+      let size = float.abs();
+      if float != 0.0 && (size < below || size >= above) {
+        return YmlValue::String(match self.precision {
+          Some(places) => format!("{:.*e}", places, float),
+          None => format!("{:e}", float),
+        });
+      }
+    }
+
+    let rounded = self.precision.map(|places| format!("{:.*}", places, float));
+    match (self.thousands, rounded) {
+      (Some(separator), rounded) => YmlValue::String(group_thousands(
+        &rounded.unwrap_or_else(|| float.to_string()),
+        separator,
+      )),
+      (None, Some(rounded)) => rounded
+        .parse::<f64>()
+        .map(YmlValue::from)
+        .unwrap_or_else(|_| YmlValue::Number(number.clone())),
+      (None, None) => YmlValue::Number(number.clone()),
+    }
+  }
+}
+
+/// Which values are written as readable scalars rather than as serde writes them
+///
+/// Serde writes a `Duration` as a mapping of `secs` and `nanos`, and a byte count is only a number.
+/// Durations can be spotted by their shape, but sizes can't, so they're picked by their key.
+///
+/// ```yaml
+/// # Before
+/// took:
+///   secs: 1
+///   nanos: 200000000
+/// size: 4718592
+/// # After ValueFormat::new().durations().bytes("size")
+/// took: 1.2 s
+/// size: 4.5 MiB
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueFormat {
+  /// Write `secs`/`nanos` mappings as a duration
+  durations: bool,
+
+  /// The keys whose integer values are sizes in bytes
+  bytes: Vec<String>,
+}
+
+impl ValueFormat {
+  pub fn new() -> ValueFormat {
+    Default::default()
+  }
+
+  /// Write serialized durations in the largest unit they have one of, such as `1.2 s`
+  pub fn durations(mut self) -> ValueFormat {
+    self.durations = true;
+    self
+  }
+
+  /// Write the integers under the key in binary units, such as `4.5 MiB`
+  pub fn bytes(mut self, key: impl std::fmt::Display) -> ValueFormat {
+    self.bytes.push(key.to_string());
+    self
+  }
+
+  /// Format every duration in the value and every size under its mappings' keys
+  pub fn apply(&self, value: &YmlValue) -> YmlValue {
+    if let Some(duration) = self.duration(value) {
+      return YmlValue::String(human_duration(duration));
+    }
+    match value {
+      YmlValue::Sequence(seq) => {
+        YmlValue::Sequence(seq.iter().map(|item| self.apply(item)).collect())
+      }
+      YmlValue::Mapping(mapping) => YmlValue::Mapping(self.mapping(mapping)),
+      YmlValue::Tagged(tagged) => YmlValue::Tagged(Box::new(TaggedValue {
+        tag: tagged.tag.clone(),
+        value: self.apply(&tagged.value),
+      })),
+      other => other.clone(),
+    }
+  }
+
+  /// Format the values of the block's message, fields and children
+  pub(crate) fn apply_block(&self, block: &mut Block) {
+    match &mut block.message {
+      MessageType::Value(value) => *value = self.apply(value),
+      MessageType::KeyValue(key, value) => *value = self.entry(key, value),
+      _ => (),
+    }
+    if let Some(fields) = &mut block.fields {
+      *fields = self.mapping(fields);
+    }
+    if let Some(children) = &mut block.children {
+      children
+        .iter_mut()
+        .for_each(|child| self.apply_block(child));
+    }
+  }
+
+  fn mapping(&self, mapping: &Mapping) -> Mapping {
+    mapping
+      .iter()
+      .map(|(key, value)| (key.clone(), self.entry(key, value)))
+      .collect()
+  }
+
+  fn entry(&self, key: &YmlValue, value: &YmlValue) -> YmlValue {
+    let is_size = key
+      .as_str()
+      .is_some_and(|key| self.bytes.iter().any(|bytes| bytes == key));
+    match value.as_u64() {
+      Some(bytes) if is_size => YmlValue::String(human_bytes(bytes)),
+      _ => self.apply(value),
+    }
+  }
+
+  /// Read the value as a duration if it has the shape serde gives them
+  fn duration(&self, value: &YmlValue) -> Option<std::time::Duration> {
+    let mapping = value.as_mapping().filter(|_| self.durations)?;
+    let secs = mapping.get("secs")?.as_u64()?;
+    let nanos = mapping
+      .get("nanos")?
+      .as_u64()
+      .filter(|nanos| *nanos < 1_000_000_000)?;
+    match mapping.len() {
+      2 => Some(std::time::Duration::new(secs, nanos as u32)),
+      _ => None,
+    }
+  }
+}
+
+/// Insert the separator between each group of three digits before the decimal point
+fn group_thousands(number: &str, separator: char) -> String {
+  let (sign, unsigned) = match number.strip_prefix('-') {
+    Some(unsigned) => ("-", unsigned),
+    None => ("", number),
+  };
+  let (whole, fraction) = match unsigned.find('.') {
+    Some(point) => unsigned.split_at(point),
+    None => (unsigned, ""),
+  };
+
+  let mut grouped = String::from(sign);
+  for (i, digit) in whole.chars().enumerate() {
+    if i > 0 && (whole.len() - i) % 3 == 0 {
+      grouped.push(separator);
+    }
+    grouped.push(digit);
+  }
+  grouped.push_str(fraction);
+  grouped
+}
+
+/// Write a duration in the largest unit it has one of, such as `1.2 s` or `350.0 µs`
+pub(crate) fn human_duration(duration: std::time::Duration) -> String {
+  let secs = duration.as_secs_f64();
+  match duration.as_nanos() {
+    0..=999 => format!("{} ns", duration.as_nanos()),
+    1_000..=999_999 => format!("{:.1} µs", secs * 1e6),
+    1_000_000..=999_999_999 => format!("{:.1} ms", secs * 1e3),
+    _ if secs < 60.0 => format!("{:.1} s", secs),
+    _ if secs < 3600.0 => format!("{:.1} min", secs / 60.0),
+    _ => format!("{:.1} h", secs / 3600.0),
+  }
+}
+
+/// Write a size in binary units, such as `512 B` or `3.4 MiB`
+pub(crate) fn human_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+  if bytes < 1024 {
+    return format!("{} B", bytes);
+  }
+  let mut size = bytes as f64 / 1024.0;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  format!("{:.1} {}", size, UNITS[unit])
+}
+
 /// A description of the
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
+#[allow(dead_code)]
 pub enum LastWriteItem {
   /// The formatter is brand new and hasn't written anything yet
+  #[default]
   None,
 
   /// A CR/LF was printed.
@@ -338,23 +1099,17 @@ pub enum LastWriteItem {
   _Colon,
 }
 
-impl Default for LastWriteItem {
-  fn default() -> Self {
-    LastWriteItem::None
-  }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ItemType {
   /// Last printed a scalar
   Scalar,
 
   /// Item started with a "-"
-  _SequenceItem,
+  SequenceItem,
 
   /// The key of a Mapping pair
   _MappingKey,
 
   // The value of a Mapping pair
-  _MappingValue,
+  MappingValue,
 }
@@ -0,0 +1,183 @@
+//! Log state machine transitions in one shape, so histories can be rebuilt from the log
+//!
+//! Each transition is a `!transition` record with the entity, both states and the reason as
+//! fields. [`Counters`] optionally numbers the transitions of each entity, which makes gaps easy to
+//! spot, and [`histories`] collects them back out of a [parsed](crate::reader::parse) log.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Result as IoResult, Write};
+use std::sync::Mutex;
+
+use crate::prelude::*;
+use crate::reporter::log_global;
+use crate::Timestamp;
+
+/// The YAML tag the transitions are written with
+pub const TRANSITION_TAG: &str = "transition";
+
+/// An entity moving from one state to another
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Transition {
+  pub entity: String,
+  pub from: String,
+  pub to: String,
+  pub reason: String,
+
+  /// The number of transitions the entity has made, including this one, if it is being counted
+  pub count: Option<u64>,
+
+  /// When the record was stamped, if it was read back from a log
+  pub timestamp: Option<Timestamp>,
+}
+
+impl Transition {
+  pub fn new(
+    entity: impl std::fmt::Display,
+    from: impl std::fmt::Display,
+    to: impl std::fmt::Display,
+    reason: impl std::fmt::Display,
+  ) -> Transition {
+    Transition {
+      entity: entity.to_string(),
+      from: from.to_string(),
+      to: to.to_string(),
+      reason: reason.to_string(),
+      count: None,
+      timestamp: None,
+    }
+  }
+
+  /// Make a `!transition` record, with a message like `order-7: pending -> paid`
+  pub fn to_block(&self) -> Block {
+    let mut block = Block::new();
+    let _ = block.set_message(format!("{}: {} -> {}", self.entity, self.from, self.to));
+    block.set_tag_type(TRANSITION_TAG);
+    let _ = block.add_field("entity", &self.entity);
+    let _ = block.add_field("from", &self.from);
+    let _ = block.add_field("to", &self.to);
+    let _ = block.add_field("reason", &self.reason);
+    if let Some(count) = self.count {
+      let _ = block.add_field("count", count);
+    }
+    if let Some(timestamp) = self.timestamp {
+      block.set_timestamp(timestamp);
+    }
+    block
+  }
+
+  /// Read a transition back from its record, or None if the block isn't one
+  pub fn from_block(block: &Block) -> Option<Transition> {
+    if block.tag_type() != Some(TRANSITION_TAG) {
+      return None;
+    }
+    let field = |name: &str| block.field(name)?.as_str().map(str::to_string);
+    Some(Transition {
+      entity: field("entity")?,
+      from: field("from")?,
+      to: field("to")?,
+      reason: field("reason").unwrap_or_default(),
+      count: block.field("count").and_then(|count| count.as_u64()),
+      timestamp: block.timestamp().cloned(),
+    })
+  }
+
+  /// Write the transition to the log
+  pub fn log<T>(&self, logger: &YmLog<T>, actions: Option<&str>) -> IoResult<Option<RecordHandle>>
+  where
+    T: Write + Send + Sync + 'static,
+  {
+    logger.log(&mut self.to_block(), actions)
+  }
+}
+
+/// Counts the transitions made by each entity, adding the count to each record written
+#[derive(Debug, Default)]
+pub struct Counters {
+  counts: Mutex<HashMap<String, u64>>,
+}
+
+impl Counters {
+  pub fn new() -> Counters {
+    Default::default()
+  }
+
+  /// The number of transitions counted for the entity so far
+  pub fn count(&self, entity: &str) -> u64 {
+    self.lock().get(entity).copied().unwrap_or(0)
+  }
+
+  /// Count the transition and add the count to it
+  pub fn record(&self, mut transition: Transition) -> Transition {
+    let mut counts = self.lock();
+    let count = counts.entry(transition.entity.clone()).or_insert(0);
+    *count += 1;
+    transition.count = Some(*count);
+    transition
+  }
+
+  /// Count the transition and write it to the log
+  pub fn log<T>(
+    &self,
+    logger: &YmLog<T>,
+    transition: Transition,
+    actions: Option<&str>,
+  ) -> IoResult<Option<RecordHandle>>
+  where
+    T: Write + Send + Sync + 'static,
+  {
+    self.record(transition).log(logger, actions)
+  }
+
+  /// Count the transition and write it to the global log
+  pub fn log_transition(
+    &self,
+    entity_id: impl std::fmt::Display,
+    from: impl std::fmt::Display,
+    to: impl std::fmt::Display,
+    reason: impl std::fmt::Display,
+  ) {
+    let transition = self.record(Transition::new(entity_id, from, to, reason));
+    log_global(transition.to_block());
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, u64>> {
+    self
+      .counts
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+}
+
+/// Write a transition to the global log without counting it
+pub fn log_transition(
+  entity_id: impl std::fmt::Display,
+  from: impl std::fmt::Display,
+  to: impl std::fmt::Display,
+  reason: impl std::fmt::Display,
+) {
+  log_global(Transition::new(entity_id, from, to, reason).to_block());
+}
+
+/// Collect the transitions of each entity from the records and their children, in log order
+pub fn histories<'a>(
+  records: impl IntoIterator<Item = &'a Block>,
+) -> BTreeMap<String, Vec<Transition>> {
+  fn collect(block: &Block, histories: &mut BTreeMap<String, Vec<Transition>>) {
+    if let Some(transition) = Transition::from_block(block) {
+      histories
+        .entry(transition.entity.clone())
+        .or_default()
+        .push(transition);
+    }
+    block
+      .children()
+      .iter()
+      .for_each(|child| collect(child, histories));
+  }
+
+  let mut histories = BTreeMap::new();
+  records
+    .into_iter()
+    .for_each(|record| collect(record, &mut histories));
+  histories
+}
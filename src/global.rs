@@ -0,0 +1,86 @@
+//! The process wide logger used by the macros
+//!
+//! Downstream crates only need to point it at an output, rather than declaring their own static.
+
+use std::backtrace::Backtrace;
+use std::fs::File;
+use std::io::{IsTerminal, Result as IoResult, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::prelude::*;
+
+/// Any output the global logger can write to
+pub type GlobalWriter = Box<dyn Write + Send + Sync>;
+
+/// The tag the panic hook writes panics with
+pub const PANIC_TAG: &str = "ymlog/panic";
+
+static GLOBAL: OnceLock<YmLog<GlobalWriter>> = OnceLock::new();
+
+/// Get the logger the `ymlog!` macro writes to
+///
+/// It is created on first use without an output, so one of the init functions needs to be called
+/// before anything is logged.
+pub fn global() -> &'static YmLog<GlobalWriter> {
+  GLOBAL.get_or_init(YmLog::new)
+}
+
+/// Send the global log to the given writer, replacing any previous output
+pub fn init_writer(writable: GlobalWriter) {
+  global().set_output(writable);
+}
+
+/// Send the global log to a file, truncating it if it already exists
+pub fn init_file(path: impl AsRef<Path>) -> IoResult<()> {
+  let file = File::create(path)?;
+  init_writer(Box::new(file));
+  Ok(())
+}
+
+/// Send the global log to stderr
+pub fn init_stderr() {
+  init_writer(Box::new(std::io::stderr()));
+  global().set_terminal(std::io::stderr().is_terminal());
+}
+
+/// Write panics to the global logger instead of stderr
+///
+/// Each panic is written as an error record tagged `!ymlog/panic`, with the payload as its message
+/// and the location, thread and a backtrace as fields. The logger is flushed before the panic
+/// unwinds or aborts, so the record isn't lost with the process. A panic the logger can't write,
+/// such as one while it is busy writing on the same thread, goes to the hook installed before.
+pub fn install_panic_hook() {
+  let previous = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    let payload = info
+      .payload()
+      .downcast_ref::<&str>()
+      .copied()
+      .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+      .unwrap_or("Box<dyn Any>");
+
+    let mut block = Block::new();
+    let _ = block.set_message(payload);
+    block.set_log_level(Level::Error);
+    block.set_tag_type(PANIC_TAG);
+    if let Some(location) = info.location() {
+      let _ = block.add_field("location", location.to_string());
+    }
+    let _ = block.add_field(
+      "thread",
+      std::thread::current().name().unwrap_or("<unnamed>"),
+    );
+    // Backtraces end with a newline, which would make the block keep its last line
+    let backtrace = Backtrace::force_capture().to_string();
+    let _ = block.add_field("backtrace", backtrace.trim_end());
+
+    if global()
+      .log_within(&mut block, Duration::from_millis(100))
+      .is_err()
+    {
+      previous(info);
+    }
+  }));
+}
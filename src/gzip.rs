@@ -0,0 +1,52 @@
+//! A gzip codec, for compressed messages and rotated logs
+//!
+//! Only available with the `gzip` feature, which adds `flate2`. Files made by any gzip tool are
+//! read, including ones with several members, and rotated files are decompressed as they are read
+//! rather than all at once.
+//!
+//! With the feature on, [`Opener::new`](crate::reader::Opener::new) reads gzip files without
+//! adding a codec.
+
+use std::io::{BufRead, Read, Result as IoResult, Write};
+
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::compress::{Codec, Corrupt};
+
+/// The bytes every gzip member starts with
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compress with gzip, and decompress anything gzip wrote
+///
+/// ```ignore
+/// logger.set_compression(Compression::new(Box::new(Gzip), 64 * 1024));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gzip;
+
+impl Codec for Gzip {
+  fn name(&self) -> &str {
+    "gzip"
+  }
+
+  fn compress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+  }
+
+  fn decompress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    let mut out = vec![];
+    Corrupt(MultiGzDecoder::new(data)).read_to_end(&mut out)?;
+    Ok(out)
+  }
+
+  fn decoder(&self, compressed: Box<dyn BufRead + Send>) -> IoResult<Box<dyn Read + Send>> {
+    Ok(Box::new(Corrupt(MultiGzDecoder::new(compressed))))
+  }
+
+  fn magic(&self) -> &[u8] {
+    &MAGIC
+  }
+}
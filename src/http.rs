@@ -0,0 +1,158 @@
+//! Log HTTP requests and their responses
+//!
+//! A request is written as a record holding the method, path, headers and body size, then the log
+//! is indented so anything written while handling it is nested underneath. Responding writes the
+//! status and latency as its last child and dedents back to where the request was written.
+//!
+//! Headers that carry credentials are redacted by default. More can be added with
+//! [`Request::redact`].
+
+use std::io::{Result as IoResult, Write};
+use std::time::{Duration, Instant};
+
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::prelude::*;
+
+/// Headers whose values are never written, compared ignoring case
+pub const REDACTED_HEADERS: &[&str] = &[
+  "authorization",
+  "cookie",
+  "proxy-authorization",
+  "set-cookie",
+  "x-api-key",
+];
+
+/// The value written in place of a redacted header
+pub const REDACTED: &str = "<redacted>";
+
+/// The parts of a request worth logging
+#[derive(Debug, Clone)]
+pub struct Request {
+  method: String,
+  path: String,
+  headers: Vec<(String, String)>,
+  body_size: Option<u64>,
+  redacted: Vec<String>,
+}
+
+impl Request {
+  pub fn new(method: impl std::fmt::Display, path: impl std::fmt::Display) -> Request {
+    Request {
+      method: method.to_string(),
+      path: path.to_string(),
+      headers: vec![],
+      body_size: None,
+      redacted: REDACTED_HEADERS
+        .iter()
+        .map(|name| name.to_string())
+        .collect(),
+    }
+  }
+
+  /// Add a header, in the order it should be written
+  pub fn header(mut self, name: impl std::fmt::Display, value: impl std::fmt::Display) -> Request {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  /// The size of the request body in bytes
+  pub fn body_size(mut self, bytes: u64) -> Request {
+    self.body_size = Some(bytes);
+    self
+  }
+
+  /// Hide the value of another header
+  pub fn redact(mut self, name: impl std::fmt::Display) -> Request {
+    self.redacted.push(name.to_string());
+    self
+  }
+
+  fn is_redacted(&self, name: &str) -> bool {
+    self
+      .redacted
+      .iter()
+      .any(|redacted| redacted.eq_ignore_ascii_case(name))
+  }
+
+  /// Make an info record of the request, with the details as fields
+  pub fn to_block(&self) -> Block {
+    let mut headers = Mapping::new();
+    for (name, value) in &self.headers {
+      let value = match self.is_redacted(name) {
+        true => REDACTED,
+        false => value,
+      };
+      headers.insert(YmlValue::from(name.as_str()), YmlValue::from(value));
+    }
+
+    let mut block = Block::new();
+    let _ = block.set_message(format!("{} {}", self.method, self.path));
+    block.set_log_level(Level::Info);
+    let _ = block.add_field("method", &self.method);
+    let _ = block.add_field("path", &self.path);
+    if !headers.is_empty() {
+      let _ = block.add_field("headers", headers);
+    }
+    if let Some(bytes) = self.body_size {
+      let _ = block.add_field("body_bytes", bytes);
+    }
+    block
+  }
+
+  /// Write the request and indent the log, starting the clock on the response
+  pub fn log<T>(&self, logger: &YmLog<T>) -> IoResult<Exchange>
+  where
+    T: Write + Send + Sync + 'static,
+  {
+    let handle = logger.log(&mut self.to_block(), Some("_+"))?;
+    Ok(Exchange {
+      started: Instant::now(),
+      handle,
+    })
+  }
+}
+
+/// A request that was logged and is waiting for its response
+#[derive(Debug)]
+pub struct Exchange {
+  started: Instant,
+  handle: Option<RecordHandle>,
+}
+
+impl Exchange {
+  /// Where the request was written, if it wasn't filtered out
+  pub fn handle(&self) -> Option<&RecordHandle> {
+    self.handle.as_ref()
+  }
+
+  /// The time since the request was written
+  pub fn elapsed(&self) -> Duration {
+    self.started.elapsed()
+  }
+
+  /// Write the response under the request and dedent the log
+  pub fn respond<T>(self, logger: &YmLog<T>, status: u16) -> IoResult<Option<RecordHandle>>
+  where
+    T: Write + Send + Sync + 'static,
+  {
+    logger.log(&mut response(status, self.elapsed()), Some("_-"))
+  }
+}
+
+/// Make a record of a response
+///
+/// Server errors are logged as errors and client errors as warnings, so they pass level filters
+/// that drop the successful responses.
+pub fn response(status: u16, latency: Duration) -> Block {
+  let mut block = Block::new();
+  let _ = block.set_message(format!("Response {}", status));
+  block.set_log_level(match status {
+    500.. => Level::Error,
+    400..=499 => Level::Warn,
+    _ => Level::Info,
+  });
+  let _ = block.add_field("status", status);
+  let _ = block.add_field("latency_ms", latency.as_secs_f64() * 1000.0);
+  block
+}
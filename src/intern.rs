@@ -0,0 +1,61 @@
+//! Shared copies of the strings that repeat from record to record
+//!
+//! Services logging millions of records tend to use the same few tags on all of them. Each tag is
+//! formatted into a reused buffer and looked up, so a tag seen before costs a reference count
+//! rather than an allocation. The table is bounded, so tags made from ids or other unbounded
+//! values are only copied once the table is full, never leaked.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Display, Write};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// The most strings kept, so unbounded values can't grow the table forever
+const CAPACITY: usize = 4096;
+
+/// Longer strings are unlikely to repeat, so they aren't worth keeping
+const MAX_LEN: usize = 64;
+
+static TABLE: OnceLock<RwLock<HashSet<Arc<str>>>> = OnceLock::new();
+
+thread_local! {
+  static BUFFER: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Get the shared copy of a string, keeping one if there is room
+pub(crate) fn intern(text: &str) -> Arc<str> {
+  if text.len() > MAX_LEN {
+    return Arc::from(text);
+  }
+  let table = TABLE.get_or_init(Default::default);
+  if let Some(shared) = table
+    .read()
+    .unwrap_or_else(|err| err.into_inner())
+    .get(text)
+  {
+    return Arc::clone(shared);
+  }
+
+  let mut table = table.write().unwrap_or_else(|err| err.into_inner());
+  if let Some(shared) = table.get(text) {
+    return Arc::clone(shared);
+  }
+  let shared: Arc<str> = Arc::from(text);
+  if table.len() < CAPACITY {
+    table.insert(Arc::clone(&shared));
+  }
+  shared
+}
+
+/// Get the shared copy of a value's text, without allocating to format it
+pub(crate) fn intern_display(value: impl Display) -> Arc<str> {
+  BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+    Ok(mut buffer) => {
+      buffer.clear();
+      let _ = write!(buffer, "{}", value);
+      intern(&buffer)
+    }
+    // Only a Display impl that makes tags itself gets here
+    Err(_) => intern(&value.to_string()),
+  })
+}
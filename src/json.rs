@@ -0,0 +1,171 @@
+//! Write blocks as JSON Lines
+//!
+//! Each record becomes a single JSON object on its own line, with the indentation the YAML would
+//! have had recorded as its depth. serde_yaml values map onto JSON directly, except for tags which
+//! become a `{"tag": ..., "value": ...}` object and non-finite floats which become null.
+
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::logger::TimestampFormat;
+use crate::message::MessageType;
+use crate::prelude::*;
+use crate::scan;
+
+/// Convert the block into a single line JSON object, ending with a newline
+pub(crate) fn record(block: &Block, depth: usize, timestamps: &TimestampFormat) -> String {
+  let mut result = String::new();
+  write_record(block, Some(depth), timestamps, &mut result);
+  result.push('\n');
+  result
+}
+
+fn write_record(
+  block: &Block,
+  depth: Option<usize>,
+  timestamps: &TimestampFormat,
+  out: &mut String,
+) {
+  out.push('{');
+  if let Some(depth) = depth {
+    out.push_str(&format!("\"depth\":{},", depth));
+  }
+  if let Some(timestamp) = &block.timestamp {
+    out.push_str("\"timestamp\":");
+    write_value(&timestamps.render(timestamp), out);
+    out.push(',');
+  }
+  if let Some(source) = &block.source {
+    out.push_str("\"source\":");
+    write_str(source, out);
+    out.push(',');
+  }
+  if let Some(level) = &block.log_level {
+    out.push_str("\"log_level\":");
+    write_str(level.name(), out);
+    out.push(',');
+  }
+  if let Some(tags) = &block.tags {
+    out.push_str("\"tags\":[");
+    for (i, tag) in tags.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      write_str(tag, out);
+    }
+    out.push_str("],");
+  }
+  if let Some(fields) = &block.fields {
+    out.push_str("\"fields\":");
+    write_mapping(fields, out);
+    out.push(',');
+  }
+  if let Some(tag_type) = &block.tag_type {
+    out.push_str("\"tag_type\":");
+    write_str(tag_type, out);
+    out.push(',');
+  }
+
+  out.push_str("\"message\":");
+  match &block.message {
+    MessageType::None => out.push_str("null"),
+    MessageType::Unserializable { type_name, error } => {
+      write_value(MessageType::fallback(type_name, error).unwrap(), out)
+    }
+    MessageType::Value(value) => write_value(value, out),
+    MessageType::Text(text) => write_str(text.as_str(), out),
+    MessageType::KeyValue(key, value) => {
+      out.push('{');
+      write_key(key, out);
+      out.push(':');
+      write_value(value, out);
+      out.push('}');
+    }
+  }
+
+  if let Some(children) = &block.children {
+    out.push_str(",\"children\":[");
+    for (i, child) in children.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      write_record(child, None, timestamps, out);
+    }
+    out.push(']');
+  }
+  out.push('}');
+}
+
+/// Append a YAML value as JSON
+pub(crate) fn write_value(value: &YmlValue, out: &mut String) {
+  match value {
+    YmlValue::Null => out.push_str("null"),
+    YmlValue::Bool(value) => out.push_str(&value.to_string()),
+    YmlValue::Number(number) => match number.as_f64() {
+      Some(float) if !float.is_finite() => out.push_str("null"),
+      _ => out.push_str(&number.to_string()),
+    },
+    YmlValue::String(value) => write_str(value, out),
+    YmlValue::Sequence(seq) => {
+      out.push('[');
+      for (i, item) in seq.iter().enumerate() {
+        if i > 0 {
+          out.push(',');
+        }
+        write_value(item, out);
+      }
+      out.push(']');
+    }
+    YmlValue::Mapping(mapping) => write_mapping(mapping, out),
+    YmlValue::Tagged(tagged) => {
+      out.push_str("{\"tag\":");
+      write_str(&tagged.tag.to_string(), out);
+      out.push_str(",\"value\":");
+      write_value(&tagged.value, out);
+      out.push('}');
+    }
+  }
+}
+
+fn write_mapping(mapping: &Mapping, out: &mut String) {
+  out.push('{');
+  for (i, (key, value)) in mapping.iter().enumerate() {
+    if i > 0 {
+      out.push(',');
+    }
+    write_key(key, out);
+    out.push(':');
+    write_value(value, out);
+  }
+  out.push('}');
+}
+
+/// JSON keys have to be strings, so anything else is written as its YAML
+fn write_key(key: &YmlValue, out: &mut String) {
+  match key {
+    YmlValue::String(key) => write_str(key, out),
+    other => {
+      let yaml = serde_yaml::to_string(other).unwrap_or_default();
+      write_str(yaml.trim_end(), out)
+    }
+  }
+}
+
+/// Append a quoted and escaped JSON string, copying the runs between escapes as they are
+fn write_str(value: &str, out: &mut String) {
+  out.push('"');
+  let mut rest = value;
+  while let Some(at) = scan::find_json_escape(rest) {
+    out.push_str(&rest[..at]);
+    match rest.as_bytes()[at] {
+      b'"' => out.push_str("\\\""),
+      b'\\' => out.push_str("\\\\"),
+      b'\n' => out.push_str("\\n"),
+      b'\r' => out.push_str("\\r"),
+      b'\t' => out.push_str("\\t"),
+      byte => out.push_str(&format!("\\u{:04x}", byte)),
+    }
+    rest = &rest[at + 1..];
+  }
+  out.push_str(rest);
+  out.push('"');
+}
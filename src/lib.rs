@@ -1,17 +1,95 @@
 //! ymlog indented log file writer
 //!
 
+#[cfg(feature = "alloc-stats")]
+pub mod alloc;
+mod color;
+mod compress;
+mod config;
+pub mod context;
+pub mod db;
+mod deny;
+mod emitter;
+mod env;
+mod failover;
+mod filter;
 mod formatter;
+pub mod fsm;
+mod global;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+pub mod http;
+mod intern;
+mod json;
+mod loggable;
 mod logger;
 mod macros;
 mod message;
+pub mod metrics;
+mod node;
+mod pipeline;
+mod progress;
+pub mod query;
+pub mod reader;
+pub mod redact;
+mod repeats;
+pub mod report;
+mod reporter;
+#[cfg(feature = "resources")]
+pub mod resources;
+pub mod retry;
+mod run;
+mod scan;
+pub mod sinks;
+mod span;
+mod strict;
+mod throttle;
+mod time;
+mod watchdog;
+mod writer;
+#[cfg(feature = "zstd")]
+pub mod zstd;
 
-pub use formatter::{Chomp, Style, YamlFormatter};
-pub use logger::{Level, YmLog};
-pub use message::Block;
+pub use color::ColorChoice;
+pub use compress::{Codec, Compression};
+pub use context::ScopedLogger;
+pub use deny::{never_log_keys, removed_count, REMOVED};
+pub use emitter::Emitter;
+pub use env::ENV_VAR;
+pub use failover::FAILOVER_TAG;
+pub use filter::TagFilter;
+pub use formatter::{Chomp, Indent, NumberFormat, Style, ValueFormat, YamlFormatter, WRAP_AT};
+pub use global::{
+  global, init_file, init_stderr, init_writer, install_panic_hook, GlobalWriter, PANIC_TAG,
+};
+pub use loggable::Loggable;
+pub use logger::{
+  Commenter, DedentPolicy, ErrorHandler, LastBlockType, Level, OutputFormat, RecordHandle,
+  SchemaMode, SerializePolicy, StateBlob, TimestampFormat, Tracker, Validator, YmLog,
+  STATIC_MAX_LEVEL,
+};
+pub use message::{Block, Tag, Text};
+pub use pipeline::{Pipeline, Predicate, Sampler, Stage, Transform};
+pub use progress::ProgressBlock;
+pub use reporter::Reporter;
+pub use run::RUN_TAG;
+pub use span::{Span, DURATION_KEY};
+pub use throttle::{RateKey, RateLimit};
+pub use time::Timestamp;
+pub use watchdog::STALLED_TAG;
+pub use writer::{FlushPolicy, SinkHealth, SinkStatus, TimeoutPolicy, WriteTimeout};
+
+#[doc(hidden)]
+pub mod __private {
+  //! Used by the macros, so the crates using them don't need serde_yaml
+  pub use crate::logger::{compiled_in, moves_depth};
+  pub use serde_yaml::Value as YmlValue;
+}
 
 pub mod prelude {
-  pub use crate::{ymlog, ymlogger};
+  pub use crate::{ymlog, ymlog_err, ymlogger};
 
-  pub use super::{Block, Chomp, Level, Style, YamlFormatter, YmLog};
+  pub use super::{
+    Block, Chomp, Level, Loggable, OutputFormat, RecordHandle, Style, YamlFormatter, YmLog,
+  };
 }
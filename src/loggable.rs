@@ -0,0 +1,30 @@
+//! Values written one way for people and another for machines
+//!
+//! Domain enums read best as words on a console, but anything parsing the log wants identifiers
+//! that don't change when the wording does. A [`Loggable`] value added to a block keeps both, and
+//! outputs that are terminals get the human version while everything else gets the machine one.
+//! Use [`Block::humanize`] in a pipeline to send the human version to another output.
+//!
+//! ```ignore
+//! #[derive(Loggable)]
+//! enum OrderState {
+//!   PendingPayment,
+//!   #[loggable(human = "Sent to the customer", machine = "shipped")]
+//!   InTransit,
+//! }
+//! ```
+
+use serde_yaml::Value as YmlValue;
+
+pub use ymlog_derive::Loggable;
+
+/// A value with a friendly description and a stable identifier
+///
+/// This can be derived for enums, see the [derive macro](derive@Loggable).
+pub trait Loggable {
+  /// A description for people reading the log, which can change between versions
+  fn human(&self) -> String;
+
+  /// An identifier for tools reading the log, which should never change
+  fn machine(&self) -> YmlValue;
+}
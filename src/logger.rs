@@ -1,131 +1,715 @@
 //! An instance of a Logger
 
-// use std::fs::OpenOptions;
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::ops::Range;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, TryLockError, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::value::Tag;
+use serde_yaml::Value as YmlValue;
+
+use crate::color::{self, ColorChoice};
+use crate::compress::Compression;
+use crate::deny;
+use crate::failover::Failover;
+use crate::filter::{Filter, TagFilter};
+use crate::formatter::{self, Indent, NumberFormat, ValueFormat};
+use crate::json;
+use crate::message::{Anchor, MessageType};
+use crate::node::{self, Node};
+use crate::pipeline::{Dropped, Pipeline};
+use crate::prelude::*;
+use crate::redact::Redaction;
+use crate::strict;
+use crate::throttle::{self, RateLimit, Throttle, Verdict};
+use crate::watchdog::Watchdog;
+use crate::writer::{
+  is_terminal, AsyncWriter, Buffered, FlushPolicy, Fragments, Output, Sink, SinkHealth, SinkStatus,
+  TimedWriter, WriteTimeout,
+};
+use crate::Timestamp;
+
+/// The lowest level the logging macros compile in, or None if they compile to nothing
+///
+/// It is set by the `max_level_*` features, and by the `release_max_level_*` features in builds
+/// without debug assertions. The most restrictive one enabled wins. Macros below it still run their
+/// indent and dedent actions, so the records around them nest the same way.
+pub const STATIC_MAX_LEVEL: Option<Level> = static_max_level();
+
+const fn static_max_level() -> Option<Level> {
+  let release = !cfg!(debug_assertions);
+  if cfg!(feature = "max_level_off") || release && cfg!(feature = "release_max_level_off") {
+    None
+  } else if cfg!(feature = "max_level_error")
+    || release && cfg!(feature = "release_max_level_error")
+  {
+    Some(Level::Error)
+  } else if cfg!(feature = "max_level_warn") || release && cfg!(feature = "release_max_level_warn")
+  {
+    Some(Level::Warn)
+  } else if cfg!(feature = "max_level_info") || release && cfg!(feature = "release_max_level_info")
+  {
+    Some(Level::Info)
+  } else if cfg!(feature = "max_level_debug")
+    || release && cfg!(feature = "release_max_level_debug")
+  {
+    Some(Level::Debug)
+  } else {
+    Some(Level::Trace)
+  }
+}
 
-use serde_yaml::{Mapping, Value as YmlValue};
+/// Check if the macros compile in a block starting at the level, given the actions
+pub const fn compiled_in(actions: Option<&str>, start: Level) -> bool {
+  Level::lowest_written(actions, start).compiled_in()
+}
 
-use crate::message::MessageType;
-use crate::prelude::*;
+/// Check if the actions indent, dedent or reset, so skipping them would change the nesting
+pub const fn moves_depth(actions: Option<&str>) -> bool {
+  let actions = match actions {
+    Some(actions) => actions.as_bytes(),
+    None => return false,
+  };
+  let mut i = 0;
+  while i < actions.len() {
+    if matches!(actions[i], b'+' | b'-' | b'r') {
+      return true;
+    }
+    i += 1;
+  }
+  false
+}
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
+/// How important a record is, ordered from Trace up to Error
+///
+/// Levels are ordered by their rank, so custom levels can go between or beyond the built in ones,
+/// which rank from 10 for Trace to 50 for Error. Levels with the same rank are ordered by name.
+///
+/// ```
+/// use ymlog::Level;
+///
+/// const AUDIT: Level = Level::Custom(45, "Audit");
+/// const FATAL: Level = Level::Custom(60, "Fatal");
+///
+/// assert!(Level::Warn < AUDIT && AUDIT < Level::Error && Level::Error < FATAL);
+/// ```
+#[derive(Debug, Clone, Copy)]
 pub enum Level {
   Trace,
   Debug,
   Info,
   Warn,
   Error,
+
+  /// A level of the caller's own, with its rank and the name it is written with
+  ///
+  /// [`Level::register`] it so its name can be read back by the readers and filters.
+  Custom(u8, &'static str),
+}
+
+/// The custom levels that can be found by name
+static REGISTERED: RwLock<Vec<Level>> = RwLock::new(Vec::new());
+
+impl Level {
+  const ALL: [Level; 5] = [
+    Level::Trace,
+    Level::Debug,
+    Level::Info,
+    Level::Warn,
+    Level::Error,
+  ];
+
+  /// Every built in level, from the least important to the most
+  pub fn iter() -> impl Iterator<Item = Level> {
+    Level::ALL.iter().copied()
+  }
+
+  /// How important the level is, which is what levels are compared by
+  pub const fn rank(&self) -> u8 {
+    match self {
+      Level::Trace => 10,
+      Level::Debug => 20,
+      Level::Info => 30,
+      Level::Warn => 40,
+      Level::Error => 50,
+      Level::Custom(rank, _) => *rank,
+    }
+  }
+
+  /// The name written to the log
+  pub fn name(&self) -> &'static str {
+    match self {
+      Level::Trace => "Trace",
+      Level::Debug => "Debug",
+      Level::Info => "Info",
+      Level::Warn => "Warn",
+      Level::Error => "Error",
+      Level::Custom(_, name) => name,
+    }
+  }
+
+  /// Let a custom level be found by its name, such as in filter directives or records being read
+  ///
+  /// Built in names can't be taken, and registering a name again replaces its rank.
+  pub fn register(level: Level) -> IoResult<()> {
+    if Level::iter().any(|known| known.name().eq_ignore_ascii_case(level.name())) {
+      return Err(IoError::new(
+        ErrorKind::InvalidInput,
+        format!("{:?} is already a built in log level", level.name()),
+      ));
+    }
+    let mut registered = REGISTERED.write().unwrap_or_else(|err| err.into_inner());
+    registered.retain(|known| !known.name().eq_ignore_ascii_case(level.name()));
+    registered.push(level);
+    Ok(())
+  }
+
+  /// The level set by a letter in the logging actions, such as `D` for Debug
+  pub(crate) const fn from_action(action: char) -> Option<Level> {
+    match action {
+      'T' => Some(Level::Trace),
+      'D' => Some(Level::Debug),
+      'I' => Some(Level::Info),
+      'W' => Some(Level::Warn),
+      'E' => Some(Level::Error),
+      _ => None,
+    }
+  }
+
+  /// Check if the macros compile in records at this level, given [`STATIC_MAX_LEVEL`]
+  pub const fn compiled_in(self) -> bool {
+    match STATIC_MAX_LEVEL {
+      Some(max) => self.rank() >= max.rank(),
+      None => false,
+    }
+  }
+
+  /// The lowest level the actions write a block at, if the block starts at `start`
+  pub(crate) const fn lowest_written(actions: Option<&str>, start: Level) -> Level {
+    let actions = match actions {
+      Some(actions) => actions.as_bytes(),
+      None => b"",
+    };
+    let mut level = start;
+    let mut lowest: Option<Level> = None;
+    let mut i = 0;
+    while i < actions.len() {
+      if actions[i] == b'_' {
+        lowest = match lowest {
+          Some(lowest) if lowest.rank() <= level.rank() => Some(lowest),
+          _ => Some(level),
+        };
+      } else if let Some(set) = Level::from_action(actions[i] as char) {
+        level = set;
+      }
+      i += 1;
+    }
+    match lowest {
+      Some(lowest) => lowest,
+      None => level,
+    }
+  }
+
+  /// Find the level with the given name, ignoring case. "Warning" is accepted for Warn.
+  pub(crate) fn from_name(name: &str) -> Option<Level> {
+    match name.to_ascii_lowercase().as_str() {
+      "trace" => Some(Level::Trace),
+      "debug" => Some(Level::Debug),
+      "info" => Some(Level::Info),
+      "warn" | "warning" => Some(Level::Warn),
+      "error" => Some(Level::Error),
+      _ => REGISTERED
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .find(|level| level.name().eq_ignore_ascii_case(name))
+        .copied(),
+    }
+  }
+}
+
+impl PartialEq for Level {
+  fn eq(&self, other: &Level) -> bool {
+    self.rank() == other.rank() && self.name() == other.name()
+  }
+}
+
+impl Eq for Level {}
+
+impl PartialOrd for Level {
+  fn partial_cmp(&self, other: &Level) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Level {
+  fn cmp(&self, other: &Level) -> std::cmp::Ordering {
+    (self.rank(), self.name()).cmp(&(other.rank(), other.name()))
+  }
+}
+
+impl std::hash::Hash for Level {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    (self.rank(), self.name()).hash(state)
+  }
+}
+
+impl Serialize for Level {
+  /// Levels are written by name
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.name())
+  }
+}
+
+impl<'de> Deserialize<'de> for Level {
+  /// Read a level name, ignoring case
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Level, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+impl std::fmt::Display for Level {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+impl std::str::FromStr for Level {
+  type Err = IoError;
+
+  /// Parse a level name, ignoring case
+  fn from_str(name: &str) -> IoResult<Level> {
+    Level::from_name(name).ok_or_else(|| {
+      IoError::new(
+        ErrorKind::InvalidInput,
+        format!("{:?} is not a log level", name),
+      )
+    })
+  }
+}
+
+/// The syntax the records are written in
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+  /// An indented YAML stream, where children are nested under their parents
+  #[default]
+  Yaml,
+
+  /// One JSON object per line, with the indentation recorded as a "depth" field
+  JsonLines,
+}
+
+/// What to do with a block whose message couldn't be serialized
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum SerializePolicy {
+  /// Write a record naming the message type and the error in place of the message
+  #[default]
+  Fallback,
+
+  /// Drop the block without writing anything
+  Skip,
+
+  /// Panic with the serialization error
+  Panic,
+}
+
+/// What happens to a block the validator rejects
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SchemaMode {
+  /// Write the block anyway, then report the failure
+  #[default]
+  Lenient,
+
+  /// Report the failure and withhold the block from every output
+  Strict,
+}
+
+/// What happens when the actions dedent past the document root
+///
+/// Each record at the root is its own document, so there is nothing to leave and staying at the
+/// root is the same as starting a new document. The policies only differ in who hears about it.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DedentPolicy {
+  /// Stay at the root without saying anything
+  #[default]
+  Clamp,
+
+  /// Stay at the root, and pass an error to the logger's error handler
+  Report,
+
+  /// Return an `InvalidInput` error, leaving the depth and the rest of the actions alone
+  Error,
+}
+
+/// A check each block must pass, returning the reason it was rejected
+pub type Validator = Box<dyn Fn(&Block) -> Result<(), String> + Send + Sync>;
+
+/// Makes the comment written above each block, if it gets one
+pub type Commenter = Box<dyn Fn(&Block) -> Option<String> + Send + Sync>;
+
+/// What to do with an error the caller never sees, such as one raised inside the `ymlog!` macro
+#[derive(Default)]
+pub enum ErrorHandler {
+  /// Write a `!ymlog/error` record describing the error to the log
+  ///
+  /// If that can't be written either, the error is printed to stderr instead.
+  #[default]
+  MetaRecord,
+
+  /// Panic with the error
+  Panic,
+
+  /// Drop the error without telling anyone
+  Ignore,
+
+  /// Pass the error to a function
+  Custom(Box<dyn Fn(&IoError) + Send + Sync>),
+}
+
+/// How timestamps are written in the records
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum TimestampFormat {
+  /// A string such as `2024-03-01T12:30:00.123456789+00:00`
+  #[default]
+  Rfc3339,
+
+  /// The number of milliseconds since the Unix epoch
+  EpochMillis,
+
+  /// A chrono format string, such as `%Y-%m-%d %H:%M:%S`
+  ///
+  /// Day and month names are always English, so the locale doesn't change what is written. An
+  /// invalid format string falls back to RFC 3339 rather than failing the write, as does every
+  /// format string in builds without the `chrono` feature.
+  Custom(String),
 }
 
-/// A flag to tell what has been written at the current indent level
-#[derive(Debug)]
-enum LastBlockType {
-  // Nothing has yet been written
+impl TimestampFormat {
+  /// Convert the timestamp into the value written to the log
+  pub(crate) fn render(&self, timestamp: &Timestamp) -> YmlValue {
+    match self {
+      TimestampFormat::Rfc3339 => YmlValue::String(timestamp.to_rfc3339()),
+      TimestampFormat::EpochMillis => YmlValue::Number(timestamp.millis().into()),
+      #[cfg(feature = "chrono")]
+      TimestampFormat::Custom(format) => {
+        let time = chrono::DateTime::<chrono::Utc>::from(*timestamp);
+        let mut result = String::new();
+        match write!(result, "{}", time.format(format)) {
+          Ok(()) => YmlValue::String(result),
+          Err(_) => YmlValue::String(timestamp.to_rfc3339()),
+        }
+      }
+      #[cfg(not(feature = "chrono"))]
+      TimestampFormat::Custom(_) => YmlValue::String(timestamp.to_rfc3339()),
+    }
+  }
+}
+
+/// What was last written at one level of indentation, which decides how the next record attaches
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LastBlockType {
+  /// Nothing has been written at this level yet
+  #[default]
   None,
 
-  // A reset has been sent, so we need to prefix a '\n'
+  /// A reset started a new root, so the next record starts a new document on a new line
   Reset,
 
-  // Wrote key message
+  /// A reset wrote the `---` marker, so the next record is the root of the new document
+  Document,
+
+  /// A plain record, which can become a key with `:` if the next record is indented
   Message,
 
-  // Wrote a message as a block (requires a "children:")
+  /// A multiline record, which is written as a block and can't be used as a key
   BlockMessage,
 
-  // An indent record was requested for the next block
+  /// An indent after a plain record, so the next record adds the `:` and nests under it
   Indent,
 
-  // A block was printed, so we need to use a different character to use it as a
+  /// An indent after a multiline record, so the next record nests under a phony `""` key
   BlockIndent,
 
-  /// Printed a key/value pair and should dedent before automatically should dedent when found
+  /// Key/value pairs on a new indent, so the next plain record dedents automatically
   KeyValue,
+
+  /// A key/value pair after other records at the same depth, so there is nothing to dedent
+  SiblingKeyValue,
+
+  /// A record with its metadata as a mapping, so children go under a `children` key
+  Record,
+
+  /// A record whose children were dedented out of, holding what was last written among them so
+  /// indenting again carries on after it
+  Parent(Box<LastBlockType>),
+
+  /// An indent after a record mapping, so the next record starts its `children`
+  RecordIndent,
 }
 
-impl Default for LastBlockType {
-  fn default() -> LastBlockType {
-    LastBlockType::None
-  }
+/// Write the comment as `#` lines, indented to match the start of the record
+fn comment_lines(comment: &str, record: &str) -> String {
+  let indent = &record[..record.len() - record.trim_start_matches([' ', '\t']).len()];
+  comment.lines().fold(String::new(), |mut lines, line| {
+    let _ = match line.is_empty() {
+      true => writeln!(lines, "{}#", indent),
+      false => writeln!(lines, "{}# {}", indent, line),
+    };
+    lines
+  })
 }
 
-/// This handles tracking items that need to be remembered in order to create valid YAML
+/// Tracks what has been written to an output, so each new record continues valid YAML
 ///
-/// The tracker follows a generator pattern, where it uses the depth to figure out the indentation
-/// of new records, and the proper way to concatenate each item to the previous one.
-#[derive(Default)]
-struct Tracker {
-  /// A list the last item
+/// There is one entry per level of indentation, holding the [`LastBlockType`] written there. The
+/// last entry decides how the next record is joined to the one before it: as a new document, a
+/// sibling in the same sequence, or a child under it. The logger drives one of these per output,
+/// but formatters can use it directly:
+///
+/// - [`Tracker::indent`] only takes effect after a record that can hold children. Indenting again
+///   before the next record, right after a reset, or before anything is written does nothing, as
+///   does indenting after a multiline record at the root.
+/// - [`Tracker::dedent`] never removes the root level, so dedenting there does nothing. Neither
+///   does [`Tracker::dedent_to`], which removes as many levels as it takes at once.
+/// - [`Tracker::reset`] drops every level, so the next record starts a new document on a new line
+///   whatever was open, including a pending indent. This adds a blank line before the first
+///   document if nothing was written yet.
+/// - A plain record after key/value pairs written on a new indent dedents back over them first.
+/// - Indenting after dedenting out of a record's children adds more children to it.
+#[derive(Debug, Default, Clone)]
+pub struct Tracker {
+  /// What was last written at each level of indentation, starting with the root
   depth: Vec<LastBlockType>,
+
+  /// The anchors written in the current document
+  anchors: HashSet<String>,
+
+  /// Writes each record, and holds the flow width and what each level of records is indented with
+  formatter: YamlFormatter,
 }
 
 impl Tracker {
-  /// Recursively use the block to build a YAML object
+  pub fn new() -> Tracker {
+    Default::default()
+  }
+
+  /// Write nested mappings and sequences that fit in the width in flow style, like
+  /// [`YamlFormatter::set_flow_width`]
+  pub fn set_flow_width(&mut self, width: Option<usize>) {
+    self.formatter.set_flow_width(width);
+  }
+
+  /// Indent each level of records with the indent
+  ///
+  /// The levels the tracker streams and the lines within each record are both indented this way.
+  /// YAML doesn't allow tabs as indentation, so [`Indent::Tab`] is written as two spaces.
+  pub fn set_indent(&mut self, indent: Indent) {
+    self.formatter.set_indent(indent);
+  }
+
+  /// What was last written at each level of indentation, starting with the root
+  pub fn levels(&self) -> &[LastBlockType] {
+    &self.depth
+  }
+
+  /// Recursively use the block to build the node it is written as
   ///
-  /// This handles adding the children to the message (if appropriate) and updating the depth
+  /// This handles adding the children to the message (if appropriate) and updating the depth. The
+  /// message is written as the given node if there is one, which carries its style and anchor.
   // FIXME: Children aren't handled properly with a scan. Need to think about how to define them
   // TODO: Test how nested children affect the depth
-  fn build_value(block: &Block) -> (YmlValue, Vec<LastBlockType>) {
+  fn build_value(
+    block: &Block,
+    timestamps: &TimestampFormat,
+    message: Option<Node>,
+  ) -> (Node, Vec<LastBlockType>) {
+    // Text is only copied into a YAML value now that it is being written
+    let text;
+    let value_message = match (&block.message, &block.children) {
+      (MessageType::Text(inner), Some(_)) => {
+        text = MessageType::Value(inner.to_value());
+        &text
+      }
+      (value_message, _) => value_message,
+    };
+
     // One or the other, both makes no sense
-    match (&block.message, &block.children) {
+    let (node, depth) = match (value_message, &block.children) {
       // Always fail if there is no message
       (MessageType::None, _) => {
         panic!("Logs must always have a base message set")
       }
 
-      (MessageType::Value(YmlValue::Mapping(_)), Some(_)) => {
-        panic!("Log message blocks either have children or a map, not both")
-      }
+      // The logger applies its policy before this, but a pipeline stage may have broken one
+      (MessageType::Unserializable { type_name, error }, _) => (
+        Node::Value(MessageType::fallback(type_name, error).unwrap().clone()),
+        vec![LastBlockType::Message],
+      ),
 
       (MessageType::Value(value), Some(children)) => {
         // We will continue at the depth of the last child
         let mut last_depth = vec![];
         let seq = children.iter().fold(vec![], |mut acc, child| {
-          let (kid, depth) = Tracker::build_value(child);
+          let (kid, depth) = Tracker::build_value(child, timestamps, None);
           last_depth = depth;
           acc.push(kid);
           acc
         });
 
-        let mut mapping = Mapping::new();
-        mapping.insert(value.clone(), YmlValue::Sequence(seq));
-        (YmlValue::Mapping(mapping), last_depth)
+        let message = message.unwrap_or_else(|| Node::Value(value.clone()));
+        let mut pairs = vec![];
+        match block.has_metadata() {
+          true => {
+            Tracker::insert_metadata(block, timestamps, &mut pairs);
+            pairs.push((Node::Value("message".into()), message));
+            pairs.push((Node::Value("children".into()), Node::Sequence(seq)));
+          }
+          false => pairs.push((message, Node::Sequence(seq))),
+        }
+        (Node::Mapping(pairs), last_depth)
       }
 
-      (MessageType::Value(value), None) => (value.clone(), vec![LastBlockType::Message]),
+      (MessageType::Value(value), None) => (
+        message.unwrap_or_else(|| Node::Value(value.clone())),
+        vec![LastBlockType::Message],
+      ),
 
       (MessageType::KeyValue(_, _), Some(_)) => {
         panic!("Key/Value log messages cannot have children")
       }
 
+      (MessageType::Text(text), None) => (
+        message.unwrap_or_else(|| Node::Value(text.to_value())),
+        vec![LastBlockType::Message],
+      ),
+
+      (MessageType::Text(_), Some(_)) => {
+        unreachable!("Text messages with children were converted to values above")
+      }
+
       (MessageType::KeyValue(key, value), None) => {
-        let mut mapping = Mapping::new();
-        mapping.insert(key.to_owned(), value.to_owned());
+        let value = message.unwrap_or_else(|| Node::Value(value.clone()));
+        (
+          Node::Mapping(vec![(Node::Value(key.clone()), value)]),
+          vec![LastBlockType::KeyValue],
+        )
+      }
+    };
+
+    // Children have already been placed with the metadata
+    let node = match block.has_metadata() && block.children.is_none() {
+      true => {
+        let mut pairs = vec![];
+        Tracker::insert_metadata(block, timestamps, &mut pairs);
+        pairs.push((Node::Value("message".into()), node));
+        Node::Mapping(pairs)
+      }
+      false => node,
+    };
 
-        (YmlValue::Mapping(mapping), vec![LastBlockType::KeyValue])
+    // Compressed messages are already tagged, and YAML only allows one tag per node
+    match &block.tag_type {
+      Some(tag) if !matches!(node, Node::Value(YmlValue::Tagged(_))) => {
+        (Node::Tagged(Tag::new(tag), Box::new(node)), depth)
       }
+      _ => (node, depth),
+    }
+  }
+
+  /// Add the fields written ahead of the message in a record mapping
+  fn insert_metadata(block: &Block, timestamps: &TimestampFormat, pairs: &mut Vec<(Node, Node)>) {
+    let key = |key: &str| Node::Value(key.into());
+    if let Some(timestamp) = &block.timestamp {
+      pairs.push((key("timestamp"), Node::Value(timestamps.render(timestamp))));
+    }
+    if let Some(source) = &block.source {
+      pairs.push((key("source"), Node::Value(source.as_str().into())));
+    }
+    if let Some(level) = block.written_level() {
+      pairs.push((key("log_level"), Node::Value(level.name().into())));
+    }
+    if let Some(tags) = block.tags.as_ref().filter(|tags| !tags.is_empty()) {
+      let tags = tags
+        .iter()
+        .map(|tag| Node::Value(tag.as_ref().into()))
+        .collect();
+      pairs.push((key("tags"), Node::Sequence(tags)));
+    }
+    if let Some(fields) = &block.fields {
+      pairs.push((
+        key("fields"),
+        Node::Value(YmlValue::Mapping(fields.clone())),
+      ));
     }
   }
 
-  /// If it is a plain string, If it finds any \n in the message, it turns it into a block
-  /// HACK: This is a lack in rust-yaml, which trickled into serde_yaml. Blocks are not detected,
-  ///       and cannot be set manually. So I'm just going to handle the simple message.
-  fn is_block(value: &YmlValue) -> bool {
-    if let YmlValue::String(inner) = value {
-      return inner.contains('\n');
+  /// The node the block's message is written as, if it is styled or has an anchor
+  ///
+  /// Anchors are remembered for the rest of the document, so reusing one that was already written
+  /// writes an alias to it instead.
+  fn message_node(&mut self, block: &Block) -> Option<Node> {
+    let styled = Tracker::styled_message(block);
+    let (name, reuse) = match &block.anchor {
+      Some(Anchor::Define(name)) => (name, false),
+      Some(Anchor::Reuse(name)) => (name, true),
+      None => return styled,
+    };
+    let value = match &block.message {
+      // Compressed messages already have their tag
+      MessageType::Value(YmlValue::Tagged(_)) => return styled,
+      MessageType::Value(value) => value.clone(),
+      MessageType::Text(text) => text.to_value(),
+      _ => return styled,
+    };
+    if name.is_empty() {
+      return styled;
+    }
+
+    if reuse && self.anchors.contains(name) {
+      return Some(Node::Alias(name.clone()));
     }
-    false
+    self.anchors.insert(name.clone());
+    let node = styled.unwrap_or(Node::Value(value));
+    Some(Node::Anchor(name.clone(), Box::new(node)))
   }
 
-  /// Add the proper indentation around the block".to_string()
+  /// The block's string written in the style it was given, if that style can hold it
   ///
-  /// It also adds a "__Cut Here__" so when stringified, we can remove the plain indents that do not
-  /// need to be added
-  fn indent_string(&mut self, value: YmlValue) -> String {
-    // println!("\n\nIndenting value: {:?}", value);
-    // println!("At depth: {:?}", self.depth);
+  /// A key/value pair's value is the string styled.
+  fn styled_message(block: &Block) -> Option<Node> {
+    let text = match &block.message {
+      MessageType::Text(text) => text.as_str(),
+      MessageType::Value(YmlValue::String(text)) => text,
+      MessageType::KeyValue(_, YmlValue::String(text)) => text,
+      _ => return None,
+    };
+    let style = formatter::message_style(text, block.style.as_ref()?)?;
+    Some(Node::Styled(text.to_string(), style))
+  }
+
+  /// Start a new root document with the node
+  fn new_document(&self, node: &Node, flow: bool) -> String {
+    format!("---\n{}", self.document_body(node, flow))
+  }
+
+  /// The node as it is written after the document marker
+  fn document_body(&self, node: &Node, flow: bool) -> String {
+    self.formatter.write_node(node, "", flow)
+  }
 
-    let is_block = Tracker::is_block(&value);
-    if is_block {
+  /// Write the node at the current depth: as the document below the root, or as an item of the
+  /// sequence at its level
+  fn indent_string(&mut self, node: Node, flow: bool) -> String {
+    if node.is_block() {
       if let Some(last) = self.depth.last_mut() {
         *last = LastBlockType::BlockMessage;
       }
@@ -135,74 +719,56 @@ impl Tracker {
       0 => unreachable!("Should never be able to get here with a zero depth"),
 
       // Print a root level message (new document)
-      1 => match is_block {
-        true => {
-          if let YmlValue::String(inner) = value {
-            return format!("|+ {}", inner);
-          };
-          unreachable!("It's a block, so it's always a string")
-        }
-        false => serde_yaml::to_string(&value).unwrap(),
-      },
+      1 => self.new_document(&node, flow),
 
-      // Pad out the value so the message and children have the proper indentation
+      // Write the node as an item of the level's sequence, with each line starting at the level
       _ => {
-        let mut tmp = serde_yaml::Mapping::new();
-        tmp.insert(
-          YmlValue::String("__Cut Here__".into()),
-          YmlValue::Sequence(vec![value]),
-        );
-        let mut padded = YmlValue::Mapping(tmp);
-
-        // Pad out the value with indentations
-        for _ in 1..(self.depth.len() - 1) {
-          let mut tmp = serde_yaml::Mapping::new();
-          tmp.insert(YmlValue::String("".into()), padded);
-          padded = YmlValue::Mapping(tmp);
-        }
-
-        // println!("The padded message is: {:?}", padded);
-
-        // Find the placeholder and get rid of it
-        match serde_yaml::to_string(&padded)
-          .unwrap()
-          .split_once("__Cut Here__:\n")
-        {
-          None => panic!(
-            "\n\n--> Could not find '__Cut Here__:' in the serialized message block:\n{:#?}",
-            serde_yaml::to_string(&padded).unwrap()
-          ),
-          Some((_, message)) => match is_block {
-            true => {
-              let i_size = self.depth.len() * 2;
-
-              // Split after the initial indent
-              let (indent, end) = message.split_at(i_size);
-
-              // Add an indent after each carriage return
-              let block_indent = " ".repeat(i_size);
-
-              let sliced = &end[1..end.len() - 2].replace("\\n", &format!("\n{}", block_indent));
-              format!("{}|-\n{}{}\n", indent, block_indent, sliced)
-            }
-            false => message.to_string(),
-          },
-        }
+        let indent = self
+          .formatter
+          .indent()
+          .make(Some(self.depth.len() as u8 - 1));
+        self
+          .formatter
+          .write_node(&Node::Sequence(vec![node]), &indent, flow)
       }
     }
   }
 
   /// Convert it to a writable string, updating the Tracker state
-  pub fn serialize(&mut self, block: &mut Block) -> String {
-    // Convert the block into a pure YmlValue and its depth
-    let (value, _new_depth) = Tracker::build_value(block);
+  pub fn serialize(&mut self, block: &Block, timestamps: &TimestampFormat) -> String {
+    self.serialize_fragments(block, timestamps).join()
+  }
 
-    // Convert the value to a string with proper indentation
-    let indented = match self.depth.last() {
+  /// Convert it to the pieces of a writable string, updating the Tracker state
+  pub(crate) fn serialize_fragments(
+    &mut self,
+    block: &Block,
+    timestamps: &TimestampFormat,
+  ) -> Fragments {
+    let starts_document = matches!(
+      self.depth.last(),
+      None | Some(LastBlockType::None) | Some(LastBlockType::Reset) | Some(LastBlockType::Document)
+    );
+    if starts_document {
+      self.anchors.clear();
+    }
+    let message = self.message_node(block);
+    let (node, _new_depth) = Tracker::build_value(block, timestamps, message);
+    // Children are streamed as later records, so only records without them are flowed
+    let flow = block.children.is_none();
+    self.close_key_values(block);
+    let pair = self.pair_state(block);
+
+    // Convert the node to a string with proper indentation, starting with what separates it from
+    // the record before
+    let starts_block = starts_document && node.is_block();
+    let mut fragments = Fragments::default();
+    match self.depth.last() {
       // First message in the document is done plain
       None => {
         self.depth.push(LastBlockType::Message);
-        format!("\n{}", serde_yaml::to_string(&value).unwrap())
+        fragments.push("---\n");
+        fragments.push(self.document_body(&node, flow));
       }
 
       // Same as None, but has written the document tag. It appends a newline, so the next document
@@ -211,7 +777,8 @@ impl Tracker {
         if let Some(last) = self.depth.last_mut() {
           *last = LastBlockType::Message;
         }
-        format!("\n{}", serde_yaml::to_string(&value).unwrap())
+        fragments.push("\n---\n");
+        fragments.push(self.document_body(&node, flow));
       }
 
       // After an explicit reset, we need to add a newline
@@ -219,17 +786,50 @@ impl Tracker {
         if let Some(last) = self.depth.last_mut() {
           *last = LastBlockType::Message;
         }
-        format!("\n{}", serde_yaml::to_string(&value).unwrap())
+        fragments.push("\n---\n");
+        fragments.push(self.document_body(&node, flow));
+      }
+
+      // The marker was already written when the document was started
+      Some(LastBlockType::Document) => {
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+        fragments.push(self.document_body(&node, flow));
       }
 
       // The last item was in a sequence (this is the plain record)
       Some(LastBlockType::Message) => {
-        format!("\n{}", self.indent_string(value))
+        fragments.push("\n");
+        fragments.push(self.indent_string(node, flow));
+      }
+
+      // After dedenting out of a record's children, this is its sibling
+      Some(LastBlockType::Parent(_)) => {
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+        fragments.push("\n");
+        fragments.push(self.indent_string(node, flow));
+      }
+
+      // Key/value pairs are single entry mappings, so more records are just added to the sequence
+      Some(LastBlockType::KeyValue) | Some(LastBlockType::SiblingKeyValue) => {
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+        fragments.push("\n");
+        fragments.push(self.indent_string(node, flow));
       }
 
-      // The last item was a block. This only affects indents after
+      // The last item was a block. This only affects indents after, so a plain record can be a
+      // key again
       Some(LastBlockType::BlockMessage) => {
-        format!("\n{}", self.indent_string(value))
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+        fragments.push("\n");
+        fragments.push(self.indent_string(node, flow));
       }
 
       // An indent was requested for this item
@@ -238,11 +838,14 @@ impl Tracker {
         if let Some(last) = self.depth.last_mut() {
           *last = LastBlockType::Message;
         }
-        format!(":\n{}", self.indent_string(value))
+        fragments.push(":\n");
+        fragments.push(self.indent_string(node, flow));
       }
 
       // An indent was requested for this item
-      // HACK: To make this work as a stream, we have to add a phony key
+      // The block was already written and has to end its line, so it can't become the key the
+      // children go under. Streaming means it can't be rewritten either, so they go under an empty
+      // key in another item.
       Some(LastBlockType::BlockIndent) => {
         // Tell the tracker we've taken care of the indent
         if let Some(last) = self.depth.last_mut() {
@@ -250,182 +853,1756 @@ impl Tracker {
         }
 
         // This adds another item to the sequence and the phony key
-        format!(
-          "\n{}- \"\" :\n{}",
-          "  ".repeat(self.depth.len() - 2),
-          self.indent_string(value)
-        )
+        let indent = self
+          .formatter
+          .indent()
+          .make(Some(self.depth.len() as u8 - 2));
+        fragments.push(format!("\n{}- \"\" :\n", indent));
+        fragments.push(self.indent_string(node, flow));
+      }
+
+      // A mapping can't be turned into a key, so the children are added as another field
+      Some(LastBlockType::RecordIndent) => {
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+
+        // The root record's fields aren't in a sequence, so they have no indentation. Others line
+        // up after the dash of their item.
+        let record = self.depth.len() - 1;
+        let padding = match record {
+          1 => String::new(),
+          _ => self.formatter.indent().make(Some(record as u8)),
+        };
+        fragments.push(format!("\n{}children:\n", padding));
+        fragments.push(self.indent_string(node, flow));
       }
 
-      _ => unimplemented!("'KeyValue' still needs to be implemented"),
+      // A record mapping can't take children once a plain record follows it
+      Some(LastBlockType::Record) => {
+        if let Some(last) = self.depth.last_mut() {
+          *last = LastBlockType::Message;
+        }
+        fragments.push("\n");
+        fragments.push(self.indent_string(node, flow));
+      }
     }
-    .trim_end()
-    .to_string();
+    // A multiline document can't become a key
+    if let (true, Some(last)) = (starts_block, self.depth.last_mut()) {
+      *last = LastBlockType::BlockMessage;
+    }
+    if let Some(comment) = &block.comment {
+      fragments.insert_before_last(comment_lines(comment, fragments.last()));
+    }
+    fragments.trim_end();
 
     // Update the depth, if needed
+    let written = match pair {
+      Some(pair) => Some(pair),
+      None if block.has_metadata() => Some(LastBlockType::Record),
+      None => None,
+    };
+    if let (Some(written), Some(last)) = (written, self.depth.last_mut()) {
+      *last = written;
+    }
+
+    fragments
+  }
+
+  /// Update the state for a record written in a format that doesn't need the YAML
+  ///
+  /// This returns the depth the record was written at, with zero being the document root
+  pub fn advance(&mut self, block: &Block) -> usize {
+    self.close_key_values(block);
+    let pair = self.pair_state(block);
+    let is_block = match &block.message {
+      MessageType::Value(value) => node::is_block_value(value),
+      MessageType::Text(text) => node::is_block_text(text.as_str()),
+      _ => false,
+    };
+    match self.depth.last_mut() {
+      None => self.depth.push(pair.unwrap_or(LastBlockType::Message)),
+      Some(last) => {
+        *last = match (pair, &last, is_block) {
+          (Some(pair), _, _) => pair,
+          (None, LastBlockType::BlockIndent, _) | (None, _, true) => LastBlockType::BlockMessage,
+          _ => LastBlockType::Message,
+        }
+      }
+    }
+    self.depth.len() - 1
+  }
+
+  /// Automatically dedent when a plain record follows key/value pairs written on a new indent
+  ///
+  /// This lets details be attached to the last record with `+k`, without needing to dedent after.
+  fn close_key_values(&mut self, block: &Block) {
+    let is_pair = matches!(block.message, MessageType::KeyValue(_, _));
+    if !is_pair
+      && self.depth.len() > 1
+      && matches!(self.depth.last(), Some(LastBlockType::KeyValue))
+    {
+      self.dedent();
+    }
+  }
 
-    // And return the value
-    indented
+  /// The state to record if the block is a key/value pair, depending on what came before it
+  fn pair_state(&self, block: &Block) -> Option<LastBlockType> {
+    if !matches!(block.message, MessageType::KeyValue(_, _)) {
+      return None;
+    }
+    match self.depth.last() {
+      Some(LastBlockType::Indent)
+      | Some(LastBlockType::BlockIndent)
+      | Some(LastBlockType::RecordIndent)
+      | Some(LastBlockType::KeyValue) => Some(LastBlockType::KeyValue),
+      _ => Some(LastBlockType::SiblingKeyValue),
+    }
   }
 
-  /// Add a new indentation from the last block written and return the prefix needed
+  /// Nest the next record under the last one written
   ///
   /// To indent a message, the last item needs to be turned into a key using a ":". Each parent node
-  /// only indents once, so additional attempts to indent are ignored.
+  /// only indents once, so additional attempts to indent are ignored. Key/value pairs already have
+  /// a value, so they can't be indented under either.
   pub fn indent(&mut self) {
     match &self.depth.last() {
       Some(LastBlockType::Message) => self.depth.push(LastBlockType::Indent),
-      Some(LastBlockType::BlockMessage) => self.depth.push(LastBlockType::BlockIndent),
+      // A multiline document has nothing to hang a phony key from
+      Some(LastBlockType::BlockMessage) if self.depth.len() > 1 => {
+        self.depth.push(LastBlockType::BlockIndent)
+      }
+      Some(LastBlockType::Record) => self.depth.push(LastBlockType::RecordIndent),
+      // The children's sequence is already open, so the next record carries it on
+      Some(LastBlockType::Parent(children)) => {
+        let children = match **children {
+          LastBlockType::KeyValue => LastBlockType::SiblingKeyValue,
+          ref children => children.clone(),
+        };
+        self.depth.push(children)
+      }
       _ => (),
     };
   }
 
-  /// Remove a level of indentation.
+  /// Remove a level of indentation, leaving the root in place
+  ///
+  /// Dropping the root would make the next record start a document without a newline before it.
   pub fn dedent(&mut self) {
-    let _ = self.depth.pop();
+    if self.depth.len() > 1 {
+      self.dedent_to(self.depth.len() - 2);
+    }
+  }
+
+  /// Check if the next plain record would be written at the document root
+  pub fn at_root(&self) -> bool {
+    self.depth.len() <= 1
+  }
+
+  /// Remove levels until the next record is written no deeper than the depth, with zero being
+  /// the document root
+  pub fn dedent_to(&mut self, depth: usize) {
+    let removed = self.depth.get(depth + 1).cloned();
+    self.depth.truncate(depth + 1);
+    // A pending indent leaves the record without children
+    match (removed, self.depth.last_mut()) {
+      (
+        Some(LastBlockType::Indent)
+        | Some(LastBlockType::BlockIndent)
+        | Some(LastBlockType::RecordIndent),
+        _,
+      ) => (),
+      (Some(children), Some(last)) => *last = LastBlockType::Parent(Box::new(children)),
+      _ => (),
+    }
+  }
+
+  /// The depth the next plain record will be written at, with zero being the document root
+  pub fn next_depth(&self) -> usize {
+    let mut block = Block::new();
+    block.message = MessageType::Value(YmlValue::Null);
+    self.clone().advance(&block)
   }
 
   /// Make a new root document
   ///
-  /// TODO: Test what happens when a trailing ':' is left
+  /// A pending indent is dropped, so the record it was waiting on is left without children.
   pub fn reset(&mut self) {
     self.depth.clear();
     self.depth.push(LastBlockType::Reset);
   }
-}
 
-/// Contains the state tracker and a pointer to the output write stream
-pub struct YmLog<T>
-where
-  T: std::io::Write + Send + Sync + 'static,
-{
-  // The state caused by data being written to the logger
-  tracker: Tracker,
-  // Minimum level to be written to the logger
-  log_level: Level,
-  // The output buffer of the log
-  logger: Option<RefCell<T>>,
-}
-
-impl<T> Default for YmLog<T>
-where
-  T: std::io::Write + Send + Sync + 'static,
-{
-  fn default() -> YmLog<T> {
-    YmLog {
-      tracker: Default::default(),
-      log_level: Level::Warn,
-      logger: None,
+  /// Make a new root document, returning the `---` marker to write if one hasn't been already
+  ///
+  /// Starting a document straight after another was started leaves the first one empty, so it is
+  /// only marked once.
+  pub fn start_document(&mut self) -> Option<&'static str> {
+    if self.depth == [LastBlockType::Document] {
+      return None;
     }
+    self.anchors.clear();
+    self.depth.clear();
+    self.depth.push(LastBlockType::Document);
+    Some("\n---\n")
   }
-}
 
-impl<T> YmLog<T>
-where
-  T: std::io::Write + Send + Sync + 'static,
-{
-  pub fn new() -> Self {
-    Default::default()
+  /// Follow a root record read back from a log, ending up where the tracker that wrote it was
+  ///
+  /// Only the state is kept. The levels down to the last descendant are left open, so the next
+  /// record is written as its sibling.
+  pub(crate) fn replay(&mut self, root: Block, timestamps: &TimestampFormat) {
+    self.replay_record(root, true, timestamps)
   }
 
-  pub fn set_output(&mut self, writable: T) {
-    // let file = OpenOptions::new()
-    //     .create(true)
-    //     .write(true)
-    //     .truncate(true)
-    //     .open(log_path)
-    //     .unwrap();
+  fn replay_record(&mut self, mut block: Block, last: bool, timestamps: &TimestampFormat) {
+    let children = block.children.take().unwrap_or_default();
+    self.serialize(&block, timestamps);
+    if children.is_empty() {
+      return;
+    }
 
-    self.logger = Some(RefCell::new(writable));
+    self.indent();
+    let count = children.len();
+    for (i, child) in children.into_iter().enumerate() {
+      self.replay_record(child, last && i + 1 == count, timestamps);
+    }
+    if !last {
+      self.dedent();
+    }
   }
+}
 
-  /// Change the level threshhold for writing a message to the log
-  pub fn set_level(&mut self, level: Level) {
-    self.log_level = level;
-  }
+/// What a logger has written to one output, so a new process can carry on appending to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OutputState {
+  depth: Vec<LastBlockType>,
+  sequence: u64,
+  offset: u64,
+}
 
-  /// Borrow the logger and write the string to it
-  fn write(&mut self, block: &mut Block) {
-    //-> Result<(), std::io::Error> {
+/// The state of a logger's outputs, taken with [`YmLog::snapshot_state`]
+///
+/// This implements serde's traits, so it can be saved with the checkpoint of a batch job and
+/// passed to [`YmLog::resume`] after a restart. The settings of the logger, such as its level and
+/// format, aren't included and need to be set again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateBlob {
+  outputs: Vec<OutputState>,
+}
 
-    let level = block.log_level.as_ref().unwrap_or(&Level::Info);
-    if self.log_level > *level {
-      return;
+/// Where a record was written in the output
+///
+/// The range is measured from the start of the output, so callers can use it to index or
+/// cross-reference records without re-reading the log. With several outputs, it describes the
+/// first one.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecordHandle {
+  /// The count of records written to the output before this one
+  pub sequence: u64,
+
+  /// The bytes of the output holding the record
+  pub range: Range<u64>,
+}
+
+/// Contains the state trackers and pointers to the output write streams
+///
+/// Everything is kept behind a lock inside the logger, so it can be shared between threads as it
+/// is. Each record is written whole, but the threads share the same indentation.
+pub struct YmLog<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  state: Arc<Mutex<State<T>>>,
+
+  /// The thread flushing the outputs on an interval, if one was started
+  flusher: Mutex<Option<Background>>,
+
+  /// The thread checking for stalled scopes, if one was started
+  watchdog: Mutex<Option<Background>>,
+}
+
+/// A thread working on a logger's state until it is told to stop or the logger is gone
+struct Background {
+  stop: Sender<()>,
+  handle: JoinHandle<()>,
+}
+
+impl Background {
+  /// Run the task on the state after each wait
+  fn spawn<T>(
+    name: &str,
+    state: Weak<Mutex<State<T>>>,
+    wait: impl Fn() -> Duration + Send + 'static,
+    mut task: impl FnMut(&mut State<T>) + Send + 'static,
+  ) -> Background
+  where
+    T: std::io::Write + Send + Sync + 'static,
+  {
+    let (stop, stopped) = channel::<()>();
+    let handle = std::thread::Builder::new()
+      .name(name.to_string())
+      .spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait()) {
+          let state = match state.upgrade() {
+            Some(state) => state,
+            None => break,
+          };
+          let mut state = state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+          task(&mut state);
+        }
+      })
+      .unwrap_or_else(|err| panic!("Could not start the {} thread: {}", name, err));
+    Background { stop, handle }
+  }
+
+  /// Wait somewhere between half the interval and all of it, so processes started together
+  /// don't all flush at once
+  fn jitter(interval: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let fraction = (random % 1024) as f64 / 2048.0;
+    interval.mul_f64(0.5 + fraction)
+  }
+
+  /// Stop the thread, waiting for the task in progress to finish
+  fn stop(self) {
+    let _ = self.stop.send(());
+    let _ = self.handle.join();
+  }
+}
+
+impl<T> Drop for YmLog<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  /// Close the log, passing any errors to the error handler
+  ///
+  /// The log is closed by then, so an error that would have been a `!ymlog/error` record is
+  /// printed to stderr instead, as is one that would panic while already panicking.
+  fn drop(&mut self) {
+    self.stop_flusher();
+    self.stop_watchdog();
+    let mut state = self.lock();
+    if let Err(error) = state.close() {
+      match state.error_handler {
+        ErrorHandler::MetaRecord => eprintln!("ymlog: {}", error),
+        ErrorHandler::Panic if std::thread::panicking() => eprintln!("ymlog: {}", error),
+        _ => state.report(error),
+      }
+    }
+  }
+}
+
+/// The settings and outputs of a logger, only touched while holding its lock
+struct State<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  // Minimum level to be written to the logger
+  log_level: Level,
+  // Levels for specific modules, replacing log_level for them
+  filter: Filter,
+  // The tags a block needs, or must not have, to be written
+  tags: TagFilter,
+  // The syntax of the records
+  format: OutputFormat,
+  // The outputs the log is written to, each tracking the state caused by the data written to it
+  sinks: Vec<Sink<T>>,
+  // How to shrink very large messages
+  compression: Option<Compression>,
+  // How numbers are written, if they're changed at all
+  numbers: Option<NumberFormat>,
+
+  // Which values are written as readable durations and sizes, if any are
+  values: Option<ValueFormat>,
+  // The width nested mappings and sequences are written in flow style within, if any
+  flow_width: Option<usize>,
+
+  // The secrets masked before the block reaches any output
+  redaction: Option<Redaction>,
+  // Write the line endings in strings as `\n`
+  normalize_line_endings: bool,
+  // What to do with messages that couldn't be serialized
+  serialize_policy: SerializePolicy,
+  // Stamp blocks with the current time when they are written
+  auto_timestamp: bool,
+  // Add a backtrace to error blocks when they are written
+  error_backtraces: bool,
+  // The scopes being watched for stalls, if the watchdog was started
+  watchdog: Option<Watchdog>,
+  // Which member of each failover group is taking the records
+  failover: Failover,
+  // How the timestamps are written
+  timestamp_format: TimestampFormat,
+  // Where errors go when there is no caller to return them to
+  error_handler: ErrorHandler,
+  // The indentation of the YAML records
+  indent: Indent,
+  // Whether the records are written with ANSI colors
+  color: ColorChoice,
+  // When buffered records are written out to the outputs
+  flush_policy: FlushPolicy,
+  // A record written at the root when the log is closed
+  footer: Option<Block>,
+  // The smallest payload written once per document, with repeats referring back to it
+  repeat_threshold: Option<usize>,
+  // The depth of the next record in each output when each mark was made
+  marks: HashMap<String, Vec<usize>>,
+  // A check each block is given before it is written
+  validator: Option<Validator>,
+  // Makes the comments written above the blocks
+  commenter: Option<Commenter>,
+  // Whether blocks the validator rejects are still written
+  schema_mode: SchemaMode,
+  // What a dedent at the document root does
+  dedent_policy: DedentPolicy,
+  // The sampling rate and rate limit applied before the outputs' pipelines
+  throttle: Throttle,
+  // Write a run of identical records as the first and a copy with their count
+  collapse_duplicates: bool,
+  // The first record of the current run and how many repeats of it were held back
+  duplicates: Option<(Block, u64)>,
+  // Quote every plain scalar a YAML 1.1 loader would read as something other than a string
+  strict_yaml: bool,
+}
+
+impl<T> Default for State<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  fn default() -> State<T> {
+    State {
+      log_level: Level::Warn,
+      filter: Default::default(),
+      tags: Default::default(),
+      format: Default::default(),
+      sinks: vec![],
+      compression: None,
+      numbers: None,
+      values: None,
+      flow_width: None,
+      redaction: None,
+      normalize_line_endings: false,
+      serialize_policy: Default::default(),
+      auto_timestamp: false,
+      error_backtraces: false,
+      watchdog: None,
+      failover: Default::default(),
+      timestamp_format: Default::default(),
+      error_handler: Default::default(),
+      indent: Default::default(),
+      color: Default::default(),
+      flush_policy: Default::default(),
+      footer: None,
+      repeat_threshold: None,
+      marks: HashMap::new(),
+      validator: None,
+      commenter: None,
+      schema_mode: Default::default(),
+      dedent_policy: Default::default(),
+      throttle: Default::default(),
+      collapse_duplicates: false,
+      duplicates: None,
+      strict_yaml: false,
+    }
+  }
+}
+
+impl<T> Default for YmLog<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  fn default() -> YmLog<T> {
+    YmLog {
+      state: Arc::new(Mutex::new(Default::default())),
+      flusher: Mutex::new(None),
+      watchdog: Mutex::new(None),
+    }
+  }
+}
+
+impl<T> YmLog<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  pub fn new() -> Self {
+    Default::default()
+  }
+
+  /// A panic elsewhere while the lock was held shouldn't stop the rest of the program logging
+  fn lock(&self) -> MutexGuard<'_, State<T>> {
+    self
+      .state
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+
+  /// Write the log to the output alone, replacing every output added before
+  ///
+  /// Files can be opened with the right options by [`YmLog::set_output_path`].
+  pub fn set_output(&self, writable: T) {
+    let mut state = self.lock();
+    let output = Output::Direct(Buffered::new(writable, state.flush_policy.clone()));
+    state.sinks = vec![Sink::new(output, Pipeline::new())];
+  }
+
+  /// Write the log to another output as well, which only receives blocks at or above the level
+  ///
+  /// Indentation actions are applied to every output, so outputs accepting the same blocks receive
+  /// identical YAML. An output that skipped a block nests the following records under the last one
+  /// it received, keeping its YAML valid on its own.
+  pub fn add_output(&self, writable: T, level: Level) {
+    self.add_output_with(writable, Pipeline::new().level(level));
+  }
+
+  /// Write the log to another output, passing each block through the pipeline first
+  ///
+  /// Unless the pipeline sets a level, the output uses the logger's level.
+  pub fn add_output_with(&self, writable: T, pipeline: Pipeline) {
+    let mut state = self.lock();
+    let output = Output::Direct(Buffered::new(writable, state.flush_policy.clone()));
+    state.sinks.push(Sink::new(output, pipeline));
+  }
+
+  /// Write the log to an output that can stall, such as a network collector, as well
+  ///
+  /// Records are written from a background thread, and logging waits at most the timeout for
+  /// each. Once a write times out the output is stalled, and the policy decides what happens to
+  /// the records logged until it recovers. Flushing and closing the logger give a stalled output
+  /// the timeout to recover before returning a `TimedOut` error.
+  pub fn add_timed_output(&self, writable: T, pipeline: Pipeline, timeout: WriteTimeout) {
+    let mut state = self.lock();
+    let output = Output::Timed(TimedWriter::spawn(
+      writable,
+      state.flush_policy.clone(),
+      timeout,
+    ));
+    state.sinks.push(Sink::new(output, pipeline));
+  }
+
+  /// Choose how long a failed member of a failover group is left alone before it is tried again
+  ///
+  /// While a backup is taking the records, the next record after each interval is offered to the
+  /// members ranked above it first. The default is ten seconds.
+  pub fn set_failback_interval(&self, interval: Duration) {
+    self.lock().failover.interval = interval;
+  }
+
+  /// Create a logger that writes from a background thread
+  ///
+  /// Serialized blocks are queued on a channel, so logging never waits on the output. Use
+  /// [`YmLog::flush`] or [`YmLog::shutdown`] to make sure the queue has been written.
+  pub fn with_async_writer(writable: T) -> Self {
+    let logger = YmLog::new();
+    let terminal = is_terminal(&writable);
+    logger.lock().sinks = vec![Sink::new(
+      Output::Queued(AsyncWriter::spawn(writable, Default::default())),
+      Pipeline::new(),
+    )];
+    logger.set_terminal(terminal);
+    logger
+  }
+
+  /// Check each output, in the order they were added
+  ///
+  /// Services can report this on their readiness endpoints. An output has failed when its last
+  /// write did, including writes made from a background thread.
+  pub fn health(&self) -> Vec<SinkHealth> {
+    self
+      .lock()
+      .sinks
+      .iter_mut()
+      .map(|sink| sink.health())
+      .collect()
+  }
+
+  /// Wait until everything logged so far has been written and flush the outputs
+  pub fn flush(&self) -> IoResult<()> {
+    let mut state = self.lock();
+    state.end_duplicates();
+    state.sinks.iter_mut().try_for_each(|sink| sink.flush())
+  }
+
+  /// Write the block at the current depth and flush, giving up if the lock isn't free in time
+  ///
+  /// The panic hook uses this, as the thread panicking could be the one holding the lock. Running
+  /// out of time is a `WouldBlock` error.
+  pub(crate) fn log_within(&self, block: &mut Block, wait: Duration) -> IoResult<()> {
+    let deadline = Instant::now() + wait;
+    let mut state = loop {
+      match self.state.try_lock() {
+        Ok(state) => break state,
+        Err(TryLockError::Poisoned(poisoned)) => break poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+          std::thread::sleep(Duration::from_millis(1))
+        }
+        Err(TryLockError::WouldBlock) => {
+          return Err(IoError::new(
+            ErrorKind::WouldBlock,
+            "The logger was busy for too long",
+          ))
+        }
+      }
     };
+    state.log(block, None)?;
+    state.end_duplicates();
+    state.sinks.iter_mut().try_for_each(|sink| sink.flush())
+  }
+
+  /// Flush the outputs, then wait until the ones that are files have their records on disk
+  ///
+  /// This is the checkpoint for daemons that can't lose what was logged before it, even to a
+  /// power cut. Files behind a `BufWriter` or `LineWriter` are synced too, while outputs that
+  /// aren't files are only flushed.
+  pub fn sync_all(&self) -> IoResult<()> {
+    let mut state = self.lock();
+    state.end_duplicates();
+    state.sinks.iter_mut().try_for_each(|sink| sink.sync_all())
+  }
 
-    if let Some(logger) = &self.logger {
-      let value = self.tracker.serialize(block);
-      let _ = logger.borrow_mut().write_all(value.as_bytes());
+  /// Flush the outputs from a background thread, so records are never held back much longer
+  /// than the interval by a flush policy or a buffered writer
+  ///
+  /// Each wait is picked at random between half the interval and all of it. Starting another
+  /// flusher replaces this one, and it is stopped when the logger is dropped. Errors flushing are
+  /// passed to the error handler.
+  pub fn start_flusher(&self, interval: Duration) {
+    let flusher = Background::spawn(
+      "ymlog-flusher",
+      Arc::downgrade(&self.state),
+      move || Background::jitter(interval),
+      |state| {
+        let flushed = state.sinks.iter_mut().try_for_each(|sink| sink.flush());
+        if let Err(err) = flushed {
+          state.report(err);
+        }
+      },
+    );
+    if let Some(old) = YmLog::<T>::slot(&self.flusher).replace(flusher) {
+      old.stop();
+    }
+  }
+
+  /// Stop the background flusher, if one was started
+  pub fn stop_flusher(&self) {
+    if let Some(flusher) = YmLog::<T>::slot(&self.flusher).take() {
+      flusher.stop();
     }
   }
 
-  fn split_block(&mut self, block: &mut Block) {
+  /// Warn when a scope stays open without a record written under it for longer than the limit
+  ///
+  /// A background thread checks the innermost scope of the first output, and writes a
+  /// `!ymlog/stalled` warning naming the record the scope is under and when it was opened. Each
+  /// stall is only warned about once, until a record is written under the scope. Starting another
+  /// watchdog replaces this one, and it is stopped when the logger is dropped.
+  pub fn start_watchdog(&self, limit: Duration) {
+    let watchdog = Watchdog::new(limit);
+    let period = watchdog.period();
+    {
+      let mut state = self.lock();
+      state.watchdog = Some(watchdog);
+      state.watch_depth();
+    }
+    let thread = Background::spawn(
+      "ymlog-watchdog",
+      Arc::downgrade(&self.state),
+      move || period,
+      State::check_watchdog,
+    );
+    if let Some(old) = YmLog::<T>::slot(&self.watchdog).replace(thread) {
+      old.stop();
+    }
+  }
+
+  /// Stop the watchdog, if one was started
+  pub fn stop_watchdog(&self) {
+    if let Some(thread) = YmLog::<T>::slot(&self.watchdog).take() {
+      thread.stop();
+    }
+    self.lock().watchdog = None;
+  }
+
+  fn slot(slot: &Mutex<Option<Background>>) -> MutexGuard<'_, Option<Background>> {
+    slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+
+  /// Finish the log, so each output ends on a complete document
+  ///
+  /// The footer is written as a new document if one was set. Outputs that had anything kept
+  /// from them since the last close then get a `!ymlog/summary` record counting the blocks
+  /// filtered or sampled out, skipped as unserializable or failed to write, and the values
+  /// redacted or collapsed, so readers know what they aren't seeing. Then YAML outputs get a `...`
+  /// end marker and a trailing newline, and everything is flushed. This happens automatically when
+  /// the logger is dropped. Outputs that haven't written anything since the last close are left
+  /// alone, and records logged afterwards start a new document.
+  pub fn close(&self) -> IoResult<()> {
+    self.lock().close()
+  }
+
+  /// Start a new document in every output, like the `r` action
+  ///
+  /// Outputs already written to get the `---` marker straight away, and the next record is the
+  /// root of the new document.
+  pub fn new_document(&self) -> IoResult<()> {
+    self.skip(Some("r"))
+  }
+
+  /// End the document in every YAML output written to with a `...` marker
+  ///
+  /// Unlike [`YmLog::close`], no footer or summary is written. The next record starts a new
+  /// document.
+  pub fn end_document(&self) -> IoResult<()> {
+    let mut state = self.lock();
+    state.end_duplicates();
+    state.end_documents()
+  }
+
+  /// Write a record, such as "log closed", when the log is closed
+  pub fn set_footer(&self, footer: Block) {
+    self.lock().footer = Some(footer);
+  }
+
+  /// Drain anything still queued and close the outputs
+  ///
+  /// Nothing more will be written until a new output is set.
+  pub fn shutdown(&self) -> IoResult<()> {
+    self
+      .lock()
+      .sinks
+      .drain(..)
+      .try_for_each(|mut sink| sink.shutdown())
+  }
+
+  /// Change the level threshhold for writing a message to the log
+  pub fn set_level(&self, level: Level) {
+    self.lock().log_level = level;
+  }
+
+  /// Set levels per module, such as `warn,my_app::db=trace,hyper=off`
+  ///
+  /// The directives are comma separated. A bare level replaces the logger's level, a bare module
+  /// path logs everything from it, and `off` drops everything from it. Blocks are matched by their
+  /// target, using the longest module path that matches. Like the logger's level, the filter does
+  /// not apply to outputs whose pipeline sets a level. An empty string removes the filter.
+  pub fn set_filter(&self, spec: &str) -> IoResult<()> {
+    let filter = Filter::parse(spec)?;
+    self.lock().filter = filter;
+    Ok(())
+  }
+
+  /// Only write blocks with one of the included tags, unless it is empty, and none of the excluded
+  ///
+  /// Like the level, this does not apply to outputs whose pipeline has its own tags stage, so a tag
+  /// excluded here can still be routed to an output of its own.
+  pub fn filter_tags<I, E>(&self, include: I, exclude: E)
+  where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+    E: IntoIterator,
+    E::Item: std::fmt::Display,
+  {
+    self.lock().tags = TagFilter::new(include, exclude);
+  }
+
+  /// Change the syntax the records are written in
+  ///
+  /// Outputs with a format stage in their pipeline keep their own.
+  pub fn set_format(&self, format: OutputFormat) {
+    self.lock().format = format;
+  }
+
+  /// Change the indentation of each level of YAML records, as described by [`Tracker::set_indent`]
+  pub fn set_indent(&self, indent: Indent) {
+    self.lock().indent = indent;
+  }
+
+  /// The indentation of the YAML records
+  pub fn indent(&self) -> Indent {
+    self.lock().indent.clone()
+  }
+
+  /// Remember the current depth by name, so [`YmLog::unwind_to`] can return to it
+  ///
+  /// Marking a name again moves the mark. Marks are depths rather than places in the log, so
+  /// unwinding after the document was reset goes back to the same depth in the new one.
+  pub fn mark(&self, name: &str) {
+    let mut state = self.lock();
+    let depths = state
+      .sinks
+      .iter()
+      .map(|sink| sink.tracker.next_depth())
+      .collect();
+    state.marks.insert(name.to_string(), depths);
+  }
+
+  /// Dedent back to a mark, however many indents were made since
+  ///
+  /// Records logged next are siblings of the first record written after the mark. Outputs that
+  /// are already shallower than the mark are left alone. An unknown mark is a `NotFound` error.
+  pub fn unwind_to(&self, name: &str) -> IoResult<()> {
+    let mut state = self.lock();
+    let state = &mut *state;
+    state.end_duplicates();
+    let depths = state.marks.get(name).ok_or_else(|| {
+      IoError::new(
+        ErrorKind::NotFound,
+        format!("There is no mark named {:?}", name),
+      )
+    })?;
+    for (sink, depth) in state.sinks.iter_mut().zip(depths) {
+      sink.tracker.dedent_to(*depth);
+    }
+    Ok(())
+  }
+
+  /// Dedent until the next record is written no deeper than the depth, zero being the root
+  ///
+  /// This never indents, so a depth below the current one does nothing.
+  pub fn dedent_to(&self, depth: usize) {
+    let mut state = self.lock();
+    state.end_duplicates();
+    state
+      .sinks
+      .iter_mut()
+      .for_each(|sink| sink.tracker.dedent_to(depth));
+  }
+
+  /// The depth the next plain record will be written at in the first output, zero being the root
+  pub fn current_depth(&self) -> usize {
+    self
+      .lock()
+      .sinks
+      .first()
+      .map_or(0, |sink| sink.tracker.next_depth())
+  }
+
+  /// Flush the outputs and record the depth and document state of each
+  pub fn snapshot_state(&self) -> IoResult<StateBlob> {
+    self.flush()?;
+    let outputs = self
+      .lock()
+      .sinks
+      .iter()
+      .map(|sink| OutputState {
+        depth: sink.tracker.depth.clone(),
+        sequence: sink.sequence,
+        offset: sink.offset,
+      })
+      .collect();
+    Ok(StateBlob { outputs })
+  }
+
+  /// Create a logger that carries on from the snapshot, appending to the writer
+  ///
+  /// The writer should be the output the snapshot was taken from, opened for appending. Only the
+  /// state of the first output is used, and an empty snapshot is an error.
+  ///
+  /// Dropping a logger closes its documents, so a snapshot taken before then is out of date. When
+  /// stopping cleanly, [close](YmLog::close) the logger before taking the snapshot, and the resumed
+  /// logger will start a new document.
+  pub fn resume(state: StateBlob, writable: T) -> IoResult<Self> {
+    let output = state.outputs.into_iter().next().ok_or_else(|| {
+      IoError::new(
+        ErrorKind::InvalidInput,
+        "The snapshot doesn't have any outputs to resume",
+      )
+    })?;
+
+    let logger = YmLog::new();
+    logger.set_output(writable);
+    if let Some(sink) = logger.lock().sinks.first_mut() {
+      sink.tracker.depth = output.depth;
+      sink.sequence = output.sequence;
+      sink.offset = output.offset;
+    }
+    Ok(logger)
+  }
+
+  /// Carry on from a root record read back from the first output, as if this logger wrote it
+  pub(crate) fn replay(&self, root: Block, offset: u64) {
+    let mut guard = self.lock();
+    let state = &mut *guard;
+    if let Some(sink) = state.sinks.first_mut() {
+      sink.tracker.replay(root, &state.timestamp_format);
+      sink.offset = offset;
+    }
+  }
+
+  /// Choose when records are written out to the outputs, including ones added later
+  ///
+  /// By default every record is written as soon as it is logged. Buffering them is much faster for
+  /// files at high volume, at the cost of losing the buffer if the process is killed. Whatever is
+  /// buffered is written when the logger is flushed or dropped.
+  pub fn set_flush_policy(&self, policy: FlushPolicy) -> IoResult<()> {
+    let mut state = self.lock();
+    state.flush_policy = policy.clone();
+    state
+      .sinks
+      .iter_mut()
+      .try_for_each(|sink| sink.set_flush_policy(policy.clone()))
+  }
+
+  /// Choose whether records are written with ANSI colors. The default only colors terminals.
+  pub fn set_color(&self, choice: ColorChoice) {
+    self.lock().color = choice;
+  }
+
+  /// Quote every plain scalar that a YAML 1.1 loader would read as a boolean, number or date
+  ///
+  /// Values like `yes`, `on`, `1:30` and `2001-12-14` are strings in YAML 1.2, but older loaders
+  /// disagree, so this is for logs read by something other than a 1.2 core schema loader.
+  pub fn set_strict_yaml(&self, strict: bool) {
+    self.lock().strict_yaml = strict;
+  }
+
+  /// Collapse runs of identical records at the same depth, such as those from a retry loop
+  ///
+  /// The first record of a run is written as usual. The repeats are held back until a different
+  /// record, an indent or dedent, a flush or a close ends the run, and are then written as one copy
+  /// of the last repeat with a `count` field saying how many it stands for. Timestamps are ignored
+  /// when comparing, and the logger's own `!ymlog/...` records never start a run.
+  pub fn collapse_duplicates(&self, collapse: bool) {
+    let mut state = self.lock();
+    if !collapse {
+      state.end_duplicates();
+    }
+    state.collapse_duplicates = collapse;
+  }
+
+  /// Keep each block with the probability of the rate, such as 0.1 for one in ten
+  ///
+  /// This applies to every output, before their pipelines. A rate of 1 or more keeps everything.
+  pub fn sample(&self, rate: f64) {
+    self.lock().throttle.rate = match rate < 1.0 {
+      true => Some(rate.max(0.0)),
+      false => None,
+    };
+  }
+
+  /// Limit how fast records with the same key are written, replacing the limit set before
+  ///
+  /// Records over the limit are dropped from every output. When a key gets a record through again,
+  /// a `!ymlog/suppressed` record with the count it lost is written first, and anything still
+  /// suppressed when the log is closed is counted in its summary. None removes the limit.
+  pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+    self.lock().throttle.limit = limit;
+  }
+
+  /// Check every block with the validator before it is written, replacing the one set before
+  ///
+  /// Failures are passed to the error handler as `InvalidData` errors, so by default they become
+  /// `!ymlog/error` records. The logger's own `!ymlog/...` records aren't validated, and None
+  /// removes the validator.
+  pub fn set_validator(&self, validator: Option<Validator>) {
+    self.lock().validator = validator;
+  }
+
+  /// Write the comment the function makes above each block in YAML outputs, replacing the one set
+  /// before
+  ///
+  /// A comment set on the block with [`Block::set_comment`] is written after it. The logger's own
+  /// `!ymlog/...` records don't get one, and None removes the function.
+  ///
+  /// ```
+  /// # let logger = ymlog::YmLog::<Vec<u8>>::new();
+  /// logger.set_commenter(Some(Box::new(|block| {
+  ///   block.timestamp().map(|at| format!("Logged at {}", at))
+  /// })));
+  /// ```
+  pub fn set_commenter(&self, commenter: Option<Commenter>) {
+    self.lock().commenter = commenter;
+  }
+
+  /// Choose whether blocks the validator rejects are still written
+  pub fn set_schema_mode(&self, mode: SchemaMode) {
+    self.lock().schema_mode = mode;
+  }
+
+  /// Choose what happens when the actions dedent past the document root
+  pub fn set_dedent_policy(&self, policy: DedentPolicy) {
+    self.lock().dedent_policy = policy;
+  }
+
+  /// Mark whether the first output is a terminal, for writers that can't be checked like boxes
+  pub(crate) fn set_terminal(&self, terminal: bool) {
+    if let Some(sink) = self.lock().sinks.first_mut() {
+      sink.terminal = terminal;
+    }
+  }
+
+  /// Choose what happens to blocks whose message failed to serialize
+  pub fn set_serialize_policy(&self, policy: SerializePolicy) {
+    self.lock().serialize_policy = policy;
+  }
+
+  /// Stamp every block written with the current time
+  ///
+  /// Blocks that were already stamped keep their timestamp.
+  pub fn auto_timestamp(&self, enabled: bool) {
+    self.lock().auto_timestamp = enabled;
+  }
+
+  /// Add a backtrace to every block written at error level or above
+  ///
+  /// The backtrace is a child of the block, like the one added by [`Block::with_backtrace`], so
+  /// blocks that already have one aren't given another. Capturing is slow, so this is for services
+  /// that rarely log errors.
+  pub fn error_backtraces(&self, enabled: bool) {
+    self.lock().error_backtraces = enabled;
+  }
+
+  /// Change how timestamps are written in the records
+  pub fn set_timestamp_format(&self, format: TimestampFormat) {
+    self.lock().timestamp_format = format;
+  }
+
+  /// Choose what happens to the errors passed to [`YmLog::report`]
+  ///
+  /// A custom handler is called while the logger is locked, so it must not log to the same logger.
+  pub fn set_error_handler(&self, handler: ErrorHandler) {
+    self.lock().error_handler = handler;
+  }
+
+  /// Hand an error to the error handler
+  ///
+  /// The `ymlog!` macro uses this for everything that goes wrong while logging, so it never panics
+  /// unless the handler was set to.
+  pub fn report(&self, error: IoError) {
+    self.lock().report(error)
+  }
+
+  /// Change how the numbers in messages and fields are written
+  pub fn set_number_format(&self, numbers: NumberFormat) {
+    self.lock().numbers = Some(numbers);
+  }
+
+  /// Mask the values under keys matching the key patterns, and the parts of strings matching the
+  /// value patterns, replacing the ones set before
+  ///
+  /// Key patterns are globs, such as `*token*`, matched ignoring case, and value patterns are
+  /// regular expressions, such as `AKIA[0-9A-Z]{16}`. See [`redact`](crate::redact) for where they
+  /// are checked. Empty lists turn masking off. A value pattern that isn't a regex is an
+  /// `InvalidInput` error, leaving the patterns set before in place.
+  pub fn redact<K, P>(&self, keys: K, patterns: P) -> IoResult<()>
+  where
+    K: IntoIterator,
+    K::Item: std::fmt::Display,
+    P: IntoIterator,
+    P::Item: std::fmt::Display,
+  {
+    let redaction = Redaction::new(
+      keys.into_iter().map(|key| key.to_string()).collect(),
+      patterns
+        .into_iter()
+        .map(|pattern| pattern.to_string())
+        .collect(),
+    )?;
+    self.lock().redaction = Some(redaction).filter(|redaction| !redaction.is_empty());
+    Ok(())
+  }
+
+  /// Change which values in messages and fields are written as readable durations and sizes
+  pub fn set_value_format(&self, values: ValueFormat) {
+    self.lock().values = Some(values);
+  }
+
+  /// Write nested mappings and sequences, such as the fields, in flow style when they fit in the
+  /// width, or None to always write them as blocks
+  ///
+  /// With a width of 40, a record's fields are written as `fields: {user: 7, retries: 3}`. Records
+  /// with children are always written as blocks, since the children follow as later records.
+  pub fn set_flow_width(&self, width: Option<usize>) {
+    self.lock().flow_width = width;
+  }
+
+  /// Write the `\r\n` and lone `\r` line endings in messages, fields and pairs as `\n`
+  ///
+  /// Records are always written with `\n`, but text embedded from files or other programs keeps
+  /// the endings of the platform it came from. Normalizing them makes logs from every platform
+  /// compare byte for byte.
+  pub fn normalize_line_endings(&self, normalize: bool) {
+    self.lock().normalize_line_endings = normalize;
+  }
+
+  /// Compress string messages larger than the threshold
+  ///
+  /// The message is written as a base64 string tagged with the codec name
+  pub fn set_compression(&self, compression: Compression) {
+    self.lock().compression = Some(compression);
+  }
+
+  /// Write large payloads once per document, replacing later copies with `same_as: <id>`
+  ///
+  /// Strings and structured values in messages and fields of at least the threshold in bytes are
+  /// remembered, and a copy later in the same document refers back to the sequence of the first
+  /// record holding it on that output. None writes every copy, which is the default.
+  pub fn set_repeat_threshold(&self, threshold: Option<usize>) {
+    self.lock().repeat_threshold = threshold;
+  }
+
+  /// Convert and write the block to the log
+  ///
+  /// Returns the handle of the last record written, or None if the block was filtered out. If the
+  /// actions write the block more than once, the earlier handles are dropped.
+  ///
+  /// Invalid actions, a block without a message, or a logger without an output are returned as
+  /// `InvalidInput` or `NotConnected` errors. Actions before the invalid one have already been run.
+  pub fn log(&self, block: &mut Block, actions: Option<&str>) -> IoResult<Option<RecordHandle>> {
+    self.lock().log(block, actions)
+  }
+
+  /// Check if a block at the level from the target could be written to any output
+  ///
+  /// Only the levels are checked, so a pipeline may still filter the block out.
+  pub fn enabled(&self, level: Level, target: Option<&str>) -> bool {
+    self.lock().enabled(&level, target)
+  }
+
+  /// Like [`YmLog::log`], but only builds the block if it could be written
+  ///
+  /// The level is the lowest the actions write the block at, so `D_` skips building it unless
+  /// Debug records from the target are written somewhere. Skipped blocks still run the indent and
+  /// dedent actions, so the records after them nest the same way. The target is set on the block if
+  /// it doesn't have one.
+  pub fn log_lazy(
+    &self,
+    actions: Option<&str>,
+    target: &str,
+    build: impl FnOnce() -> Block,
+  ) -> IoResult<Option<RecordHandle>> {
+    let mut state = self.lock();
+    let level = Level::lowest_written(actions, Level::Info);
+    match state.enabled(&level, Some(target)) {
+      true => {
+        let mut block = build();
+        if block.target().is_none() {
+          block.set_target(target);
+        }
+        state.log(&mut block, actions)
+      }
+      false => state.run_actions(None, actions),
+    }
+  }
+
+  /// Run only the indent, dedent and reset actions, for a block that is never built
+  ///
+  /// The macros use this for levels compiled out by [`STATIC_MAX_LEVEL`], so the records after
+  /// them still nest the same way.
+  pub fn skip(&self, actions: Option<&str>) -> IoResult<()> {
+    self.lock().run_actions(None, actions).map(|_| ())
+  }
+}
+
+impl<T> State<T>
+where
+  T: std::io::Write + Send + Sync + 'static,
+{
+  fn report(&mut self, error: IoError) {
+    match &self.error_handler {
+      ErrorHandler::MetaRecord => {
+        let mut block = Block::new();
+        block.message = MessageType::Value(YmlValue::String(format!("<ymlog error: {}>", error)));
+        block.set_tag_type("ymlog/error");
+        block.set_log_level(Level::Error);
+
+        let written = match self.sinks.is_empty() {
+          true => false,
+          false => self.write(&mut block).is_ok(),
+        };
+        if !written {
+          eprintln!("ymlog: {}", error);
+        }
+      }
+      ErrorHandler::Panic => panic!("ymlog: {}", error),
+      ErrorHandler::Ignore => (),
+      ErrorHandler::Custom(handler) => handler(&error),
+    }
+  }
+
+  /// Write the repeats held back from the current run of identical records, if there were any
+  ///
+  /// Errors are passed to the error handler, since the caller ending the run didn't log anything.
+  fn end_duplicates(&mut self) {
+    match self.duplicates.take() {
+      Some((mut last, count)) if count > 0 => {
+        let _ = last.add_field("count", count);
+        if let Err(err) = self.write(&mut last) {
+          self.report(err);
+        }
+        self.duplicates = None;
+      }
+      _ => (),
+    }
+  }
+
+  /// Write the footer and end markers to the outputs written to since they were last closed
+  fn close(&mut self) -> IoResult<()> {
+    self.end_duplicates();
+    let throttled = self.throttle.take_suppressed();
+    self
+      .sinks
+      .iter_mut()
+      .for_each(|sink| sink.withheld.throttled += throttled);
+    let is_withheld = |sink: &Sink<T>| !sink.withheld.counts().is_empty();
+    if !self
+      .sinks
+      .iter()
+      .any(|sink| State::is_open(sink) || is_withheld(sink))
+    {
+      return self.sinks.iter_mut().try_for_each(|sink| sink.flush());
+    }
+
+    let mut error = None;
+    if let Some(mut footer) = self.footer.clone() {
+      self.sinks.iter_mut().for_each(|sink| sink.tracker.reset());
+      error = self.write(&mut footer).err();
+    }
+
+    // The summary is the last record, so it also counts anything the footer lost
+    let (default_format, timestamps, color) = (&self.format, &self.timestamp_format, &self.color);
+    let strict = self.strict_yaml;
+    for sink in self.sinks.iter_mut().filter(|sink| is_withheld(sink)) {
+      let mut summary = Block::new();
+      summary.message = MessageType::Value("Withheld from this output".into());
+      summary.set_tag_type("ymlog/summary");
+      for (name, count) in std::mem::take(&mut sink.withheld).counts() {
+        let _ = summary.add_field(name, count);
+      }
+      sink.tracker.reset();
+      let format = sink
+        .format
+        .clone()
+        .unwrap_or_else(|| default_format.clone());
+      let value = State::render(sink, &summary, &format, timestamps, color, strict);
+      if let Err(err) = sink.write(value) {
+        error = error.or(Some(err));
+      }
+    }
+
+    let ended = self.end_documents();
+    match error {
+      Some(err) => Err(err),
+      None => ended,
+    }
+  }
+
+  /// Write the `...` end marker to the YAML outputs with an open document, and flush them
+  fn end_documents(&mut self) -> IoResult<()> {
+    let mut error = None;
+    for sink in self.sinks.iter_mut().filter(|sink| State::is_open(sink)) {
+      let ended = match sink.format {
+        Some(OutputFormat::Yaml) => sink.write_raw("\n...\n"),
+        _ => Ok(()),
+      };
+      sink.tracker.reset();
+      if let Err(err) = ended.and_then(|_| sink.flush()) {
+        error = error.or(Some(err));
+      }
+    }
+    match error {
+      Some(err) => Err(err),
+      None => Ok(()),
+    }
+  }
+
+  /// Check if anything was written to the output since its document was last ended
+  fn is_open(sink: &Sink<T>) -> bool {
+    !matches!(sink.tracker.levels(), [] | [LastBlockType::Reset])
+  }
+
+  /// Serialize the block for the output in the format, moving its tracker along
+  fn render(
+    sink: &mut Sink<T>,
+    block: &Block,
+    format: &OutputFormat,
+    timestamps: &TimestampFormat,
+    color: &ColorChoice,
+    strict: bool,
+  ) -> Fragments {
+    sink.format = Some(format.clone());
+    let colored = color.enabled(sink.terminal);
+    if let (OutputFormat::Yaml, false, false) = (format, strict, colored) {
+      return sink.tracker.serialize_fragments(block, timestamps);
+    }
+
+    let value = match format {
+      OutputFormat::Yaml if strict => {
+        strict::quote_ambiguous(&sink.tracker.serialize(block, timestamps))
+      }
+      OutputFormat::Yaml => sink.tracker.serialize(block, timestamps),
+      OutputFormat::JsonLines => json::record(block, sink.tracker.advance(block), timestamps),
+    };
+    Fragments::from(match colored {
+      true => color::paint(&value, block.log_level(), format),
+      false => value,
+    })
+  }
+
+  /// Run the block through each output's pipeline and write what comes out
+  ///
+  /// This returns None if the first output didn't receive the block. Every output is attempted,
+  /// but only the first error is returned.
+  fn write(&mut self, block: &mut Block) -> IoResult<Option<RecordHandle>> {
+    // Blocks no output takes are dropped before they are validated, throttled or counted
+    if !self.wanted(block) {
+      return Ok(None);
+    }
+    if self.auto_timestamp && block.timestamp.is_none() {
+      block.stamp();
+    }
+
+    // The logger's own records say what went wrong themselves
+    let is_meta = block
+      .tag_type()
+      .is_some_and(|tag| tag.starts_with("ymlog/"));
+    if self.error_backtraces
+      && !is_meta
+      && *block.log_level() >= Level::Error
+      && !block.has_backtrace()
+    {
+      block.with_backtrace();
+    }
+
+    if let MessageType::Unserializable { type_name, error } = &block.message {
+      match self.serialize_policy {
+        SerializePolicy::Fallback => block.message = MessageType::fallback(type_name, error),
+        SerializePolicy::Skip => {
+          self
+            .sinks
+            .iter_mut()
+            .for_each(|sink| sink.withheld.unserializable += 1);
+          return Ok(None);
+        }
+        SerializePolicy::Panic => {
+          panic!("Could not serialize the {} message: {}", type_name, error)
+        }
+      }
+    }
+
+    if let Some(redaction) = &self.redaction {
+      let masked = redaction.apply(block);
+      if masked > 0 {
+        self
+          .sinks
+          .iter_mut()
+          .for_each(|sink| sink.withheld.redacted += masked);
+      }
+    }
+
+    if self.collapse_duplicates && !is_meta {
+      if let Some((first, count)) = &mut self.duplicates {
+        if first.same_entry(block) {
+          first.timestamp = block.timestamp;
+          *count += 1;
+          return Ok(None);
+        }
+      }
+      self.end_duplicates();
+      self.duplicates = Some((block.clone(), 0));
+    }
+
+    if self.normalize_line_endings {
+      block.normalize_line_endings();
+    }
+    if let Some(values) = &self.values {
+      values.apply_block(block);
+    }
+    if let Some(numbers) = &self.numbers {
+      numbers.apply_block(block);
+    }
+
+    if let Some(comment) = self.commenter.as_ref().filter(|_| !is_meta) {
+      if let Some(comment) = comment(block) {
+        block.comment = Some(match block.comment.take() {
+          Some(own) => format!("{}\n{}", comment, own),
+          None => comment,
+        });
+      }
+    }
+
+    if let Some(compression) = &self.compression {
+      let packed = match &block.message {
+        MessageType::Value(value) => compression.pack(value)?,
+        MessageType::Text(text) => compression.pack(&text.to_value())?,
+        _ => None,
+      };
+      if let Some(packed) = packed {
+        block.message = MessageType::Value(packed);
+      }
+    }
+
+    let rejection = match &self.validator {
+      Some(validator) if !is_meta => validator(block).err(),
+      _ => None,
+    };
+    let rejection = rejection.map(|reason| {
+      IoError::new(
+        ErrorKind::InvalidData,
+        format!("A record failed validation: {}", reason),
+      )
+    });
+    let rejection = match (rejection, self.schema_mode) {
+      (Some(rejected), SchemaMode::Strict) => {
+        self
+          .sinks
+          .iter_mut()
+          .for_each(|sink| sink.withheld.invalid += 1);
+        self.report(rejected);
+        return Ok(None);
+      }
+      (rejection, _) => rejection,
+    };
+
+    let verdict = match is_meta {
+      true => Verdict::Keep(None),
+      false => self.throttle.check(block),
+    };
+    match verdict {
+      Verdict::Keep(None) => (),
+      Verdict::Keep(Some((key, count))) => {
+        let mut summary = throttle::summary(&key, count);
+        summary.set_log_level(*block.log_level());
+        if let Some(target) = block.target() {
+          summary.set_target(target);
+        }
+        self.write(&mut summary)?;
+      }
+      Verdict::Sampled => {
+        self
+          .sinks
+          .iter_mut()
+          .for_each(|sink| sink.withheld.sampled += 1);
+        return Ok(None);
+      }
+      Verdict::Throttled => return Ok(None),
+    }
+
+    let threshold = self.threshold(block.target()).copied();
+    let mut handle = None;
+    let mut error = None;
+    let mut attempts = self
+      .failover
+      .attempts(self.sinks.iter().map(|sink| sink.pipeline.failover_group()));
+    for (i, sink) in self.sinks.iter_mut().enumerate() {
+      let offer = attempts.should_offer(i, || match sink.health().status {
+        SinkStatus::Ok => Ok(()),
+        SinkStatus::Degraded(reason) => Err(reason),
+        SinkStatus::Failed { error, .. } => Err(error),
+      });
+      if !offer {
+        continue;
+      }
+
+      let mut processed = match sink.pipeline.run(block, threshold.as_ref(), &self.tags) {
+        Ok(processed) => processed,
+        Err(dropped) => {
+          attempts.took(i);
+          match dropped {
+            Dropped::Level | Dropped::Tags => (),
+            Dropped::Filtered => sink.withheld.filtered += 1,
+            Dropped::Sampled => sink.withheld.sampled += 1,
+          }
+          continue;
+        }
+      };
+      if sink.terminal && processed.block.has_human() {
+        processed.block.to_mut().humanize();
+      }
+      if let Some((scrubbed, removed)) = deny::scrub(&processed.block) {
+        processed.block = Cow::Owned(scrubbed);
+        sink.withheld.redacted += removed;
+      }
+      if let Some(threshold) = self.repeat_threshold {
+        let depth = sink.tracker.clone().advance(&processed.block);
+        let collapsed = sink
+          .repeats
+          .collapse(&processed.block, threshold, sink.sequence, depth);
+        if let Some((collapsed, count)) = collapsed {
+          processed.block = Cow::Owned(collapsed);
+          sink.withheld.collapsed += count;
+        }
+      }
+
+      let format = processed.format.as_ref().unwrap_or(&self.format);
+      sink.tracker.set_flow_width(self.flow_width);
+      sink.tracker.set_indent(self.indent.clone());
+      let value = State::render(
+        sink,
+        &processed.block,
+        format,
+        &self.timestamp_format,
+        &self.color,
+        self.strict_yaml,
+      );
+      match sink.write(value) {
+        Ok(written) => {
+          attempts.took(i);
+          if i == 0 {
+            handle = Some(written);
+          }
+        }
+        Err(err) => {
+          sink.withheld.failed += 1;
+          // Another member of the output's failover group gets the record instead
+          if !attempts.failed(i, err.to_string()) {
+            error = error.or(Some(err));
+          }
+        }
+      }
+    }
+    for mut change in self.failover.finish(attempts) {
+      if let Err(err) = self.write(&mut change) {
+        self.report(err);
+      }
+    }
+
+    if let (Some(watchdog), Some(_), false) = (&mut self.watchdog, &handle, is_meta) {
+      watchdog.wrote(block);
+    }
+    self.watch_depth();
+
+    if let Some(rejected) = rejection {
+      self.report(rejected);
+    }
+    // Writes made in the background fail after the call that queued them has returned
+    let background = self
+      .sinks
+      .iter()
+      .flat_map(|sink| sink.take_errors())
+      .collect::<Vec<_>>();
+    for failed in background {
+      self.report(failed);
+    }
+    match error {
+      Some(err) => Err(err),
+      None => Ok(handle),
+    }
+  }
+
+  fn split_block(&mut self, block: &mut Block) -> IoResult<()> {
+    let invalid = |msg: String| Err(IoError::new(ErrorKind::InvalidInput, msg));
+
     // Fail if message doesn't have a colon
     let msg = match &block.message {
-      MessageType::Value(YmlValue::String(msg)) => msg,
-      MessageType::Value(_) => panic!("Only string messages can be split"),
+      MessageType::Value(YmlValue::String(msg)) => msg.as_str(),
+      MessageType::Text(text) => text.as_str(),
+      MessageType::Value(_) => return invalid("Only string messages can be split".to_string()),
       MessageType::KeyValue(key, _) => {
-        panic!("Tried to re-split a logging block with key {:?}", key)
+        return invalid(format!(
+          "Tried to re-split a logging block with key {:?}",
+          key
+        ))
+      }
+      MessageType::None => return invalid("Cannot split message that wasn't set".to_string()),
+      MessageType::Unserializable { type_name, .. } => {
+        return invalid(format!(
+          "Cannot split an unserializable {} message",
+          type_name
+        ))
       }
-      MessageType::None => panic!("Cannot split message that wasn't set"),
     };
 
     let (key, value) = match msg.split_once(':') {
       Some(x) => x,
-      None => panic!("Could not find a ':' to split at\nmsg => {:?}", msg),
+      None => return invalid(format!("Could not find a ':' to split {:?} at", msg)),
     };
 
+    // The space after the colon is part of the YAML syntax, not the value
     block.message = MessageType::KeyValue(
       YmlValue::String(key.to_string()),
-      YmlValue::String(value.to_string()),
+      YmlValue::String(value.trim_start().to_string()),
     );
+    Ok(())
   }
 
-  /// Convert and write the block to the log
-  pub fn log(&mut self, block: &mut Block, actions: Option<&str>) {
+  /// The level blocks from the target need, or None if the target is turned off
+  fn threshold(&self, target: Option<&str>) -> Option<&Level> {
+    match self.filter.level_for(target) {
+      Some(threshold) => threshold.as_ref(),
+      None => Some(&self.log_level),
+    }
+  }
+
+  /// Whether any output would take the block, going by its level, target and tags
+  fn wanted(&self, block: &Block) -> bool {
+    let threshold = self.threshold(block.target());
+    self
+      .sinks
+      .iter()
+      .any(|sink| sink.pipeline.takes(block, threshold, &self.tags))
+  }
+
+  fn enabled(&self, level: &Level, target: Option<&str>) -> bool {
+    let threshold = self.threshold(target);
+    self
+      .sinks
+      .iter()
+      .any(|sink| match sink.pipeline.own_level() {
+        Some(own) => level >= own,
+        None => threshold.is_some_and(|threshold| level >= threshold),
+      })
+  }
+
+  /// Indent, dedent or reset every output
+  ///
+  /// A dedent with any output at the root is handled by the dedent policy.
+  fn move_depth(&mut self, action: char) -> IoResult<()> {
+    if action == '-' && self.sinks.iter().any(|sink| sink.tracker.at_root()) {
+      let error = IoError::new(
+        ErrorKind::InvalidInput,
+        "The actions dedented past the document root",
+      );
+      match self.dedent_policy {
+        DedentPolicy::Clamp => (),
+        DedentPolicy::Report => self.report(error),
+        DedentPolicy::Error => return Err(error),
+      }
+    }
+    let mut error = None;
+    for sink in self.sinks.iter_mut() {
+      match action {
+        '+' => sink.tracker.indent(),
+        '-' => sink.tracker.dedent(),
+        // Outputs that haven't written a YAML record yet mark the document with their first one
+        _ => match sink.format {
+          Some(OutputFormat::Yaml) => {
+            if let Some(marker) = sink.tracker.start_document() {
+              if let Err(err) = sink.write_raw(marker) {
+                error = error.or(Some(err));
+              }
+            }
+          }
+          _ => sink.tracker.reset(),
+        },
+      }
+    }
+    self.watch_depth();
+    match error {
+      Some(err) => Err(err),
+      None => Ok(()),
+    }
+  }
+
+  /// Open and close the watchdog's scopes to match the levels open in the first output
+  fn watch_depth(&mut self) {
+    if let Some(watchdog) = &mut self.watchdog {
+      let open = self
+        .sinks
+        .first()
+        .map_or(0, |sink| sink.tracker.levels().len().saturating_sub(1));
+      watchdog.open(open);
+    }
+  }
+
+  /// Warn about the innermost scope if it has stalled
+  fn check_watchdog(&mut self) {
+    self.watch_depth();
+    let warning = self.watchdog.as_mut().and_then(Watchdog::check);
+    if let Some(mut warning) = warning {
+      if let Err(err) = self.write(&mut warning) {
+        self.report(err);
+      }
+    }
+  }
+
+  fn log(&mut self, block: &mut Block, actions: Option<&str>) -> IoResult<Option<RecordHandle>> {
+    crate::context::add_thread_context(block);
+    self.run_actions(Some(block), actions)
+  }
+
+  /// Run the actions, leaving out everything touching the block if there is none
+  ///
+  /// Without a block this only moves the depth, for a block that was never built because it
+  /// wouldn't have been written anywhere.
+  fn run_actions(
+    &mut self,
+    mut block: Option<&mut Block>,
+    actions: Option<&str>,
+  ) -> IoResult<Option<RecordHandle>> {
     // println!("Building a block: {:#?}", block.message);
     // Skip working on
 
     // Make sure we know the logger is correct
-    assert!(self.logger.is_some(), "The logger wasn't initialized");
+    if self.sinks.is_empty() {
+      return Err(IoError::new(
+        ErrorKind::NotConnected,
+        "The logger wasn't initialized",
+      ));
+    }
+    if block.as_ref().is_some_and(|block| block.message.is_none()) {
+      return Err(IoError::new(
+        ErrorKind::InvalidInput,
+        "Logs must always have a base message set",
+      ));
+    }
 
     let mut has_printed = false;
+    let mut handle = None;
     let acts = actions.unwrap_or("");
 
     // println!("Processing actions: {:#?}", actions);
     for c in acts.chars() {
       match c {
         // Indentation options
-        '+' => self.tracker.indent(),
-        '-' => self.tracker.dedent(),
-        'r' => self.tracker.reset(),
+        '+' | '-' | 'r' => {
+          self.end_duplicates();
+          self.move_depth(c)?;
+        }
 
-        // TODO: Add this feature
         // Split the message at the first colon, making the left a key and the right a block
-        'k' => self.split_block(block),
+        'k' => {
+          if let Some(block) = block.as_mut() {
+            self.split_block(block)?
+          }
+        }
 
-        // Formatting options for the message
-        // 'b' => block.set_style(Style::Literal(Chomp::Clip)),
+        // Write the message as a literal or folded block, or double quoted. Blocks keep the
+        // trailing newlines the message has.
+        'b' | 'f' | 'q' => {
+          if let Some(block) = block.as_mut() {
+            let chomp = match &block.message {
+              MessageType::Text(text) => Chomp::matching(text.as_str()),
+              MessageType::Value(YmlValue::String(text))
+              | MessageType::KeyValue(_, YmlValue::String(text)) => Chomp::matching(text),
+              _ => Chomp::default(),
+            };
+            block.set_style(match c {
+              'b' => Style::Literal(chomp),
+              'f' => Style::Folded(chomp),
+              _ => Style::Double,
+            });
+          }
+        }
 
         // Write the block
         '_' => {
-          self.write(block);
+          if let Some(block) = block.as_mut() {
+            handle = self.write(block)?;
+          }
           has_printed = true;
         }
 
         // Change the log level of the message
-        'T' => block.set_log_level(Level::Trace),
-        'D' => block.set_log_level(Level::Debug),
-        'I' => block.set_log_level(Level::Info),
-        'W' => block.set_log_level(Level::Warn),
-        'E' => block.set_log_level(Level::Error),
+        'T' | 'D' | 'I' | 'W' | 'E' => {
+          if let (Some(block), Some(level)) = (block.as_mut(), Level::from_action(c)) {
+            block.set_log_level(level);
+          }
+        }
 
-        _ => panic!("invalid character {} found in logging statement", c),
+        _ => {
+          return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid character {:?} found in the logging actions", c),
+          ))
+        }
       }
     }
 
-    if !has_printed {
-      self.write(block);
+    match (has_printed, block) {
+      (false, Some(block)) => self.write(block),
+      _ => Ok(handle),
     }
   }
 }
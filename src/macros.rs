@@ -4,7 +4,7 @@
 #[macro_export]
 macro_rules! ymlogger {
   ($output:expr) => {{
-    let mut logger = YmLog::new();
+    let logger = YmLog::new();
     logger.set_output($output);
     logger
   };};
@@ -12,113 +12,246 @@ macro_rules! ymlogger {
 
 /// Format and append a message to the log
 ///
+/// The message is written to the [`global`](crate::global) logger. Anything serializable can be
+/// the message, such as `ymlog!("_" => &order)`, and structs and sequences are written under a
+/// `message` key so records can still be nested under them.
 ///
+/// A whole block can be built by giving its parts instead of a message. The parts are `msg`,
+/// `level` (a [`Level`](crate::Level) variant), `tags`, `fields`, `tag_type`, `source`, `target`
+/// and `timestamp`:
+///
+/// ```ignore
+/// ymlog!("+_" => {
+///   msg: "Query failed",
+///   level: Error,
+///   tags: ["db", "retry"],
+///   fields: { attempt: 3 },
+/// });
+/// ```
+///
+/// Fields can also follow the message after a `;`, the way `tracing` captures them. `%` writes a
+/// value with Display, `?` with Debug, and anything else must be serializable. A name alone
+/// captures the variable of that name:
+///
+/// ```ignore
+/// ymlog!("_" => "User logged in"; user_id = %id, attempt = ?attempt, retries);
+/// ```
+///
+/// The message, fields and format arguments are only evaluated if the level the actions give it is
+/// written somewhere, so `ymlog!("D" => "{}", expensive_dump())` costs nothing while the level is
+/// Warn. The parts of a whole block are always evaluated.
+///
+/// Levels below [`STATIC_MAX_LEVEL`](crate::STATIC_MAX_LEVEL), set by features such as
+/// `max_level_info` or `release_max_level_warn`, compile to nothing but their indent and dedent
+/// actions.
+///
+/// The macro never panics by default. A message that fails to serialize is written as a fallback
+/// record (see [`SerializePolicy`](crate::SerializePolicy)), and any other error is passed to the
+/// logger's [`ErrorHandler`](crate::ErrorHandler), which writes it to the log as a `!ymlog/error`
+/// record unless told otherwise.
 #[macro_export]
 macro_rules! ymlog {
 
   // --- Block Parameters
 
+  // The error is kept on the block, so the logger decides how to write it
   (@msg $block:ident $msg:expr) => { let _ = $block.set_message($msg); };
   (@msg $block:ident $($msg:expr),+) => { let _ = $block.set_message(format!($($msg),+)); };
 
-  (@block $block:ident $($key:expr => $($value:expr),+),+) => {
-    $(ymlog!(@$key, $block, $($values),+));+
+  // Fill in the block one `key: value` at a time
+  (@fill $block:ident) => {};
+  (@fill $block:ident , $($rest:tt)*) => { $crate::ymlog!(@fill $block $($rest)*) };
+  (@fill $block:ident msg: $msg:expr) => { $crate::ymlog!(@msg $block $msg); };
+  (@fill $block:ident msg: $msg:expr, $($rest:tt)*) => {
+    $crate::ymlog!(@msg $block $msg);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident level: $level:ident $($rest:tt)*) => {
+    $block.set_log_level($crate::Level::$level);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident tags: [$($tag:expr),* $(,)?] $($rest:tt)*) => {
+    let tags: ::std::vec::Vec<::std::string::String> = vec![$($tag.to_string()),*];
+    $block.set_tags(tags);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident fields: {$($key:ident: $value:expr),* $(,)?} $($rest:tt)*) => {
+    $(let _ = $block.add_field(stringify!($key), $value);)*
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident tag_type: $tag:expr) => { $block.set_tag_type($tag); };
+  (@fill $block:ident tag_type: $tag:expr, $($rest:tt)*) => {
+    $block.set_tag_type($tag);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident source: $source:expr) => { $block.set_source($source); };
+  (@fill $block:ident source: $source:expr, $($rest:tt)*) => {
+    $block.set_source($source);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident target: $target:expr) => { $block.set_target($target); };
+  (@fill $block:ident target: $target:expr, $($rest:tt)*) => {
+    $block.set_target($target);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident timestamp: $timestamp:expr) => { $block.set_timestamp($timestamp); };
+  (@fill $block:ident timestamp: $timestamp:expr, $($rest:tt)*) => {
+    $block.set_timestamp($timestamp);
+    $crate::ymlog!(@fill $block $($rest)*)
+  };
+  (@fill $block:ident $key:ident $($rest:tt)*) => {
+    compile_error!(concat!("Unknown ymlog! block key: ", stringify!($key)))
   };
 
   // --- Send the message
-  (@send $block:ident $acts:ident) => {
-    crate::LOG.lock().unwrap().log(&mut $block, $acts);
+  (@send $block:ident $acts:ident) => {{
+    if !$crate::__private::compiled_in($acts, *$block.log_level()) {
+      $crate::ymlog!(@skip $acts)
+    } else {
+      if $block.target().is_none() {
+        $block.set_target(module_path!());
+      }
+
+      let logger = $crate::global();
+      if let Err(err) = logger.log(&mut $block, $acts) {
+        logger.report(err);
+      }
+    }
+  }};
+
+  // --- Fields captured after the message, the way tracing does
+  (@fields $block:ident) => {};
+  (@fields $block:ident , $($rest:tt)*) => { $crate::ymlog!(@fields $block $($rest)*) };
+  (@fields $block:ident $key:ident = % $value:expr $(, $($rest:tt)*)?) => {
+    let _ = $block.add_field(stringify!($key), ::std::format!("{}", $value));
+    $crate::ymlog!(@fields $block $($($rest)*)?)
+  };
+  (@fields $block:ident $key:ident = ? $value:expr $(, $($rest:tt)*)?) => {
+    let _ = $block.add_field(stringify!($key), ::std::format!("{:?}", $value));
+    $crate::ymlog!(@fields $block $($($rest)*)?)
+  };
+  (@fields $block:ident $key:ident = $value:expr $(, $($rest:tt)*)?) => {
+    let _ = $block.add_field(stringify!($key), $value);
+    $crate::ymlog!(@fields $block $($($rest)*)?)
+  };
+  (@fields $block:ident % $key:ident $(, $($rest:tt)*)?) => {
+    $crate::ymlog!(@fields $block $key = % $key $(, $($rest)*)?)
+  };
+  (@fields $block:ident ? $key:ident $(, $($rest:tt)*)?) => {
+    $crate::ymlog!(@fields $block $key = ? $key $(, $($rest)*)?)
+  };
+  (@fields $block:ident $key:ident $(, $($rest:tt)*)?) => {
+    $crate::ymlog!(@fields $block $key = $key $(, $($rest)*)?)
   };
 
-  // --- Entry points
+  // --- Only evaluate the message and fields if the block could be written. The fields come first,
+  // as they can't be parsed as an expression
+  (@lazy $acts:ident, [$($fields:tt)*], $($msg:expr),+) => {{
+    if !$crate::__private::compiled_in($acts, $crate::Level::Info) {
+      $crate::ymlog!(@skip $acts)
+    } else {
+      let logger = $crate::global();
+      let built = logger.log_lazy($acts, module_path!(), || {
+        let mut block = $crate::Block::new();
+        $crate::ymlog!(@msg block $($msg),+);
+        $crate::ymlog!(@fields block $($fields)*);
+        block
+      });
+      if let Err(err) = built {
+        logger.report(err);
+      }
+    }
+  }};
+  (@lazy $acts:ident, $($msg:expr),+) => {{
+    if !$crate::__private::compiled_in($acts, $crate::Level::Info) {
+      $crate::ymlog!(@skip $acts)
+    } else {
+      let logger = $crate::global();
+      let built = logger.log_lazy($acts, module_path!(), || {
+        let mut block = $crate::Block::new();
+        $crate::ymlog!(@msg block $($msg),+);
+        block
+      });
+      if let Err(err) = built {
+        logger.report(err);
+      }
+    }
+  }};
 
-  // A bare message string
-  ( $($msg:expr),+ ) => {{
-    let mut block = ymlog::Block::new();
-    ymlog!(@msg block $($msg),+);
-    ymlog!(@send block None)
+  // --- Keep the depth of a block below the compiled in level, without touching the logger
+  // unless it has to
+  (@skip $acts:ident) => {{
+    if $crate::__private::moves_depth($acts) {
+      let logger = $crate::global();
+      if let Err(err) = logger.skip($acts) {
+        logger.report(err);
+      }
+    }
   }};
 
-  // Block Only
-  ( $params:block ) => {{
-    let mut block = ymlog::Block::new();
-    ymlog!(@block $block $params)
-    ymlog!(@send block None)
+  // --- Entry points
+
+  // A full block definition. These come first, since a block can't be parsed as an expression
+  ( {$($block_def:tt)*} ) => {{
+    let acts: ::std::option::Option<&str> = None;
+    let mut block = $crate::Block::new();
+    $crate::ymlog!(@fill block $($block_def)*);
+    $crate::ymlog!(@send block acts)
   }};
 
   // Actions with a full Block
-  ( $actions:expr => {$block_def:tt} ) => {{
+  ( $actions:expr => {$($block_def:tt)*} ) => {{
     let acts = Some($actions);
-    let mut block = ymlog::Block::new();
-    ymlog!(@params block $block_def);
-    ymlog!(@send block acts)
+    let mut block = $crate::Block::new();
+    $crate::ymlog!(@fill block $($block_def)*);
+    $crate::ymlog!(@send block acts)
   }};
 
-  // With Actions around a basic expression
-  ( $actions:expr => $($msg:expr),+ ) => {{
-    let acts = Some($actions);
-    let mut block = ymlog::Block::new();
-    ymlog!(@msg block $($msg),+);
-    ymlog!(@send block acts)
+  // A message with fields captured after it
+  ( $($msg:expr),+ ; $($fields:tt)* ) => {{
+    let acts: ::std::option::Option<&str> = None;
+    $crate::ymlog!(@lazy acts, [$($fields)*], $($msg),+)
   }};
 
-}
+  ( $actions:expr => $($msg:expr),+ ; $($fields:tt)* ) => {{
+    let acts = Some($actions);
+    $crate::ymlog!(@lazy acts, [$($fields)*], $($msg),+)
+  }};
 
-#[macro_export]
-macro_rules! ymlog_old {
-  // ---  Main processors
   // A bare message string
   ( $($msg:expr),+ ) => {{
-    let mut block = crate::Block::new();
-    ymlog!(@msg block $($msg),+);
-    // let _ = crate::LOG.lock().unwrap().print(None, &mut block);
+    let acts: ::std::option::Option<&str> = None;
+    $crate::ymlog!(@lazy acts, $($msg),+)
   }};
 
-  // A block definition with no preceding options
-  ( $block:tt ) => {
-    let mut block = crate::logger::Block::new();
-    ymlog!(@params block $block);
-    let _ = crate::LOG.lock().unwrap().print(None, &mut block);
-  };
-
-  // Starts with a string of action tokens
-  ($tokens:tt => $($rest:expr),+) => {
-    let mut block = crate::logger::Block::new();
-    ymlog!(@msg block $($rest),+);
-    let _ = crate::LOG.lock().unwrap().print(Some($tokens), &mut block).map_err(|x| panic!("Got an error printing the log: {}", x));
-  };
-
-  // Starts with a string of action tokens
-  ($tokens:tt => $params:tt) => {
-    let mut block = crate::logger::Block::new();
-    ymlog!(@params block $params);
-    let _ = crate::LOG.lock().unwrap().print(Some($tokens), &mut block);
-  };
-
-
-
-  // ---  Parameters
-
-  // Get params from a block
-  (@params $block:ident $($type:literal => $params:tt),+) => {
-
-
-
-  (@send $block:ident) => {
-    println!("The Block: {:#+}", $block);
-  }
-  };
-
-  // Set the params from the block
-  (@param $block:ident stamp) => {};
-  (@param $block:ident level => $level:ident) => {};
-  (@param $block:ident msg => $($msg:expr),+) => {
+  // With Actions around a basic expression
+  ( $actions:expr => $($msg:expr),+ ) => {{
+    let acts = Some($actions);
+    $crate::ymlog!(@lazy acts, $($msg),+)
+  }};
 
-  };
+}
 
-  // Format a string message
-  (@msg $block:ident $msg:expr) => { $block.set_message($msg); };
-  (@msg $block:ident $($msg:expr),+) => { $block.set_message(format!($($msg),+)); };
+/// Append an error to the log, with each of its sources nested under the one it caused
+///
+/// Anything that derefs to a [`std::error::Error`] works, such as `ymlog_err!(&err)` or
+/// `ymlog_err!("r" => &*boxed)`. The record is written at Error level the same way as
+/// [`Block::set_error`](crate::Block::set_error) builds it.
+#[macro_export]
+macro_rules! ymlog_err {
+  (@send $acts:ident, $error:expr) => {{
+    let mut block = $crate::Block::new();
+    block.set_error($error);
+    $crate::ymlog!(@send block $acts)
+  }};
 
+  ( $error:expr ) => {{
+    let acts: ::std::option::Option<&str> = None;
+    $crate::ymlog_err!(@send acts, $error)
+  }};
 
+  ( $actions:expr => $error:expr ) => {{
+    let acts = Some($actions);
+    $crate::ymlog_err!(@send acts, $error)
+  }};
 }
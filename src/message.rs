@@ -1,40 +1,89 @@
 //! Building blocks of the log
 
-use chrono::{DateTime, Utc};
-use serde::{ser::SerializeStruct, Serialize, Serializer};
-use serde_yaml::{Error as YmlError, Value as YmlValue};
+use std::borrow::Cow;
+use std::sync::Arc;
 
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml::{Error as YmlError, Mapping, Value as YmlValue};
+
+use crate::formatter::{human_bytes, human_duration};
+use crate::intern;
 use crate::prelude::*;
+use crate::Timestamp;
+
+/// The key of the child holding a block's backtrace
+const BACKTRACE_KEY: &str = "backtrace";
 
 /// A block is a message formatting container
 ///
 /// Serialization is customized based on the blocks filled in
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Block {
   /// The local time the message was generated
-  pub(crate) timestamp: Option<DateTime<Utc>>,
+  pub(crate) timestamp: Option<Timestamp>,
 
   /// The level of the message
   pub(crate) log_level: Option<Level>,
 
+  /// The module the block was logged from, used to pick its level filter
+  pub(crate) target: Option<String>,
+
+  /// Where the record came from, such as the file name or host it was merged from
+  pub(crate) source: Option<String>,
+
   /// Searchable strings in the output log
-  pub(crate) tags: Option<Vec<String>>,
+  pub(crate) tags: Option<Vec<Tag>>,
+
+  /// Structured data written alongside the message, in the order it was added
+  pub(crate) fields: Option<Mapping>,
+
+  /// An application defined YAML tag written on the record, such as `!deploy`
+  pub(crate) tag_type: Option<String>,
 
   /// The content of the message
   pub(crate) message: MessageType,
 
-  /// The style that the message should be written in
-  pub(crate) _style: Option<YamlFormatter>,
+  /// The style a string message is written in by YAML outputs
+  pub(crate) style: Option<Style>,
 
   /// Any indented child blocks
   ///
   /// This is really only used for deserializing. A user is never allowed to directly add children
   /// because keeping the indentation level straight becomes too heavy.
   pub(crate) children: Option<Vec<Block>>,
+
+  /// The human versions of any Loggable message or fields, written in their place to terminals
+  pub(crate) human: Option<Box<Human>>,
+
+  /// The YAML anchor the message is written with, or refers back to
+  pub(crate) anchor: Option<Anchor>,
+
+  /// Written as `#` lines above the record in YAML outputs
+  pub(crate) comment: Option<String>,
+}
+
+/// How a block's message uses a YAML anchor
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Anchor {
+  /// Write the message with the anchor
+  Define(String),
+
+  /// Refer to the anchor if it was already written in the document, or write it if not
+  Reuse(String),
+}
+
+/// The readable versions of the Loggable values on a block
+#[derive(Clone, Default)]
+pub(crate) struct Human {
+  message: Option<String>,
+  fields: Mapping,
 }
 
 impl Serialize for Block {
   /// Customize the format of the output based on the given fields
+  ///
+  /// A block with only a message is written as the message itself, and anything else as a record
+  /// mapping using the same keys as JSON lines, so it can be deserialized again.
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
     S: Serializer,
@@ -48,36 +97,41 @@ impl Serialize for Block {
     // If any of these are included, we loop. Otherwise, make a simple string of the level and message
     let count = vec![
       self.timestamp.is_some(),
+      self.source.is_some(),
+      self.log_level.is_some(),
       self.tags.is_some(),
+      self.fields.is_some(),
+      self.tag_type.is_some(),
       self.children.is_some(),
-      self.log_level.is_some(),
     ]
     .into_iter()
     .filter(|x| x.to_owned())
     .count();
     match count == 0 {
-      true => self.message.unwrap().serialize(serializer),
+      true => self.message.serialize(serializer),
       false => {
-        let mut state = serializer.serialize_struct("Block", count)?;
-        if self.timestamp.is_some() {
-          state.serialize_field("timestamp", &self.timestamp.unwrap())?
+        let mut state = serializer.serialize_struct("Block", count + 1)?;
+        if let Some(timestamp) = &self.timestamp {
+          state.serialize_field("timestamp", timestamp)?
+        };
+        if let Some(source) = &self.source {
+          state.serialize_field("source", source)?
+        };
+        if let Some(level) = &self.log_level {
+          state.serialize_field("log_level", level.name())?
         };
-        if self.log_level.is_some() {
-          state.serialize_field(
-            "log_level",
-            match &self.log_level {
-              Some(Level::Trace) => "Trace",
-              Some(Level::Debug) => "Debug",
-              Some(Level::Info) => "Info",
-              Some(Level::Warn) => "Warn",
-              Some(Level::Error) => "Error",
-              None => unreachable!("Already checked for None"),
-            },
-          )?
+        if let Some(tags) = &self.tags {
+          state.serialize_field("tags", tags)?
         };
-        state.serialize_field("message", self.message.unwrap())?;
-        if self.children.is_some() {
-          state.serialize_field("children", &self.timestamp.unwrap())?
+        if let Some(fields) = &self.fields {
+          state.serialize_field("fields", fields)?
+        };
+        if let Some(tag_type) = &self.tag_type {
+          state.serialize_field("tag_type", tag_type)?
+        };
+        state.serialize_field("message", &self.message)?;
+        if let Some(children) = &self.children {
+          state.serialize_field("children", children)?
         };
         state.end()
       }
@@ -85,21 +139,255 @@ impl Serialize for Block {
   }
 }
 
+impl<'de> Deserialize<'de> for Block {
+  /// Read a block in any of the shapes the log is written in, as the reader does
+  fn deserialize<D>(deserializer: D) -> Result<Block, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let value = YmlValue::deserialize(deserializer)?;
+    crate::reader::from_yaml(value).map_err(serde::de::Error::custom)
+  }
+}
+
 impl Block {
   pub fn new() -> Block {
     Default::default()
   }
 
   /// Change the message to the Display message of the object passed in
-  pub fn set_message(&mut self, message: impl Serialize) -> Result<(), YmlError> {
-    // println!("Setting the message");
-    self.message = MessageType::Value(serde_yaml::to_value(message)?);
-    Ok(())
+  ///
+  /// If the message can't be serialized, the error is kept on the block as well as returned, so
+  /// the logger can write a fallback record in its place. Serialize doesn't imply Debug, so the
+  /// fallback names the type rather than showing its value.
+  pub fn set_message<T: Serialize>(&mut self, message: T) -> Result<(), YmlError> {
+    match serde_yaml::to_value(message) {
+      Ok(value) => {
+        self.message = MessageType::Value(value);
+        Ok(())
+      }
+      Err(err) => {
+        self.message = MessageType::Unserializable {
+          type_name: std::any::type_name::<T>(),
+          error: err.to_string(),
+        };
+        Err(err)
+      }
+    }
+  }
+
+  /// Make the message a string without copying it, such as a static string or a shared buffer
+  ///
+  /// This is cheaper than [`Block::set_message`] for strings, which converts the message to a YAML
+  /// value up front. [`Block::message`] returns None for these, so use [`Block::text`] to read it.
+  pub fn set_text(&mut self, text: impl Into<Text>) {
+    self.message = MessageType::Text(text.into());
+  }
+
+  /// Get the message if it is a string, however it was set
+  pub fn text(&self) -> Option<&str> {
+    match &self.message {
+      MessageType::Text(text) => Some(text.as_str()),
+      MessageType::Value(YmlValue::String(text)) => Some(text),
+      _ => None,
+    }
   }
 
   /// Set the tags of the current block
+  ///
+  /// Tags used before share their text with the blocks they were used on, so tagging every record
+  /// the same doesn't copy the tags for each one.
   pub fn set_tags(&mut self, tags: Vec<impl std::fmt::Display>) {
-    self.tags = Some(tags.iter().map(|tag| tag.to_string()).collect());
+    self.tags = Some(tags.iter().map(Tag::new).collect());
+  }
+
+  /// The tags of the block
+  pub fn tags(&self) -> &[Tag] {
+    self.tags.as_deref().unwrap_or(&[])
+  }
+
+  /// Get the message, unless it is unset, a key/value pair or text set with [`Block::set_text`]
+  pub fn message(&self) -> Option<&YmlValue> {
+    match &self.message {
+      MessageType::Value(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  /// The message as text, as it would be written on its own, with pairs as `key: value`
+  pub(crate) fn message_text(&self) -> Cow<'_, str> {
+    let yaml = |value: &YmlValue| {
+      serde_yaml::to_string(value)
+        .map(|text| text.trim_end().to_string())
+        .unwrap_or_default()
+    };
+    match &self.message {
+      MessageType::Text(text) => text.as_str().into(),
+      MessageType::Value(YmlValue::String(text)) => text.as_str().into(),
+      MessageType::Value(value) => yaml(value).into(),
+      MessageType::KeyValue(key, value) => format!("{}: {}", yaml(key), yaml(value)).into(),
+      _ => "".into(),
+    }
+  }
+
+  /// Make the message a key/value pair, written as a single entry mapping
+  pub fn set_key_value<K: Serialize, V: Serialize>(
+    &mut self,
+    key: K,
+    value: V,
+  ) -> Result<(), YmlError> {
+    self.message = MessageType::KeyValue(serde_yaml::to_value(key)?, serde_yaml::to_value(value)?);
+    Ok(())
+  }
+
+  /// Get the key and value of a key/value message
+  pub fn key_value(&self) -> Option<(&YmlValue, &YmlValue)> {
+    match &self.message {
+      MessageType::KeyValue(key, value) => Some((key, value)),
+      _ => None,
+    }
+  }
+
+  /// Add a named value to the record's fields, replacing any earlier value with the same name
+  ///
+  /// Fields are kept in the order they were first added. A value that can't be serialized is not
+  /// added, and the error is returned.
+  pub fn add_field<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), YmlError> {
+    let value = serde_yaml::to_value(value)?;
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), value);
+    Ok(())
+  }
+
+  /// Get the value of a field
+  pub fn field(&self, key: &str) -> Option<&YmlValue> {
+    self.fields.as_ref().and_then(|fields| fields.get(key))
+  }
+
+  /// Set the message to the machine version of the value, keeping the human one for terminals
+  pub fn set_loggable(&mut self, value: &impl Loggable) {
+    self.message = MessageType::Value(value.machine());
+    self.human.get_or_insert_with(Default::default).message = Some(value.human());
+  }
+
+  /// Add a field with the machine version of the value, keeping the human one for terminals
+  pub fn add_loggable(&mut self, key: &str, value: &impl Loggable) {
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), value.machine());
+    self
+      .human
+      .get_or_insert_with(Default::default)
+      .fields
+      .insert(key.into(), value.human().into());
+  }
+
+  /// Replace the Loggable values on the block and its children with their human versions
+  pub fn humanize(&mut self) {
+    if let Some(human) = self.human.take() {
+      if let Some(message) = human.message {
+        self.message = MessageType::Value(message.into());
+      }
+      let fields = self.fields.get_or_insert_with(Mapping::new);
+      for (key, value) in human.fields {
+        fields.insert(key, value);
+      }
+    }
+    self.children.iter_mut().flatten().for_each(Block::humanize);
+  }
+
+  /// Write every `\r\n` and lone `\r` in the strings of the block and its children as `\n`
+  pub(crate) fn normalize_line_endings(&mut self) {
+    match &mut self.message {
+      MessageType::Text(text) if text.as_str().contains('\r') => {
+        *text = Text::Owned(normalize_str(text.as_str()))
+      }
+      MessageType::Value(value) => normalize_value(value),
+      MessageType::KeyValue(key, value) => {
+        normalize_value(key);
+        normalize_value(value);
+      }
+      _ => (),
+    }
+    if let Some(fields) = &mut self.fields {
+      fields.values_mut().for_each(normalize_value);
+    }
+    self
+      .children
+      .iter_mut()
+      .flatten()
+      .for_each(Block::normalize_line_endings);
+  }
+
+  /// Check if the block or its children have human versions of any values
+  pub(crate) fn has_human(&self) -> bool {
+    self.human.is_some() || self.children().iter().any(Block::has_human)
+  }
+
+  /// Add a duration field, written as a readable `human` string and the raw milliseconds in `ms`
+  ///
+  /// ```yaml
+  /// elapsed:
+  ///   human: 1.2 s
+  ///   ms: 1234.5
+  /// ```
+  pub fn field_duration(&mut self, key: &str, duration: std::time::Duration) {
+    let mut value = Mapping::new();
+    value.insert("human".into(), human_duration(duration).into());
+    value.insert("ms".into(), (duration.as_secs_f64() * 1000.0).into());
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), YmlValue::Mapping(value));
+  }
+
+  /// Add a size field, written as a readable `human` string and the raw count in `bytes`
+  pub fn field_bytes(&mut self, key: &str, bytes: u64) {
+    let mut value = Mapping::new();
+    value.insert("human".into(), human_bytes(bytes).into());
+    value.insert("bytes".into(), bytes.into());
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), YmlValue::Mapping(value));
+  }
+
+  /// Add a duration field written as one readable scalar, such as `took: 1.2 s`
+  ///
+  /// Use [`Block::field_duration`] to keep the raw milliseconds alongside it.
+  pub fn set_field_duration(&mut self, key: &str, duration: std::time::Duration) {
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), human_duration(duration).into());
+  }
+
+  /// Add a size field written as one readable scalar, such as `size: 4.5 MiB`
+  pub fn set_field_bytes(&mut self, key: &str, bytes: u64) {
+    self
+      .fields
+      .get_or_insert_with(Mapping::new)
+      .insert(key.into(), human_bytes(bytes).into());
+  }
+
+  /// Write the record with a YAML tag (`!deploy`, `!retry`) so tools can tell its type
+  ///
+  /// The leading '!' is optional, and an empty name removes the tag. Compressed messages keep the
+  /// compression tag instead, as YAML only allows one per node.
+  pub fn set_tag_type(&mut self, tag: &str) {
+    let tag = tag.trim_start_matches('!');
+    self.tag_type = match tag.is_empty() {
+      true => None,
+      false => Some(tag.to_string()),
+    };
+  }
+
+  /// Get the YAML tag the record is written with
+  pub fn tag_type(&self) -> Option<&str> {
+    self.tag_type.as_deref()
   }
 
   /// Add child blocks that have been aggregated in code
@@ -107,25 +395,390 @@ impl Block {
     self.children = Some(children);
   }
 
-  /// Updates the level. If left unset, it defaults to debug.
+  /// The child blocks, such as the ones read back in from a log
+  pub fn children(&self) -> &[Block] {
+    self.children.as_deref().unwrap_or(&[])
+  }
+
+  /// Updates the level. If left unset, it defaults to info.
   pub fn set_log_level(&mut self, level: Level) {
     self.log_level = Some(level);
   }
 
+  /// The level the block is logged at
+  pub fn log_level(&self) -> &Level {
+    self.log_level.as_ref().unwrap_or(&Level::Info)
+  }
+
   /// Set the timestamp to the current time
   pub fn stamp(&mut self) {
-    self.timestamp = Some(Utc::now());
+    self.timestamp = Some(Timestamp::now());
+  }
+
+  /// Add a `backtrace` child with the stack of the caller, written as a literal block
+  pub fn with_backtrace(&mut self) {
+    let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+    let mut child = Block::new();
+    child.message = MessageType::KeyValue(
+      BACKTRACE_KEY.into(),
+      YmlValue::String(backtrace.trim_end().to_string()),
+    );
+    self.children.get_or_insert_with(Vec::new).push(child);
+  }
+
+  /// Make the block an Error record for the error, with each of its sources nested a level deeper
+  /// than the one it caused
+  pub fn set_error(&mut self, error: &dyn std::error::Error) {
+    self.set_text(error.to_string());
+    self.set_log_level(Level::Error);
+
+    let mut sources = Vec::new();
+    let mut source = error.source();
+    while let Some(cause) = source {
+      sources.push(cause);
+      source = cause.source();
+    }
+    let chain = sources.into_iter().rev().fold(None, |inner, cause| {
+      let mut block = Block::new();
+      block.set_text(cause.to_string());
+      block.children = inner.map(|inner| vec![inner]);
+      Some(block)
+    });
+    if let Some(chain) = chain {
+      self.children.get_or_insert_with(Vec::new).push(chain);
+    }
+  }
+
+  /// Check if a backtrace was already added with [`Block::with_backtrace`]
+  pub(crate) fn has_backtrace(&self) -> bool {
+    self.children().iter().any(|child| {
+      matches!(&child.message, MessageType::KeyValue(key, _) if key.as_str() == Some(BACKTRACE_KEY))
+    })
+  }
+
+  /// Write the text as `#` comment lines above the record, which YAML readers skip
+  ///
+  /// Comments are only written to YAML outputs, and a logger's
+  /// [`commenter`](crate::YmLog::set_commenter) adds its own lines before these.
+  pub fn set_comment(&mut self, text: impl std::fmt::Display) {
+    self.comment = Some(text.to_string());
+  }
+
+  /// The comment written above the record
+  pub fn comment(&self) -> Option<&str> {
+    self.comment.as_deref()
+  }
+
+  /// Write a string message, or the value of a key/value message, in the style in YAML outputs
+  ///
+  /// A block style that can't hold the string, such as one with control characters or leading
+  /// whitespace, or a Plain or Single one that would change it, is double quoted instead. Guess leaves
+  /// the style to the output, which is the default.
+  pub fn set_style(&mut self, style: Style) {
+    self.style = match style {
+      Style::Guess => None,
+      style => Some(style),
+    };
+  }
+
+  /// The style the message is written in
+  pub fn style(&self) -> Option<&Style> {
+    self.style.as_ref()
+  }
+
+  /// Write the message with a YAML anchor, so later records in the document can refer back to it
+  ///
+  /// Only letters, digits, `-` and `_` are kept from the name. Compressed messages already have a
+  /// tag on their node, so they are written without one.
+  pub fn set_anchor(&mut self, name: &str) {
+    self.anchor = Some(Anchor::Define(anchor_name(name)));
+  }
+
+  /// Write the message as an alias of the anchor, if it was already written in the document
+  ///
+  /// Otherwise the message is written with the anchor, so logging the same block each time writes
+  /// it in full once per document. The message is still needed for that and for JSON outputs.
+  pub fn set_alias(&mut self, name: &str) {
+    self.anchor = Some(Anchor::Reuse(anchor_name(name)));
+  }
+
+  /// The name of the anchor set with [`Block::set_anchor`] or [`Block::set_alias`]
+  pub fn anchor(&self) -> Option<&str> {
+    match &self.anchor {
+      Some(Anchor::Define(name)) | Some(Anchor::Reuse(name)) => Some(name),
+      None => None,
+    }
+  }
+
+  /// Set the time the message was generated
+  pub fn set_timestamp(&mut self, timestamp: impl Into<Timestamp>) {
+    self.timestamp = Some(timestamp.into());
+  }
+
+  /// Get the time the message was generated, if it was stamped
+  pub fn timestamp(&self) -> Option<&Timestamp> {
+    self.timestamp.as_ref()
+  }
+
+  /// Set the module path the block was logged from, which the `ymlog!` macro does automatically
+  pub fn set_target(&mut self, target: &str) {
+    self.target = Some(target.to_string());
+  }
+
+  /// Get the module path the block was logged from
+  pub fn target(&self) -> Option<&str> {
+    self.target.as_deref()
+  }
+
+  /// Record where the block came from, such as a file name or host
+  pub fn set_source(&mut self, source: impl std::fmt::Display) {
+    self.source = Some(source.to_string());
+  }
+
+  /// Get where the block came from
+  pub fn source(&self) -> Option<&str> {
+    self.source.as_deref()
+  }
+
+  /// Check if the record must be written as a mapping, because it has fields besides the message
+  ///
+  /// Levels other than Info, the level of blocks without one, are written so they can be read
+  /// back. A mapping or sequence message can't be made a key for the records nested under it, so
+  /// it is written under a `message` key as well, and its children under `children`.
+  pub(crate) fn has_metadata(&self) -> bool {
+    self.timestamp.is_some()
+      || self.source.is_some()
+      || self.written_level().is_some()
+      || self.tags.as_ref().is_some_and(|tags| !tags.is_empty())
+      || self.fields.is_some()
+      || matches!(&self.message, MessageType::Value(value) if is_structured(value))
+  }
+
+  /// The level a YAML record is written with, which is left out when it is Info
+  pub(crate) fn written_level(&self) -> Option<&Level> {
+    self
+      .log_level
+      .as_ref()
+      .filter(|level| **level != Level::Info)
+  }
+
+  /// Check if the blocks would be written as the same record, apart from their timestamps
+  pub(crate) fn same_entry(&self, other: &Block) -> bool {
+    let same_message = match (&self.message, &other.message) {
+      (MessageType::Value(a), MessageType::Value(b)) => a == b,
+      (MessageType::KeyValue(a, x), MessageType::KeyValue(b, y)) => a == b && x == y,
+      _ => self.text().is_some() && self.text() == other.text(),
+    };
+    same_message
+      && self.log_level == other.log_level
+      && self.source == other.source
+      && self.tags == other.tags
+      && self.fields == other.fields
+      && self.tag_type == other.tag_type
+      && self.children.is_none()
+      && other.children.is_none()
+  }
+}
+
+fn is_structured(value: &YmlValue) -> bool {
+  match value {
+    YmlValue::Mapping(_) | YmlValue::Sequence(_) => true,
+    YmlValue::Tagged(tagged) => is_structured(&tagged.value),
+    _ => false,
+  }
+}
+
+/// Keep the characters that are safe in a YAML anchor name
+fn anchor_name(name: &str) -> String {
+  name
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+    .collect()
+}
+
+/// A string message that is only copied when it is written
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Text {
+  Static(&'static str),
+  Owned(String),
+
+  /// A buffer shared with the caller, so cloning the block doesn't copy it
+  Shared(Arc<str>),
+}
+
+impl Text {
+  pub fn as_str(&self) -> &str {
+    match self {
+      Text::Static(text) => text,
+      Text::Owned(text) => text,
+      Text::Shared(text) => text,
+    }
+  }
+
+  /// Copy the text into a YAML string
+  pub(crate) fn to_value(&self) -> YmlValue {
+    YmlValue::String(self.as_str().to_string())
+  }
+}
+
+impl From<&'static str> for Text {
+  fn from(text: &'static str) -> Text {
+    Text::Static(text)
+  }
+}
+
+impl From<String> for Text {
+  fn from(text: String) -> Text {
+    Text::Owned(text)
+  }
+}
+
+impl From<Cow<'static, str>> for Text {
+  fn from(text: Cow<'static, str>) -> Text {
+    match text {
+      Cow::Borrowed(text) => Text::Static(text),
+      Cow::Owned(text) => Text::Owned(text),
+    }
+  }
+}
+
+impl From<Arc<str>> for Text {
+  fn from(text: Arc<str>) -> Text {
+    Text::Shared(text)
+  }
+}
+
+/// A tag on a block, sharing its text with the other blocks tagged the same
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Tag(Arc<str>);
+
+impl Tag {
+  pub fn new(tag: impl std::fmt::Display) -> Tag {
+    Tag(intern::intern_display(tag))
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl std::ops::Deref for Tag {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl AsRef<str> for Tag {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl std::borrow::Borrow<str> for Tag {
+  fn borrow(&self) -> &str {
+    &self.0
+  }
+}
+
+impl std::fmt::Display for Tag {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl PartialEq<str> for Tag {
+  fn eq(&self, other: &str) -> bool {
+    &*self.0 == other
+  }
+}
+
+impl PartialEq<&str> for Tag {
+  fn eq(&self, other: &&str) -> bool {
+    &*self.0 == *other
+  }
+}
+
+impl PartialEq<String> for Tag {
+  fn eq(&self, other: &String) -> bool {
+    *self.0 == **other
+  }
+}
+
+impl Serialize for Tag {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.0)
   }
 }
 
 /// Encapsulate a message with special formatting options
+#[derive(Clone, Default)]
 pub enum MessageType {
+  #[default]
   None,
   Value(YmlValue),
+
+  /// A string that hasn't been converted to a YAML value yet
+  Text(Text),
   KeyValue(YmlValue, YmlValue),
+
+  /// Setting the message failed, so this holds what is needed to describe the failure
+  Unserializable {
+    type_name: &'static str,
+    error: String,
+  },
+}
+
+impl Serialize for MessageType {
+  /// Values are written as they are and pairs as a mapping of one key
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    match self {
+      MessageType::None => Err(serde::ser::Error::custom(
+        "Tried to serialize an empty ymlog message",
+      )),
+      MessageType::Value(value) => value.serialize(serializer),
+      MessageType::Text(text) => serializer.serialize_str(text.as_str()),
+      MessageType::KeyValue(key, value) => {
+        let mut pair = Mapping::new();
+        pair.insert(key.clone(), value.clone());
+        pair.serialize(serializer)
+      }
+      MessageType::Unserializable { type_name, error } => {
+        MessageType::fallback(type_name, error).serialize(serializer)
+      }
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+  /// A mapping of one key is read as a key/value pair, and anything else as a value
+  fn deserialize<D>(deserializer: D) -> Result<MessageType, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    Ok(match YmlValue::deserialize(deserializer)? {
+      YmlValue::Mapping(pair) if pair.len() == 1 => match pair.into_iter().next() {
+        Some((key, value)) => MessageType::KeyValue(key, value),
+        None => MessageType::None,
+      },
+      value => MessageType::Value(value),
+    })
+  }
 }
 
 impl MessageType {
+  /// Make the message written in place of one that couldn't be serialized
+  pub(crate) fn fallback(type_name: &str, error: &str) -> MessageType {
+    MessageType::Value(YmlValue::String(format!(
+      "<ymlog could not serialize the {} message: {}>",
+      type_name, error
+    )))
+  }
+
   pub fn is_none(&self) -> bool {
     matches!(self, MessageType::None)
   }
@@ -135,10 +788,14 @@ impl MessageType {
     match self {
       MessageType::Value(value) => value,
       MessageType::None => panic!("Tried to unwrap an empty message"),
+      MessageType::Text(text) => panic!("Tried to unwrap a text message: {:?}", text),
       MessageType::KeyValue(key, value) => panic!(
         "Tried to unwrap a key/value message: ({:?}, {:?})",
         key, value
       ),
+      MessageType::Unserializable { type_name, .. } => {
+        panic!("Tried to unwrap an unserializable {} message", type_name)
+      }
     }
   }
 
@@ -148,12 +805,24 @@ impl MessageType {
       MessageType::KeyValue(key, value) => (key, value),
       MessageType::None => panic!("Tried to unwrap an empty message"),
       MessageType::Value(value) => panic!("Tried to unwrap a simple value message: {:?}", value),
+      MessageType::Text(text) => panic!("Tried to unwrap a text message: {:?}", text),
+      MessageType::Unserializable { type_name, .. } => {
+        panic!("Tried to unwrap an unserializable {} message", type_name)
+      }
     }
   }
 }
 
-impl Default for MessageType {
-  fn default() -> Self {
-    MessageType::None
+fn normalize_value(value: &mut YmlValue) {
+  match value {
+    YmlValue::String(text) if text.contains('\r') => *text = normalize_str(text),
+    YmlValue::Sequence(items) => items.iter_mut().for_each(normalize_value),
+    YmlValue::Mapping(mapping) => mapping.values_mut().for_each(normalize_value),
+    YmlValue::Tagged(tagged) => normalize_value(&mut tagged.value),
+    _ => (),
   }
 }
+
+fn normalize_str(text: &str) -> String {
+  text.replace("\r\n", "\n").replace('\r', "\n")
+}
@@ -0,0 +1,241 @@
+//! Counters, gauges and histograms written as one `metrics` record
+//!
+//! Metrics are registered by name and updated through cheap handles, then written on demand or on
+//! an interval as a key/value record holding every metric in name order:
+//!
+//! ```yaml
+//! metrics:
+//!   files_read: 120
+//!   queue_depth: 4.0
+//!   row_ms:
+//!     count: 3
+//!     sum: 18.0
+//!     min: 2.0
+//!     max: 12.0
+//!     mean: 6.0
+//! ```
+//!
+//! Batch tools can log the registry as the last record of each run to end it with a `metrics:`
+//! section.
+
+use std::collections::BTreeMap;
+use std::io::{Result as IoResult, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::message::MessageType;
+use crate::prelude::*;
+use crate::reporter::{log_global, Reporter};
+
+/// The key the metrics are written under
+pub const METRICS_KEY: &str = "metrics";
+
+/// Metrics registered by name. Clones share the same metrics.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  metrics: Arc<Mutex<BTreeMap<String, Metric>>>,
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+  Counter(Counter),
+  Gauge(Gauge),
+  Histogram(Histogram),
+}
+
+impl Metrics {
+  pub fn new() -> Metrics {
+    Metrics::default()
+  }
+
+  /// Get the counter with the name, registering it if this is the first time it was asked for
+  ///
+  /// A metric of another kind already registered under the name is replaced.
+  pub fn counter(&self, name: impl std::fmt::Display) -> Counter {
+    let mut metrics = self.lock();
+    let metric = metrics
+      .entry(name.to_string())
+      .or_insert_with(|| Metric::Counter(Counter::default()));
+    match metric {
+      Metric::Counter(counter) => counter.clone(),
+      _ => {
+        let counter = Counter::default();
+        *metric = Metric::Counter(counter.clone());
+        counter
+      }
+    }
+  }
+
+  /// Get the gauge with the name, registering it if this is the first time it was asked for
+  pub fn gauge(&self, name: impl std::fmt::Display) -> Gauge {
+    let mut metrics = self.lock();
+    let metric = metrics
+      .entry(name.to_string())
+      .or_insert_with(|| Metric::Gauge(Gauge::default()));
+    match metric {
+      Metric::Gauge(gauge) => gauge.clone(),
+      _ => {
+        let gauge = Gauge::default();
+        *metric = Metric::Gauge(gauge.clone());
+        gauge
+      }
+    }
+  }
+
+  /// Get the histogram with the name, registering it if this is the first time it was asked for
+  pub fn histogram(&self, name: impl std::fmt::Display) -> Histogram {
+    let mut metrics = self.lock();
+    let metric = metrics
+      .entry(name.to_string())
+      .or_insert_with(|| Metric::Histogram(Histogram::default()));
+    match metric {
+      Metric::Histogram(histogram) => histogram.clone(),
+      _ => {
+        let histogram = Histogram::default();
+        *metric = Metric::Histogram(histogram.clone());
+        histogram
+      }
+    }
+  }
+
+  /// The current values of every metric, in name order
+  pub fn to_value(&self) -> YmlValue {
+    let metrics = self.lock();
+    let mut values = Mapping::new();
+    for (name, metric) in metrics.iter() {
+      let value = match metric {
+        Metric::Counter(counter) => counter.get().into(),
+        Metric::Gauge(gauge) => gauge.get().into(),
+        Metric::Histogram(histogram) => histogram.to_value(),
+      };
+      values.insert(name.as_str().into(), value);
+    }
+    YmlValue::Mapping(values)
+  }
+
+  /// Make a `metrics` key/value record of the current values
+  pub fn to_block(&self) -> Block {
+    let mut block = Block::new();
+    block.message = MessageType::KeyValue(METRICS_KEY.into(), self.to_value());
+    block
+  }
+
+  /// Start writing the metrics to the global log on an interval
+  pub fn report_every(&self, interval: Duration) -> Reporter {
+    let metrics = self.clone();
+    Reporter::spawn("ymlog-metrics", interval, move || {
+      log_global(metrics.to_block())
+    })
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Metric>> {
+    self.metrics.lock().unwrap_or_else(|err| err.into_inner())
+  }
+}
+
+/// A count that only goes up
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+  pub fn inc(&self) {
+    self.add(1)
+  }
+
+  pub fn add(&self, count: u64) {
+    self.0.fetch_add(count, Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> u64 {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// The last value set, such as the depth of a queue
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+  pub fn set(&self, value: f64) {
+    self.0.store(value.to_bits(), Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> f64 {
+    f64::from_bits(self.0.load(Ordering::Relaxed))
+  }
+}
+
+/// The count, sum, smallest and largest of the values recorded, such as request latencies
+#[derive(Debug, Clone, Default)]
+pub struct Histogram(Arc<Mutex<Summary>>);
+
+#[derive(Debug, Default)]
+struct Summary {
+  count: u64,
+  sum: f64,
+  min: f64,
+  max: f64,
+}
+
+impl Histogram {
+  pub fn record(&self, value: f64) {
+    let mut summary = self.0.lock().unwrap_or_else(|err| err.into_inner());
+    summary.min = match summary.count {
+      0 => value,
+      _ => summary.min.min(value),
+    };
+    summary.max = match summary.count {
+      0 => value,
+      _ => summary.max.max(value),
+    };
+    summary.count += 1;
+    summary.sum += value;
+  }
+
+  /// The count and sum of the values, and with any recorded, the min, max and mean
+  pub fn to_value(&self) -> YmlValue {
+    let summary = self.0.lock().unwrap_or_else(|err| err.into_inner());
+    let mut value = Mapping::new();
+    value.insert("count".into(), summary.count.into());
+    value.insert("sum".into(), summary.sum.into());
+    if summary.count > 0 {
+      value.insert("min".into(), summary.min.into());
+      value.insert("max".into(), summary.max.into());
+      value.insert("mean".into(), (summary.sum / summary.count as f64).into());
+    }
+    YmlValue::Mapping(value)
+  }
+}
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Write the current values of the metrics as a key/value record
+  pub fn log_metrics(
+    &self,
+    metrics: &Metrics,
+    actions: Option<&str>,
+  ) -> IoResult<Option<RecordHandle>> {
+    self.log(&mut metrics.to_block(), actions)
+  }
+}
+
+/// The metrics shared by the whole process
+pub fn global() -> &'static Metrics {
+  static GLOBAL: OnceLock<Metrics> = OnceLock::new();
+  GLOBAL.get_or_init(Metrics::new)
+}
+
+/// Write the global metrics to the global log at its current depth
+pub fn log_metrics() {
+  log_global(global().to_block())
+}
+
+/// Start writing the global metrics to the global log on an interval
+pub fn report_every(interval: Duration) -> Reporter {
+  global().report_every(interval)
+}
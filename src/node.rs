@@ -0,0 +1,734 @@
+//! Writing records as YAML, node by node
+//!
+//! Records used to go through serde_yaml and have the parts it can't write, such as anchors and
+//! styled messages, swapped in afterwards. The writer here lays out values the way serde_yaml
+//! does, which is libyaml's layout, so records read the same, and writes the rest directly.
+
+use serde_yaml::value::Tag;
+use serde_yaml::Value as YmlValue;
+
+use crate::formatter::{self, Style};
+use crate::scan;
+
+/// A part of a record, with what YAML can say about it that a serde_yaml value can't
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+  /// A value, written the way serde_yaml writes it
+  Value(YmlValue),
+  Mapping(Vec<(Node, Node)>),
+  Sequence(Vec<Node>),
+  Tagged(Tag, Box<Node>),
+  /// The node, with an anchor the records after it can refer back to
+  Anchor(String, Box<Node>),
+  /// A reference to a node anchored earlier in the document
+  Alias(String),
+  /// A string written in the style, which has already been checked to hold it
+  Styled(String, Style),
+}
+
+impl Node {
+  /// Check if the node is a string written as a block, which has to be the last thing on its line
+  ///
+  /// serde_yaml double quotes anything with a `\r`, which then fits on one line.
+  pub(crate) fn is_block(&self) -> bool {
+    match self {
+      Node::Value(value) => is_block_value(value),
+      Node::Tagged(_, node) | Node::Anchor(_, node) => node.is_block(),
+      Node::Styled(_, style) => matches!(style, Style::Literal(_) | Style::Folded(_)),
+      _ => false,
+    }
+  }
+}
+
+/// Check if the value is a string written as a block
+pub(crate) fn is_block_value(value: &YmlValue) -> bool {
+  match value {
+    YmlValue::String(text) => is_block_text(text),
+    YmlValue::Tagged(tagged) => is_block_value(&tagged.value),
+    _ => false,
+  }
+}
+
+/// Check if the string is written as a block, rather than quoted on one line
+pub(crate) fn is_block_text(text: &str) -> bool {
+  scan::has_newline(text) && !text.contains('\r') && !escapes(text)
+}
+
+/// A node or value, so the two can be written by the same code
+#[derive(Clone, Copy)]
+enum Item<'a> {
+  Node(&'a Node),
+  Value(&'a YmlValue),
+}
+
+/// Where a node is being written
+#[derive(Clone, Copy, Default)]
+struct Context {
+  /// A key or value of a block mapping
+  mapping: bool,
+
+  /// A key that fits on the line before its `:`
+  simple_key: bool,
+
+  /// A mapping value or sequence item, which the flow width applies to
+  nested: bool,
+}
+
+/// What is written ahead of the node itself
+#[derive(Default)]
+struct Properties<'a> {
+  anchor: Option<&'a str>,
+  tag: Option<String>,
+}
+
+/// Writes a single node as YAML, following libyaml's emitter
+pub(crate) struct Writer<'a> {
+  out: String,
+
+  /// Written at the start of every line that isn't empty
+  base: &'a str,
+
+  /// Spaces added for each level of nesting
+  step: usize,
+
+  /// Nested mappings and sequences that fit in this width are written in flow style
+  flow_width: Option<usize>,
+
+  /// The column the last character was written at, after the base
+  column: usize,
+
+  /// If the base has been written on the current line
+  started: bool,
+
+  /// If the last character written was whitespace, so no space is needed before the next token
+  whitespace: bool,
+
+  /// If only indentation and indicators have been written on the current line
+  indention: bool,
+
+  /// The column the current level of nesting starts at, if there is one
+  indent: Option<usize>,
+}
+
+impl<'a> Writer<'a> {
+  pub(crate) fn new(base: &'a str, step: usize, flow_width: Option<usize>) -> Writer<'a> {
+    Writer {
+      out: String::new(),
+      base,
+      step,
+      flow_width,
+      column: 0,
+      started: false,
+      whitespace: true,
+      indention: true,
+      indent: None,
+    }
+  }
+
+  /// Write the node as a document, ending with a line break
+  pub(crate) fn document(mut self, node: &Node) -> String {
+    self.item(Item::Node(node), Context::default(), Properties::default());
+    self.write_indent();
+    self.out
+  }
+
+  fn item<'n>(&mut self, item: Item<'n>, context: Context, mut properties: Properties<'n>) {
+    match item {
+      Item::Node(Node::Value(value)) => self.value(value, context, properties),
+      Item::Node(Node::Mapping(pairs)) => {
+        let pairs = pairs
+          .iter()
+          .map(|(key, value)| (Item::Node(key), Item::Node(value)));
+        self.mapping(pairs.collect(), properties)
+      }
+      Item::Node(Node::Sequence(items)) => {
+        self.sequence(items.iter().map(Item::Node).collect(), context, properties)
+      }
+      Item::Node(Node::Tagged(tag, node)) => {
+        properties.tag.get_or_insert_with(|| tag_text(tag));
+        self.item(Item::Node(node), context.unnested(), properties)
+      }
+      Item::Node(Node::Anchor(name, node)) => {
+        properties.anchor.get_or_insert(name);
+        self.item(Item::Node(node), context.unnested(), properties)
+      }
+      // An alias is the node it refers to, so it can't have its own properties
+      Item::Node(Node::Alias(name)) => {
+        self.indicator("*", true, false, false);
+        self.text(name);
+        if context.simple_key {
+          self.put(" ");
+        }
+      }
+      Item::Node(Node::Styled(text, style)) => {
+        self.properties(&properties);
+        let column = self.base.len() + self.indent.map_or(self.step, |indent| indent + self.step);
+        match style {
+          Style::Literal(_) | Style::Folded(_) => {
+            self.raw(&formatter::write_styled(text, style, column))
+          }
+          _ => self.raw(&formatter::write_styled(text, style, 0)),
+        }
+      }
+      Item::Value(value) => self.value(value, context, properties),
+    }
+  }
+
+  fn value(&mut self, value: &YmlValue, context: Context, mut properties: Properties<'_>) {
+    if let Some(flowed) = self.flowed(value, context) {
+      self.properties(&properties);
+      return self.raw(&flowed);
+    }
+    match value {
+      YmlValue::Null => self.scalar("null", Request::Plain, context, properties),
+      YmlValue::Bool(true) => self.scalar("true", Request::Plain, context, properties),
+      YmlValue::Bool(false) => self.scalar("false", Request::Plain, context, properties),
+      YmlValue::Number(number) => {
+        self.scalar(&number.to_string(), Request::Plain, context, properties)
+      }
+      // libyaml writes the separators as they are, but readers following YAML 1.1 break lines at
+      // them, so the string wouldn't read back or might not parse at all
+      YmlValue::String(text) if escapes(text) => {
+        self.properties(&properties);
+        self.raw(&Style::double_quote(text))
+      }
+      YmlValue::String(text) => self.scalar(text, Request::of(text), context, properties),
+      YmlValue::Sequence(seq) => {
+        self.sequence(seq.iter().map(Item::Value).collect(), context, properties)
+      }
+      YmlValue::Mapping(mapping) => {
+        let pairs = mapping
+          .iter()
+          .map(|(key, value)| (Item::Value(key), Item::Value(value)));
+        self.mapping(pairs.collect(), properties)
+      }
+      YmlValue::Tagged(tagged) => {
+        properties.tag.get_or_insert_with(|| tag_text(&tagged.tag));
+        self.value(&tagged.value, context.unnested(), properties)
+      }
+    }
+  }
+
+  /// The value in flow style, if it is nested and fits in the flow width
+  fn flowed(&self, value: &YmlValue, context: Context) -> Option<String> {
+    match context.nested {
+      true => formatter::flow_within(value, self.flow_width?),
+      false => None,
+    }
+  }
+
+  fn sequence(&mut self, items: Vec<Item<'_>>, context: Context, properties: Properties<'_>) {
+    self.properties(&properties);
+    if items.is_empty() {
+      self.indicator("[", true, true, false);
+      return self.indicator("]", false, false, false);
+    }
+
+    // A sequence under a key starts at the key's column, unless it starts on a `?` line
+    let outer = self.indent;
+    self.increase_indent(false, context.mapping && !self.indention);
+    for item in items {
+      self.write_indent();
+      self.indicator("-", true, false, true);
+      self.item(item, Context::nested(false), Properties::default());
+    }
+    self.indent = outer;
+  }
+
+  fn mapping(&mut self, pairs: Vec<(Item<'_>, Item<'_>)>, properties: Properties<'_>) {
+    self.properties(&properties);
+    if pairs.is_empty() {
+      self.indicator("{", true, true, false);
+      return self.indicator("}", false, false, false);
+    }
+
+    let outer = self.indent;
+    self.increase_indent(false, false);
+    for (key, value) in pairs {
+      self.write_indent();
+      match is_simple_key(key) {
+        true => {
+          let context = Context {
+            mapping: true,
+            simple_key: true,
+            nested: false,
+          };
+          self.item(key, context, Properties::default());
+          self.indicator(":", false, false, false);
+        }
+        // Keys that take more than a line are written after a `?`, with the value on a line after
+        false => {
+          self.indicator("?", true, false, true);
+          self.item(key, Context::nested(true).unnested(), Properties::default());
+          self.write_indent();
+          self.indicator(":", true, false, true);
+        }
+      }
+      self.item(value, Context::nested(true), Properties::default());
+    }
+    self.indent = outer;
+  }
+
+  fn scalar(&mut self, text: &str, request: Request, context: Context, properties: Properties<'_>) {
+    let analysis = analyze(text);
+    let mut request = match context.simple_key && analysis.multiline {
+      true => Request::Double,
+      false => request,
+    };
+    if request == Request::Plain
+      && (!analysis.block_plain || (text.is_empty() && context.simple_key))
+    {
+      request = Request::Single;
+    }
+    if request == Request::Single && !analysis.single {
+      request = Request::Double;
+    }
+    if request == Request::Literal && (!analysis.block || context.simple_key) {
+      request = Request::Double;
+    }
+
+    self.properties(&properties);
+    let outer = self.indent;
+    self.increase_indent(true, false);
+    match request {
+      Request::Plain => {
+        if !self.whitespace && !text.is_empty() {
+          self.put(" ");
+        }
+        self.text(text);
+      }
+      Request::Single => {
+        self.indicator("'", true, false, false);
+        self.put(&text.replace('\'', "''"));
+        self.indicator("'", false, false, false);
+      }
+      Request::Double => {
+        self.indicator("\"", true, false, false);
+        self.put(&libyaml_escape(text));
+        self.indicator("\"", false, false, false);
+      }
+      Request::Literal => self.literal(text),
+    }
+    self.indent = outer;
+  }
+
+  /// Write the text as a literal block, with its header giving the indent and chomp it needs
+  fn literal(&mut self, text: &str) {
+    self.indicator("|", true, false, false);
+    if text.starts_with([' ', '\n']) {
+      self.indicator(&self.step.to_string(), false, false, false);
+    }
+    let mut last = text.chars().rev();
+    let chomp = match (last.next(), last.next()) {
+      (Some('\n'), None) | (Some('\n'), Some('\n')) => "+",
+      (Some('\n'), Some(_)) => "",
+      _ => "-",
+    };
+    if !chomp.is_empty() {
+      self.indicator(chomp, false, false, false);
+    }
+
+    self.line_break();
+    self.indention = true;
+    self.whitespace = true;
+    for (i, line) in text.split('\n').enumerate() {
+      if i > 0 {
+        self.line_break();
+        self.indention = true;
+      }
+      if !line.is_empty() {
+        self.write_indent();
+        self.put(line);
+        self.indention = false;
+      }
+    }
+  }
+
+  fn properties(&mut self, properties: &Properties<'_>) {
+    if let Some(anchor) = properties.anchor {
+      self.indicator("&", true, false, false);
+      self.text(anchor);
+    }
+    if let Some(tag) = &properties.tag {
+      match tag.strip_prefix('!').filter(|suffix| !suffix.is_empty()) {
+        Some(suffix) => {
+          if !self.whitespace {
+            self.put(" ");
+          }
+          self.put("!");
+          self.text(&escape_tag(suffix));
+        }
+        None => {
+          self.indicator("!<", true, false, false);
+          self.put(&escape_tag(tag));
+          self.indicator(">", false, false, false);
+        }
+      }
+    }
+  }
+
+  /// Write text that was already laid out, continuing the line
+  fn raw(&mut self, text: &str) {
+    if !self.whitespace {
+      self.put(" ");
+    }
+    self.text(text);
+  }
+
+  /// Write a token that isn't an indicator, such as a plain scalar, anchor name or tag
+  fn text(&mut self, text: &str) {
+    self.put(text);
+    self.whitespace = false;
+    self.indention = false;
+  }
+
+  fn increase_indent(&mut self, flow: bool, indentless: bool) {
+    self.indent = match self.indent {
+      None if flow => Some(self.step),
+      None => Some(0),
+      Some(indent) if !indentless => Some(indent + self.step),
+      Some(indent) => Some(indent),
+    };
+  }
+
+  /// Start a new line at the current indent, unless the line has nothing but indentation yet
+  fn write_indent(&mut self) {
+    let indent = self.indent.unwrap_or(0);
+    if !self.indention || self.column > indent || (self.column == indent && !self.whitespace) {
+      self.line_break();
+    }
+    if self.column < indent {
+      self.put(&" ".repeat(indent - self.column));
+    }
+    self.whitespace = true;
+    self.indention = true;
+  }
+
+  fn indicator(
+    &mut self,
+    indicator: &str,
+    need_whitespace: bool,
+    whitespace: bool,
+    indention: bool,
+  ) {
+    if need_whitespace && !self.whitespace {
+      self.put(" ");
+    }
+    self.put(indicator);
+    self.whitespace = whitespace;
+    self.indention = self.indention && indention;
+  }
+
+  fn put(&mut self, text: &str) {
+    if !self.started {
+      self.out.push_str(self.base);
+      self.started = true;
+    }
+    self.out.push_str(text);
+    // Only text laid out elsewhere, such as a styled block, holds line breaks
+    match text.rfind('\n') {
+      Some(newline) => self.column = text[newline + 1..].chars().count(),
+      None => self.column += text.chars().count(),
+    }
+  }
+
+  fn line_break(&mut self) {
+    self.out.push('\n');
+    self.column = 0;
+    self.started = false;
+  }
+}
+
+impl Context {
+  /// A mapping value or sequence item
+  fn nested(mapping: bool) -> Context {
+    Context {
+      mapping,
+      simple_key: false,
+      nested: true,
+    }
+  }
+
+  /// The context of a node under a tag or anchor, which the flow width doesn't apply to
+  fn unnested(self) -> Context {
+    Context {
+      nested: false,
+      ..self
+    }
+  }
+}
+
+/// The style serde_yaml asks libyaml for, which writes another if the scalar can't be held in it
+#[derive(Clone, Copy, PartialEq)]
+enum Request {
+  Plain,
+  Single,
+  Double,
+  Literal,
+}
+
+impl Request {
+  /// Strings with line breaks are literal blocks, and anything that would be read back as another
+  /// type is quoted
+  fn of(text: &str) -> Request {
+    match text.contains('\n') {
+      true => Request::Literal,
+      false if reads_as_other_type(text) => Request::Single,
+      false => Request::Plain,
+    }
+  }
+}
+
+/// Which styles a scalar can be written in
+struct Analysis {
+  multiline: bool,
+  block_plain: bool,
+  single: bool,
+  block: bool,
+}
+
+/// Check which styles can hold the text, as libyaml does
+fn analyze(text: &str) -> Analysis {
+  if text.is_empty() {
+    return Analysis {
+      multiline: false,
+      block_plain: true,
+      single: true,
+      block: false,
+    };
+  }
+
+  let chars = text.chars().collect::<Vec<_>>();
+  let mut indicators = text.starts_with("---") || text.starts_with("...");
+  let (mut line_breaks, mut special) = (false, false);
+  let (mut leading_space, mut leading_break) = (false, false);
+  let (mut trailing_space, mut trailing_break) = (false, false);
+  let (mut break_space, mut space_break) = (false, false);
+  let (mut previous_space, mut previous_break) = (false, false);
+  let mut preceded_by_whitespace = true;
+  for (i, &c) in chars.iter().enumerate() {
+    let (first, last) = (i == 0, i == chars.len() - 1);
+    let followed_by_whitespace = chars.get(i + 1).is_none_or(|&next| is_blankz(next));
+    match first {
+      true => {
+        indicators |= "#,[]{}&*!|>'\"%@`".contains(c)
+          || (matches!(c, '?' | ':' | '-') && followed_by_whitespace)
+      }
+      false => {
+        indicators |= (c == ':' && followed_by_whitespace) || (c == '#' && preceded_by_whitespace)
+      }
+    }
+    special |= !is_printable(c);
+    line_breaks |= is_break(c);
+    if c == ' ' {
+      leading_space |= first;
+      trailing_space |= last;
+      break_space |= previous_break;
+      (previous_space, previous_break) = (true, false);
+    } else if is_break(c) {
+      leading_break |= first;
+      trailing_break |= last;
+      space_break |= previous_space;
+      (previous_space, previous_break) = (false, true);
+    } else {
+      (previous_space, previous_break) = (false, false);
+    }
+    preceded_by_whitespace = is_blankz(c);
+  }
+
+  let spaced = leading_space || leading_break || trailing_space || trailing_break;
+  Analysis {
+    multiline: line_breaks,
+    block_plain: !(spaced || break_space || space_break || special || line_breaks || indicators),
+    single: !(break_space || space_break || special),
+    block: !(trailing_space || space_break || special),
+  }
+}
+
+/// Check if a key can be written on the line before its `:`
+fn is_simple_key(key: Item<'_>) -> bool {
+  let mut length = 0;
+  let mut key = key;
+  loop {
+    key = match key {
+      Item::Node(Node::Tagged(tag, node)) => {
+        length += tag_text(tag).len();
+        Item::Node(node)
+      }
+      Item::Node(Node::Anchor(name, node)) => {
+        length += name.len();
+        Item::Node(node)
+      }
+      Item::Value(YmlValue::Tagged(tagged)) | Item::Node(Node::Value(YmlValue::Tagged(tagged))) => {
+        length += tag_text(&tagged.tag).len();
+        Item::Value(&tagged.value)
+      }
+      Item::Node(Node::Value(value)) => Item::Value(value),
+      _ => break,
+    };
+  }
+  let length = length
+    + match key {
+      Item::Node(Node::Alias(name)) => name.len(),
+      Item::Node(Node::Styled(text, style)) => match style {
+        Style::Literal(_) | Style::Folded(_) => return false,
+        _ => text.len(),
+      },
+      Item::Node(Node::Mapping(pairs)) if pairs.is_empty() => 0,
+      Item::Node(Node::Sequence(items)) if items.is_empty() => 0,
+      Item::Value(YmlValue::Mapping(mapping)) if mapping.is_empty() => 0,
+      Item::Value(YmlValue::Sequence(seq)) if seq.is_empty() => 0,
+      Item::Value(YmlValue::String(text)) if escapes(text) => text.len(),
+      Item::Value(YmlValue::String(text)) if analyze(text).multiline => return false,
+      Item::Value(YmlValue::String(text)) => text.len(),
+      Item::Value(YmlValue::Number(number)) => number.to_string().len(),
+      Item::Value(YmlValue::Bool(true)) => 4,
+      Item::Value(YmlValue::Bool(false)) => 5,
+      Item::Value(YmlValue::Null) => 4,
+      _ => return false,
+    };
+  length <= 128
+}
+
+/// Check if the string holds a line or paragraph separator, so it is always double quoted
+fn escapes(text: &str) -> bool {
+  text.contains(['\u{2028}', '\u{2029}'])
+}
+
+/// Check if serde_yaml would read the string back as something else without quotes
+fn reads_as_other_type(text: &str) -> bool {
+  let is_keyword = matches!(
+    text,
+    "" | "~" | "null" | "Null" | "NULL" | "true" | "True" | "TRUE" | "false" | "False" | "FALSE"
+  );
+  is_keyword || is_int(text) || digits_but_not_number(text) || is_float(text)
+}
+
+/// Leading zeros followed by digits are a string in YAML 1.2, but serde_yaml quotes them anyway
+fn digits_but_not_number(text: &str) -> bool {
+  let text = text.strip_prefix(['-', '+']).unwrap_or(text);
+  text.len() > 1 && text.starts_with('0') && text[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_int(text: &str) -> bool {
+  let unpositive = text.strip_prefix('+').unwrap_or(text);
+  for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+    if let Some(rest) = unpositive.strip_prefix(prefix) {
+      if rest.starts_with(['+', '-']) {
+        return false;
+      }
+      if u128::from_str_radix(rest, radix).is_ok() {
+        return true;
+      }
+    }
+  }
+  if !unpositive.starts_with(['+', '-'])
+    && !digits_but_not_number(text)
+    && unpositive.parse::<u128>().is_ok()
+  {
+    return true;
+  }
+
+  for (prefix, radix) in [("-0x", 16), ("-0o", 8), ("-0b", 2)] {
+    if let Some(rest) = text.strip_prefix(prefix) {
+      if i128::from_str_radix(&format!("-{}", rest), radix).is_ok() {
+        return true;
+      }
+    }
+  }
+  !digits_but_not_number(text) && text.parse::<i128>().is_ok()
+}
+
+fn is_float(text: &str) -> bool {
+  let unpositive = match text.strip_prefix('+') {
+    Some(unpositive) if unpositive.starts_with(['+', '-']) => return false,
+    Some(unpositive) => unpositive,
+    None => text,
+  };
+  matches!(unpositive, ".inf" | ".Inf" | ".INF")
+    || matches!(text, "-.inf" | "-.Inf" | "-.INF" | ".nan" | ".NaN" | ".NAN")
+    || unpositive.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Characters libyaml writes as they are
+fn is_printable(c: char) -> bool {
+  matches!(c, '\n' | ' '..='~' | '\u{a0}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..)
+    && c != '\u{feff}'
+}
+
+fn is_break(c: char) -> bool {
+  matches!(c, '\r' | '\n' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+fn is_blankz(c: char) -> bool {
+  matches!(c, ' ' | '\t' | '\0') || is_break(c)
+}
+
+/// Escape the text for double quotes as libyaml does
+fn libyaml_escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    if is_printable(c) && !is_break(c) && c != '"' && c != '\\' {
+      escaped.push(c);
+      continue;
+    }
+    let short = match c {
+      '\0' => '0',
+      '\u{7}' => 'a',
+      '\u{8}' => 'b',
+      '\t' => 't',
+      '\n' => 'n',
+      '\u{b}' => 'v',
+      '\u{c}' => 'f',
+      '\r' => 'r',
+      '\u{1b}' => 'e',
+      '"' => '"',
+      '\\' => '\\',
+      '\u{85}' => 'N',
+      '\u{2028}' => 'L',
+      '\u{2029}' => 'P',
+      c => {
+        escaped.push_str(&match c as u32 {
+          code @ 0..=0xff => format!("\\x{:02X}", code),
+          code @ 0..=0xffff => format!("\\u{:04X}", code),
+          code => format!("\\U{:08X}", code),
+        });
+        continue;
+      }
+    };
+    escaped.push('\\');
+    escaped.push(short);
+  }
+  escaped
+}
+
+/// The tag as serde_yaml gives it to libyaml, which reads it as a C string that ends at any NUL
+///
+/// A tag already starting with `!!` after its own `!` keeps only the ones it was given.
+fn tag_text(tag: &Tag) -> String {
+  let tag = tag.to_string();
+  let tag = match tag[1..].starts_with('!') {
+    true => tag[1..].to_string(),
+    false => tag,
+  };
+  match tag.split_once('\0') {
+    Some((tag, _)) => tag.to_string(),
+    None => tag,
+  }
+}
+
+/// Escape the characters a tag can't hold as `%` and their UTF-8 bytes in hex
+fn escape_tag(tag: &str) -> String {
+  let mut escaped = String::with_capacity(tag.len());
+  for c in tag.chars() {
+    match c.is_ascii_alphanumeric() || ";/?:@&=+$,_.~*'()[]-".contains(c) {
+      true => escaped.push(c),
+      false => {
+        for byte in c.to_string().bytes() {
+          escaped.push_str(&format!("%{:02X}", byte));
+        }
+      }
+    }
+  }
+  escaped
+}
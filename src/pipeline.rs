@@ -0,0 +1,246 @@
+//! The stages a block goes through on its way to an output
+//!
+//! Each output has its own pipeline, run in order for every block logged. Stages can rewrite the
+//! block, drop it, or change how it is written, so one output can get a redacted JSON stream while
+//! another gets everything as YAML.
+
+use std::borrow::Cow;
+
+use crate::filter::TagFilter;
+use crate::prelude::*;
+
+/// A function that changes the block before it is written
+pub type Transform = Box<dyn Fn(&mut Block) + Send + Sync>;
+
+/// A function deciding if the block should be written
+pub type Predicate = Box<dyn Fn(&Block) -> bool + Send + Sync>;
+
+/// One step of a pipeline
+pub enum Stage {
+  /// Add information to the block, such as tags
+  Enrich(Transform),
+
+  /// Remove or mask anything that shouldn't reach the output
+  Redact(Transform),
+
+  /// Only blocks the predicate returns true for continue through the pipeline
+  Filter(Predicate),
+
+  /// Only every nth block reaching this stage continues through the pipeline
+  Sample(Sampler),
+
+  /// Only blocks at or above the level continue. This replaces the logger's level for the output.
+  Level(Level),
+
+  /// Only blocks the tag filter allows continue. This replaces the logger's tag filter for the
+  /// output.
+  Tags(TagFilter),
+
+  /// Write the block with the given syntax. The last format in the pipeline wins.
+  Format(OutputFormat),
+}
+
+/// Keeps a count of the blocks seen, letting one through every `every` blocks
+pub struct Sampler {
+  every: u32,
+  seen: u32,
+}
+
+impl Sampler {
+  pub fn new(every: u32) -> Sampler {
+    Sampler {
+      every: every.max(1),
+      seen: 0,
+    }
+  }
+
+  /// Count the block and check if it should be kept
+  fn keep(&mut self) -> bool {
+    let keep = self.seen.is_multiple_of(self.every);
+    self.seen = self.seen.wrapping_add(1);
+    keep
+  }
+}
+
+/// Why a pipeline didn't let a block through
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Dropped {
+  /// The block was below the level of the logger or the pipeline
+  Level,
+
+  /// The block's tags weren't allowed by the logger or the pipeline
+  Tags,
+
+  /// A filter stage rejected the block
+  Filtered,
+
+  /// A sample stage skipped the block
+  Sampled,
+}
+
+/// The block after running through a pipeline, ready to be written
+pub(crate) struct Processed<'a> {
+  pub block: Cow<'a, Block>,
+  pub format: Option<OutputFormat>,
+}
+
+/// An ordered list of stages
+#[derive(Default)]
+pub struct Pipeline {
+  stages: Vec<Stage>,
+
+  /// If a level stage was added, it replaces the logger's level
+  sets_level: bool,
+
+  /// If a tags stage was added, it replaces the logger's tag filter
+  sets_tags: bool,
+
+  /// The failover group the output belongs to, if any
+  failover: Option<String>,
+}
+
+impl Pipeline {
+  pub fn new() -> Pipeline {
+    Default::default()
+  }
+
+  /// Append a stage to the end of the pipeline
+  pub fn stage(mut self, stage: Stage) -> Pipeline {
+    self.sets_level |= matches!(stage, Stage::Level(_));
+    self.sets_tags |= matches!(stage, Stage::Tags(_));
+    self.stages.push(stage);
+    self
+  }
+
+  pub fn enrich(self, transform: impl Fn(&mut Block) + Send + Sync + 'static) -> Pipeline {
+    self.stage(Stage::Enrich(Box::new(transform)))
+  }
+
+  pub fn redact(self, transform: impl Fn(&mut Block) + Send + Sync + 'static) -> Pipeline {
+    self.stage(Stage::Redact(Box::new(transform)))
+  }
+
+  pub fn filter(self, predicate: impl Fn(&Block) -> bool + Send + Sync + 'static) -> Pipeline {
+    self.stage(Stage::Filter(Box::new(predicate)))
+  }
+
+  /// Drop blocks below the level
+  ///
+  /// Without one of these, the pipeline uses the threshold set on the logger before any stage.
+  pub fn level(self, level: Level) -> Pipeline {
+    self.stage(Stage::Level(level))
+  }
+
+  /// Only write blocks with one of the included tags, unless it is empty, and none of the excluded
+  ///
+  /// Use this to route tags to their own output, such as `audit` records to a separate file.
+  /// Without one of these, the pipeline uses the tag filter set on the logger.
+  pub fn tags<I, E>(self, include: I, exclude: E) -> Pipeline
+  where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+    E: IntoIterator,
+    E::Item: std::fmt::Display,
+  {
+    self.stage(Stage::Tags(TagFilter::new(include, exclude)))
+  }
+
+  pub fn sample(self, every: u32) -> Pipeline {
+    self.stage(Stage::Sample(Sampler::new(every)))
+  }
+
+  pub fn format(self, format: OutputFormat) -> Pipeline {
+    self.stage(Stage::Format(format))
+  }
+
+  /// Make the output a member of a failover group, after the members added before it
+  ///
+  /// Each record goes to the first member of the group that is healthy, rather than to all of
+  /// them, so a local file can back up a network collector. See [`YmLog::set_failback_interval`]
+  /// for when a failed member is tried again.
+  pub fn failover(mut self, group: impl std::fmt::Display) -> Pipeline {
+    self.failover = Some(group.to_string());
+    self
+  }
+
+  /// The failover group the output belongs to, if any
+  pub(crate) fn failover_group(&self) -> Option<&str> {
+    self.failover.as_deref()
+  }
+
+  /// The lowest level a block needs to get through the level stages, if there are any
+  pub(crate) fn own_level(&self) -> Option<&Level> {
+    self
+      .stages
+      .iter()
+      .filter_map(|stage| match stage {
+        Stage::Level(level) => Some(level),
+        _ => None,
+      })
+      .max()
+  }
+
+  /// Whether a block could get past the pipeline's level and tag checks
+  ///
+  /// This is judged before any stage runs, so an output with its own level stage is checked
+  /// against it the same way [`YmLog::enabled`] does.
+  pub(crate) fn takes(&self, block: &Block, threshold: Option<&Level>, tags: &TagFilter) -> bool {
+    let level = block.log_level();
+    let level_passes = match self.own_level() {
+      Some(own) => level >= own,
+      None => threshold.is_some_and(|threshold| level >= threshold),
+    };
+    level_passes && (self.sets_tags || tags.allows(block.tags()))
+  }
+
+  /// Run the block through every stage
+  ///
+  /// The block is only copied if a stage needs to change it. Returns why it was dropped if it
+  /// was. A threshold of None drops everything, unless the pipeline sets its own level. The tag
+  /// filter is the logger's, which is skipped if the pipeline has its own.
+  pub(crate) fn run<'a>(
+    &mut self,
+    block: &'a Block,
+    threshold: Option<&Level>,
+    tags: &TagFilter,
+  ) -> Result<Processed<'a>, Dropped> {
+    if !self.sets_level && threshold.is_none_or(|threshold| block.log_level() < threshold) {
+      return Err(Dropped::Level);
+    }
+    if !self.sets_tags && !tags.allows(block.tags()) {
+      return Err(Dropped::Tags);
+    }
+
+    let mut processed = Processed {
+      block: Cow::Borrowed(block),
+      format: None,
+    };
+    for stage in self.stages.iter_mut() {
+      match stage {
+        Stage::Enrich(transform) | Stage::Redact(transform) => transform(processed.block.to_mut()),
+        Stage::Filter(predicate) => {
+          if !predicate(&processed.block) {
+            return Err(Dropped::Filtered);
+          }
+        }
+        Stage::Sample(sampler) => {
+          if !sampler.keep() {
+            return Err(Dropped::Sampled);
+          }
+        }
+        Stage::Level(level) => {
+          if processed.block.log_level() < level {
+            return Err(Dropped::Level);
+          }
+        }
+        Stage::Tags(filter) => {
+          if !filter.allows(processed.block.tags()) {
+            return Err(Dropped::Tags);
+          }
+        }
+        Stage::Format(format) => processed.format = Some(format.clone()),
+      }
+    }
+    Ok(processed)
+  }
+}
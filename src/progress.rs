@@ -0,0 +1,127 @@
+//! Counters for tight loops that are only written once they finish
+//!
+//! Logging every step of a loop over thousands of files floods the log. A progress handle counts
+//! the steps instead, and writes a single summary with the count, how long it took and the rate
+//! when it is finished or dropped.
+
+use std::io::{Result as IoResult, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Start counting the steps of some work, expecting the total if it is known
+  pub fn progress(
+    &self,
+    name: impl std::fmt::Display,
+    total: impl Into<Option<u64>>,
+  ) -> ProgressBlock<'_, T> {
+    ProgressBlock {
+      logger: self,
+      name: name.to_string(),
+      total: total.into(),
+      count: AtomicU64::new(0),
+      started: Instant::now(),
+      open: true,
+    }
+  }
+}
+
+/// Steps of some work being counted, written as one summary when finished or dropped
+///
+/// Counting only needs a shared reference, so threads working through the same queue can share
+/// one handle.
+pub struct ProgressBlock<'a, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  logger: &'a YmLog<T>,
+  name: String,
+  total: Option<u64>,
+  count: AtomicU64,
+  started: Instant,
+  open: bool,
+}
+
+impl<T> ProgressBlock<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Count a step
+  pub fn inc(&self) {
+    self.add(1)
+  }
+
+  /// Count several steps at once
+  pub fn add(&self, steps: u64) {
+    self.count.fetch_add(steps, Ordering::Relaxed);
+  }
+
+  /// The steps counted so far
+  pub fn count(&self) -> u64 {
+    self.count.load(Ordering::Relaxed)
+  }
+
+  /// The time since counting started
+  pub fn elapsed(&self) -> Duration {
+    self.started.elapsed()
+  }
+
+  /// Make the summary record written when the work finishes
+  pub fn summary(&self) -> Block {
+    let count = self.count();
+    let elapsed = self.elapsed();
+    let mut block = Block::new();
+    block.set_text(self.name.clone());
+    let _ = block.add_field("count", count);
+    if let Some(total) = self.total {
+      let _ = block.add_field("total", total);
+    }
+    block.field_duration("duration", elapsed);
+    if !elapsed.is_zero() {
+      let _ = block.add_field("per_second", count as f64 / elapsed.as_secs_f64());
+    }
+    block
+  }
+
+  /// Write the summary at the current depth
+  pub fn finish(mut self) -> IoResult<Option<RecordHandle>> {
+    self.close()
+  }
+
+  fn close(&mut self) -> IoResult<Option<RecordHandle>> {
+    self.open = false;
+    self.logger.log(&mut self.summary(), None)
+  }
+}
+
+impl<T> Drop for ProgressBlock<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  fn drop(&mut self) {
+    if self.open {
+      if let Err(err) = self.close() {
+        self.logger.report(err);
+      }
+    }
+  }
+}
+
+impl<T> std::fmt::Debug for ProgressBlock<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ProgressBlock")
+      .field("name", &self.name)
+      .field("total", &self.total)
+      .field("count", &self.count)
+      .field("started", &self.started)
+      .finish()
+  }
+}
@@ -0,0 +1,379 @@
+//! Combining and filtering records that have been read back in
+//!
+//! [`Query::filter`] only checks root blocks. Their children come along with them, so a matching
+//! record keeps its context. [`Query::subtrees`] and [`Query::flatten`] search the whole tree.
+//!
+//! Queries can also be written as text, so tools can take them from their users:
+//!
+//! ```
+//! use ymlog::query::Query;
+//!
+//! let query: Query = r#"level>=warn AND tag:db AND msg~"timeout" AND depth<3"#.parse().unwrap();
+//! ```
+//!
+//! Conditions are joined with `AND`, and each is a field, an operator and a value:
+//!
+//! - `level` and `depth` take `=`, `<`, `<=`, `>` or `>=`, with roots at depth zero
+//! - `tag:` and `source:` match the record's tags or YAML tag, and its source
+//! - `msg~` matches records whose message contains the text
+//!
+//! Values with spaces are put in double quotes, with `\"` for a quote inside them.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::ops::{Bound, RangeBounds};
+
+use crate::prelude::*;
+use crate::Timestamp;
+
+/// Interleave the records of several logs into one, ordered by their timestamps
+///
+/// Each record is given the name of the log it came from as its source, unless it already has one
+/// from an earlier merge. Records without a timestamp can't be ordered, so they are kept right
+/// after the record that preceded them in their own log.
+pub fn merge<S, I>(sources: impl IntoIterator<Item = (S, I)>) -> Vec<Block>
+where
+  S: std::fmt::Display,
+  I: IntoIterator<Item = Block>,
+{
+  let mut logs = sources
+    .into_iter()
+    .map(|(name, blocks)| (name.to_string(), blocks.into_iter().peekable()))
+    .collect::<Vec<_>>();
+
+  let mut merged = vec![];
+  loop {
+    // Untimed records go first, otherwise the earliest. Ties go to the log listed first.
+    let next = logs
+      .iter_mut()
+      .enumerate()
+      .filter_map(|(i, (_, blocks))| blocks.peek().map(|block| (block.timestamp().cloned(), i)))
+      .min_by(|(a_time, a), (b_time, b)| match (a_time, b_time) {
+        (None, None) => a.cmp(b),
+        (None, Some(_)) => std::cmp::Ordering::Less,
+        (Some(_), None) => std::cmp::Ordering::Greater,
+        (Some(a_time), Some(b_time)) => a_time.cmp(b_time).then(a.cmp(b)),
+      });
+
+    let (name, blocks) = match next {
+      Some((_, i)) => &mut logs[i],
+      None => return merged,
+    };
+    if let Some(mut block) = blocks.next() {
+      if block.source().is_none() {
+        block.set_source(&*name);
+      }
+      merged.push(block);
+    }
+  }
+}
+
+/// A set of conditions a record must match
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+  /// Only records from this source
+  source: Option<String>,
+
+  /// Only records with a level in this range
+  levels: Option<(Bound<Level>, Bound<Level>)>,
+
+  /// Only records with all of these tags, each in their tags or as their YAML tag
+  tags: Vec<String>,
+
+  /// Only records whose message contains this text
+  message: Option<String>,
+
+  /// Only records this deep in the tree, with roots at zero
+  depths: Option<(Bound<usize>, Bound<usize>)>,
+
+  /// Only records stamped within this range, including both ends
+  since: Option<Timestamp>,
+  until: Option<Timestamp>,
+}
+
+impl Query {
+  pub fn new() -> Query {
+    Default::default()
+  }
+
+  /// Only keep records merged from the named source
+  pub fn source(mut self, source: impl std::fmt::Display) -> Query {
+    self.source = Some(source.to_string());
+    self
+  }
+
+  /// Only keep records at or above the level
+  ///
+  /// YAML logs don't record levels, so their records are read back in as info.
+  pub fn level(self, level: Level) -> Query {
+    self.levels(level..)
+  }
+
+  /// Only keep records with a level in the range, such as `..Level::Warn` for the ones below warn
+  pub fn levels(mut self, range: impl RangeBounds<Level>) -> Query {
+    self.levels = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+    self
+  }
+
+  /// Only keep records with the tag, either in their tags or as their YAML tag
+  ///
+  /// Each tag added is another the records must have.
+  pub fn tag(mut self, tag: impl std::fmt::Display) -> Query {
+    self
+      .tags
+      .push(tag.to_string().trim_start_matches('!').to_string());
+    self
+  }
+
+  /// Only keep records whose message contains the text
+  ///
+  /// Structured messages are searched as they are written, and pairs as `key: value`.
+  pub fn message(mut self, text: impl std::fmt::Display) -> Query {
+    self.message = Some(text.to_string());
+    self
+  }
+
+  /// Only keep records nested this deep, with roots at zero
+  ///
+  /// [`Query::filter`] and [`Query::matches`] only see roots, so any other depth drops everything.
+  pub fn depths(mut self, range: impl RangeBounds<usize>) -> Query {
+    self.depths = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+    self
+  }
+
+  /// Only keep records stamped at or after the time. Untimed records are dropped.
+  pub fn since(mut self, time: impl Into<Timestamp>) -> Query {
+    self.since = Some(time.into());
+    self
+  }
+
+  /// Only keep records stamped at or before the time. Untimed records are dropped.
+  pub fn until(mut self, time: impl Into<Timestamp>) -> Query {
+    self.until = Some(time.into());
+    self
+  }
+
+  /// Parse a query written as text, such as `level>=warn AND tag:db`
+  pub fn parse(text: &str) -> IoResult<Query> {
+    Query::new().refine(text)
+  }
+
+  /// Add the conditions of a query written as text to this one
+  pub fn refine(self, text: &str) -> IoResult<Query> {
+    conditions(text)?
+      .into_iter()
+      .try_fold(self, |query, condition| query.condition(&condition))
+  }
+
+  /// Add one `field`, operator, value condition
+  fn condition(self, condition: &str) -> IoResult<Query> {
+    let invalid = |reason: &str| {
+      Err(IoError::new(
+        ErrorKind::InvalidInput,
+        format!("The query condition {:?} {}", condition, reason),
+      ))
+    };
+    let split = condition
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+      .unwrap_or(condition.len());
+    let (field, rest) = condition.split_at(split);
+    let op = ["<=", ">=", "<", ">", "=", ":", "~"]
+      .iter()
+      .copied()
+      .find(|op| rest.starts_with(op));
+    let (op, value) = match op {
+      Some(op) => (op, unquote(&rest[op.len()..])),
+      None => return invalid("has no operator"),
+    };
+    if value.is_empty() {
+      return invalid("has no value");
+    }
+
+    match (field.to_ascii_lowercase().as_str(), op) {
+      ("level", op) if op != ":" && op != "~" => {
+        let level = value.parse::<Level>()?;
+        Ok(self.levels(bounds(op, level)))
+      }
+      ("depth", op) if op != ":" && op != "~" => match value.parse::<usize>() {
+        Ok(depth) => Ok(self.depths(bounds(op, depth))),
+        Err(_) => invalid("doesn't have a whole number depth"),
+      },
+      ("tag", ":") => Ok(self.tag(value)),
+      ("source", ":") => Ok(self.source(value)),
+      ("msg", "~") | ("message", "~") => Ok(self.message(value)),
+      ("level", _) | ("depth", _) | ("tag", _) | ("source", _) | ("msg", _) | ("message", _) => {
+        invalid(&format!("can't use {:?} with {}", op, field))
+      }
+      _ => invalid("isn't on level, depth, tag, source or msg"),
+    }
+  }
+
+  /// Check if the record meets every condition
+  ///
+  /// The record is checked as a root, so a depth other than zero never matches.
+  pub fn matches(&self, block: &Block) -> bool {
+    self.matches_at(block, 0)
+  }
+
+  fn matches_at(&self, block: &Block, depth: usize) -> bool {
+    let source = match &self.source {
+      Some(source) => block.source() == Some(source.as_str()),
+      None => true,
+    };
+    let level = self
+      .levels
+      .as_ref()
+      .is_none_or(|levels| levels.contains(block.log_level()));
+    let tags = self.tags.iter().all(|tag| {
+      block.tag_type() == Some(tag.as_str()) || block.tags().iter().any(|other| other == tag)
+    });
+    let message = self
+      .message
+      .as_ref()
+      .is_none_or(|text| block.message_text().contains(text.as_str()));
+    let depth = self
+      .depths
+      .as_ref()
+      .is_none_or(|depths| depths.contains(&depth));
+    let time = match (&self.since, &self.until) {
+      (None, None) => true,
+      (since, until) => block.timestamp().is_some_and(|time| {
+        since.is_none_or(|since| time >= &since) && until.is_none_or(|until| time <= &until)
+      }),
+    };
+    source && level && tags && message && depth && time
+  }
+
+  /// Keep the records matching the query
+  pub fn filter(&self, blocks: impl IntoIterator<Item = Block>) -> Vec<Block> {
+    blocks
+      .into_iter()
+      .filter(|block| self.matches(block))
+      .collect()
+  }
+
+  /// Find the matching records anywhere in the trees, each with everything nested under it
+  ///
+  /// Records under a match are part of its subtree, so they aren't returned again.
+  pub fn subtrees(&self, blocks: &[Block]) -> Vec<Block> {
+    let mut found = vec![];
+    self.walk(blocks, &mut |block| {
+      found.push(block.clone());
+      false
+    });
+    found
+  }
+
+  /// Find every matching record anywhere in the trees, without their children
+  pub fn flatten(&self, blocks: &[Block]) -> Vec<Block> {
+    let mut found = vec![];
+    self.walk(blocks, &mut |block| {
+      let mut block = block.clone();
+      block.children = None;
+      found.push(block);
+      true
+    });
+    found
+  }
+
+  /// Call the visitor on each match in order, which returns if its children should be searched
+  fn walk(&self, blocks: &[Block], visit: &mut impl FnMut(&Block) -> bool) {
+    self.walk_at(blocks, 0, visit)
+  }
+
+  fn walk_at(&self, blocks: &[Block], depth: usize, visit: &mut impl FnMut(&Block) -> bool) {
+    for block in blocks {
+      if !self.matches_at(block, depth) || visit(block) {
+        self.walk_at(block.children(), depth + 1, visit);
+      }
+    }
+  }
+}
+
+impl std::str::FromStr for Query {
+  type Err = IoError;
+
+  fn from_str(text: &str) -> IoResult<Query> {
+    Query::parse(text)
+  }
+}
+
+/// The range of values on the operator's side of the value
+fn bounds<T>(op: &str, value: T) -> (Bound<T>, Bound<T>)
+where
+  T: Clone,
+{
+  match op {
+    "<" => (Bound::Unbounded, Bound::Excluded(value)),
+    "<=" => (Bound::Unbounded, Bound::Included(value)),
+    ">" => (Bound::Excluded(value), Bound::Unbounded),
+    ">=" => (Bound::Included(value), Bound::Unbounded),
+    _ => (Bound::Included(value.clone()), Bound::Included(value)),
+  }
+}
+
+/// Split a query into its conditions at each `AND` outside of quotes
+///
+/// The spaces between the parts of a condition are dropped, so `level >= warn` works too.
+fn conditions(text: &str) -> IoResult<Vec<String>> {
+  let mut conditions = vec![];
+  let mut current = String::new();
+  let mut word = String::new();
+  let mut quoted = false;
+  let mut escaped = false;
+  let mut end_word = |word: &mut String, current: &mut String| {
+    match word.eq_ignore_ascii_case("and") {
+      true => conditions.push(std::mem::take(current)),
+      false => current.push_str(word),
+    }
+    word.clear();
+  };
+
+  for c in text.chars() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if quoted => escaped = true,
+      '"' => quoted = !quoted,
+      c if c.is_whitespace() && !quoted => {
+        end_word(&mut word, &mut current);
+        continue;
+      }
+      _ => (),
+    }
+    word.push(c);
+  }
+  if quoted {
+    return Err(IoError::new(
+      ErrorKind::InvalidInput,
+      format!("The query {:?} has an unclosed quote", text),
+    ));
+  }
+  end_word(&mut word, &mut current);
+  conditions.push(current);
+
+  match conditions.iter().any(String::is_empty) {
+    true if text.trim().is_empty() => Ok(vec![]),
+    true => Err(IoError::new(
+      ErrorKind::InvalidInput,
+      format!("The query {:?} has an empty condition", text),
+    )),
+    false => Ok(conditions),
+  }
+}
+
+/// Take a value out of its quotes, if it has them
+fn unquote(value: &str) -> String {
+  match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+    Some(inner) => {
+      let mut unquoted = String::new();
+      let mut chars = inner.chars();
+      while let Some(c) = chars.next() {
+        match c {
+          '\\' => unquoted.extend(chars.next()),
+          c => unquoted.push(c),
+        }
+      }
+      unquoted
+    }
+    None => value.to_string(),
+  }
+}
@@ -0,0 +1,576 @@
+//! Reading logs back in
+//!
+//! Rotated logs are often compressed, so files are checked for a compression format when opened
+//! and passed through the matching codec. Gzip files are read with the `gzip` feature and zstd
+//! files with the `zstd` feature, and other codecs are added by the user, like the writer's:
+//!
+//! ```ignore
+//! let opener = ymlog::reader::Opener::new().codec(Box::new(Gzip));
+//! for (path, log) in opener.open_dir("/var/log/app")? {
+//!   // ...
+//! }
+//! ```
+//!
+//! A whole rotation set can also be read as a single stream with [`LogSet`], and [`parse`] turns
+//! a log back into blocks. [`Opener::parse`] does the same, expanding the messages its codecs
+//! compressed.
+
+use std::fs::File;
+use std::io::{
+  BufRead, BufReader, Cursor, Error as IoError, ErrorKind, Lines, Read, Result as IoResult,
+};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_yaml::value::TaggedValue;
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::compress::{self, Codec};
+use crate::message::{MessageType, Tag};
+use crate::prelude::*;
+use crate::Timestamp;
+
+/// Well known compression formats, so we can give a useful error when there is no codec for one
+const KNOWN_FORMATS: &[(&str, &[u8])] = &[
+  ("gzip", &[0x1f, 0x8b]),
+  ("zstd", &[0x28, 0xb5, 0x2f, 0xfd]),
+  ("xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+  ("bzip2", b"BZh"),
+];
+
+/// Opens log files, decompressing any that were compressed after rotation
+pub struct Opener {
+  codecs: Vec<Box<dyn Codec>>,
+}
+
+impl Default for Opener {
+  /// An opener with the codecs shipped with the crate, which are gzip and zstd with their features
+  fn default() -> Opener {
+    let codecs: Vec<Box<dyn Codec>> = vec![
+      #[cfg(feature = "gzip")]
+      Box::new(crate::gzip::Gzip),
+      #[cfg(feature = "zstd")]
+      Box::new(crate::zstd::Zstd),
+    ];
+    Opener { codecs }
+  }
+}
+
+impl Opener {
+  pub fn new() -> Opener {
+    Default::default()
+  }
+
+  /// Decompress files starting with the codec's magic bytes
+  pub fn codec(mut self, codec: Box<dyn Codec>) -> Opener {
+    self.codecs.push(codec);
+    self
+  }
+
+  /// Open the log, whether it is plain or compressed
+  ///
+  /// Compressed files are decompressed as they are read, by the codec's
+  /// [`decoder`](Codec::decoder).
+  pub fn open(&self, path: impl AsRef<Path>) -> IoResult<Box<dyn BufRead + Send>> {
+    let path = path.as_ref();
+    let mut file = BufReader::new(File::open(path)?);
+    let start = file.fill_buf()?;
+
+    let codec = self
+      .codecs
+      .iter()
+      .find(|codec| !codec.magic().is_empty() && start.starts_with(codec.magic()));
+    match codec {
+      Some(codec) => Ok(Box::new(BufReader::new(codec.decoder(Box::new(file))?))),
+      None => match KNOWN_FORMATS
+        .iter()
+        .find(|(_, magic)| start.starts_with(magic))
+      {
+        Some((format, _)) => Err(IoError::new(
+          ErrorKind::Unsupported,
+          format!(
+            "{} is {} compressed, but no codec was added for it",
+            path.display(),
+            format
+          ),
+        )),
+        None => Ok(Box::new(file)),
+      },
+    }
+  }
+
+  /// Open every file in the directory, sorted by name
+  ///
+  /// Subdirectories are skipped.
+  pub fn open_dir(
+    &self,
+    dir: impl AsRef<Path>,
+  ) -> IoResult<Vec<(PathBuf, Box<dyn BufRead + Send>)>> {
+    let mut paths = std::fs::read_dir(dir)?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect::<IoResult<Vec<_>>>()?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+
+    paths
+      .into_iter()
+      .map(|path| self.open(&path).map(|log| (path, log)))
+      .collect()
+  }
+
+  /// Read a log back into its root records like [`parse`], expanding the messages compressed by
+  /// any of the codecs
+  pub fn parse<'a, R: Read + 'a>(&'a self, log: R) -> impl Iterator<Item = IoResult<Block>> + 'a {
+    Records::new(log, &self.codecs)
+  }
+}
+
+/// The current log and its rotated copies, read as one stream from oldest to newest
+///
+/// File boundaries are hidden from the caller. Each file is only opened once the ones before it
+/// have been read, and a newline is added between them so the last record of one file doesn't run
+/// into the first document marker of the next.
+pub struct LogSet {
+  opener: Opener,
+
+  /// The files left to read, oldest first
+  files: Vec<PathBuf>,
+
+  /// The file being read, and the index of the next one
+  current: Option<Box<dyn BufRead + Send>>,
+  next: usize,
+}
+
+impl LogSet {
+  /// Find the files matching the pattern, such as `/var/log/app.yml*`
+  ///
+  /// Only the file name may contain wildcards: `*` matches any run of characters and `?` matches
+  /// one. Files are ordered by modification time, then by name. Compressed files need
+  /// [`LogSet::with_opener`].
+  pub fn open(pattern: impl AsRef<Path>) -> IoResult<LogSet> {
+    LogSet::with_opener(pattern, Opener::new())
+  }
+
+  /// Find the files matching the pattern, opening them with the given codecs
+  pub fn with_opener(pattern: impl AsRef<Path>, opener: Opener) -> IoResult<LogSet> {
+    let pattern = pattern.as_ref();
+    let name = pattern
+      .file_name()
+      .and_then(|name| name.to_str())
+      .ok_or_else(|| {
+        IoError::new(
+          ErrorKind::InvalidInput,
+          format!("{} does not end in a file name", pattern.display()),
+        )
+      })?;
+    let dir = match pattern.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+      let entry = entry?;
+      let matched = entry
+        .file_name()
+        .to_str()
+        .map(|file| {
+          let name = name.chars().collect::<Vec<_>>();
+          wildcard(&name, &file.chars().collect::<Vec<_>>())
+        })
+        .unwrap_or(false);
+      if matched && entry.file_type()?.is_file() {
+        files.push((entry.metadata()?.modified()?, entry.path()));
+      }
+    }
+    files.sort();
+
+    Ok(LogSet::from_files(
+      files.into_iter().map(|(_, path)| path).collect(),
+      opener,
+    ))
+  }
+
+  /// Read the files in the order given
+  pub fn from_files(files: Vec<PathBuf>, opener: Opener) -> LogSet {
+    LogSet {
+      opener,
+      files,
+      current: None,
+      next: 0,
+    }
+  }
+
+  /// The files in the set, oldest first
+  pub fn files(&self) -> &[PathBuf] {
+    &self.files
+  }
+
+  /// When the newest file in the set was last written to
+  pub fn modified(&self) -> IoResult<Option<SystemTime>> {
+    match self.files.last() {
+      Some(path) => std::fs::metadata(path)?.modified().map(Some),
+      None => Ok(None),
+    }
+  }
+}
+
+impl Read for LogSet {
+  fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    loop {
+      if let Some(current) = &mut self.current {
+        let count = current.read(buf)?;
+        if count > 0 || buf.is_empty() {
+          return Ok(count);
+        }
+        self.current = None;
+      }
+
+      let path = match self.files.get(self.next) {
+        Some(path) => path,
+        None => return Ok(0),
+      };
+      let log = self.opener.open(path)?;
+      self.current = Some(match self.next {
+        0 => log,
+        _ => Box::new(Cursor::new(b"\n".to_vec()).chain(log)),
+      });
+      self.next += 1;
+    }
+  }
+}
+
+/// The keys of a record written as a mapping, in either format
+const RECORD_KEYS: &[&str] = &[
+  "timestamp",
+  "source",
+  "log_level",
+  "tags",
+  "fields",
+  "tag_type",
+  "message",
+  "children",
+];
+
+/// Read a log back into its root records, each with its children nested under it
+///
+/// Logs written as YAML and as JSON lines are both understood, picked by the first line. Records
+/// without a level are read as Info, the level YAML leaves out. Timestamps in a custom format
+/// can't be read back and are left unset, and compressed messages are returned as written,
+/// tagged with their codec, unless read with [`Opener::parse`]. Malformed records are returned as
+/// `InvalidData` errors, and reading carries on with the next one.
+pub fn parse<R: Read>(reader: R) -> impl Iterator<Item = IoResult<Block>> {
+  Records::new(reader, &[])
+}
+
+/// The iterator behind [`parse`]
+struct Records<'a, R> {
+  lines: Lines<R>,
+
+  /// Expand the messages they compressed
+  codecs: &'a [Box<dyn Codec>],
+
+  /// Decided by the first line that isn't blank
+  format: Option<OutputFormat>,
+
+  /// The YAML document being read
+  document: String,
+
+  /// The JSON records that can still get children, with their depths
+  open: Vec<(usize, Block)>,
+}
+
+impl<'a, R: Read> Records<'a, BufReader<R>> {
+  fn new(reader: R, codecs: &'a [Box<dyn Codec>]) -> Records<'a, BufReader<R>> {
+    Records {
+      lines: BufReader::new(reader).lines(),
+      codecs,
+      format: None,
+      document: String::new(),
+      open: vec![],
+    }
+  }
+}
+
+impl<R: BufRead> Iterator for Records<'_, R> {
+  type Item = IoResult<Block>;
+
+  fn next(&mut self) -> Option<IoResult<Block>> {
+    let record = self.read()?;
+    Some(record.and_then(|mut block| {
+      expand(&mut block, self.codecs)?;
+      Ok(block)
+    }))
+  }
+}
+
+impl<R: BufRead> Records<'_, R> {
+  /// Read the next root record as it was written
+  fn read(&mut self) -> Option<IoResult<Block>> {
+    loop {
+      let line = match self.lines.next() {
+        Some(Ok(line)) => line,
+        Some(Err(err)) => return Some(Err(err)),
+        None => {
+          return match self.format {
+            Some(OutputFormat::JsonLines) => self.close(0).map(Ok),
+            _ => self.take_document(),
+          }
+        }
+      };
+
+      let format = match &self.format {
+        Some(format) => format,
+        None if line.trim().is_empty() => continue,
+        None => self
+          .format
+          .insert(match line.trim_start().starts_with('{') {
+            true => OutputFormat::JsonLines,
+            false => OutputFormat::Yaml,
+          }),
+      };
+      match format {
+        OutputFormat::Yaml => {
+          let start = match line.strip_prefix("---") {
+            Some(rest) if rest.is_empty() || rest.starts_with(' ') => Some(rest.to_string()),
+            _ => None,
+          };
+          match start {
+            Some(rest) => {
+              let document = self.take_document();
+              self.document = rest;
+              self.document.push('\n');
+              if document.is_some() {
+                return document;
+              }
+            }
+            None => {
+              self.document.push_str(&line);
+              self.document.push('\n');
+            }
+          }
+        }
+        OutputFormat::JsonLines => {
+          if line.trim().is_empty() {
+            continue;
+          }
+          match self.json_record(&line) {
+            Ok(Some(root)) => return Some(Ok(root)),
+            Ok(None) => (),
+            Err(err) => return Some(Err(err)),
+          }
+        }
+      }
+    }
+  }
+}
+
+impl<R> Records<'_, R> {
+  /// Convert the YAML document read so far, if it had anything in it
+  fn take_document(&mut self) -> Option<IoResult<Block>> {
+    let document = std::mem::take(&mut self.document);
+    if document.trim().is_empty() {
+      return None;
+    }
+    Some(
+      serde_yaml::from_str(&document)
+        .map_err(invalid)
+        .and_then(from_yaml),
+    )
+  }
+
+  /// Add a JSON line under the open record it belongs to, returning the root record it closed
+  fn json_record(&mut self, line: &str) -> IoResult<Option<Block>> {
+    let mut record = match serde_yaml::from_str(line).map_err(invalid)? {
+      YmlValue::Mapping(record) => record,
+      _ => return Err(invalid("A JSON record wasn't an object")),
+    };
+    let depth = record
+      .remove("depth")
+      .and_then(|depth| depth.as_u64())
+      .ok_or_else(|| invalid("A JSON record is missing its depth"))? as usize;
+
+    // A hole in the depths would leave the record without a parent, so it goes under the last one
+    let depth = depth.min(self.open.last().map_or(0, |(last, _)| last + 1));
+    let root = self.close(depth);
+    self.open.push((depth, from_record(record)?));
+    Ok(root)
+  }
+
+  /// Attach the open records at or below the depth to their parents
+  fn close(&mut self, depth: usize) -> Option<Block> {
+    while self.open.last().is_some_and(|(last, _)| *last >= depth) {
+      let (_, block) = self.open.pop()?;
+      match self.open.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(block),
+        None => return Some(block),
+      }
+    }
+    None
+  }
+}
+
+fn invalid(err: impl std::fmt::Display) -> IoError {
+  IoError::new(ErrorKind::InvalidData, err.to_string())
+}
+
+/// Expand the messages of the block and its children that were compressed by one of the codecs
+fn expand(block: &mut Block, codecs: &[Box<dyn Codec>]) -> IoResult<()> {
+  if codecs.is_empty() {
+    return Ok(());
+  }
+  for child in block.children.iter_mut().flatten() {
+    expand(child, codecs)?;
+  }
+
+  // A plain record's only tag is read as its type, and JSON lines write tags as objects
+  let (tagged, from_type) = match (&block.message, &block.tag_type) {
+    (MessageType::Value(value @ YmlValue::Tagged(_)), _) => (value.clone(), false),
+    (MessageType::Value(value @ YmlValue::String(_)), Some(tag)) => (tagged(tag, value), true),
+    (MessageType::Value(YmlValue::Mapping(object)), _) if object.len() == 2 => {
+      match (object.get("tag"), object.get("value")) {
+        (Some(YmlValue::String(tag)), Some(value)) => (tagged(tag, value), false),
+        _ => return Ok(()),
+      }
+    }
+    _ => return Ok(()),
+  };
+
+  for codec in codecs {
+    if let Some(text) = compress::unpack(codec.as_ref(), &tagged)? {
+      block.message = MessageType::Value(YmlValue::String(text));
+      if from_type {
+        block.tag_type = None;
+      }
+      break;
+    }
+  }
+  Ok(())
+}
+
+fn tagged(tag: &str, value: &YmlValue) -> YmlValue {
+  YmlValue::Tagged(Box::new(TaggedValue {
+    tag: serde_yaml::value::Tag::new(tag),
+    value: value.clone(),
+  }))
+}
+
+/// Convert a record in any of the shapes the tracker writes back into a block
+pub(crate) fn from_yaml(value: YmlValue) -> IoResult<Block> {
+  match value {
+    YmlValue::Tagged(tagged) => {
+      let mut block = from_yaml(tagged.value)?;
+      block.set_tag_type(&tagged.tag.to_string());
+      Ok(block)
+    }
+    YmlValue::Mapping(record) if is_record(&record) => from_record(record),
+
+    // A plain record with children is written as a mapping of the message to them
+    YmlValue::Mapping(pair) if pair.len() == 1 => {
+      let mut block = Block::new();
+      if let Some((key, value)) = pair.into_iter().next() {
+        match value {
+          YmlValue::Sequence(children) => {
+            block.message = MessageType::Value(key);
+            block.children = Some(from_sequence(children)?);
+          }
+          value => block.message = MessageType::KeyValue(key, value),
+        }
+      }
+      Ok(block)
+    }
+    value => {
+      let mut block = Block::new();
+      block.message = MessageType::Value(value);
+      Ok(block)
+    }
+  }
+}
+
+/// Convert a sequence of children
+///
+/// Children of a multiline message can't be put under it as a key, so they are written as the
+/// next item under an empty key. Those are moved back onto the message.
+fn from_sequence(items: Vec<YmlValue>) -> IoResult<Vec<Block>> {
+  let mut blocks: Vec<Block> = vec![];
+  for item in items {
+    if let (YmlValue::Mapping(pair), Some(last)) = (&item, blocks.last_mut()) {
+      if let Some(YmlValue::Sequence(children)) = pair.get("").filter(|_| pair.len() == 1) {
+        last.children = Some(from_sequence(children.clone())?);
+        continue;
+      }
+    }
+    blocks.push(from_yaml(item)?);
+  }
+  Ok(blocks)
+}
+
+fn is_record(record: &Mapping) -> bool {
+  record.contains_key("message")
+    && record
+      .keys()
+      .all(|key| key.as_str().is_some_and(|key| RECORD_KEYS.contains(&key)))
+}
+
+/// Convert a record mapping, from YAML or a JSON line without its depth
+fn from_record(record: Mapping) -> IoResult<Block> {
+  let mut block = Block::new();
+  for (key, value) in record {
+    match (key.as_str().unwrap_or_default(), value) {
+      ("timestamp", value) => block.timestamp = parse_timestamp(&value),
+      ("source", YmlValue::String(source)) => block.source = Some(source),
+      ("log_level", YmlValue::String(level)) => {
+        block.log_level = Some(
+          Level::from_name(&level)
+            .ok_or_else(|| invalid(format!("{:?} is not a log level", level)))?,
+        )
+      }
+      ("tags", YmlValue::Sequence(tags)) => {
+        block.tags = Some(
+          tags
+            .iter()
+            .filter_map(|tag| tag.as_str().map(Tag::new))
+            .collect(),
+        )
+      }
+      ("fields", YmlValue::Mapping(fields)) => block.fields = Some(fields),
+      ("tag_type", YmlValue::String(tag)) => block.set_tag_type(&tag),
+      ("message", YmlValue::Mapping(pair)) if pair.len() == 1 => {
+        if let Some((key, value)) = pair.into_iter().next() {
+          block.message = MessageType::KeyValue(key, value);
+        }
+      }
+      ("message", value) => block.message = MessageType::Value(value),
+      ("children", YmlValue::Sequence(children)) => block.children = Some(from_sequence(children)?),
+      (key, value) => {
+        return Err(invalid(format!(
+          "Unexpected {} in a record: {:?}",
+          key, value
+        )))
+      }
+    }
+  }
+  Ok(block)
+}
+
+/// Timestamps are written as RFC 3339 strings or milliseconds since the epoch
+fn parse_timestamp(value: &YmlValue) -> Option<Timestamp> {
+  match value {
+    YmlValue::String(timestamp) => Timestamp::parse_rfc3339(timestamp),
+    YmlValue::Number(millis) => Some(Timestamp::from_millis(millis.as_i64()?)),
+    _ => None,
+  }
+}
+
+/// Check if the name matches a pattern of `*` and `?` wildcards
+fn wildcard(pattern: &[char], name: &[char]) -> bool {
+  match (pattern.first(), name.first()) {
+    (None, None) => true,
+    (Some('*'), _) => {
+      wildcard(&pattern[1..], name) || (!name.is_empty() && wildcard(pattern, &name[1..]))
+    }
+    (Some('?'), Some(_)) => wildcard(&pattern[1..], &name[1..]),
+    (Some(p), Some(n)) if p == n => wildcard(&pattern[1..], &name[1..]),
+    _ => false,
+  }
+}
@@ -0,0 +1,167 @@
+//! Masking secrets before a logger writes them
+//!
+//! Each block is checked before it reaches any output: a value under a key matching one of the key
+//! patterns is replaced with [`REDACTED`], as is each part of a string matching one of the value
+//! patterns. Keys are matched ignoring case at any depth of the message and fields, and pairs are
+//! matched by their key. Text messages are checked against the value patterns too.
+//!
+//! Key patterns are globs, where `*` matches any run of characters and `?` any one, and have to
+//! match the whole key, so `*token*` matches `api_token`. Value patterns are regular expressions,
+//! found anywhere in a string, and only the match is masked: `Bearer \S+` masks the credential in
+//! `Retrying with Bearer abc123 in 5s`, leaving the rest of the message readable. The patterns are
+//! compiled once, when they are set.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+
+use regex::Regex;
+use serde_yaml::Value as YmlValue;
+
+use crate::message::MessageType;
+use crate::prelude::*;
+
+/// The value written in place of a masked one
+pub const REDACTED: &str = "<redacted>";
+
+/// The key and value patterns a logger masks
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Redaction {
+  /// Every key pattern, as one regex matching a whole key in any case
+  keys: Option<Regex>,
+  /// Every value pattern, as one regex
+  patterns: Option<Regex>,
+}
+
+impl Redaction {
+  /// Compile the patterns, returning an `InvalidInput` error for a value pattern that isn't a
+  /// regular expression
+  pub(crate) fn new(keys: Vec<String>, patterns: Vec<String>) -> IoResult<Redaction> {
+    for pattern in &patterns {
+      if let Err(err) = Regex::new(pattern) {
+        return Err(IoError::new(
+          ErrorKind::InvalidInput,
+          format!(
+            "The redaction pattern {:?} is not a regex: {}",
+            pattern, err
+          ),
+        ));
+      }
+    }
+    let keys = keys.iter().map(|key| glob_regex(key)).collect::<Vec<_>>();
+    Ok(Redaction {
+      keys: any_of(&keys, "(?is)^(?:", ")$")?,
+      patterns: any_of(&patterns, "(?:", ")")?,
+    })
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.keys.is_none() && self.patterns.is_none()
+  }
+
+  /// Mask the secrets in the block and its children, returning how many there were
+  pub(crate) fn apply(&self, block: &mut Block) -> u64 {
+    let mut masked = match &mut block.message {
+      MessageType::Text(text) => match self.mask(text.as_str()) {
+        Some(masked) => {
+          *text = masked.into();
+          1
+        }
+        None => 0,
+      },
+      MessageType::Value(value) => self.value(value),
+      MessageType::KeyValue(key, value) => self.entry(key, value),
+      _ => 0,
+    };
+    if let Some(fields) = &mut block.fields {
+      masked += fields
+        .iter_mut()
+        .map(|(key, value)| self.entry(key, value))
+        .sum::<u64>();
+    }
+    for child in block.children.iter_mut().flatten() {
+      masked += self.apply(child);
+    }
+    masked
+  }
+
+  fn entry(&self, key: &YmlValue, value: &mut YmlValue) -> u64 {
+    let secret_key = match (&self.keys, key.as_str()) {
+      (Some(keys), Some(key)) => keys.is_match(key),
+      _ => false,
+    };
+    match secret_key {
+      true => {
+        *value = REDACTED.into();
+        1
+      }
+      false => self.value(value),
+    }
+  }
+
+  fn value(&self, value: &mut YmlValue) -> u64 {
+    match value {
+      YmlValue::String(text) => match self.mask(text) {
+        Some(masked) => {
+          *text = masked;
+          1
+        }
+        None => 0,
+      },
+      YmlValue::Mapping(mapping) => mapping
+        .iter_mut()
+        .map(|(key, value)| self.entry(key, value))
+        .sum(),
+      YmlValue::Sequence(items) => items.iter_mut().map(|item| self.value(item)).sum(),
+      YmlValue::Tagged(tagged) => self.value(&mut tagged.value),
+      _ => 0,
+    }
+  }
+
+  /// The text with each part matching a value pattern masked, or None if nothing matched
+  ///
+  /// Matches are taken from the left, by the first pattern matching there. Empty matches don't
+  /// count, as there would be nothing to mask.
+  fn mask(&self, text: &str) -> Option<String> {
+    let patterns = self.patterns.as_ref().filter(|_| text != REDACTED)?;
+    let mut result = String::with_capacity(text.len());
+    let mut end = 0;
+    for found in patterns.find_iter(text).filter(|found| !found.is_empty()) {
+      result.push_str(&text[end..found.start()]);
+      result.push_str(REDACTED);
+      end = found.end();
+    }
+    if end == 0 {
+      return None;
+    }
+    result.push_str(&text[end..]);
+    Some(result)
+  }
+}
+
+/// One regex matching any of the patterns, each wrapped in the prefix and suffix, or None if there
+/// are none
+fn any_of(patterns: &[String], prefix: &str, suffix: &str) -> IoResult<Option<Regex>> {
+  if patterns.is_empty() {
+    return Ok(None);
+  }
+  let joined = patterns
+    .iter()
+    .map(|pattern| format!("(?:{})", pattern))
+    .collect::<Vec<_>>()
+    .join("|");
+  Regex::new(&format!("{}{}{}", prefix, joined, suffix))
+    .map(Some)
+    .map_err(|err| IoError::new(ErrorKind::InvalidInput, err))
+}
+
+/// The regex for a key glob, where `*` is any run of characters and `?` is one
+fn glob_regex(glob: &str) -> String {
+  let mut pattern = String::with_capacity(glob.len());
+  for c in glob.chars() {
+    match c {
+      '*' => pattern.push_str(".*"),
+      '?' => pattern.push('.'),
+      c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+    }
+  }
+  pattern
+}
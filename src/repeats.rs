@@ -0,0 +1,107 @@
+//! Collapsing payloads repeated within a document
+//!
+//! Retry loops often dump the same request body on every attempt. With a threshold set, each
+//! string or structured value at least that many bytes long in a message or field is hashed, and
+//! later copies in the same document are written as `same_as: <id>`. The id is the sequence of the
+//! first record holding it, as in its [`RecordHandle`](crate::RecordHandle), counted separately for
+//! each output. Only the hashes are kept, and they are forgotten when the next root record starts a
+//! new document.
+
+use std::borrow::Cow;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::message::MessageType;
+use crate::prelude::*;
+
+/// The payloads written to one output in the current document
+#[derive(Debug, Default)]
+pub(crate) struct Repeats {
+  /// The hash of each payload and the sequence of the record it was first written in
+  seen: HashMap<u64, u64>,
+}
+
+impl Repeats {
+  /// A copy of the block with its repeated payloads replaced and how many there were, or None if
+  /// it had none
+  ///
+  /// The depth is where the record will be written, so a root record clears the payloads seen.
+  pub fn collapse(
+    &mut self,
+    block: &Block,
+    threshold: usize,
+    sequence: u64,
+    depth: usize,
+  ) -> Option<(Block, u64)> {
+    if depth == 0 {
+      self.seen.clear();
+    }
+
+    let mut collapsed: Option<Block> = None;
+    let mut count = 0;
+    let message = match &block.message {
+      MessageType::Value(value) | MessageType::KeyValue(_, value) => Some(Cow::Borrowed(value)),
+      MessageType::Text(text) if text.as_str().len() >= threshold => {
+        Some(Cow::Owned(text.to_value()))
+      }
+      _ => None,
+    };
+    if let Some(first) = message.and_then(|value| self.check(&value, threshold, sequence)) {
+      count += 1;
+      let copy = collapsed.get_or_insert_with(|| block.clone());
+      match &mut copy.message {
+        MessageType::Value(value) | MessageType::KeyValue(_, value) => *value = same_as(first),
+        MessageType::Text(_) => copy.message = MessageType::Value(same_as(first)),
+        _ => (),
+      }
+    }
+
+    for (key, value) in block.fields.iter().flatten() {
+      if let Some(first) = self.check(value, threshold, sequence) {
+        count += 1;
+        let copy = collapsed.get_or_insert_with(|| block.clone());
+        if let Some(fields) = &mut copy.fields {
+          fields.insert(key.clone(), same_as(first));
+        }
+      }
+    }
+    collapsed.map(|collapsed| (collapsed, count))
+  }
+
+  /// Remember a large payload, returning the record it was first seen in if it is a repeat
+  fn check(&mut self, value: &YmlValue, threshold: usize, sequence: u64) -> Option<u64> {
+    if size(value) < threshold {
+      return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    match self.seen.entry(hasher.finish()) {
+      Entry::Occupied(first) => Some(*first.get()),
+      Entry::Vacant(entry) => {
+        entry.insert(sequence);
+        None
+      }
+    }
+  }
+}
+
+/// The bytes a payload takes to write, with scalars other than strings never counting
+fn size(value: &YmlValue) -> usize {
+  match value {
+    YmlValue::String(value) => value.len(),
+    YmlValue::Tagged(tagged) => size(&tagged.value),
+    YmlValue::Mapping(_) | YmlValue::Sequence(_) => serde_yaml::to_string(value)
+      .map(|yaml| yaml.len())
+      .unwrap_or_default(),
+    _ => 0,
+  }
+}
+
+fn same_as(first: u64) -> YmlValue {
+  let mut reference = Mapping::new();
+  reference.insert("same_as".into(), first.into());
+  YmlValue::Mapping(reference)
+}
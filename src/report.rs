@@ -0,0 +1,170 @@
+//! Turn a parsed log into an HTML page to share
+//!
+//! Each record becomes a line of the page, colored by its level. Records with children can be
+//! expanded and collapsed, starting with only the root records open. The page needs no scripts or
+//! outside files, so it can be attached to a build as it is:
+//!
+//! ```ignore
+//! let records = ymlog::reader::parse(File::open("build.yml")?).collect::<IoResult<Vec<_>>>()?;
+//! std::fs::write("build.html", ymlog::report::html("Nightly build", &records))?;
+//! ```
+
+use std::fmt::Write as _;
+use std::io::{Result as IoResult, Write};
+
+use serde_yaml::Value as YmlValue;
+
+use crate::prelude::*;
+
+const STYLE: &str = "\
+body { font-family: monospace; margin: 1em 2em; }
+details > div, .record.leaf { margin-left: 1.5em; }
+summary, .record { padding: 1px 0; }
+.level { display: inline-block; width: 4em; font-weight: bold; }
+.trace .level { color: #888; }
+.debug .level { color: #2a6fdb; }
+.info .level { color: #1e8a3c; }
+.warn .level { color: #c77700; }
+.error .level { color: #c62828; }
+.error > .message, .error > summary > .message { color: #c62828; }
+.time, .tag, .field { color: #666; margin-right: 0.5em; }
+.field .key { font-style: italic; }
+pre { display: inline-block; margin: 0; vertical-align: top; }
+";
+
+/// Make a whole HTML page of the records
+pub fn html(title: &str, records: &[Block]) -> String {
+  let mut page = String::new();
+  let _ = write!(
+    page,
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+    escape(title),
+    STYLE,
+    escape(title)
+  );
+  for record in records {
+    node(record, 0, &mut page);
+  }
+  page.push_str("</body>\n</html>\n");
+  page
+}
+
+/// Write the page to the output
+pub fn write_html(title: &str, records: &[Block], out: &mut impl Write) -> IoResult<()> {
+  out.write_all(html(title, records).as_bytes())
+}
+
+/// Add the record, with its children in a collapsible list
+fn node(block: &Block, depth: usize, page: &mut String) {
+  let level = block.log_level().name();
+  let class = level.to_ascii_lowercase();
+  let mut line = format!("<span class=\"level\">{}</span>", level);
+  if let Some(timestamp) = block.timestamp() {
+    let _ = write!(
+      line,
+      "<span class=\"time\">{}</span>",
+      timestamp.to_rfc3339()
+    );
+  }
+  if let Some(tag) = block.tag_type() {
+    let _ = write!(line, "<span class=\"tag\">!{}</span>", escape(tag));
+  }
+  let _ = write!(line, "<span class=\"message\">{}</span>", message(block));
+  if let Some(fields) = block.fields.as_ref().filter(|fields| !fields.is_empty()) {
+    for (key, value) in fields {
+      let _ = write!(
+        line,
+        " <span class=\"field\"><span class=\"key\">{}</span>={}</span>",
+        escape(&scalar(key)),
+        escape(&scalar(value))
+      );
+    }
+  }
+
+  match block.children().is_empty() {
+    true => {
+      let leaf = match depth {
+        0 => "",
+        _ => " leaf",
+      };
+      let _ = writeln!(
+        page,
+        "<div class=\"record{} {}\">{}</div>",
+        leaf, class, line
+      );
+    }
+    false => {
+      let open = match depth {
+        0 => " open",
+        _ => "",
+      };
+      let _ = writeln!(
+        page,
+        "<details class=\"{}\"{}><summary>{}</summary>\n<div>",
+        class, open, line
+      );
+      for child in block.children() {
+        node(child, depth + 1, page);
+      }
+      page.push_str("</div>\n</details>\n");
+    }
+  }
+}
+
+/// The message as HTML, keeping the lines of multiline strings and structured values
+fn message(block: &Block) -> String {
+  if let Some((key, value)) = block.key_value() {
+    return format!("{}: {}", escape(&scalar(key)), escape(&scalar(value)));
+  }
+  if let Some(text) = block.text() {
+    return match text.contains('\n') {
+      false => escape(text),
+      true => format!("<pre>{}</pre>", escape(text.trim_end())),
+    };
+  }
+  match block.message() {
+    Some(value) if is_scalar(value) => escape(&scalar(value)),
+    Some(value) => format!(
+      "<pre>{}</pre>",
+      escape(serde_yaml::to_string(value).unwrap_or_default().trim_end())
+    ),
+    None => String::new(),
+  }
+}
+
+fn is_scalar(value: &YmlValue) -> bool {
+  !matches!(
+    value,
+    YmlValue::Sequence(_) | YmlValue::Mapping(_) | YmlValue::Tagged(_)
+  )
+}
+
+/// Write a value on one line, using flow style for anything nested
+fn scalar(value: &YmlValue) -> String {
+  match value {
+    YmlValue::String(text) => text.clone(),
+    YmlValue::Null => "null".to_string(),
+    YmlValue::Bool(value) => value.to_string(),
+    YmlValue::Number(number) => number.to_string(),
+    other => {
+      let mut out = String::new();
+      crate::json::write_value(other, &mut out);
+      out
+    }
+  }
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
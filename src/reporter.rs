@@ -0,0 +1,54 @@
+//! Background threads that log something on an interval
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A background thread writing to the global log on an interval. It stops when dropped.
+pub struct Reporter {
+  stop: Option<Sender<()>>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+  /// Start a thread calling the report function every interval
+  pub(crate) fn spawn(
+    name: &str,
+    interval: Duration,
+    report: impl Fn() + Send + 'static,
+  ) -> Reporter {
+    let (stop, wait) = channel::<()>();
+    let handle = std::thread::Builder::new()
+      .name(name.to_string())
+      .spawn(move || loop {
+        match wait.recv_timeout(interval) {
+          Err(RecvTimeoutError::Timeout) => report(),
+          _ => return,
+        }
+      })
+      .expect("Could not start the ymlog reporter thread");
+
+    Reporter {
+      stop: Some(stop),
+      handle: Some(handle),
+    }
+  }
+}
+
+impl Drop for Reporter {
+  fn drop(&mut self) {
+    // Dropping the sender wakes the thread up to finish
+    self.stop = None;
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+/// Write the block to the global log at its current depth
+pub(crate) fn log_global(mut block: crate::Block) {
+  let logger = crate::global();
+  if let Err(err) = logger.log(&mut block, None) {
+    logger.report(err);
+  }
+}
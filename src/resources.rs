@@ -0,0 +1,135 @@
+//! Snapshots of the resources the process is using
+//!
+//! Only available with the `resources` feature. The numbers are read from `/proc/self`, so they're
+//! only available on Linux. Elsewhere, taking a snapshot returns an `Unsupported` error.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::message::MessageType;
+use crate::prelude::*;
+use crate::reporter::{log_global, Reporter};
+
+/// The resources in use at a moment in time
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Resources {
+  /// Resident memory, in bytes
+  pub rss_bytes: u64,
+
+  /// User and system CPU time used by every thread so far
+  pub cpu_millis: u64,
+
+  /// Open file descriptors
+  pub open_fds: u64,
+
+  /// Threads in the process
+  pub threads: u64,
+}
+
+impl Resources {
+  /// Read the current numbers for this process
+  pub fn snapshot() -> IoResult<Resources> {
+    imp::snapshot()
+  }
+
+  /// Make a `resources` key/value record of the snapshot
+  pub fn to_block(&self) -> Block {
+    let mut block = Block::new();
+    block.message = MessageType::KeyValue(
+      "resources".into(),
+      serde_yaml::to_value(self).unwrap_or_default(),
+    );
+    block
+  }
+}
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Write a snapshot of the process' resources as a key/value record
+  ///
+  /// Like any key/value record, logging it with `+` attaches it to the last record.
+  pub fn log_resources(&self, actions: Option<&str>) -> IoResult<Option<RecordHandle>> {
+    let mut block = Resources::snapshot()?.to_block();
+    self.log(&mut block, actions)
+  }
+}
+
+/// Write a snapshot to the global log at its current depth, reporting any error to its handler
+pub fn log_resources() {
+  match Resources::snapshot() {
+    Ok(resources) => log_global(resources.to_block()),
+    Err(err) => crate::global().report(err),
+  }
+}
+
+/// Start writing snapshots to the global log on an interval
+pub fn report_every(interval: Duration) -> Reporter {
+  Reporter::spawn("ymlog-resources", interval, log_resources)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+  use super::*;
+
+  /// The kernel always reports CPU time in /proc in hundredths of a second (USER_HZ)
+  const TICKS_PER_SECOND: u64 = 100;
+
+  pub fn snapshot() -> IoResult<Resources> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let field = |name: &str| -> IoResult<u64> {
+      status
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid(&format!("{} is missing from /proc/self/status", name)))
+    };
+
+    // The command name can hold spaces and parens, so the fields are counted from the last ')'
+    let stat = std::fs::read_to_string("/proc/self/stat")?;
+    let fields = stat
+      .rsplit_once(')')
+      .map(|(_, fields)| fields.split_whitespace().collect::<Vec<_>>())
+      .unwrap_or_default();
+    let ticks = |index: usize| -> IoResult<u64> {
+      fields
+        .get(index)
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or_else(|| invalid("/proc/self/stat is missing the CPU times"))
+    };
+    // utime and stime are the 14th and 15th fields, counting the pid and command name
+    let cpu_ticks = ticks(11)? + ticks(12)?;
+
+    // Reading the directory opens one more descriptor, so it isn't counted
+    let open_fds = std::fs::read_dir("/proc/self/fd")?
+      .count()
+      .saturating_sub(1) as u64;
+
+    Ok(Resources {
+      rss_bytes: field("VmRSS:")? * 1024,
+      cpu_millis: cpu_ticks * 1000 / TICKS_PER_SECOND,
+      open_fds,
+      threads: field("Threads:")?,
+    })
+  }
+
+  fn invalid(msg: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, msg.to_string())
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+  use super::*;
+
+  pub fn snapshot() -> IoResult<Resources> {
+    Err(IoError::new(
+      ErrorKind::Unsupported,
+      "Resource snapshots are only available on Linux",
+    ))
+  }
+}
@@ -0,0 +1,152 @@
+//! Run an operation until it succeeds, logging each failed attempt
+//!
+//! The operation's record is written first and indented once. Every failed attempt is written
+//! under it with the error and the backoff before the next try, followed by an `outcome` key/value
+//! summing up how it ended:
+//!
+//! ```yaml
+//! Retrying fetch:
+//!   - fields:
+//!       attempt: 1
+//!       error: connection refused
+//!       backoff_ms: 100
+//!     message: Attempt 1 failed
+//!   - outcome: succeeded on attempt 2
+//! ```
+//!
+//! Problems writing the log are passed to the logger's error handler, so they never change the
+//! result of the operation.
+
+use std::io::Write;
+use std::time::Duration;
+
+use serde_yaml::Value as YmlValue;
+
+use crate::message::MessageType;
+use crate::prelude::*;
+
+/// How many times to try, and how long to wait between them
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Retry {
+  attempts: u32,
+  backoff: Duration,
+  factor: u32,
+}
+
+impl Default for Retry {
+  /// Three attempts, waiting 100ms and then 200ms
+  fn default() -> Retry {
+    Retry {
+      attempts: 3,
+      backoff: Duration::from_millis(100),
+      factor: 2,
+    }
+  }
+}
+
+impl Retry {
+  /// Try up to `attempts` times, waiting `backoff` after the first failure
+  pub fn new(attempts: u32, backoff: Duration) -> Retry {
+    Retry {
+      attempts: attempts.max(1),
+      backoff,
+      ..Default::default()
+    }
+  }
+
+  /// Multiply the backoff by the factor after each failure. The default is 2, and 1 keeps it fixed.
+  pub fn factor(mut self, factor: u32) -> Retry {
+    self.factor = factor.max(1);
+    self
+  }
+
+  /// Run the operation with the attempt number, starting at 1, logging to the given logger
+  pub fn run<W, R, E>(
+    &self,
+    logger: &YmLog<W>,
+    name: impl std::fmt::Display,
+    mut operation: impl FnMut(u32) -> Result<R, E>,
+  ) -> Result<R, E>
+  where
+    W: Write + Send + Sync + 'static,
+    E: std::fmt::Display,
+  {
+    let log = |block: &mut Block, actions: &str| {
+      if let Err(err) = logger.log(block, Some(actions)) {
+        logger.report(err);
+      }
+    };
+
+    let mut title = Block::new();
+    let _ = title.set_message(format!("Retrying {}", name));
+    log(&mut title, "_+");
+
+    let mut backoff = self.backoff;
+    let mut attempt = 1;
+    let result = loop {
+      let err = match operation(attempt) {
+        Ok(value) => break Ok(value),
+        Err(err) if attempt >= self.attempts => break Err(err),
+        Err(err) => err,
+      };
+
+      log(&mut failed(attempt, &err, Some(backoff)), "_");
+      std::thread::sleep(backoff);
+      backoff *= self.factor;
+      attempt += 1;
+    };
+
+    let mut summary = match &result {
+      Ok(_) => outcome(format!("succeeded on attempt {}", attempt), Level::Info),
+      Err(err) => {
+        log(&mut failed(attempt, err, None), "_");
+        outcome(format!("failed after {} attempts", attempt), Level::Error)
+      }
+    };
+    log(&mut summary, "_-");
+    result
+  }
+
+  /// Run the operation, logging to the global logger
+  pub fn log_attempts<R, E>(
+    &self,
+    name: impl std::fmt::Display,
+    operation: impl FnMut(u32) -> Result<R, E>,
+  ) -> Result<R, E>
+  where
+    E: std::fmt::Display,
+  {
+    self.run(crate::global(), name, operation)
+  }
+}
+
+/// Run the operation with the default [`Retry`], logging to the global logger
+pub fn log_attempts<R, E>(
+  name: impl std::fmt::Display,
+  operation: impl FnMut(u32) -> Result<R, E>,
+) -> Result<R, E>
+where
+  E: std::fmt::Display,
+{
+  Retry::default().log_attempts(name, operation)
+}
+
+/// A warning record of a failed attempt, with the wait before the next one if there is one
+fn failed(attempt: u32, err: &impl std::fmt::Display, backoff: Option<Duration>) -> Block {
+  let mut block = Block::new();
+  let _ = block.set_message(format!("Attempt {} failed", attempt));
+  block.set_log_level(Level::Warn);
+  let _ = block.add_field("attempt", attempt);
+  let _ = block.add_field("error", err.to_string());
+  if let Some(backoff) = backoff {
+    let _ = block.add_field("backoff_ms", backoff.as_millis() as u64);
+  }
+  block
+}
+
+fn outcome(summary: String, level: Level) -> Block {
+  let mut block = Block::new();
+  block.message = MessageType::KeyValue("outcome".into(), YmlValue::String(summary));
+  block.set_log_level(level);
+  block
+}
@@ -0,0 +1,63 @@
+//! Headers that make each run in a log describe itself
+//!
+//! Logs often hold many runs of a service, appended one after another. [`YmLog::start_run`]
+//! starts each one with a document saying when and where it ran, so a reader doesn't need to
+//! line the records up with deploys or process lists to tell the runs apart.
+
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+
+use serde::Serialize;
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use crate::message::MessageType;
+use crate::prelude::*;
+use crate::Timestamp;
+
+/// The tag the run headers are written with
+pub const RUN_TAG: &str = "ymlog/run";
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Start a new document with a header describing this run
+  ///
+  /// The header is a mapping of when the run started, the hostname, the process id and the ymlog
+  /// version, followed by the entries of `meta`. The metadata has to serialize to a mapping, or
+  /// be `()` when there is none.
+  pub fn start_run(&self, meta: &impl Serialize) -> IoResult<Option<RecordHandle>> {
+    let invalid = |err: String| IoError::new(ErrorKind::InvalidInput, err);
+    let mut header = Mapping::new();
+    header.insert("started".into(), Timestamp::now().to_rfc3339().into());
+    if let Some(hostname) = hostname() {
+      header.insert("hostname".into(), hostname.into());
+    }
+    header.insert("pid".into(), std::process::id().into());
+    header.insert("ymlog_version".into(), env!("CARGO_PKG_VERSION").into());
+    match serde_yaml::to_value(meta).map_err(|err| invalid(err.to_string()))? {
+      YmlValue::Mapping(meta) => header.extend(meta),
+      YmlValue::Null => (),
+      _ => return Err(invalid("The run metadata isn't a mapping".to_string())),
+    }
+
+    let mut block = Block::new();
+    block.message = MessageType::Value(YmlValue::Mapping(header));
+    block.set_tag_type(RUN_TAG);
+    // A record at the root already starts a new document
+    let actions = match self.current_depth() {
+      0 => None,
+      _ => Some("r"),
+    };
+    self.log(&mut block, actions)
+  }
+}
+
+/// The machine's name, from the environment or the kernel
+fn hostname() -> Option<String> {
+  std::env::var("HOSTNAME")
+    .ok()
+    .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+    .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+    .map(|name| name.trim().to_string())
+    .filter(|name| !name.is_empty())
+}
@@ -0,0 +1,76 @@
+//! Searches for the bytes that change how a string is written
+//!
+//! Every record's message is checked for newlines, every JSON string for characters that need
+//! escaping, and every YAML string for the `:`, `#` and other characters that stop it being written
+//! plain. These check eight bytes at a time with plain integer operations, which is several times
+//! faster than a byte loop on long messages without needing unsafe code or a dependency.
+
+use std::convert::TryInto;
+
+const ONES: u64 = 0x0101_0101_0101_0101;
+const HIGHS: u64 = 0x8080_8080_8080_8080;
+
+/// Check if any byte of the word is below the limit, which must be at most 128
+fn has_below(word: u64, limit: u8) -> bool {
+  word.wrapping_sub(ONES * u64::from(limit)) & !word & HIGHS != 0
+}
+
+/// Check if any byte of the word is the one spread across the pattern
+fn has_byte(word: u64, pattern: u64) -> bool {
+  has_below(word ^ pattern, 1)
+}
+
+/// Find the first of the bytes, any byte below `below`, or with `non_ascii` any byte of a
+/// non-ASCII character, in the text
+fn find<const N: usize>(text: &[u8], bytes: [u8; N], below: u8, non_ascii: bool) -> Option<usize> {
+  let patterns = bytes.map(|byte| ONES * u64::from(byte));
+  let is_match =
+    |byte: &u8| *byte < below || (non_ascii && !byte.is_ascii()) || bytes.contains(byte);
+  let mut chunks = text.chunks_exact(8);
+  for (i, chunk) in chunks.by_ref().enumerate() {
+    let word = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+    if has_below(word, below)
+      || (non_ascii && word & HIGHS != 0)
+      || patterns.iter().any(|pattern| has_byte(word, *pattern))
+    {
+      return chunk.iter().position(is_match).map(|offset| i * 8 + offset);
+    }
+  }
+  let start = text.len() - chunks.remainder().len();
+  chunks
+    .remainder()
+    .iter()
+    .position(is_match)
+    .map(|offset| start + offset)
+}
+
+/// Check if the text has a newline, so it must be written as a block
+pub(crate) fn has_newline(text: &str) -> bool {
+  find(text.as_bytes(), [b'\n'], 0, false).is_some()
+}
+
+/// Find the first character a JSON string has to escape: a quote, a backslash or a control
+/// character
+pub(crate) fn find_json_escape(text: &str) -> Option<usize> {
+  find(text.as_bytes(), [b'"', b'\\'], 0x20, false)
+}
+
+/// Find the first character that could stop a YAML string being written plain: a `:` or `#`, an
+/// ASCII control character, or the start of a non-ASCII character
+///
+/// Only a few non-ASCII characters have to be escaped, such as the byte order mark, so the caller
+/// checks the one found.
+pub(crate) fn find_yaml_special(text: &str) -> Option<usize> {
+  find(text.as_bytes(), [b':', b'#', 0x7f], 0x20, true)
+}
+
+/// Check if the text has one of the characters that end a plain scalar inside a flow collection
+pub(crate) fn has_flow_indicator(text: &str) -> bool {
+  find(
+    text.as_bytes(),
+    [b',', b'[', b']', b'{', b'}', b'#', b':'],
+    0,
+    false,
+  )
+  .is_some()
+}
@@ -0,0 +1,279 @@
+//! Writers for the standard streams, and opening log files
+//!
+//! Each record is written while holding the stream's lock, so output from other threads and
+//! `println!` can't land in the middle of it, including records written in pieces with
+//! `write_vectored`. Stdout is line buffered by the standard library,
+//! which would hold back the last line of each record, so it is flushed after every record when
+//! it is a terminal. Piped output stays buffered until the logger is flushed.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, IoSlice, IsTerminal, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::prelude::*;
+use crate::reader::parse;
+
+/// How much of a log is read at first when looking for its last document
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+/// Writes the log to stdout
+#[derive(Debug)]
+pub struct Stdout {
+  handle: std::io::Stdout,
+  terminal: bool,
+}
+
+impl Stdout {
+  pub fn new() -> Stdout {
+    let handle = std::io::stdout();
+    Stdout {
+      terminal: handle.is_terminal(),
+      handle,
+    }
+  }
+
+  /// Check if stdout is a terminal
+  pub fn is_terminal(&self) -> bool {
+    self.terminal
+  }
+}
+
+impl Default for Stdout {
+  fn default() -> Stdout {
+    Stdout::new()
+  }
+}
+
+impl Write for Stdout {
+  fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    self.handle.lock().write(buf)
+  }
+
+  fn flush(&mut self) -> IoResult<()> {
+    self.handle.lock().flush()
+  }
+
+  fn write_all(&mut self, buf: &[u8]) -> IoResult<()> {
+    let mut handle = self.handle.lock();
+    handle.write_all(buf)?;
+    match self.terminal {
+      true => handle.flush(),
+      false => Ok(()),
+    }
+  }
+
+  /// Write every piece while holding the lock
+  fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IoResult<usize> {
+    let mut handle = self.handle.lock();
+    for buf in bufs {
+      handle.write_all(buf)?;
+    }
+    if self.terminal {
+      handle.flush()?;
+    }
+    Ok(bufs.iter().map(|buf| buf.len()).sum())
+  }
+}
+
+/// Writes the log to stderr, which isn't buffered
+#[derive(Debug)]
+pub struct Stderr {
+  handle: std::io::Stderr,
+  terminal: bool,
+}
+
+impl Stderr {
+  pub fn new() -> Stderr {
+    let handle = std::io::stderr();
+    Stderr {
+      terminal: handle.is_terminal(),
+      handle,
+    }
+  }
+
+  /// Check if stderr is a terminal
+  pub fn is_terminal(&self) -> bool {
+    self.terminal
+  }
+}
+
+impl Default for Stderr {
+  fn default() -> Stderr {
+    Stderr::new()
+  }
+}
+
+impl Write for Stderr {
+  fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    self.handle.lock().write(buf)
+  }
+
+  fn flush(&mut self) -> IoResult<()> {
+    self.handle.lock().flush()
+  }
+
+  fn write_all(&mut self, buf: &[u8]) -> IoResult<()> {
+    self.handle.lock().write_all(buf)
+  }
+
+  /// Write every piece while holding the lock
+  fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IoResult<usize> {
+    let mut handle = self.handle.lock();
+    for buf in bufs {
+      handle.write_all(buf)?;
+    }
+    Ok(bufs.iter().map(|buf| buf.len()).sum())
+  }
+}
+
+impl YmLog<Stderr> {
+  /// Create a logger writing to stderr
+  pub fn to_stderr() -> YmLog<Stderr> {
+    let logger = YmLog::new();
+    logger.set_output(Stderr::new());
+    logger
+  }
+}
+
+impl YmLog<Stdout> {
+  /// Create a logger writing to stdout
+  pub fn to_stdout() -> YmLog<Stdout> {
+    let logger = YmLog::new();
+    logger.set_output(Stdout::new());
+    logger
+  }
+}
+
+/// What to do with a log file that already exists
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FileMode {
+  /// Add to the end of it, creating it if it doesn't exist
+  Append,
+
+  /// Empty it first, creating it if it doesn't exist
+  Truncate,
+
+  /// Fail with an `AlreadyExists` error, so an earlier log is never touched
+  CreateNew,
+}
+
+impl FileMode {
+  /// Open the file for writing the log, creating any missing parent directories first
+  pub fn open(self, path: impl AsRef<Path>) -> IoResult<File> {
+    let path = path.as_ref();
+    if let Some(parent) = path
+      .parent()
+      .filter(|parent| !parent.as_os_str().is_empty())
+    {
+      std::fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    match self {
+      FileMode::Append => options.append(true).create(true),
+      FileMode::Truncate => options.write(true).truncate(true).create(true),
+      FileMode::CreateNew => options.write(true).create_new(true),
+    };
+    options.open(path)
+  }
+}
+
+impl YmLog<File> {
+  /// Create a logger writing to the file
+  pub fn to_file(path: impl AsRef<Path>, mode: FileMode) -> IoResult<YmLog<File>> {
+    let logger = YmLog::new();
+    logger.set_output_path(path, mode)?;
+    Ok(logger)
+  }
+
+  /// Write the log to the file alone, replacing every output added before
+  ///
+  /// Appending to a log that was left open starts a new document on a new line, since how the
+  /// records before were nested is unknown. [`YmLog::resume_file`] carries on nesting under them.
+  pub fn set_output_path(&self, path: impl AsRef<Path>, mode: FileMode) -> IoResult<()> {
+    let path = path.as_ref();
+    let file = mode.open(path)?;
+    let left_open = mode == FileMode::Append && !ends_with_newline(path)?;
+    self.set_output(file);
+    match left_open {
+      true => self.skip(Some("r")),
+      false => Ok(()),
+    }
+  }
+
+  /// Carry on appending to a YAML log that was left open, nesting under the records before
+  ///
+  /// The last document is read back to find how its records were nested, so the next record is
+  /// written as a sibling of the last one. A log that was closed, or whose last document can't be
+  /// read back, gets a new document instead.
+  pub fn resume_file(path: impl AsRef<Path>) -> IoResult<YmLog<File>> {
+    let path = path.as_ref();
+    let file = FileMode::Append.open(path)?;
+    let logger = YmLog::new();
+    if ends_with_newline(path)? {
+      logger.set_output(file);
+      return Ok(logger);
+    }
+
+    let (document, offset) = last_document(path)?;
+    let root = parse(Cursor::new(document)).last();
+    logger.set_output(file);
+    match root {
+      Some(Ok(root)) => {
+        logger.replay(root, offset);
+        Ok(logger)
+      }
+      _ => logger.skip(Some("r")).map(|_| logger),
+    }
+  }
+}
+
+/// Read the last document of a log, from its `---` line to the end, along with the file's length
+///
+/// The file is read backwards in growing chunks, so only the tail of a long log is read.
+fn last_document(path: &Path) -> IoResult<(Vec<u8>, u64)> {
+  let mut file = File::open(path)?;
+  let len = file.metadata()?.len();
+  let mut start = len;
+  let mut tail = Vec::new();
+  while start > 0 {
+    let read = start.min(TAIL_CHUNK.max(tail.len() as u64));
+    start -= read;
+    let mut chunk = vec![0; read as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut chunk)?;
+    chunk.extend_from_slice(&tail);
+    tail = chunk;
+
+    if let Some(at) = document_start(&tail, start == 0) {
+      tail.drain(..at);
+      break;
+    }
+  }
+  Ok((tail, len))
+}
+
+/// Find the start of the last `---` line, which is only known to start a line at the file's start
+/// or after a newline
+fn document_start(text: &[u8], at_file_start: bool) -> Option<usize> {
+  (0..text.len()).rev().find(|&at| {
+    let starts_line = match at {
+      0 => at_file_start,
+      _ => text[at - 1] == b'\n',
+    };
+    starts_line
+      && text[at..].starts_with(b"---")
+      && matches!(text.get(at + 3), None | Some(b'\n') | Some(b' '))
+  })
+}
+
+/// Check if the file is empty or ends a line, as a log closed by the logger does
+fn ends_with_newline(path: &Path) -> IoResult<bool> {
+  let mut file = File::open(path)?;
+  if file.metadata()?.len() == 0 {
+    return Ok(true);
+  }
+  let mut last = [0];
+  file.seek(SeekFrom::End(-1))?;
+  file.read_exact(&mut last)?;
+  Ok(last[0] == b'\n')
+}
@@ -0,0 +1,97 @@
+//! Timed sections of the log
+//!
+//! A span writes its opening record and indents, so everything logged while it is open is nested
+//! underneath. Ending it, or dropping it, writes a `duration_ms` pair as its last child and dedents
+//! back to where it was opened, so each stage of a pipeline gets its timing for free.
+
+use std::io::{Result as IoResult, Write};
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+
+/// The key of the pair closing a span
+pub const DURATION_KEY: &str = "duration_ms";
+
+impl<T> YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Write the block and indent the log until the returned span ends
+  pub fn span(&self, block: &mut Block) -> IoResult<Span<'_, T>> {
+    let handle = self.log(block, Some("_+"))?;
+    Ok(Span {
+      logger: self,
+      started: Instant::now(),
+      level: *block.log_level(),
+      handle,
+      open: true,
+    })
+  }
+}
+
+/// A section of the log that is still open, closed with its duration when it ends or is dropped
+pub struct Span<'a, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  logger: &'a YmLog<T>,
+  started: Instant,
+  level: Level,
+  handle: Option<RecordHandle>,
+  open: bool,
+}
+
+impl<T> Span<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Where the opening record was written, if it wasn't filtered out
+  pub fn handle(&self) -> Option<&RecordHandle> {
+    self.handle.as_ref()
+  }
+
+  /// The time since the span was opened
+  pub fn elapsed(&self) -> Duration {
+    self.started.elapsed()
+  }
+
+  /// Write the duration under the opening record and dedent the log
+  pub fn end(mut self) -> IoResult<Option<RecordHandle>> {
+    self.close()
+  }
+
+  fn close(&mut self) -> IoResult<Option<RecordHandle>> {
+    self.open = false;
+    let mut block = Block::new();
+    let _ = block.set_key_value(DURATION_KEY, self.elapsed().as_secs_f64() * 1000.0);
+    // The same level as the opening record, so filters keep or drop both
+    block.set_log_level(self.level);
+    self.logger.log(&mut block, Some("_-"))
+  }
+}
+
+impl<T> Drop for Span<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  fn drop(&mut self) {
+    if self.open {
+      if let Err(err) = self.close() {
+        self.logger.report(err);
+      }
+    }
+  }
+}
+
+impl<T> std::fmt::Debug for Span<'_, T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Span")
+      .field("started", &self.started)
+      .field("level", &self.level)
+      .field("handle", &self.handle)
+      .finish()
+  }
+}
@@ -0,0 +1,122 @@
+//! Quoting for loaders that don't agree on what a plain scalar means
+//!
+//! The records are written by the YAML 1.2 core schema, where `yes`, `on` and `1:30` are strings.
+//! Loaders still following YAML 1.1 read them as booleans and sexagesimal numbers, and strict 1.2
+//! loaders reject some of them. In strict mode every plain scalar that any of them could read as
+//! something other than a string is quoted, so all of them get the same value back.
+
+use crate::color::split_key;
+
+/// Quote the plain scalars in a serialized record that a YAML 1.1 loader would type differently
+pub(crate) fn quote_ambiguous(record: &str) -> String {
+  let mut out = String::with_capacity(record.len() + 8);
+  // The indentation of the line that started the block scalar being written
+  let mut scalar_indent: Option<usize> = None;
+  for (i, line) in record.split('\n').enumerate() {
+    if i > 0 {
+      out.push('\n');
+    }
+
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if let Some(start) = scalar_indent {
+      if indent > start || line.trim().is_empty() {
+        out.push_str(line);
+        continue;
+      }
+      scalar_indent = None;
+    }
+
+    let rest = &line[indent..];
+    if rest.starts_with("---") || rest == "..." || rest.starts_with('#') {
+      out.push_str(line);
+      continue;
+    }
+
+    // Sequence items and the explicit key and value indicators come before the scalars
+    let mut body = rest;
+    while let Some(item) = ["- ", "? ", ": "]
+      .iter()
+      .find_map(|indicator| body.strip_prefix(indicator))
+      .or_else(|| body.strip_prefix(['-', '?', ':']).filter(|r| r.is_empty()))
+    {
+      body = item;
+    }
+    out.push_str(&line[..line.len() - body.len()]);
+
+    let value = match split_key(body) {
+      Some((key, separator, value)) => {
+        push_scalar(&mut out, key);
+        out.push_str(separator);
+        value
+      }
+      None => body,
+    };
+    push_scalar(&mut out, value);
+
+    if value.starts_with('|') || value.starts_with('>') {
+      scalar_indent = Some(indent);
+    }
+  }
+  out
+}
+
+/// Write a key or value, quoting it if it is an ambiguous plain scalar
+fn push_scalar(out: &mut String, text: &str) {
+  // A tag stays in front of the scalar it applies to
+  let (tag, scalar) = match text.starts_with('!') {
+    true => match text.find(' ') {
+      Some(end) => text.split_at(end + 1),
+      None => (text, ""),
+    },
+    false => ("", text),
+  };
+  out.push_str(tag);
+  match is_ambiguous(scalar) {
+    true => {
+      out.push('\'');
+      out.push_str(&scalar.replace('\'', "''"));
+      out.push('\'');
+    }
+    false => out.push_str(scalar),
+  }
+}
+
+/// Check if a plain scalar could be read as something other than a string
+pub(crate) fn is_ambiguous(scalar: &str) -> bool {
+  const WORDS: [&str; 12] = [
+    "y", "n", "yes", "no", "on", "off", "true", "false", "null", "~", "=", "<<",
+  ];
+  if WORDS.iter().any(|word| word.eq_ignore_ascii_case(scalar)) {
+    return true;
+  }
+
+  let unsigned = scalar.trim_start_matches(['-', '+']);
+  let starts_with_digit = unsigned.starts_with(|c: char| c.is_ascii_digit());
+  let numeric = |c: char| c.is_ascii_digit() || c == '_' || c == '.';
+  // Numbers with digit separators, like 1_000
+  if starts_with_digit && unsigned.contains('_') && unsigned.chars().all(numeric) {
+    return true;
+  }
+  // Sexagesimal numbers, like 1:30 or 190:20:30.15
+  if starts_with_digit && unsigned.contains(':') && unsigned.chars().all(|c| numeric(c) || c == ':')
+  {
+    return true;
+  }
+  is_date(scalar)
+}
+
+/// Check if a scalar starts with a date like 2001-12-14, which YAML 1.1 reads as a timestamp
+fn is_date(scalar: &str) -> bool {
+  let mut parts = scalar.splitn(3, '-');
+  let digits = |part: &str, min: usize, max: usize| {
+    (min..=max).contains(&part.len()) && part.chars().all(|c| c.is_ascii_digit())
+  };
+  let (year, month, rest) = match (parts.next(), parts.next(), parts.next()) {
+    (Some(year), Some(month), Some(rest)) => (year, month, rest),
+    _ => return false,
+  };
+  let day_len = rest
+    .find(|c: char| !c.is_ascii_digit())
+    .unwrap_or(rest.len());
+  digits(year, 4, 4) && digits(month, 1, 2) && (1..=2).contains(&day_len)
+}
@@ -0,0 +1,148 @@
+//! Limits on how many records are written from hot loops
+//!
+//! Blocks are kept at random with a sampling rate, and then by a token bucket per key, which is
+//! either the block's tags or the start of its message. A bucket holds up to `burst` records and
+//! refills at `per_second`. Once a throttled key gets a record through again, a
+//! `!ymlog/suppressed` record saying how many were left out is written before it.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Instant;
+
+use crate::prelude::*;
+
+/// What the records are grouped by for rate limiting
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RateKey {
+  /// The block's tags, so untagged blocks aren't limited
+  Tags,
+
+  /// The first characters of a string message, or the key of a key/value message
+  Prefix(usize),
+}
+
+/// A token bucket applied to each key separately
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+  key: RateKey,
+  burst: f64,
+  per_second: f64,
+}
+
+impl RateLimit {
+  /// Allow bursts of up to `burst` records per key, refilling at `per_second`
+  pub fn new(key: RateKey, burst: u32, per_second: f64) -> RateLimit {
+    RateLimit {
+      key,
+      burst: f64::from(burst.max(1)),
+      per_second: per_second.max(0.0),
+    }
+  }
+
+  /// The key the block is limited under, if it has one
+  fn key_for(&self, block: &Block) -> Option<String> {
+    match &self.key {
+      RateKey::Tags => match block.tags() {
+        [] => None,
+        tags => Some(tags.join(",")),
+      },
+      RateKey::Prefix(len) => {
+        let text = match block.key_value() {
+          Some((key, _)) => key.as_str()?,
+          None => block.text()?,
+        };
+        Some(text.chars().take(*len).collect())
+      }
+    }
+  }
+}
+
+struct Bucket {
+  tokens: f64,
+  filled: Instant,
+  suppressed: u64,
+}
+
+/// What the throttle decided for a block
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Verdict {
+  /// Write the block, after a summary of the records with the same key left out before it
+  Keep(Option<(String, u64)>),
+
+  /// The sampling rate left it out
+  Sampled,
+
+  /// Its key is out of tokens
+  Throttled,
+}
+
+#[derive(Default)]
+pub(crate) struct Throttle {
+  /// The chance of each block being kept, if the records are sampled
+  pub rate: Option<f64>,
+
+  pub limit: Option<RateLimit>,
+
+  buckets: HashMap<String, Bucket>,
+}
+
+impl Throttle {
+  pub fn check(&mut self, block: &Block) -> Verdict {
+    if let Some(rate) = self.rate {
+      let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+      if random >= rate {
+        return Verdict::Sampled;
+      }
+    }
+
+    let (limit, key) = match &self.limit {
+      Some(limit) => match limit.key_for(block) {
+        Some(key) => (limit, key),
+        None => return Verdict::Keep(None),
+      },
+      None => return Verdict::Keep(None),
+    };
+    let now = Instant::now();
+    let bucket = self.buckets.entry(key.clone()).or_insert(Bucket {
+      tokens: limit.burst,
+      filled: now,
+      suppressed: 0,
+    });
+    let refill = now.duration_since(bucket.filled).as_secs_f64() * limit.per_second;
+    bucket.tokens = (bucket.tokens + refill).min(limit.burst);
+    bucket.filled = now;
+
+    if bucket.tokens < 1.0 {
+      bucket.suppressed += 1;
+      return Verdict::Throttled;
+    }
+    bucket.tokens -= 1.0;
+    match std::mem::take(&mut bucket.suppressed) {
+      0 => Verdict::Keep(None),
+      count => Verdict::Keep(Some((key, count))),
+    }
+  }
+
+  /// Take the counts of records suppressed since their key last got one through
+  pub fn take_suppressed(&mut self) -> u64 {
+    self
+      .buckets
+      .values_mut()
+      .map(|bucket| std::mem::take(&mut bucket.suppressed))
+      .sum()
+  }
+}
+
+/// A record saying how many records with the key were left out
+pub(crate) fn summary(key: &str, count: u64) -> Block {
+  let mut block = Block::new();
+  let noun = match count {
+    1 => "message",
+    _ => "messages",
+  };
+  let _ = block.set_message(format!("Suppressed {} similar {}", count, noun));
+  let _ = block.add_field("key", key);
+  block.set_tag_type("ymlog/suppressed");
+  block
+}
@@ -0,0 +1,268 @@
+//! The times records are stamped with
+//!
+//! A [`Timestamp`] is kept as the time since the Unix epoch, so stamping records and reading them
+//! back needs nothing beyond the standard library. They are written in RFC 3339 in UTC, such as
+//! `2024-03-01T12:30:00.123+00:00`, and read in it with any offset. With the default `chrono`
+//! feature they convert to and from `chrono::DateTime<Utc>`.
+
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+/// A point in time to the nanosecond
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+  /// Whole seconds since the Unix epoch, negative before it
+  secs: i64,
+
+  /// Nanoseconds into the second
+  nanos: u32,
+}
+
+impl Timestamp {
+  /// The current time
+  pub fn now() -> Timestamp {
+    SystemTime::now().into()
+  }
+
+  /// The time the number of milliseconds after the Unix epoch
+  pub fn from_millis(millis: i64) -> Timestamp {
+    Timestamp {
+      secs: millis.div_euclid(1000),
+      nanos: millis.rem_euclid(1000) as u32 * 1_000_000,
+    }
+  }
+
+  /// The number of milliseconds since the Unix epoch
+  pub fn millis(&self) -> i64 {
+    self
+      .secs
+      .saturating_mul(1000)
+      .saturating_add((self.nanos / 1_000_000) as i64)
+  }
+
+  /// Read an RFC 3339 time, such as `2024-03-01T12:30:00Z` or `2024-03-01 13:30:00.5+01:00`
+  ///
+  /// Digits past nanoseconds are dropped. Returns None if the text isn't one.
+  pub fn parse_rfc3339(text: &str) -> Option<Timestamp> {
+    let bytes = text.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+      let digits = bytes.get(range)?;
+      match digits.iter().all(u8::is_ascii_digit) {
+        true => Some(digits.iter().fold(0, |acc, c| acc * 10 + (c - b'0') as i64)),
+        false => None,
+      }
+    };
+    let at = |i: usize, allowed: &[u8]| bytes.get(i).is_some_and(|c| allowed.contains(c));
+    if !(at(4, b"-") && at(7, b"-") && at(10, b"Tt ") && at(13, b":") && at(16, b":")) {
+      return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    let valid = (1..=12).contains(&month)
+      && (1..=days_in_month(year, month)).contains(&day)
+      && hour < 24
+      && minute < 60
+      && second <= 60;
+    if !valid {
+      return None;
+    }
+
+    let mut rest = &bytes[19..];
+    let mut nanos = 0;
+    if let Some((b'.', fraction)) = rest.split_first() {
+      let digits = fraction.iter().take_while(|c| c.is_ascii_digit()).count();
+      if digits == 0 {
+        return None;
+      }
+      nanos = fraction[..digits]
+        .iter()
+        .chain(std::iter::repeat(&b'0'))
+        .take(9)
+        .fold(0, |acc, c| acc * 10 + (c - b'0') as u32);
+      rest = &fraction[digits..];
+    }
+    let offset = match rest {
+      [b'Z' | b'z'] => 0,
+      [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+        let hours = number(text.len() - 5..text.len() - 3)?;
+        let minutes = number(text.len() - 2..text.len())?;
+        if hours >= 24 || minutes >= 60 {
+          return None;
+        }
+        let offset = hours * 3600 + minutes * 60;
+        match sign {
+          b'+' => offset,
+          _ => -offset,
+        }
+      }
+      _ => return None,
+    };
+
+    let secs =
+      days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second;
+    Some(Timestamp {
+      secs: secs - offset,
+      nanos,
+    })
+  }
+
+  /// Write the time in RFC 3339 in UTC, with as many digits of the second as it needs in
+  /// threes, such as `2024-03-01T12:30:05.250+00:00`
+  pub fn to_rfc3339(&self) -> String {
+    self.to_string()
+  }
+}
+
+impl fmt::Display for Timestamp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let (year, month, day) = civil_from_days(self.secs.div_euclid(SECS_PER_DAY));
+    let secs = self.secs.rem_euclid(SECS_PER_DAY);
+    write!(
+      f,
+      "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+      year,
+      month,
+      day,
+      secs / 3600,
+      secs / 60 % 60,
+      secs % 60
+    )?;
+    match self.nanos {
+      0 => (),
+      nanos if nanos % 1_000_000 == 0 => write!(f, ".{:03}", nanos / 1_000_000)?,
+      nanos if nanos % 1000 == 0 => write!(f, ".{:06}", nanos / 1000)?,
+      nanos => write!(f, ".{:09}", nanos)?,
+    }
+    write!(f, "+00:00")
+  }
+}
+
+impl std::str::FromStr for Timestamp {
+  type Err = IoError;
+
+  /// Parse an RFC 3339 time, like [`Timestamp::parse_rfc3339`]
+  fn from_str(text: &str) -> IoResult<Timestamp> {
+    Timestamp::parse_rfc3339(text).ok_or_else(|| {
+      IoError::new(
+        ErrorKind::InvalidInput,
+        format!("{:?} is not an RFC 3339 time", text),
+      )
+    })
+  }
+}
+
+impl serde::Serialize for Timestamp {
+  /// Timestamps are written in RFC 3339
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> serde::Deserialize<'de> for Timestamp {
+  /// Read an RFC 3339 time
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+impl From<SystemTime> for Timestamp {
+  fn from(time: SystemTime) -> Timestamp {
+    match time.duration_since(UNIX_EPOCH) {
+      Ok(since) => Timestamp {
+        secs: since.as_secs() as i64,
+        nanos: since.subsec_nanos(),
+      },
+      Err(err) => {
+        let before = err.duration();
+        match before.subsec_nanos() {
+          0 => Timestamp {
+            secs: -(before.as_secs() as i64),
+            nanos: 0,
+          },
+          nanos => Timestamp {
+            secs: -(before.as_secs() as i64) - 1,
+            nanos: NANOS_PER_SEC - nanos,
+          },
+        }
+      }
+    }
+  }
+}
+
+impl From<Timestamp> for SystemTime {
+  fn from(time: Timestamp) -> SystemTime {
+    match time.secs {
+      secs if secs >= 0 => UNIX_EPOCH + Duration::new(secs as u64, time.nanos),
+      secs => {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+          + Duration::from_nanos(time.nanos as u64)
+      }
+    }
+  }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Timestamp {
+  /// Convert from chrono, where a leap second is the last nanosecond of the second before it
+  fn from(time: chrono::DateTime<chrono::Utc>) -> Timestamp {
+    Timestamp {
+      secs: time.timestamp(),
+      nanos: time.timestamp_subsec_nanos().min(NANOS_PER_SEC - 1),
+    }
+  }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for chrono::DateTime<chrono::Utc> {
+  /// Convert to chrono, which has a narrower range: times beyond it become the Unix epoch
+  fn from(time: Timestamp) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::from_timestamp(time.secs, time.nanos).unwrap_or_default()
+  }
+}
+
+fn is_leap_year(year: i64) -> bool {
+  year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+  match month {
+    2 if is_leap_year(year) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+/// The days from the Unix epoch to the date, after Howard Hinnant's algorithm
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+/// The date the days from the Unix epoch fall on
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719_468;
+  let era = days.div_euclid(146_097);
+  let day_of_era = days - era * 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 {
+    shifted_month + 3
+  } else {
+    shifted_month - 9
+  };
+  let year = year_of_era + era * 400 + (month <= 2) as i64;
+  (year, month, day)
+}
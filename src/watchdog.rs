@@ -0,0 +1,100 @@
+//! Noticing scopes that stay open without any records written under them
+//!
+//! A step that hangs usually goes quiet while its scope is still open, so nothing in the log says
+//! anything is wrong until something else times out. The watchdog keeps the scopes open in the
+//! first output along with when each was opened, and warns once about the innermost one when no
+//! record has been written under it for too long.
+
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+use crate::Timestamp;
+
+/// The tag the warnings about stalled scopes are written with
+pub const STALLED_TAG: &str = "ymlog/stalled";
+
+/// The open scopes, and when a record was last written under them
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+  /// How long a scope can go without a record before it is stalled
+  limit: Duration,
+
+  scopes: Vec<Scope>,
+
+  /// The message of the last record written, which names the scope opened after it
+  last_message: String,
+  last_record: Instant,
+}
+
+/// A record that the records after it are nested under
+#[derive(Debug)]
+struct Scope {
+  name: String,
+  started: Timestamp,
+  opened: Instant,
+
+  /// Set once it has been warned about, until a record is written under it
+  warned: bool,
+}
+
+impl Watchdog {
+  pub(crate) fn new(limit: Duration) -> Watchdog {
+    Watchdog {
+      limit,
+      scopes: vec![],
+      last_message: String::new(),
+      last_record: Instant::now(),
+    }
+  }
+
+  /// How long to wait between checks
+  pub(crate) fn period(&self) -> Duration {
+    (self.limit / 4).max(Duration::from_millis(1))
+  }
+
+  /// Note a record being written
+  pub(crate) fn wrote(&mut self, block: &Block) {
+    self.last_message = block.message_text().into_owned();
+    self.last_record = Instant::now();
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.warned = false;
+    }
+  }
+
+  /// Match the scopes to the number of levels open under the root, opening new ones under the
+  /// last record written
+  pub(crate) fn open(&mut self, open: usize) {
+    self.scopes.truncate(open);
+    while self.scopes.len() < open {
+      self.scopes.push(Scope {
+        name: self.last_message.clone(),
+        started: Timestamp::now(),
+        opened: Instant::now(),
+        warned: false,
+      });
+    }
+  }
+
+  /// A warning for the innermost scope, if it has stalled since it was last warned about
+  pub(crate) fn check(&mut self) -> Option<Block> {
+    let last_record = self.last_record;
+    let scope = self.scopes.last_mut().filter(|scope| !scope.warned)?;
+    let quiet = scope.opened.max(last_record).elapsed();
+    if quiet < self.limit {
+      return None;
+    }
+    scope.warned = true;
+
+    let mut block = Block::new();
+    let _ = block.set_message(format!(
+      "No records for {:.1}s under {:?}",
+      quiet.as_secs_f64(),
+      scope.name
+    ));
+    block.set_log_level(Level::Warn);
+    let _ = block.add_field("scope", &scope.name);
+    let _ = block.add_field("started", scope.started.to_rfc3339());
+    block.set_tag_type(STALLED_TAG);
+    Some(block)
+  }
+}
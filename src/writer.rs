@@ -0,0 +1,990 @@
+//! Destinations for the serialized log
+//!
+//! The logger either writes directly to its output, or hands the serialized blocks to a background
+//! thread so the caller never waits on the I/O. Outputs that can stall, such as a network
+//! collector, can also be written from a thread that each record waits on for a limited time.
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{
+  BufWriter, Error as IoError, ErrorKind, IoSlice, IsTerminal, LineWriter, Result as IoResult,
+  Stderr, Stdout, Write,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::logger::Tracker;
+use crate::pipeline::Pipeline;
+use crate::prelude::*;
+use crate::repeats::Repeats;
+use crate::sinks;
+use crate::Timestamp;
+
+/// A serialized record in the pieces it was built from, such as the separator and the YAML
+///
+/// Direct outputs get the pieces in a single `write_vectored` call where the writer supports it,
+/// so they are never copied into one string. Writers without vectored support get one write per
+/// piece, and background outputs join them before queueing.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub(crate) struct Fragments {
+  parts: Vec<Cow<'static, str>>,
+}
+
+impl Fragments {
+  pub fn push(&mut self, part: impl Into<Cow<'static, str>>) {
+    let part = part.into();
+    if !part.is_empty() {
+      self.parts.push(part);
+    }
+  }
+
+  /// The length of the record in bytes
+  pub fn len(&self) -> usize {
+    self.parts.iter().map(|part| part.len()).sum()
+  }
+
+  /// Remove the whitespace at the end of the record
+  pub fn trim_end(&mut self) {
+    while let Some(last) = self.parts.last_mut() {
+      let trimmed = last.trim_end().len();
+      match trimmed {
+        0 => {
+          self.parts.pop();
+        }
+        _ => {
+          if trimmed < last.len() {
+            last.to_mut().truncate(trimmed);
+          }
+          return;
+        }
+      }
+    }
+  }
+
+  /// The last piece, or nothing if there are none
+  pub fn last(&self) -> &str {
+    self.parts.last().map_or("", |part| part)
+  }
+
+  /// Add a piece before the last one
+  pub fn insert_before_last(&mut self, part: impl Into<Cow<'static, str>>) {
+    let at = self.parts.len().saturating_sub(1);
+    self.parts.insert(at, part.into());
+  }
+
+  pub fn join(self) -> String {
+    match self.parts.len() {
+      1 => self
+        .parts
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+        .into_owned(),
+      _ => self.parts.concat(),
+    }
+  }
+
+  /// Write every piece, continuing after partial writes
+  fn write_to(&self, out: &mut impl Write) -> IoResult<()> {
+    if let [part] = self.parts.as_slice() {
+      return out.write_all(part.as_bytes());
+    }
+    let mut slices = self
+      .parts
+      .iter()
+      .map(|part| IoSlice::new(part.as_bytes()))
+      .collect::<Vec<_>>();
+    let mut slices = slices.as_mut_slice();
+    while !slices.is_empty() {
+      match out.write_vectored(slices) {
+        Ok(0) => {
+          return Err(IoError::new(
+            ErrorKind::WriteZero,
+            "The output stopped taking the record",
+          ))
+        }
+        Ok(written) => IoSlice::advance_slices(&mut slices, written),
+        Err(err) if err.kind() == ErrorKind::Interrupted => (),
+        Err(err) => return Err(err),
+      }
+    }
+    Ok(())
+  }
+}
+
+impl From<String> for Fragments {
+  fn from(value: String) -> Fragments {
+    let mut fragments = Fragments::default();
+    fragments.push(value);
+    fragments
+  }
+}
+
+/// The requests the background writer thread will handle, in the order they were sent
+enum Command {
+  /// Write the serialized block to the output, reporting back if asked to
+  Write(String, Option<Sender<IoResult<()>>>),
+
+  /// Flush the output, syncing it to disk if asked, and report back once everything queued
+  /// before it has been written
+  Flush(Sender<IoResult<()>>, bool),
+
+  /// Change when the output is flushed
+  Policy(FlushPolicy),
+}
+
+/// When records buffered for an output are written out to it
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum FlushPolicy {
+  /// Write each record to the output as soon as it is logged, without buffering
+  #[default]
+  EveryRecord,
+
+  /// Write the buffer once it holds at least this many bytes
+  Bytes(usize),
+
+  /// Write the buffer when a record is logged this long after the last write. There is no timer,
+  /// so the last records stay buffered until another is logged or the logger is flushed.
+  Interval(Duration),
+
+  /// Only write the buffer when the logger is flushed or dropped
+  OnDrop,
+
+  /// Measure how fast records are logged, writing each one while they are slow and batching them
+  /// once they come in faster than a thousand a second
+  ///
+  /// Batches are written once they reach 64 KiB or are 100ms old, and as soon as the records slow
+  /// down again. Like [`FlushPolicy::Interval`] there is no timer, so the end of a burst stays
+  /// buffered until another record is logged or the logger is flushed.
+  Adaptive,
+}
+
+/// How long, on average, records must be apart for the adaptive policy to write each one
+const ADAPTIVE_INTERACTIVE: Duration = Duration::from_millis(1);
+/// The most the adaptive policy buffers before writing
+const ADAPTIVE_BYTES: usize = 64 * 1024;
+/// The longest the adaptive policy holds a batch, checked as records are logged
+const ADAPTIVE_LATENCY: Duration = Duration::from_millis(100);
+
+/// A moving average of the time between records
+struct RecordRate {
+  last: Instant,
+  /// The average gap in seconds, starting out slow so the first records are written right away
+  interval: f64,
+}
+
+impl RecordRate {
+  fn new() -> RecordRate {
+    RecordRate {
+      last: Instant::now(),
+      interval: 1.0,
+    }
+  }
+
+  /// Count a record, checking if they are coming in slowly enough to write one at a time
+  fn record(&mut self) -> bool {
+    let now = Instant::now();
+    let gap = now.duration_since(self.last);
+    self.last = now;
+    self.interval += (gap.as_secs_f64() - self.interval) / 8.0;
+    gap >= ADAPTIVE_INTERACTIVE * 10 || self.interval >= ADAPTIVE_INTERACTIVE.as_secs_f64()
+  }
+}
+
+/// How one of the logger's outputs is doing, from [`YmLog::health`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SinkHealth {
+  pub status: SinkStatus,
+
+  /// The bytes logged that haven't been written to the output yet
+  pub queued_bytes: u64,
+}
+
+/// Whether an output is taking records
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SinkStatus {
+  Ok,
+
+  /// Records are still being taken, but not written as usual
+  Degraded(String),
+
+  /// Every write has failed since the time given, with the last error
+  Failed {
+    since: Timestamp,
+    error: String,
+  },
+}
+
+/// The writes to an output that have failed in a row
+#[derive(Debug, Clone)]
+struct Failure {
+  since: Timestamp,
+  error: String,
+}
+
+impl Failure {
+  /// Update the failure with the result of a write, clearing it on success
+  fn track(failure: &mut Option<Failure>, result: &IoResult<()>) {
+    match (result, failure.as_mut()) {
+      (Ok(()), _) => *failure = None,
+      (Err(err), Some(failure)) => failure.error = err.to_string(),
+      (Err(err), None) => {
+        *failure = Some(Failure {
+          since: Timestamp::now(),
+          error: err.to_string(),
+        })
+      }
+    }
+  }
+}
+
+/// Copy an error to report it in two places, which loses anything but its kind and message
+fn clone_error(err: &IoError) -> IoError {
+  IoError::new(err.kind(), err.to_string())
+}
+
+/// What a background writer thread shares about its output
+#[derive(Default)]
+struct Shared {
+  /// Bytes sent that the thread hasn't written yet, including its buffer
+  queued: AtomicU64,
+
+  failure: Mutex<Option<Failure>>,
+
+  /// The first error of each run of failed writes, until the logger reports them
+  unreported: Mutex<Vec<IoError>>,
+}
+
+/// An output that holds records back until its flush policy says to write them
+pub(crate) struct Buffered<T: Write> {
+  writable: T,
+  buffer: Vec<u8>,
+  policy: FlushPolicy,
+  written: Instant,
+  rate: RecordRate,
+}
+
+impl<T: Write> Buffered<T> {
+  pub fn new(writable: T, policy: FlushPolicy) -> Buffered<T> {
+    Buffered {
+      writable,
+      buffer: vec![],
+      policy,
+      written: Instant::now(),
+      rate: RecordRate::new(),
+    }
+  }
+
+  pub fn get_ref(&self) -> &T {
+    &self.writable
+  }
+
+  /// The bytes waiting in the buffer
+  pub fn buffered(&self) -> usize {
+    self.buffer.len()
+  }
+
+  /// Change the policy, writing out anything buffered under the old one first
+  pub fn set_policy(&mut self, policy: FlushPolicy) -> IoResult<()> {
+    self.policy = policy;
+    self.write_buffer()
+  }
+
+  pub fn write(&mut self, value: &Fragments) -> IoResult<()> {
+    let slow = self.policy == FlushPolicy::Adaptive && self.rate.record();
+    if (self.policy == FlushPolicy::EveryRecord || slow) && self.buffer.is_empty() {
+      self.written = Instant::now();
+      return value.write_to(&mut self.writable);
+    }
+
+    for part in value.parts.iter() {
+      self.buffer.extend_from_slice(part.as_bytes());
+    }
+    let full = match &self.policy {
+      FlushPolicy::EveryRecord => true,
+      FlushPolicy::Bytes(bytes) => self.buffer.len() >= *bytes,
+      FlushPolicy::Interval(interval) => self.written.elapsed() >= *interval,
+      FlushPolicy::OnDrop => false,
+      FlushPolicy::Adaptive => {
+        slow || self.buffer.len() >= ADAPTIVE_BYTES || self.written.elapsed() >= ADAPTIVE_LATENCY
+      }
+    };
+    match full {
+      true => self.write_buffer(),
+      false => Ok(()),
+    }
+  }
+
+  /// Write out the buffer and flush the output
+  pub fn flush(&mut self) -> IoResult<()> {
+    self.write_buffer()?;
+    self.writable.flush()
+  }
+
+  /// Flush the output, then wait until it is on disk if it is a file
+  pub fn sync_all(&mut self) -> IoResult<()>
+  where
+    T: 'static,
+  {
+    self.flush()?;
+    sync_all(&self.writable)
+  }
+
+  /// Write out the buffer. The records are dropped if this fails, so they aren't written twice.
+  fn write_buffer(&mut self) -> IoResult<()> {
+    self.written = Instant::now();
+    if self.buffer.is_empty() {
+      return Ok(());
+    }
+    let result = self.writable.write_all(&self.buffer);
+    self.buffer.clear();
+    result
+  }
+}
+
+impl<T: Write> Drop for Buffered<T> {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+/// Queues serialized blocks on a channel to be written by a dedicated thread
+pub(crate) struct AsyncWriter {
+  /// Where to send the work. This is dropped on shutdown to tell the thread to finish up
+  sender: Option<Sender<Command>>,
+
+  /// The thread doing the writing, so shutdown can wait for the queue to drain
+  handle: Option<JoinHandle<()>>,
+
+  shared: Arc<Shared>,
+}
+
+impl AsyncWriter {
+  /// Move the writable into a new thread and start listening for blocks
+  pub fn spawn<T>(writable: T, policy: FlushPolicy) -> AsyncWriter
+  where
+    T: Write + Send + 'static,
+  {
+    let mut writable = Buffered::new(writable, policy);
+    let (sender, receiver) = channel::<Command>();
+    let shared = Arc::new(Shared::default());
+    let thread_shared = Arc::clone(&shared);
+    let handle = std::thread::Builder::new()
+      .name("ymlog-writer".to_string())
+      .spawn(move || {
+        let shared = thread_shared;
+        for command in receiver {
+          let before = writable.buffered();
+          let (result, sent, is_write) = match command {
+            Command::Write(value, done) => {
+              let value = Fragments::from(value);
+              let result = writable.write(&value);
+              if let Some(done) = done {
+                let _ = done.send(result.as_ref().map_err(clone_error).copied());
+              }
+              (result, value.len(), true)
+            }
+            Command::Flush(done, sync) => {
+              let result = match sync {
+                true => writable.sync_all(),
+                false => writable.flush(),
+              };
+              let _ = done.send(result.as_ref().map_err(clone_error).copied());
+              (result, 0, false)
+            }
+            Command::Policy(policy) => (writable.set_policy(policy), 0, false),
+          };
+
+          // Failed writes are dropped from the buffer as well, so they aren't queued any more
+          let written = (before + sent).saturating_sub(writable.buffered());
+          shared.queued.fetch_sub(written as u64, Ordering::Relaxed);
+          // Only a write that works shows the output has recovered
+          if is_write || result.is_err() {
+            if let Ok(mut failure) = shared.failure.lock() {
+              if let (Err(err), None) = (&result, failure.as_ref()) {
+                if let Ok(mut unreported) = shared.unreported.lock() {
+                  unreported.push(clone_error(err));
+                }
+              }
+              Failure::track(&mut failure, &result);
+            }
+          }
+        }
+
+        // The channel was closed, so make sure everything made it out before the thread ends
+        let _ = writable.flush();
+      })
+      .expect("Could not start the ymlog writer thread");
+
+    AsyncWriter {
+      sender: Some(sender),
+      handle: Some(handle),
+      shared,
+    }
+  }
+
+  /// Add the value to the queue
+  pub fn write(&self, value: String) -> IoResult<()> {
+    self.queue(value, None)
+  }
+
+  /// Add the value to the queue, returning where the result is sent once it has been written
+  pub fn write_acked(&self, value: String) -> IoResult<Receiver<IoResult<()>>> {
+    let (done, wait) = channel();
+    self.queue(value, Some(done))?;
+    Ok(wait)
+  }
+
+  /// Count the value as queued before sending it, so the thread never takes it off the count first
+  fn queue(&self, value: String, done: Option<Sender<IoResult<()>>>) -> IoResult<()> {
+    let len = value.len() as u64;
+    self.shared.queued.fetch_add(len, Ordering::Relaxed);
+    let sent = self.send(Command::Write(value, done));
+    if sent.is_err() {
+      self.shared.queued.fetch_sub(len, Ordering::Relaxed);
+    }
+    sent
+  }
+
+  /// The bytes the thread hasn't written yet, and its last failed write if it hasn't recovered
+  fn health(&self) -> (u64, Option<Failure>) {
+    let failure = match self.shared.failure.lock() {
+      Ok(failure) => failure.clone(),
+      Err(poisoned) => poisoned.into_inner().clone(),
+    };
+    (self.shared.queued.load(Ordering::Relaxed), failure)
+  }
+
+  /// Take the errors the thread hit that haven't been reported yet
+  fn take_errors(&self) -> Vec<IoError> {
+    match self.shared.unreported.lock() {
+      Ok(mut unreported) => std::mem::take(&mut *unreported),
+      Err(poisoned) => std::mem::take(&mut *poisoned.into_inner()),
+    }
+  }
+
+  /// Start a flush, returning where the result is sent once it is done
+  fn flush_acked(&self, sync: bool) -> IoResult<Receiver<IoResult<()>>> {
+    let (done, wait) = channel();
+    self.send(Command::Flush(done, sync))?;
+    Ok(wait)
+  }
+
+  /// Change when the thread writes out what it has buffered
+  pub fn set_policy(&self, policy: FlushPolicy) -> IoResult<()> {
+    self.send(Command::Policy(policy))
+  }
+
+  /// Block until everything queued so far has been written and flushed
+  pub fn flush(&self) -> IoResult<()> {
+    self.flush_synced(false)
+  }
+
+  /// Block until everything queued so far has been written, flushed and synced if asked
+  pub fn flush_synced(&self, sync: bool) -> IoResult<()> {
+    self
+      .flush_acked(sync)?
+      .recv()
+      .map_err(|_| AsyncWriter::closed())?
+  }
+
+  /// Close the queue and wait for the thread to write everything left in it
+  pub fn shutdown(&mut self) -> IoResult<()> {
+    // Dropping the sender ends the thread's receive loop once the queue is empty
+    self.sender = None;
+    match self.handle.take() {
+      Some(handle) => handle
+        .join()
+        .map_err(|_| IoError::other("The ymlog writer thread panicked")),
+      None => Ok(()),
+    }
+  }
+
+  /// Close the queue without waiting for the thread, which finishes whenever its output allows
+  fn detach(&mut self) {
+    self.sender = None;
+    self.handle = None;
+  }
+
+  fn send(&self, command: Command) -> IoResult<()> {
+    match &self.sender {
+      Some(sender) => sender.send(command).map_err(|_| AsyncWriter::closed()),
+      None => Err(AsyncWriter::closed()),
+    }
+  }
+
+  fn closed() -> IoError {
+    IoError::new(
+      ErrorKind::BrokenPipe,
+      "The ymlog writer thread has shut down",
+    )
+  }
+}
+
+impl Drop for AsyncWriter {
+  fn drop(&mut self) {
+    let _ = self.shutdown();
+  }
+}
+
+/// How long a record may wait on a stalled output, and what happens to records after it
+pub struct WriteTimeout {
+  timeout: Duration,
+  policy: TimeoutPolicy,
+}
+
+impl WriteTimeout {
+  pub fn new(timeout: Duration, policy: TimeoutPolicy) -> WriteTimeout {
+    WriteTimeout { timeout, policy }
+  }
+}
+
+/// What to do with the records logged while an output is stalled
+///
+/// The record that timed out is still queued, and is written if the output recovers. The output
+/// recovers once that write finishes, and records wait on it again from then on.
+pub enum TimeoutPolicy {
+  /// Drop the records, returning a `TimedOut` error for each
+  Drop,
+
+  /// Queue the records in memory, to be written once the output recovers
+  Buffer,
+
+  /// Write the records to another output instead, such as a local file
+  Fallback(Box<dyn Write + Send>),
+}
+
+/// Writes from a background thread, waiting a limited time for each record
+pub(crate) struct TimedWriter {
+  writer: AsyncWriter,
+  timeout: Duration,
+  policy: TimeoutPolicy,
+
+  /// Where the result of the write that timed out will be sent, while the output is stalled
+  stalled: Option<Receiver<IoResult<()>>>,
+}
+
+impl TimedWriter {
+  pub fn spawn<T>(writable: T, flush: FlushPolicy, timeout: WriteTimeout) -> TimedWriter
+  where
+    T: Write + Send + 'static,
+  {
+    TimedWriter {
+      writer: AsyncWriter::spawn(writable, flush),
+      timeout: timeout.timeout,
+      policy: timeout.policy,
+      stalled: None,
+    }
+  }
+
+  pub fn write(&mut self, value: String) -> IoResult<()> {
+    if self.is_stalled() {
+      return match &mut self.policy {
+        TimeoutPolicy::Drop => Err(IoError::new(
+          ErrorKind::TimedOut,
+          "The output is stalled, so the record was dropped",
+        )),
+        TimeoutPolicy::Buffer => self.writer.write(value),
+        TimeoutPolicy::Fallback(fallback) => fallback.write_all(value.as_bytes()),
+      };
+    }
+    let done = self.writer.write_acked(value)?;
+    self.wait(done)
+  }
+
+  /// Flush the output, first giving a stalled write the timeout to finish
+  pub fn flush(&mut self) -> IoResult<()> {
+    self.flush_synced(false)
+  }
+
+  /// Flush the output and sync it to disk if asked, giving a stalled write the timeout to finish
+  pub fn flush_synced(&mut self, sync: bool) -> IoResult<()> {
+    if let TimeoutPolicy::Fallback(fallback) = &mut self.policy {
+      fallback.flush()?;
+    }
+    if let Some(stalled) = self.stalled.take() {
+      self.wait(stalled)?;
+      if self.stalled.is_some() {
+        return Err(TimedWriter::timed_out());
+      }
+    }
+    let done = self.writer.flush_acked(sync)?;
+    self.wait(done)?;
+    match self.stalled {
+      Some(_) => Err(TimedWriter::timed_out()),
+      None => Ok(()),
+    }
+  }
+
+  /// Flush and close the queue, leaving the thread behind if its output is stalled
+  pub fn shutdown(&mut self) -> IoResult<()> {
+    match self.flush() {
+      Ok(()) => self.writer.shutdown(),
+      Err(err) => {
+        self.writer.detach();
+        Err(err)
+      }
+    }
+  }
+
+  pub fn set_policy(&self, policy: FlushPolicy) -> IoResult<()> {
+    self.writer.set_policy(policy)
+  }
+
+  /// Wait for a result, marking the output as stalled if it doesn't arrive in time
+  fn wait(&mut self, done: Receiver<IoResult<()>>) -> IoResult<()> {
+    match done.recv_timeout(self.timeout) {
+      Ok(result) => result,
+      Err(RecvTimeoutError::Timeout) => {
+        self.stalled = Some(done);
+        Ok(())
+      }
+      Err(RecvTimeoutError::Disconnected) => Err(AsyncWriter::closed()),
+    }
+  }
+
+  /// Check if the write that timed out is still going
+  fn is_stalled(&mut self) -> bool {
+    let stalled = match &self.stalled {
+      Some(done) => matches!(done.try_recv(), Err(TryRecvError::Empty)),
+      None => false,
+    };
+    if !stalled {
+      self.stalled = None;
+    }
+    stalled
+  }
+
+  /// Why the output isn't taking records as usual, if it is stalled
+  fn degraded(&mut self) -> Option<String> {
+    if !self.is_stalled() {
+      return None;
+    }
+    let records = match self.policy {
+      TimeoutPolicy::Drop => "dropped",
+      TimeoutPolicy::Buffer => "queued",
+      TimeoutPolicy::Fallback(_) => "written to the fallback",
+    };
+    Some(format!("The output is stalled, so records are {}", records))
+  }
+
+  fn timed_out() -> IoError {
+    IoError::new(ErrorKind::TimedOut, "The output is stalled")
+  }
+}
+
+impl Drop for TimedWriter {
+  fn drop(&mut self) {
+    let _ = self.shutdown();
+  }
+}
+
+/// How the logger gets the serialized blocks to the output
+pub(crate) enum Output<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Write directly to the output from the calling thread
+  Direct(Buffered<T>),
+
+  /// Hand the blocks off to a background thread
+  Queued(AsyncWriter),
+
+  /// Hand the blocks off to a background thread, waiting a limited time for each
+  Timed(TimedWriter),
+}
+
+impl<T> Output<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  pub fn write(&mut self, value: Fragments) -> IoResult<()> {
+    match self {
+      Output::Direct(writable) => writable.write(&value),
+      Output::Queued(writer) => writer.write(value.join()),
+      Output::Timed(writer) => writer.write(value.join()),
+    }
+  }
+
+  pub fn flush(&mut self) -> IoResult<()> {
+    match self {
+      Output::Direct(writable) => writable.flush(),
+      Output::Queued(writer) => writer.flush(),
+      Output::Timed(writer) => writer.flush(),
+    }
+  }
+
+  /// Flush the output, then wait until it is on disk if it is a file
+  pub fn sync_all(&mut self) -> IoResult<()> {
+    match self {
+      Output::Direct(writable) => writable.sync_all(),
+      Output::Queued(writer) => writer.flush_synced(true),
+      Output::Timed(writer) => writer.flush_synced(true),
+    }
+  }
+
+  pub fn shutdown(&mut self) -> IoResult<()> {
+    match self {
+      Output::Direct(writable) => writable.flush(),
+      Output::Queued(writer) => writer.shutdown(),
+      Output::Timed(writer) => writer.shutdown(),
+    }
+  }
+
+  /// The bytes not written yet, a failure in the background, and why the output is degraded
+  fn health(&mut self) -> (u64, Option<Failure>, Option<String>) {
+    match self {
+      Output::Direct(writable) => (writable.buffered() as u64, None, None),
+      Output::Queued(writer) => {
+        let (queued, failure) = writer.health();
+        (queued, failure, None)
+      }
+      Output::Timed(writer) => {
+        let (queued, failure) = writer.writer.health();
+        (queued, failure, writer.degraded())
+      }
+    }
+  }
+
+  /// Errors from writes made in the background, which the caller never saw
+  fn take_errors(&self) -> Vec<IoError> {
+    match self {
+      Output::Direct(_) => vec![],
+      Output::Queued(writer) => writer.take_errors(),
+      Output::Timed(writer) => writer.writer.take_errors(),
+    }
+  }
+
+  pub fn set_policy(&mut self, policy: FlushPolicy) -> IoResult<()> {
+    match self {
+      Output::Direct(writable) => writable.set_policy(policy),
+      Output::Queued(writer) => writer.set_policy(policy),
+      Output::Timed(writer) => writer.set_policy(policy),
+    }
+  }
+}
+
+/// One of the outputs the logger fans the records out to
+pub(crate) struct Sink<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  /// Where the records are written
+  output: Output<T>,
+
+  /// The YAML state of what has been written to this output
+  pub tracker: Tracker,
+
+  /// The stages each block goes through before being written
+  pub pipeline: Pipeline,
+
+  /// The number of records written to the output
+  pub sequence: u64,
+
+  /// The number of bytes written to the output
+  pub offset: u64,
+
+  /// Whether the output is a terminal, which colors it unless colors are turned off
+  pub terminal: bool,
+
+  /// The syntax of the last record written, so closing knows how to end the output
+  pub format: Option<OutputFormat>,
+
+  /// The large payloads already written in the current document
+  pub repeats: Repeats,
+
+  /// The writes from the logger that have failed since the last one that didn't
+  failure: Option<Failure>,
+
+  /// What was kept from the output since the log was last closed
+  pub withheld: Withheld,
+}
+
+/// Counts of what was kept from an output, written in a summary when the log is closed
+///
+/// Blocks below the level or without the tags aren't counted, since leaving those out is what the
+/// level and tag filter are for.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub(crate) struct Withheld {
+  /// Blocks a filter stage dropped
+  pub filtered: u64,
+
+  /// Blocks a sample stage or the logger's sampling rate skipped
+  pub sampled: u64,
+
+  /// Blocks over the rate limit, if they weren't summarized by a `!ymlog/suppressed` record
+  pub throttled: u64,
+
+  /// Blocks skipped because their message couldn't be serialized
+  pub unserializable: u64,
+
+  /// Blocks the validator rejected in strict mode
+  pub invalid: u64,
+
+  /// Values removed because their key is on the deny-list, or masked by the logger's redaction
+  pub redacted: u64,
+
+  /// Payloads replaced with a reference to an earlier copy
+  pub collapsed: u64,
+
+  /// Records the output failed to take
+  pub failed: u64,
+}
+
+impl Withheld {
+  /// The non-zero counts by name, in a fixed order
+  pub fn counts(&self) -> Vec<(&'static str, u64)> {
+    [
+      ("filtered", self.filtered),
+      ("sampled", self.sampled),
+      ("throttled", self.throttled),
+      ("unserializable", self.unserializable),
+      ("invalid", self.invalid),
+      ("redacted", self.redacted),
+      ("collapsed", self.collapsed),
+      ("failed", self.failed),
+    ]
+    .iter()
+    .copied()
+    .filter(|(_, count)| *count > 0)
+    .collect()
+  }
+}
+
+impl<T> Sink<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  pub fn new(output: Output<T>, pipeline: Pipeline) -> Sink<T> {
+    let terminal = match &output {
+      Output::Direct(writable) => is_terminal(writable.get_ref()),
+      Output::Queued(_) | Output::Timed(_) => false,
+    };
+    Sink {
+      output,
+      tracker: Default::default(),
+      pipeline,
+      sequence: 0,
+      offset: 0,
+      terminal,
+      format: None,
+      repeats: Default::default(),
+      failure: None,
+      withheld: Default::default(),
+    }
+  }
+
+  /// Write the record and report where it ended up
+  pub fn write(&mut self, value: Fragments) -> IoResult<RecordHandle> {
+    let start = self.offset;
+    let len = value.len() as u64;
+    let written = self.output.write(value);
+    Failure::track(&mut self.failure, &written);
+    written?;
+
+    let handle = RecordHandle {
+      sequence: self.sequence,
+      range: start..start + len,
+    };
+    self.sequence += 1;
+    self.offset += len;
+    Ok(handle)
+  }
+
+  /// Write text that isn't a record, such as a document end marker
+  pub fn write_raw(&mut self, value: &str) -> IoResult<()> {
+    let written = self.output.write(Fragments::from(value.to_string()));
+    Failure::track(&mut self.failure, &written);
+    written?;
+    self.offset += value.len() as u64;
+    Ok(())
+  }
+
+  pub fn flush(&mut self) -> IoResult<()> {
+    let flushed = self.output.flush();
+    if flushed.is_err() {
+      Failure::track(&mut self.failure, &flushed);
+    }
+    flushed
+  }
+
+  /// Check the output, preferring to report a stall over the failures it causes
+  pub fn health(&mut self) -> SinkHealth {
+    let (queued_bytes, background, degraded) = self.output.health();
+    let status = match (degraded, self.failure.as_ref().or(background.as_ref())) {
+      (Some(reason), _) => SinkStatus::Degraded(reason),
+      (None, Some(failure)) => SinkStatus::Failed {
+        since: failure.since,
+        error: failure.error.clone(),
+      },
+      (None, None) => SinkStatus::Ok,
+    };
+    SinkHealth {
+      status,
+      queued_bytes,
+    }
+  }
+
+  pub fn shutdown(&mut self) -> IoResult<()> {
+    self.output.shutdown()
+  }
+
+  /// Take the errors from writes the output made in the background, once each
+  pub fn take_errors(&self) -> Vec<IoError> {
+    self.output.take_errors()
+  }
+
+  pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> IoResult<()> {
+    self.output.set_policy(policy)
+  }
+
+  pub fn sync_all(&mut self) -> IoResult<()> {
+    self.output.sync_all()
+  }
+}
+
+/// Check whether the output is a terminal
+///
+/// Only the standard streams, the writers in [`sinks`] and files can be checked. Anything else,
+/// including boxed writers, is treated as not being a terminal.
+pub(crate) fn is_terminal<T: 'static>(writable: &T) -> bool {
+  let writable = writable as &dyn Any;
+  if let Some(stderr) = writable.downcast_ref::<Stderr>() {
+    return stderr.is_terminal();
+  }
+  if let Some(stdout) = writable.downcast_ref::<Stdout>() {
+    return stdout.is_terminal();
+  }
+  if let Some(file) = writable.downcast_ref::<File>() {
+    return file.is_terminal();
+  }
+  if let Some(stderr) = writable.downcast_ref::<sinks::Stderr>() {
+    return stderr.is_terminal();
+  }
+  if let Some(stdout) = writable.downcast_ref::<sinks::Stdout>() {
+    return stdout.is_terminal();
+  }
+  false
+}
+
+/// Wait until everything written to the output is on disk, if it is a file
+///
+/// Files and files behind a `BufWriter` or `LineWriter` are synced, which assumes their buffer was
+/// just flushed. Anything else has nowhere to sync to.
+fn sync_all<T: 'static>(writable: &T) -> IoResult<()> {
+  let writable = writable as &dyn Any;
+  if let Some(file) = writable.downcast_ref::<File>() {
+    return file.sync_all();
+  }
+  if let Some(writer) = writable.downcast_ref::<BufWriter<File>>() {
+    return writer.get_ref().sync_all();
+  }
+  if let Some(writer) = writable.downcast_ref::<LineWriter<File>>() {
+    return writer.get_ref().sync_all();
+  }
+  Ok(())
+}
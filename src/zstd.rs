@@ -0,0 +1,49 @@
+//! A zstd codec, for compressed messages and rotated logs
+//!
+//! Only available with the `zstd` feature, which adds the `zstd` crate. Messages are written with
+//! a `!ymlog/zstd` tag, and files made by the zstd tool are read, frame after frame, as they are
+//! read rather than all at once.
+//!
+//! With the feature on, [`Opener::new`](crate::reader::Opener::new) reads zstd files without
+//! adding a codec.
+
+use std::io::{BufRead, Read, Result as IoResult};
+
+use ::zstd::stream::read::Decoder;
+
+use crate::compress::{Codec, Corrupt};
+
+/// The bytes every zstd frame starts with
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compress with zstd at its default level, and decompress anything zstd wrote
+///
+/// ```ignore
+/// logger.set_compression(Compression::new(Box::new(Zstd), 64 * 1024));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Zstd;
+
+impl Codec for Zstd {
+  fn name(&self) -> &str {
+    "zstd"
+  }
+
+  fn compress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    ::zstd::stream::encode_all(data, ::zstd::DEFAULT_COMPRESSION_LEVEL)
+  }
+
+  fn decompress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    let mut out = vec![];
+    Corrupt(Decoder::with_buffer(data)?).read_to_end(&mut out)?;
+    Ok(out)
+  }
+
+  fn decoder(&self, compressed: Box<dyn BufRead + Send>) -> IoResult<Box<dyn Read + Send>> {
+    Ok(Box::new(Corrupt(Decoder::with_buffer(compressed)?)))
+  }
+
+  fn magic(&self) -> &[u8] {
+    &MAGIC
+  }
+}
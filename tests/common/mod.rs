@@ -1 +1,79 @@
+//! Shared helpers for the integration tests
 
+use std::io::{IoSlice, Result, Write};
+use std::sync::{Arc, Mutex};
+
+/// A basic write buffer that we can keep a reference to to examine the contents later
+#[derive(Clone)]
+pub struct TestWriter(Arc<Mutex<Vec<u8>>>);
+
+impl TestWriter {
+  pub fn new(buffer: &Arc<Mutex<Vec<u8>>>) -> TestWriter {
+    TestWriter(Arc::clone(buffer))
+  }
+}
+
+unsafe impl Send for TestWriter {}
+unsafe impl Sync for TestWriter {}
+
+impl Write for TestWriter {
+  fn write(&mut self, buf: &[u8]) -> Result<usize> {
+    self.0.lock().unwrap().write(buf)
+  }
+
+  fn flush(&mut self) -> Result<()> {
+    self.0.lock().unwrap().flush()
+  }
+
+  fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+    self.0.lock().unwrap().write_vectored(bufs)
+  }
+
+  fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+    self.0.lock().unwrap().write_all(buf)
+  }
+
+  fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> Result<()> {
+    self.0.lock().unwrap().write_fmt(fmt)
+  }
+}
+
+/// Lower a test logger to Info, so the blocks the tests log without a level are written
+#[allow(dead_code)]
+pub fn at_info<T>(logger: ymlog::YmLog<T>) -> ymlog::YmLog<T>
+where
+  T: Write + Send + Sync + 'static,
+{
+  logger.set_level(ymlog::Level::Info);
+  logger
+}
+
+/// Make a logger that writes into a buffer we can inspect
+#[allow(dead_code)]
+pub fn buffered() -> (ymlog::YmLog<TestWriter>, Arc<Mutex<Vec<u8>>>) {
+  let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let logger = at_info(ymlog::YmLog::new());
+  logger.set_output(TestWriter::new(&buffer));
+  (logger, buffer)
+}
+
+/// Point the global logger at a buffer, at Info like the other test loggers
+#[allow(dead_code)]
+pub fn init_global(buffer: &Arc<Mutex<Vec<u8>>>) {
+  ymlog::init_writer(Box::new(TestWriter::new(buffer)));
+  ymlog::global().set_level(ymlog::Level::Info);
+}
+
+/// A block holding only a message, without a level
+#[allow(dead_code)]
+pub fn message(msg: &str) -> ymlog::Block {
+  let mut block = ymlog::Block::new();
+  block.set_message(msg).unwrap();
+  block
+}
+
+/// Everything written to the buffer so far
+#[allow(dead_code)]
+pub fn contents(buffer: &Arc<Mutex<Vec<u8>>>) -> String {
+  String::from_utf8(buffer.lock().unwrap().clone()).unwrap()
+}
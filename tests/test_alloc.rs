@@ -0,0 +1,39 @@
+//! Test the allocation counting allocator
+#![cfg(feature = "alloc-stats")]
+
+use std::alloc::System;
+use std::time::Duration;
+
+use ymlog::alloc::{report_every, stats, TrackingAllocator};
+
+mod common;
+
+#[global_allocator]
+static ALLOC: TrackingAllocator = TrackingAllocator::new(System);
+
+#[test]
+/// The counts follow allocations, and the reporter writes them to the global log
+fn allocations_are_counted_and_logged() {
+  let before = stats();
+  let large = vec![0u8; 1 << 20];
+  let during = stats();
+  assert!(during.in_use >= before.in_use + large.len());
+  assert!(during.peak >= during.in_use);
+  assert!(during.allocations > before.allocations);
+  drop(large);
+  assert!(stats().in_use < during.in_use);
+
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+  let reporter = report_every(Duration::from_millis(5));
+  std::thread::sleep(Duration::from_millis(50));
+  drop(reporter);
+
+  let output = common::contents(&buffer);
+  let first: serde_yaml::Value = serde_yaml::Deserializer::from_str(&output)
+    .next()
+    .map(|doc| serde::Deserialize::deserialize(doc).unwrap())
+    .unwrap();
+  assert_eq!(first["message"], "Allocation stats");
+  assert!(first["fields"]["peak_bytes"].as_u64().unwrap() >= 1 << 20);
+}
@@ -0,0 +1,589 @@
+//! Test how the optional parts of a block are written
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+
+mod common;
+use common::message;
+
+#[test]
+/// Typed records are written with their tag, at the root and when nested
+fn tag_types_are_written() {
+  let (logger, buffer) = common::buffered();
+
+  let mut deploy = message("Deploying");
+  deploy.set_tag_type("!deploy");
+  logger.log(&mut deploy, Some("_")).unwrap();
+
+  let mut retry = message("Retrying");
+  retry.set_tag_type("retry");
+  logger.log(&mut retry, Some("+_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(output, "---\n!deploy Deploying:\n  - !retry Retrying");
+
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  let (key, children) = parsed.as_mapping().unwrap().iter().next().unwrap();
+  match (key, &children[0]) {
+    (YmlValue::Tagged(deploy), YmlValue::Tagged(retry)) => {
+      assert_eq!(deploy.tag, "deploy");
+      assert_eq!(retry.tag, "retry");
+    }
+    other => panic!("Expected tagged records, found {:?}", other),
+  }
+}
+
+/// Something that always fails to serialize
+struct Broken;
+
+impl serde::Serialize for Broken {
+  fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+    Err(serde::ser::Error::custom("the value is broken"))
+  }
+}
+
+#[test]
+/// A message that fails to serialize still leaves a visible record, unless told otherwise
+fn unserializable_messages_fall_back() {
+  let (logger, buffer) = common::buffered();
+
+  let mut block = Block::new();
+  assert!(block.set_message(Broken).is_err());
+  logger.log(&mut block, Some("_")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\n'<ymlog could not serialize the test_block::Broken message: the value is broken>'"
+  );
+
+  logger.set_serialize_policy(ymlog::SerializePolicy::Skip);
+  let mut block = Block::new();
+  let _ = block.set_message(Broken);
+  assert_eq!(logger.log(&mut block, Some("_")).unwrap(), None);
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+/// Stamped records become mappings, with any children nested under their own key
+fn auto_timestamps_are_written() {
+  let (logger, buffer) = common::buffered();
+  logger.auto_timestamp(true);
+  logger.set_timestamp_format(ymlog::TimestampFormat::Custom("stamped".to_string()));
+
+  logger.log(&mut message("Starting"), Some("_")).unwrap();
+  logger.log(&mut message("First child"), Some("+_")).unwrap();
+  logger.log(&mut message("Grandchild"), Some("+_")).unwrap();
+  logger
+    .log(&mut message("Second child"), Some("-_"))
+    .unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    concat!(
+      "---\n",
+      "timestamp: stamped\n",
+      "message: Starting\n",
+      "children:\n",
+      "  - timestamp: stamped\n",
+      "    message: First child\n",
+      "    children:\n",
+      "    - timestamp: stamped\n",
+      "      message: Grandchild\n",
+      "  - timestamp: stamped\n",
+      "    message: Second child",
+    )
+  );
+
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(
+    parsed["children"][0]["children"][0]["message"],
+    "Grandchild"
+  );
+  assert_eq!(parsed["children"][1]["message"], "Second child");
+
+  // Blocks stamped by hand keep their time, written in the chosen format
+  let (logger, buffer) = common::buffered();
+  logger.set_timestamp_format(ymlog::TimestampFormat::EpochMillis);
+  let mut block = message("Stamped");
+  block.stamp();
+  logger.log(&mut block, Some("_")).unwrap();
+
+  let parsed: YmlValue = serde_yaml::from_str(&common::contents(&buffer)).unwrap();
+  assert!(parsed["timestamp"].as_i64().unwrap() > 0);
+}
+
+#[test]
+/// Fields are written in the order added, next to the message
+fn fields_are_written() {
+  let (logger, buffer) = common::buffered();
+
+  let mut block = message("Handled request");
+  block.add_field("request_id", "a1b2").unwrap();
+  block.add_field("user", 42).unwrap();
+  block.add_field("roles", vec!["admin", "ops"]).unwrap();
+  block.add_field("user", 7).unwrap();
+  assert!(block.add_field("broken", Broken).is_err());
+  assert_eq!(block.field("user"), Some(&YmlValue::from(7)));
+
+  logger.log(&mut block, Some("_")).unwrap();
+  logger.log(&mut message("Child"), Some("+_")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "---\n",
+      "fields:\n",
+      "  request_id: a1b2\n",
+      "  user: 7\n",
+      "  roles:\n",
+      "  - admin\n",
+      "  - ops\n",
+      "message: Handled request\n",
+      "children:\n",
+      "  - Child",
+    )
+  );
+
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+  logger.log(&mut block, Some("_")).unwrap();
+  assert!(common::contents(&buffer)
+    .contains("\"fields\":{\"request_id\":\"a1b2\",\"user\":7,\"roles\":[\"admin\",\"ops\"]}"));
+}
+
+#[test]
+/// Durations and sizes are readable, with the raw value kept alongside
+fn sizes_and_durations_are_humanized() {
+  use std::time::Duration;
+
+  let (logger, buffer) = common::buffered();
+
+  let mut block = message("Uploaded");
+  block.field_duration("elapsed", Duration::from_millis(1234));
+  block.field_bytes("size", 3_565_158);
+  logger.log(&mut block, Some("_")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "---\n",
+      "fields:\n",
+      "  elapsed:\n",
+      "    human: 1.2 s\n",
+      "    ms: 1234.0\n",
+      "  size:\n",
+      "    human: 3.4 MiB\n",
+      "    bytes: 3565158\n",
+      "message: Uploaded",
+    )
+  );
+
+  let human = |duration: Duration| {
+    let mut block = Block::new();
+    block.field_duration("d", duration);
+    block.field("d").unwrap()["human"]
+      .as_str()
+      .unwrap()
+      .to_string()
+  };
+  assert_eq!(human(Duration::from_nanos(999)), "999 ns");
+  assert_eq!(human(Duration::from_micros(350)), "350.0 µs");
+  assert_eq!(human(Duration::from_millis(12)), "12.0 ms");
+  assert_eq!(human(Duration::from_secs(150)), "2.5 min");
+  assert_eq!(human(Duration::from_secs(5400)), "1.5 h");
+
+  let mut block = Block::new();
+  block.field_bytes("small", 512);
+  block.field_bytes("large", 5 << 40);
+  assert_eq!(block.field("small").unwrap()["human"], "512 B");
+  assert_eq!(block.field("large").unwrap()["human"], "5.0 TiB");
+}
+
+#[test]
+/// Blocks serialize to the shape the log is read back from, so they can be round tripped
+fn blocks_round_trip() {
+  let plain: Block = serde_yaml::from_str("Starting").unwrap();
+  assert_eq!(plain.message(), Some(&YmlValue::from("Starting")));
+  assert_eq!(serde_yaml::to_string(&plain).unwrap(), "Starting\n");
+
+  let mut pair = Block::new();
+  pair.set_key_value("user", "jo").unwrap();
+  let yaml = serde_yaml::to_string(&pair).unwrap();
+  assert_eq!(yaml, "user: jo\n");
+  let pair: Block = serde_yaml::from_str(&yaml).unwrap();
+  assert_eq!(
+    pair.key_value(),
+    Some((&YmlValue::from("user"), &YmlValue::from("jo")))
+  );
+
+  let mut record = message("Deploying");
+  record.set_log_level(Level::Warn);
+  record.set_source("web-1");
+  record.set_tags(vec!["deploy"]);
+  record.add_field("version", 3).unwrap();
+  record.set_tag_type("deploy");
+  record.set_children(vec![message("Pulling"), message("Restarting")]);
+  let yaml = serde_yaml::to_string(&record).unwrap();
+  let record: Block = serde_yaml::from_str(&yaml).unwrap();
+  assert_eq!(record.log_level(), &Level::Warn);
+  assert_eq!(record.source(), Some("web-1"));
+  assert_eq!(record.tags(), &["deploy".to_string()]);
+  assert_eq!(record.field("version"), Some(&YmlValue::from(3)));
+  assert_eq!(record.tag_type(), Some("deploy"));
+  assert_eq!(record.children().len(), 2);
+  assert_eq!(serde_yaml::to_string(&record).unwrap(), yaml);
+
+  // Fixtures can name levels in any case
+  let level: Level = serde_yaml::from_str("warning").unwrap();
+  assert_eq!(level, Level::Warn);
+  assert!(serde_yaml::from_str::<Level>("loud").is_err());
+  assert!(serde_yaml::from_str::<Block>("{message: A, log_level: loud}").is_err());
+}
+
+/// A payload logged as the message itself
+#[derive(serde::Serialize)]
+struct Order {
+  id: u32,
+  items: Vec<&'static str>,
+}
+
+#[test]
+/// Structured messages are written under a message key, so records can still be nested in them
+fn structured_messages_take_children() {
+  let (logger, buffer) = common::buffered();
+  let order = Order {
+    id: 7,
+    items: vec!["tea", "milk"],
+  };
+  logger.log(&mut message("Checkout"), None).unwrap();
+  let mut block = Block::new();
+  block.set_message(&order).unwrap();
+  logger.log(&mut block, Some("+_+")).unwrap();
+  logger.log(&mut message("Paid"), None).unwrap();
+  logger.log(&mut message("Shipped"), Some("-")).unwrap();
+  let mut block = Block::new();
+  block.set_message(["tea", "milk"]).unwrap();
+  logger.log(&mut block, Some("r_+")).unwrap();
+  logger.log(&mut message("Packed"), None).unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    "---\nCheckout:\n  - message:\n      id: 7\n      items:\n      - tea\n      - milk\n    children:\n    - Paid\n  - Shipped\n---\nmessage:\n- tea\n- milk\nchildren:\n  - Packed"
+  );
+
+  let records = ymlog::reader::parse(output.as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  let payload = &records[0].children()[0];
+  assert_eq!(payload.message().unwrap()["id"], YmlValue::from(7));
+  assert_eq!(
+    payload.children()[0].message(),
+    Some(&YmlValue::from("Paid"))
+  );
+  assert_eq!(records[1].children().len(), 1);
+}
+
+#[test]
+/// Text is written like any string message, without copying shared buffers into the block
+fn text_is_written_like_strings() {
+  let (logger, buffer) = common::buffered();
+  let shared: std::sync::Arc<str> = "Shared buffer".into();
+
+  let mut block = Block::new();
+  block.set_text("Static");
+  assert_eq!(block.text(), Some("Static"));
+  assert!(block.message().is_none());
+  logger.log(&mut block, Some("_+")).unwrap();
+
+  let mut block = Block::new();
+  block.set_text(std::borrow::Cow::Owned(String::from("Owned: value")));
+  logger.log(&mut block, Some("k_")).unwrap();
+
+  let mut block = Block::new();
+  block.set_text(std::sync::Arc::clone(&shared));
+  let copy = block.clone();
+  assert_eq!(std::sync::Arc::strong_count(&shared), 3);
+  logger.log(&mut block, Some("_")).unwrap();
+  assert_eq!(copy.text(), Some("Shared buffer"));
+
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nStatic:\n  - Owned: value\n---\nShared buffer"
+  );
+}
+
+#[test]
+/// Blocks tagged the same share the tags' text instead of copying it
+fn repeated_tags_share_their_text() {
+  let tagged = |tags: Vec<&str>| {
+    let mut block = Block::new();
+    block.set_tags(tags);
+    block
+  };
+  let (first, second) = (tagged(vec!["billing", "retry"]), tagged(vec!["billing"]));
+  assert_eq!(first.tags(), ["billing", "retry"]);
+  assert_eq!(second.tags()[0], "billing");
+  assert_eq!(
+    first.tags()[0].as_str().as_ptr(),
+    second.tags()[0].as_str().as_ptr()
+  );
+
+  // Anything displayable is a tag
+  let mut numbered = Block::new();
+  numbered.set_tags(vec![7]);
+  assert_eq!(numbered.tags(), ["7"]);
+}
+
+#[test]
+/// Errors get a backtrace child written as a literal block, once however they asked for it
+fn error_backtraces_are_children() {
+  let (logger, buffer) = common::buffered();
+  logger.error_backtraces(true);
+
+  logger.log(&mut message("Fine"), None).unwrap();
+  let mut failed = message("Failed");
+  failed.set_log_level(Level::Error);
+  logger.log(&mut failed, None).unwrap();
+  let mut asked = message("Asked");
+  asked.with_backtrace();
+  asked.set_log_level(Level::Error);
+  logger.log(&mut asked, None).unwrap();
+
+  let output = common::contents(&buffer);
+  let documents = output.split("---\n").skip(1).collect::<Vec<_>>();
+  assert_eq!(documents[0], "Fine\n");
+  for (document, msg) in documents[1..].iter().zip(["Failed", "Asked"]) {
+    assert!(
+      document.starts_with(&format!(
+        "log_level: Error\nmessage: {}\nchildren:\n- backtrace: |",
+        msg
+      )),
+      "{}",
+      document
+    );
+    assert_eq!(document.matches("- backtrace: |").count(), 1);
+  }
+
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(roots[1].children().len(), 1);
+}
+
+/// An error caused by another, for building source chains
+#[derive(Debug)]
+struct Caused(&'static str, Option<Box<Caused>>);
+
+impl std::fmt::Display for Caused {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.0)
+  }
+}
+
+impl std::error::Error for Caused {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    self.1.as_deref().map(|cause| cause as _)
+  }
+}
+
+#[test]
+/// Each source of an error is nested a level deeper than the error it caused
+fn error_chains_are_nested() {
+  let (logger, buffer) = common::buffered();
+  let error = Caused(
+    "Loading the config failed",
+    Some(Box::new(Caused(
+      "Reading app.yml failed",
+      Some(Box::new(Caused("Permission denied", None))),
+    ))),
+  );
+
+  let mut block = Block::new();
+  block.set_error(&error);
+  assert_eq!(*block.log_level(), Level::Error);
+  logger.log(&mut block, None).unwrap();
+  let mut alone = Block::new();
+  alone.set_error(&Caused("Timed out", None));
+  logger.log(&mut alone, None).unwrap();
+
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nlog_level: Error\nmessage: Loading the config failed\nchildren:\n- Reading app.yml failed:\n  - Permission denied\n---\nlog_level: Error\nmessage: Timed out"
+  );
+}
+
+#[test]
+/// Repeated payloads are written once per document with an anchor, then as aliases of it
+fn anchors_and_aliases() {
+  let (logger, buffer) = common::buffered();
+  let mut config = serde_yaml::Mapping::new();
+  config.insert("id".into(), 7.into());
+  config.insert("name".into(), "web".into());
+  let aliased = || {
+    let mut block = Block::new();
+    block.set_message(&config).unwrap();
+    block.set_alias("config");
+    block
+  };
+
+  logger.log(&mut message("Deploying"), None).unwrap();
+  logger.log(&mut aliased(), Some("+_")).unwrap();
+  logger.log(&mut aliased(), None).unwrap();
+  let mut with_fields = aliased();
+  with_fields.add_field("attempt", 2).unwrap();
+  logger.log(&mut with_fields, None).unwrap();
+  let mut note = Block::new();
+  note.set_text("Hello");
+  note.set_tag_type("note");
+  note.set_anchor("greeting!");
+  assert_eq!(note.anchor(), Some("greeting"));
+  logger.log(&mut note, None).unwrap();
+  note.set_alias("greeting");
+  logger.log(&mut note, None).unwrap();
+  logger.log(&mut aliased(), Some("r_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    "---\nDeploying:\n  - message: &config\n      id: 7\n      name: web\n  - message: *config\n  - fields:\n      \
+     attempt: 2\n    message: *config\n  - &greeting !note Hello\n  - *greeting\n---\nmessage: &config\n  id: 7\n  \
+     name: web"
+  );
+
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  let children = roots[0].children();
+  assert_eq!(children[0].message(), children[1].message());
+  assert_eq!(children[0].message(), children[2].message());
+  assert_eq!(children[3].text(), Some("Hello"));
+  assert_eq!(children[4].text(), Some("Hello"));
+  assert_eq!(roots[1].message(), children[0].message());
+
+  // Text that looks like the marks standing in for anchors and aliases is written as it is
+  let (logger, buffer) = common::buffered();
+  let mut first = message("Hello");
+  first
+    .add_field("note", "see !__ymlog_anchor__greeting")
+    .unwrap();
+  first.set_anchor("greeting");
+  logger.log(&mut first, None).unwrap();
+  let mut second = message("Hello");
+  second.add_field("note", "__ymlog_alias__greeting").unwrap();
+  second.set_alias("greeting");
+  logger.log(&mut second, Some("+_")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  note: see !__ymlog_anchor__greeting\nmessage: &greeting Hello\nchildren:\n  - fields:\n      \
+     note: __ymlog_alias__greeting\n    message: *greeting"
+  );
+}
+
+#[test]
+/// Comments are written above their records at the same indent, and read back as nothing
+fn comments_are_written_above_records() {
+  let (logger, buffer) = common::buffered();
+  logger.set_commenter(Some(Box::new(|block| {
+    block.tag_type().map(|tag| format!("--- {} ---", tag))
+  })));
+
+  let mut root = message("Starting");
+  root.set_comment("Run 7");
+  logger.log(&mut root, None).unwrap();
+  let mut child = message("Loading");
+  child.set_comment("Two lines\n\nwith a gap");
+  logger.log(&mut child, Some("+_")).unwrap();
+  let mut typed = message("Saving");
+  typed.set_tag_type("stage");
+  typed.set_comment("Slow");
+  logger.log(&mut typed, None).unwrap();
+  logger.log(&mut message("Done"), Some("r_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    "---\n# Run 7\nStarting:\n  # Two lines\n  #\n  # with a gap\n  - Loading\n  # --- stage ---\n  \
+     # Slow\n  - !stage Saving\n---\nDone"
+  );
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(roots[0].children().len(), 2);
+}
+
+#[test]
+/// The style actions write a message as a literal or folded block, or double quoted
+fn styles_are_written() {
+  let (logger, buffer) = common::buffered();
+  let prose = "A long line of prose ".repeat(8);
+  let prose = prose.trim_end();
+  logger.log(&mut message("Styles"), None).unwrap();
+  logger
+    .log(&mut message("line one\nline two\n"), Some("+b_"))
+    .unwrap();
+  logger
+    .log(&mut message("under the block"), Some("+_-"))
+    .unwrap();
+  logger.log(&mut message(prose), Some("f_")).unwrap();
+  logger.log(&mut message("plain"), Some("q_")).unwrap();
+  logger
+    .log(&mut message("script: make\nmake install"), Some("kb_"))
+    .unwrap();
+  // Styles that can't hold the message are double quoted
+  let mut leading = message("  leading\nspaces");
+  leading.set_style(Style::Literal(Chomp::Strip));
+  logger.log(&mut leading, None).unwrap();
+  let mut single = message("it's");
+  single.set_style(Style::Single);
+  assert_eq!(single.style(), Some(&Style::Single));
+  logger.log(&mut single, None).unwrap();
+  logger.log(&mut message("Done"), Some("rb_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    format!(
+      concat!(
+        "---\nStyles:\n",
+        "  - |\n    line one\n    line two\n",
+        "  - \"\" :\n    - under the block\n",
+        "  - >-\n    {}\n    {}\n",
+        "  - \"plain\"\n",
+        "  - script: |-\n      make\n      make install\n",
+        "  - \"  leading\\nspaces\"\n",
+        "  - 'it''s'\n",
+        "---\n|-\n  Done",
+      ),
+      &prose[..116],
+      &prose[117..],
+    )
+  );
+
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  let children = roots[0].children();
+  assert_eq!(children[0].text(), Some("line one\nline two\n"));
+  assert_eq!(children[0].children()[0].text(), Some("under the block"));
+  assert_eq!(children[1].text(), Some(prose));
+  assert_eq!(children[2].text(), Some("plain"));
+  assert_eq!(children[4].text(), Some("  leading\nspaces"));
+  assert_eq!(children[5].text(), Some("it's"));
+  assert_eq!(roots[1].text(), Some("Done"));
+
+  // Only the message is swapped for its styled text
+  let (logger, buffer) = common::buffered();
+  let mut block = message("hello");
+  block
+    .add_field("note", "user wrote __ymlog_style__ here")
+    .unwrap();
+  logger.log(&mut block, Some("q_")).unwrap();
+  let mut block = message("two\nlines");
+  block.add_field("note", "__ymlog_style__").unwrap();
+  logger.log(&mut block, Some("+b_")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  note: user wrote __ymlog_style__ here\nmessage: \"hello\"\nchildren:\n  - fields:\n      \
+     note: __ymlog_style__\n    message: |-\n      two\n      lines"
+  );
+}
@@ -0,0 +1,73 @@
+//! Test the ymlog-cli binary
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+use ymlog::prelude::*;
+
+mod common;
+use common::message;
+
+#[test]
+/// The filter command prints the matching subtrees of a log
+fn filter_prints_matches() {
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+  logger.log(&mut message("Request"), Some("I_")).unwrap();
+  let mut query = message("Querying");
+  query.set_tags(vec!["db"]);
+  logger.log(&mut query, Some("+W_")).unwrap();
+  logger.log(&mut message("Slow"), Some("+W_")).unwrap();
+  logger.log(&mut message("Done"), Some("-W_")).unwrap();
+
+  let path = std::env::temp_dir().join(format!("ymlog_cli_{}.jsonl", std::process::id()));
+  std::fs::write(&path, common::contents(&buffer)).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_ymlog-cli"))
+    .args(["filter", "--level", "warn", "--tag", "db"])
+    .arg(&path)
+    .output()
+    .unwrap();
+  std::fs::remove_file(&path).unwrap();
+
+  assert!(output.status.success());
+  assert_eq!(
+    String::from_utf8(output.stdout).unwrap(),
+    "---\nlog_level: Warn\ntags:\n- db\nmessage: Querying\nchildren:\n- log_level: Warn\n  message: Slow\n...\n"
+  );
+
+  let output = Command::new(env!("CARGO_BIN_EXE_ymlog-cli"))
+    .args(["filter", "--level", "loud", "app.yml"])
+    .output()
+    .unwrap();
+  assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+/// YAML logs are filtered by the levels and tags written into their records
+fn yaml_logs_are_filtered() {
+  let (logger, buffer) = common::buffered();
+  logger.log(&mut message("Request"), Some("I_")).unwrap();
+  let mut query = message("Querying");
+  query.set_tags(vec!["db"]);
+  logger.log(&mut query, Some("+W_")).unwrap();
+  logger.log(&mut message("Slow"), Some("+W_")).unwrap();
+  let mut cached = message("Cached");
+  cached.set_tags(vec!["db"]);
+  logger.log(&mut cached, Some("-_")).unwrap();
+  logger.log(&mut message("Done"), Some("-W_")).unwrap();
+
+  let path = std::env::temp_dir().join(format!("ymlog_cli_{}.yml", std::process::id()));
+  std::fs::write(&path, common::contents(&buffer)).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_ymlog-cli"))
+    .args(["filter", "--level", "warn", "--tag", "db", "--flatten"])
+    .arg(&path)
+    .output()
+    .unwrap();
+  std::fs::remove_file(&path).unwrap();
+
+  assert!(output.status.success());
+  assert_eq!(
+    String::from_utf8(output.stdout).unwrap(),
+    "---\nlog_level: Warn\ntags:\n- db\nmessage: Querying\n...\n"
+  );
+}
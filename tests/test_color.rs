@@ -0,0 +1,82 @@
+//! Test coloring records with ANSI codes
+
+use std::str::FromStr;
+
+use ymlog::prelude::*;
+use ymlog::ColorChoice;
+
+mod common;
+use common::message;
+
+fn strip(text: &str) -> String {
+  let mut stripped = String::new();
+  let mut rest = text;
+  while let Some(start) = rest.find('\x1b') {
+    stripped.push_str(&rest[..start]);
+    rest = &rest[start + rest[start..].find('m').unwrap() + 1..];
+  }
+  stripped + rest
+}
+
+#[test]
+/// Keys are cyan and the values of warnings are yellow, without changing the YAML
+fn yaml_records_are_colored() {
+  let (logger, buffer) = common::buffered();
+  logger.set_color(ColorChoice::Always);
+
+  let mut warning = message("Disk: nearly full");
+  warning.set_log_level(Level::Warn);
+  warning.add_field("free", "2%").unwrap();
+  logger.log(&mut message("Checking"), Some("_+")).unwrap();
+  logger.log(&mut warning, Some("_")).unwrap();
+  logger
+    .log(&mut message("Line one\nLine two: still text"), Some("_-"))
+    .unwrap();
+
+  let colored = common::contents(&buffer);
+  assert!(colored.contains("\x1b[36mfields\x1b[0m:"));
+  assert!(colored.contains("\x1b[36mfree\x1b[0m: \x1b[33m2%\x1b[0m"));
+  assert!(colored.contains("\x1b[36mmessage\x1b[0m: \x1b[33m'Disk: nearly full'\x1b[0m"));
+  assert!(!colored.contains("\x1b[36mLine two"));
+
+  let (plain_logger, plain) = common::buffered();
+  plain_logger
+    .log(&mut message("Checking"), Some("_+"))
+    .unwrap();
+  plain_logger.log(&mut warning, Some("_")).unwrap();
+  plain_logger
+    .log(&mut message("Line one\nLine two: still text"), Some("_-"))
+    .unwrap();
+  assert_eq!(strip(&colored), common::contents(&plain));
+}
+
+#[test]
+/// JSON lines color the keys and the level
+fn json_lines_are_colored() {
+  let (logger, buffer) = common::buffered();
+  logger.set_color(ColorChoice::Always);
+  logger.set_format(OutputFormat::JsonLines);
+
+  let mut error = message("Failed \"badly\"");
+  error.set_log_level(Level::Error);
+  logger.log(&mut error, Some("_")).unwrap();
+  let colored = common::contents(&buffer);
+  assert!(colored.contains("\x1b[36m\"log_level\"\x1b[0m:\x1b[31m\"Error\"\x1b[0m"));
+  assert!(colored.contains("\x1b[36m\"message\"\x1b[0m:\"Failed \\\"badly\\\"\""));
+}
+
+#[test]
+/// Outputs that aren't terminals are left alone unless colors are forced
+fn colors_are_detected() {
+  let (logger, buffer) = common::buffered();
+  logger.log(&mut message("Plain"), Some("_")).unwrap();
+  logger.set_color(ColorChoice::Never);
+  logger.log(&mut message("Plainer"), Some("_")).unwrap();
+  assert!(!common::contents(&buffer).contains('\x1b'));
+
+  assert_eq!(
+    ColorChoice::from_str("ALWAYS").unwrap(),
+    ColorChoice::Always
+  );
+  assert!(ColorChoice::from_str("sometimes").is_err());
+}
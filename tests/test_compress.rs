@@ -0,0 +1,51 @@
+//! Test compressing large messages
+
+use std::io::Result as IoResult;
+use std::sync::{Arc, Mutex};
+
+use ymlog::prelude::*;
+use ymlog::{Codec, Compression};
+
+mod common;
+
+/// A stand-in codec that just reverses the bytes, so we can see the value round trip
+struct Reverse;
+
+impl Codec for Reverse {
+  fn name(&self) -> &str {
+    "reverse"
+  }
+
+  fn compress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    Ok(data.iter().rev().cloned().collect())
+  }
+
+  fn decompress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    self.compress(data)
+  }
+}
+
+#[test]
+/// Only messages over the threshold get compressed, and they expand back to the original
+fn large_messages_are_tagged() {
+  let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(common::TestWriter::new(&buffer));
+  logger.set_compression(Compression::new(Box::new(Reverse), 16));
+
+  let large = "A large literal\nthat is well over the threshold";
+  for msg in ["Small", large] {
+    let mut block = Block::new();
+    block.set_message(msg).unwrap();
+    logger.log(&mut block, Some("_")).unwrap();
+  }
+
+  let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+  let mut docs = output.split("---\n").skip(1);
+  assert_eq!(docs.next(), Some("Small\n"));
+
+  let packed: serde_yaml::Value = serde_yaml::from_str(docs.next().unwrap()).unwrap();
+  assert!(output.contains("!ymlog/reverse "));
+  let compression = Compression::new(Box::new(Reverse), 16);
+  assert_eq!(compression.unpack(&packed).unwrap().as_deref(), Some(large));
+}
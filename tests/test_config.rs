@@ -0,0 +1,87 @@
+//! Test the records written when a service starts
+
+use serde::Serialize;
+
+mod common;
+use common::message;
+
+#[derive(Serialize)]
+struct Database {
+  host: &'static str,
+  port: u16,
+  password: &'static str,
+}
+
+#[derive(Serialize)]
+struct Config {
+  name: &'static str,
+  workers: Vec<u8>,
+  database: Database,
+  secrets: Database,
+  features: Vec<&'static str>,
+}
+
+#[test]
+/// The configuration is nested under a config record, with denied keys removed at any depth
+fn configs_are_written_as_trees() {
+  ymlog::never_log_keys(["password", "secrets"]);
+  let (logger, buffer) = common::buffered();
+  let config = Config {
+    name: "orders",
+    workers: vec![1, 2],
+    database: Database {
+      host: "db.local",
+      port: 5432,
+      password: "hunter2",
+    },
+    secrets: Database {
+      host: "vault.local",
+      port: 8200,
+      password: "hunter3",
+    },
+    features: vec![],
+  };
+  logger.log(&mut message("Starting"), None).unwrap();
+  logger.log_config(&config).unwrap();
+  logger.log(&mut message("Started"), None).unwrap();
+
+  let expected = concat!(
+    "---\nStarting\n---\nconfig:\n",
+    "- name: orders\n",
+    "- workers:\n  - 1\n  - 2\n",
+    "- database:\n  - host: db.local\n  - port: 5432\n  - password: <removed>\n",
+    "- secrets: <removed>\n",
+    "- features: []\n",
+    "---\nStarted",
+  );
+  assert_eq!(common::contents(&buffer), expected);
+}
+
+#[test]
+/// Each run starts a new document with a header, even when the last run was left nested
+fn runs_start_with_a_header() {
+  let (logger, buffer) = common::buffered();
+  logger.log(&mut message("Earlier run"), Some("_+")).unwrap();
+  let mut meta = serde_yaml::Mapping::new();
+  meta.insert("service".into(), "orders".into());
+  logger.start_run(&meta).unwrap();
+  logger.log(&mut message("Listening"), None).unwrap();
+
+  let output = common::contents(&buffer);
+  let (earlier, run) = output.split_once("\n---\n").unwrap();
+  assert_eq!(earlier, "---\nEarlier run");
+  let header = run
+    .strip_prefix("!ymlog/run\nmessage:\n  started: ")
+    .unwrap();
+  let pid = format!("\n  pid: {}\n", std::process::id());
+  assert!(header.contains(&pid));
+  assert!(header.contains(concat!(
+    "  ymlog_version: ",
+    env!("CARGO_PKG_VERSION"),
+    "\n"
+  )));
+  assert!(header.ends_with("  service: orders\n---\nListening"));
+
+  let err = logger.start_run(&"orders").unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
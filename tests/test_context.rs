@@ -0,0 +1,72 @@
+//! Test loggers that add their context to every block
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+
+use serde_yaml::Value as YmlValue;
+
+mod common;
+use common::message;
+
+#[test]
+/// Every block logged through a scoped logger gets its tags and fields, sharing the same nesting
+fn scoped_loggers_add_their_context() {
+  let (logger, buffer) = common::buffered();
+  let mut request = BTreeMap::new();
+  request.insert("request_id", "abc");
+  let scoped = logger.with_context(vec!["web"], &request).unwrap();
+
+  scoped.log(&mut message("Handling"), Some("_+")).unwrap();
+  let mut table = BTreeMap::new();
+  table.insert("table", "users");
+  let db = scoped.with_context(vec!["db", "web"], &table).unwrap();
+  assert_eq!(db.tags(), ["web", "db"]);
+  let mut query = message("Querying");
+  query.set_tags(vec!["slow"]);
+  query.add_field("table", "accounts").unwrap();
+  db.log(&mut query, None).unwrap();
+  assert_eq!(query.tags(), ["web", "db", "slow"]);
+  logger.log(&mut message("Untouched"), Some("-_")).unwrap();
+
+  assert_eq!(
+    common::contents(&buffer),
+    "---\ntags:\n- web\nfields:\n  request_id: abc\nmessage: Handling\nchildren:\n  - tags:\n    - \
+     web\n    - db\n    - slow\n    fields:\n      request_id: abc\n      table: accounts\n    \
+     message: Querying\n---\nUntouched"
+  );
+
+  let err = logger.with_context(vec!["web"], 3).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  let plain = logger.with_context(Vec::<String>::new(), ()).unwrap();
+  assert!(plain.fields().is_empty());
+  assert_eq!(db.fields().get("request_id"), Some(&YmlValue::from("abc")));
+}
+
+#[test]
+/// Fields pushed onto the thread's context are added to every block logged on the thread
+fn thread_context_is_added_to_blocks() {
+  let (logger, buffer) = common::buffered();
+
+  ymlog::context::push("job", 42).unwrap();
+  {
+    let scope = ymlog::context::scope();
+    scope.push("step", "load").unwrap();
+    ymlog::context::push("attempt", 1).unwrap();
+    let mut block = message("Loading");
+    block.add_field("step", "copy").unwrap();
+    logger.log(&mut block, None).unwrap();
+  }
+  assert_eq!(ymlog::context::fields().len(), 1);
+
+  // Other threads have their own context
+  std::thread::scope(|scope| {
+    scope.spawn(|| logger.log(&mut message("Elsewhere"), None).unwrap());
+  });
+  assert_eq!(ymlog::context::pop().unwrap().0, "job");
+  logger.log(&mut message("Done"), None).unwrap();
+
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  job: 42\n  step: copy\n  attempt: 1\nmessage: Loading\n---\nElsewhere\n---\nDone"
+  );
+}
@@ -0,0 +1,67 @@
+//! Test logging SQL statements
+
+use std::time::Duration;
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::db::{self, Query};
+use ymlog::http::REDACTED;
+
+mod common;
+
+#[test]
+/// Queries are tagged literal blocks, with the parameters hidden unless asked for
+fn queries_are_tagged_records() {
+  let (logger, buffer) = common::buffered();
+
+  Query::new(
+    "SELECT * FROM users WHERE id = $1",
+    Duration::from_micros(1500),
+  )
+  .param(7)
+  .log(&logger, None)
+  .unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "---\n",
+      "!sql\n",
+      "fields:\n",
+      "  params:\n",
+      "  - <redacted>\n",
+      "message: |\n",
+      "  SELECT * FROM users WHERE id = $1\n",
+      "children:\n",
+      "- duration_ms: 1.5",
+    )
+  );
+
+  let block = Query::new("  UPDATE users\nSET name = $1 ", Duration::ZERO)
+    .param("Ana")
+    .param(YmlValue::Null)
+    .reveal_params()
+    .to_block();
+  assert_eq!(block.tag_type(), Some(db::SQL_TAG));
+  assert_eq!(
+    block.field("params").unwrap(),
+    &serde_yaml::from_str::<YmlValue>("[Ana, null]").unwrap()
+  );
+}
+
+#[test]
+/// The global helper always redacts
+fn global_queries_are_redacted() {
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+
+  db::log_query(
+    "SELECT $1, $2",
+    &["secret", "other"],
+    Duration::from_millis(3),
+  );
+  let output = common::contents(&buffer);
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert!(!output.contains("secret"));
+  assert_eq!(parsed["fields"]["params"][1], REDACTED);
+  assert_eq!(parsed["children"][0]["duration_ms"], 3.0);
+}
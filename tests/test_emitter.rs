@@ -0,0 +1,76 @@
+//! Test writing YAML streams without a logger
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::Emitter;
+
+/// A tree of the caller's own, exported by walking it
+struct Node {
+  name: &'static str,
+  children: Vec<Node>,
+}
+
+fn export(emitter: &mut Emitter<Vec<u8>>, node: &Node) {
+  match node.children.is_empty() {
+    true => emitter.scalar(node.name).unwrap(),
+    false => {
+      emitter.start_sequence_item(node.name).unwrap();
+      for child in &node.children {
+        export(emitter, child);
+      }
+      emitter.dedent();
+    }
+  }
+}
+
+fn leaf(name: &'static str) -> Node {
+  Node {
+    name,
+    children: vec![],
+  }
+}
+
+#[test]
+/// Trees are written as the same nested records the logger writes
+fn trees_export_as_nested_records() {
+  let tree = Node {
+    name: "Root",
+    children: vec![
+      Node {
+        name: "Branch",
+        children: vec![leaf("Leaf"), leaf("Second\nline")],
+      },
+      leaf("Sibling"),
+    ],
+  };
+
+  let mut emitter = Emitter::new(Vec::new());
+  export(&mut emitter, &tree);
+  assert_eq!(emitter.depth(), 0);
+  emitter.end_document();
+  emitter.pair("exported", true).unwrap();
+
+  let written = String::from_utf8(emitter.into_inner()).unwrap();
+  assert_eq!(
+    written,
+    concat!(
+      "---\n",
+      "Root:\n",
+      "  - Branch:\n",
+      "    - Leaf\n",
+      "    - |-\n",
+      "      Second\n",
+      "      line\n",
+      "  - Sibling\n",
+      "---\n",
+      "exported: true",
+    )
+  );
+
+  let documents = written
+    .split("---\n")
+    .skip(1)
+    .map(|doc| serde_yaml::from_str::<YmlValue>(doc).unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(documents.len(), 2);
+}
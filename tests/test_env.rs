@@ -0,0 +1,59 @@
+//! Test configuring a logger from a YMLOG directive string
+
+use std::io::ErrorKind;
+
+use ymlog::prelude::*;
+use ymlog::Indent;
+
+fn from(target: &str, level: Level) -> Block {
+  let mut block = Block::new();
+  block
+    .set_message(format!("{} {:?}", target, level))
+    .unwrap();
+  block.set_target(target);
+  block.set_log_level(level);
+  block
+}
+
+#[test]
+/// Every kind of directive ends up on the logger
+fn directives_configure_the_logger() {
+  let path = std::env::temp_dir().join(format!("ymlog_env_{}.jsonl", std::process::id()));
+  let directives = format!(
+    "warn, file={}, format=json, indent=tab, my_app::db=trace",
+    path.display()
+  );
+
+  let logger = YmLog::from_directives(&directives).unwrap();
+  assert_eq!(logger.indent(), Indent::Tab);
+  for (target, level) in [
+    ("my_app", Level::Info),
+    ("my_app", Level::Warn),
+    ("my_app::db", Level::Debug),
+  ] {
+    logger.log(&mut from(target, level), Some("r")).unwrap();
+  }
+  drop(logger);
+
+  let output = std::fs::read_to_string(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(
+    output,
+    concat!(
+      "{\"depth\":0,\"log_level\":\"Warn\",\"message\":\"my_app Warn\"}\n",
+      "{\"depth\":0,\"log_level\":\"Debug\",\"message\":\"my_app::db Debug\"}\n",
+    )
+  );
+}
+
+#[test]
+/// Bad values are errors rather than being ignored
+fn invalid_directives_are_errors() {
+  for directives in ["format=xml", "indent=0", "indent=wide", "my_app=loud"] {
+    let err = YmLog::from_directives(directives).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", directives);
+  }
+
+  let logger = YmLog::from_directives("").unwrap();
+  assert_eq!(logger.indent(), Indent::Space(2));
+}
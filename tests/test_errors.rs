@@ -0,0 +1,144 @@
+//! Test how errors are surfaced when logging
+
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+
+use ymlog::prelude::*;
+use ymlog::{DedentPolicy, ErrorHandler, SchemaMode};
+
+mod common;
+use common::message;
+
+#[test]
+/// Mistakes in the actions are returned rather than panicking
+fn log_returns_errors() {
+  let logger = YmLog::<common::TestWriter>::new();
+  let err = logger.log(&mut message("Nowhere"), None).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::NotConnected);
+
+  let (logger, _buffer) = common::buffered();
+  let err = logger.log(&mut message("Bad"), Some("x")).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+  let err = logger.log(&mut message("No colon"), Some("k")).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+/// The macro writes its errors into the log, and keeps working after a panic poisons the lock
+fn macro_errors_become_records() {
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+
+  ymlog!("Root");
+  ymlog!("+x_" => "Never written");
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nRoot:\n  - !ymlog/error\n    log_level: Error\n    message: '<ymlog error: Invalid character ''x'' found in the logging actions>'"
+  );
+
+  // Panicking in the handler poisons the logger's lock
+  ymlog::global().set_error_handler(ErrorHandler::Panic);
+  let _ = std::thread::spawn(|| ymlog!("x" => "Poisoning the global logger")).join();
+  ymlog::global().set_error_handler(ErrorHandler::MetaRecord);
+  ymlog!("After the panic");
+  assert!(common::contents(&buffer).ends_with("\n  - After the panic"));
+}
+
+#[test]
+/// A custom handler receives the errors reported to it
+fn custom_handlers_see_errors() {
+  let (logger, buffer) = common::buffered();
+  let seen = Arc::new(Mutex::new(vec![]));
+  let store = Arc::clone(&seen);
+  logger.set_error_handler(ErrorHandler::Custom(Box::new(move |err| {
+    store.lock().unwrap().push(err.kind())
+  })));
+
+  if let Err(err) = logger.log(&mut message("Bad"), Some("?")) {
+    logger.report(err);
+  }
+  assert_eq!(*seen.lock().unwrap(), vec![ErrorKind::InvalidInput]);
+  assert_eq!(common::contents(&buffer), "");
+}
+
+#[test]
+/// Blocks the validator rejects are reported, and only written when the mode is lenient
+fn validators_reject_blocks() {
+  let (logger, buffer) = common::buffered();
+  logger.set_validator(Some(Box::new(|block: &Block| match block.field("order") {
+    Some(_) => Ok(()),
+    None => Err("there is no order field".to_string()),
+  })));
+
+  let mut valid = message("Shipped");
+  valid.add_field("order", 7).unwrap();
+  logger.log(&mut valid, Some("_+")).unwrap();
+  logger.log(&mut message("Lost"), None).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  order: 7\nmessage: Shipped\nchildren:\n  - Lost\n  - !ymlog/error\n    log_level: Error\n    message: '<ymlog error: A record failed validation: there is no order field>'"
+  );
+
+  logger.set_schema_mode(SchemaMode::Strict);
+  let handle = logger.log(&mut message("Dropped"), None).unwrap();
+  assert!(handle.is_none());
+  assert!(!common::contents(&buffer).contains("Dropped"));
+  assert!(common::contents(&buffer).ends_with("there is no order field>'"));
+
+  logger.close().unwrap();
+  assert!(common::contents(&buffer).contains("fields:\n  invalid: 1\n"));
+}
+
+#[test]
+/// Blocks below the level are dropped before the validator sees them
+fn filtered_blocks_skip_validation() {
+  let (logger, buffer) = common::buffered();
+  logger.set_level(Level::Warn);
+  logger.set_validator(Some(Box::new(|_: &Block| Err("nope".to_string()))));
+
+  let mut debug = message("Hidden");
+  debug.set_log_level(Level::Debug);
+  assert!(logger.log(&mut debug, None).unwrap().is_none());
+  logger.log(&mut message("Also hidden"), None).unwrap();
+  assert_eq!(common::contents(&buffer), "");
+}
+
+#[test]
+/// Dedenting past the root stays there, and the policy decides who is told about it
+fn dedents_past_the_root_follow_the_policy() {
+  for policy in [
+    DedentPolicy::Clamp,
+    DedentPolicy::Report,
+    DedentPolicy::Error,
+  ]
+  .iter()
+  {
+    let (logger, buffer) = common::buffered();
+    let seen = Arc::new(Mutex::new(0));
+    let store = Arc::clone(&seen);
+    logger.set_error_handler(ErrorHandler::Custom(Box::new(move |_| {
+      *store.lock().unwrap() += 1
+    })));
+    logger.set_dedent_policy(*policy);
+
+    logger.log(&mut message("Root"), Some("_+")).unwrap();
+    logger.log(&mut message("Child"), Some("-_")).unwrap();
+    let result = logger.log(&mut message("Next"), Some("-_"));
+
+    let reported = *seen.lock().unwrap();
+    match policy {
+      DedentPolicy::Clamp => assert!(result.is_ok() && reported == 0),
+      DedentPolicy::Report => assert!(result.is_ok() && reported == 1),
+      DedentPolicy::Error => {
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(reported, 0);
+      }
+    }
+    let expected = match policy {
+      DedentPolicy::Error => "---\nRoot\n---\nChild",
+      _ => "---\nRoot\n---\nChild\n---\nNext",
+    };
+    assert_eq!(common::contents(&buffer), expected, "{:?}", policy);
+  }
+}
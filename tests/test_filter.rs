@@ -0,0 +1,150 @@
+//! Test setting levels per module, and filtering by tags
+
+use std::sync::{Arc, Mutex};
+
+use ymlog::prelude::*;
+use ymlog::Pipeline;
+
+mod common;
+
+fn from(target: &str, level: Level) -> Block {
+  let mut block = Block::new();
+  block
+    .set_message(format!("{} {:?}", target, level))
+    .unwrap();
+  block.set_target(target);
+  block.set_log_level(level);
+  block
+}
+
+#[test]
+/// The longest matching module path picks the level, and the rest use the default
+fn modules_have_their_own_levels() {
+  let (logger, buffer) = common::buffered();
+  logger
+    .set_filter("warn, my_app::db=trace, my_app::db::pool=off, chatty")
+    .unwrap();
+
+  for (target, level) in [
+    ("my_app", Level::Info),
+    ("my_app", Level::Warn),
+    ("my_app::db", Level::Trace),
+    ("my_app::dbx", Level::Debug),
+    ("my_app::db::query", Level::Debug),
+    ("my_app::db::pool", Level::Error),
+    ("chatty::inner", Level::Trace),
+  ] {
+    logger.log(&mut from(target, level), Some("r")).unwrap();
+  }
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "\n---\nlog_level: Warn\nmessage: my_app Warn",
+      "\n---\nlog_level: Trace\nmessage: my_app::db Trace",
+      "\n---\nlog_level: Debug\nmessage: my_app::db::query Debug",
+      "\n---\nlog_level: Trace\nmessage: chatty::inner Trace",
+    )
+  );
+
+  assert!(logger.set_filter("my_app=loud").is_err());
+}
+
+#[test]
+/// The macro records the module it was called from
+fn macro_sets_the_target() {
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+  ymlog::global().set_filter("info,test_filter=off").unwrap();
+
+  ymlog!("Filtered out");
+  assert_eq!(common::contents(&buffer), "");
+}
+
+#[test]
+/// Levels can be listed, printed and parsed, for configuring from the CLI and environment
+fn levels_round_trip_through_strings() {
+  let names = Level::iter()
+    .map(|level| level.to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(names, ["Trace", "Debug", "Info", "Warn", "Error"]);
+  for level in Level::iter() {
+    let upper = level.to_string().to_uppercase();
+    assert_eq!(upper.parse::<Level>().unwrap(), level);
+    assert_eq!(level.name().to_lowercase().parse::<Level>().unwrap(), level);
+  }
+  assert!(Level::iter().zip(Level::iter().skip(1)).all(|(a, b)| a < b));
+
+  let err = "verbose".parse::<Level>().unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+const AUDIT: Level = Level::Custom(45, "Audit");
+const FATAL: Level = Level::Custom(60, "Fatal");
+
+#[test]
+/// Custom levels are ranked among the built in ones and written with their own names
+fn custom_levels_have_their_own_names() {
+  Level::register(AUDIT).unwrap();
+  Level::register(FATAL).unwrap();
+  assert!(Level::register(Level::Custom(1, "info")).is_err());
+  assert_eq!("audit".parse::<Level>().unwrap(), AUDIT);
+
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+  logger.set_filter("audit").unwrap();
+  for level in [Level::Warn, AUDIT, Level::Error, FATAL] {
+    logger.log(&mut from("app", level), None).unwrap();
+  }
+  let levels = common::contents(&buffer)
+    .lines()
+    .map(|line| {
+      let record: serde_yaml::Value = serde_yaml::from_str(line).unwrap();
+      record["log_level"].as_str().unwrap().to_string()
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(levels, ["Audit", "Error", "Fatal"]);
+}
+
+fn tagged(msg: &str, tags: &[&str]) -> Block {
+  let mut block = Block::new();
+  block.set_message(msg).unwrap();
+  block.set_tags(tags.to_vec());
+  block
+}
+
+#[test]
+/// The logger's tag filter applies to outputs without their own, which can route tags elsewhere
+fn tags_filter_and_route_blocks() {
+  let (logger, buffer) = common::buffered();
+  let audit = Arc::new(Mutex::new(Vec::<u8>::new()));
+  logger.add_output_with(
+    common::TestWriter::new(&audit),
+    Pipeline::new().tags(["audit"].iter(), Vec::<String>::new()),
+  );
+  logger.filter_tags(["app", "audit"].iter(), ["audit", "debug"].iter());
+
+  for block in [
+    tagged("Untagged", &[]),
+    tagged("Started", &["app"]),
+    tagged("Noisy", &["app", "debug"]),
+    tagged("Login", &["audit"]),
+  ]
+  .iter_mut()
+  {
+    logger.log(block, Some("r")).unwrap();
+  }
+
+  let main = common::contents(&buffer);
+  assert!(main.contains("Started"), "{}", main);
+  for missing in ["Untagged", "Noisy", "Login"].iter() {
+    assert!(!main.contains(missing), "{}", main);
+  }
+  assert_eq!(
+    common::contents(&audit),
+    "\n---\ntags:\n- audit\nmessage: Login"
+  );
+
+  // Nothing is withheld, since dropping them is what the filter is for
+  logger.close().unwrap();
+  assert!(!common::contents(&buffer).contains("summary"));
+}
@@ -0,0 +1,639 @@
+//! Test converting values to strings with the YamlFormatter
+
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+use ymlog::{NumberFormat, ValueFormat};
+
+mod common;
+
+#[derive(Serialize)]
+enum Status {
+  Running { pid: u32 },
+}
+
+#[derive(Serialize)]
+struct Job {
+  name: String,
+  tags: Vec<String>,
+  limits: Vec<(u8, Option<bool>)>,
+  env: std::collections::BTreeMap<String, String>,
+  status: Status,
+  empty: Vec<u8>,
+}
+
+/// Stringify the value, and check it parses back to the same thing
+fn round_trip(value: &YmlValue, indent: Option<u8>) -> String {
+  let mut formatter = YamlFormatter::default();
+  let output = formatter.stringify(value.clone(), indent).unwrap();
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(&parsed, value, "Did not round trip:\n{}", output);
+  output
+}
+
+#[test]
+/// Nested structures are written in block style, with empty containers in flow
+fn nested_structures() {
+  let job = Job {
+    name: "build #4: release".to_string(),
+    tags: vec!["ci".to_string(), "yes".to_string()],
+    limits: vec![(1, Some(true)), (2, None)],
+    env: [("PATH", "/bin"), ("multi\nline", "key")]
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect(),
+    status: Status::Running { pid: 42 },
+    empty: vec![],
+  };
+  let value = serde_yaml::to_value(&job).unwrap();
+
+  assert_eq!(
+    round_trip(&value, None),
+    concat!(
+      "name: \"build #4: release\"\n",
+      "tags:\n",
+      "  - ci\n",
+      "  - \"yes\"\n",
+      "limits:\n",
+      "  - - 1\n",
+      "    - true\n",
+      "  - - 2\n",
+      "    - null\n",
+      "env:\n",
+      "  PATH: /bin\n",
+      "  \"multi\\nline\": key\n",
+      "status: !Running\n",
+      "  pid: 42\n",
+      "empty: []\n",
+    )
+  );
+}
+
+#[test]
+/// Nested values line up under the requested indentation, and complex keys are written in flow
+fn indents_and_keys() {
+  let value: YmlValue = serde_yaml::from_str("[{a: 1, b: [x, {}]}, {? [1, 2] : pair}]").unwrap();
+  assert_eq!(
+    round_trip(&value, Some(2)),
+    concat!(
+      "    - a: 1\n",
+      "      b:\n",
+      "        - x\n",
+      "        - {}\n",
+      "    - [1, 2]: pair\n",
+    )
+  );
+
+  let mut formatter = YamlFormatter::default();
+  formatter.set_style(Style::Single);
+  let value: YmlValue = serde_yaml::from_str("{quote: \"it's\", number: 3.5}").unwrap();
+  assert_eq!(
+    formatter.stringify(value, None).unwrap(),
+    "quote: 'it''s'\nnumber: 3.5\n"
+  );
+}
+
+#[test]
+/// Literal blocks keep every line, reading back with the trailing newlines the chomp asks for
+fn literal_blocks() {
+  let text = "First line\n\n  indented\nlast\n\n";
+  for (chomp, expected) in [
+    (Chomp::Strip, "First line\n\n  indented\nlast"),
+    (Chomp::Clip, "First line\n\n  indented\nlast\n"),
+    (Chomp::Keep, text),
+  ] {
+    let mut formatter = YamlFormatter::default();
+    formatter.set_style(Style::Literal(chomp));
+    let mut value: YmlValue = serde_yaml::from_str("{msg: x, list: [y]}").unwrap();
+    value["msg"] = YmlValue::String(text.to_string());
+    value["list"][0] = YmlValue::String(text.to_string());
+
+    let output = formatter.stringify(value, Some(1)).unwrap();
+    let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+    assert_eq!(parsed["msg"], expected, "Wrote:\n{}", output);
+    assert_eq!(parsed["list"][0], expected, "Wrote:\n{}", output);
+  }
+
+  assert_eq!(
+    Style::literal_string(
+      "  leading\nspace".to_string(),
+      0,
+      &Chomp::Strip,
+      &Default::default(),
+      120
+    )
+    .unwrap(),
+    " |2-\n    leading\n  space"
+  );
+}
+
+#[test]
+/// Folded blocks wrap by the columns the text takes, and read back as the same text
+fn folded_blocks_wrap_by_width() {
+  let fold = |text: &str, wrap_at| {
+    Style::fold_string(
+      text.to_string(),
+      0,
+      &Chomp::Strip,
+      &Default::default(),
+      wrap_at,
+    )
+    .unwrap()
+  };
+  assert_eq!(
+    fold("one two three four five", 12),
+    " >-\n  one two\n  three four\n  five"
+  );
+  // Each of these takes four columns, so two fit in a line where four of the ASCII words would
+  assert_eq!(
+    fold("日本 語の 文字 です", 12),
+    " >-\n  日本 語の\n  文字 です"
+  );
+  assert_eq!(fold("🎉🎉 ab 🎉🎉", 9), " >-\n  🎉🎉 ab\n  🎉🎉");
+  assert_eq!(
+    fold("e\u{301}e\u{301} abc de", 9),
+    " >-\n  e\u{301}e\u{301} abc\n  de"
+  );
+
+  let text = concat!(
+    "A paragraph of prose long enough to be wrapped\n",
+    "  an indented line that is never wrapped\n",
+    "next 日本語の文字 🎉 line\n",
+    "\n",
+    "spaces  are   kept, 👩‍👩‍👧 too\n",
+    "last",
+  );
+  for wrap_at in [8, 16, 40, 120] {
+    let folded = fold(text, wrap_at);
+    let parsed: YmlValue = serde_yaml::from_str(&format!("msg:{}\n", folded)).unwrap();
+    assert_eq!(parsed["msg"], text, "Wrote:\n{}", folded);
+  }
+}
+
+#[test]
+/// Literal blocks write every line ending as `\n`, while the guessed style keeps them as logged
+fn mixed_line_endings() {
+  let text = "crlf\r\nlf\ncr\rlast";
+  let mut formatter = YamlFormatter::default();
+  formatter.set_style(Style::Literal(Chomp::Strip));
+  let mut value: YmlValue = serde_yaml::from_str("{msg: x, list: [y]}").unwrap();
+  value["msg"] = YmlValue::String(text.to_string());
+  value["list"][0] = YmlValue::String(text.to_string());
+  let output = formatter.stringify(value, Some(1)).unwrap();
+  assert_eq!(
+    output,
+    concat!(
+      "  msg: |-\n",
+      "    crlf\n",
+      "    lf\n",
+      "    cr\n",
+      "    last\n",
+      "  list:\n",
+      "    - |-\n",
+      "      crlf\n",
+      "      lf\n",
+      "      cr\n",
+      "      last\n",
+    )
+  );
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(parsed["msg"], "crlf\nlf\ncr\nlast");
+  assert_eq!(parsed["list"][0], "crlf\nlf\ncr\nlast");
+
+  let mut value: YmlValue = serde_yaml::from_str("{msg: x}").unwrap();
+  value["msg"] = YmlValue::String(text.to_string());
+  assert_eq!(
+    round_trip(&value, None),
+    "msg: \"crlf\\r\\nlf\\ncr\\rlast\"\n"
+  );
+
+  // A logged message keeps its line endings, and records indented under it still parse
+  let (logger, buffer) = common::buffered();
+  let mut block = Block::new();
+  block.set_message(text).unwrap();
+  logger.log(&mut block, Some("_+")).unwrap();
+  let mut block = Block::new();
+  block.set_message("child").unwrap();
+  logger.log(&mut block, None).unwrap();
+  logger.close().unwrap();
+  let output = common::contents(&buffer);
+  let parsed: YmlValue = serde_yaml::from_str(output.trim_start_matches("---\n")).unwrap();
+  assert_eq!(parsed[text][0], "child", "Wrote:\n{}", output);
+}
+
+#[test]
+/// Floats can be rounded, switched to scientific notation, and have their thousands separated
+fn numbers_are_formatted() {
+  let value: YmlValue =
+    serde_yaml::from_str("[0.30000000000000004, 1234567, -9876543.21, 0.00001, 0.0]").unwrap();
+  let expect = |format: NumberFormat, yaml: &str| {
+    assert_eq!(
+      format.apply(&value),
+      serde_yaml::from_str::<YmlValue>(yaml).unwrap()
+    );
+  };
+
+  expect(
+    NumberFormat::new().precision(2),
+    "[0.3, 1234567, -9876543.21, 0.0, 0.0]",
+  );
+  expect(
+    NumberFormat::new().precision(1).scientific(0.001, 1e6),
+    "[0.3, 1234567, '-9.9e6', '1.0e-5', 0.0]",
+  );
+  expect(
+    NumberFormat::new().thousands(','),
+    "['0.30000000000000004', '1,234,567', '-9,876,543.21', '0.00001', '0']",
+  );
+
+  let mut formatter = YamlFormatter::default();
+  formatter.set_number_format(NumberFormat::new().precision(3));
+  let values: YmlValue = serde_yaml::from_str("{ratio: 0.1234567}").unwrap();
+  assert_eq!(formatter.stringify(values, None).unwrap(), "ratio: 0.123\n");
+}
+
+#[test]
+/// The logger formats the numbers in messages and fields
+fn logged_numbers_are_formatted() {
+  let (logger, buffer) = common::buffered();
+  logger.set_number_format(NumberFormat::new().precision(2).thousands('_'));
+
+  let mut block = Block::new();
+  block.set_message(0.1 + 0.2).unwrap();
+  block.add_field("bytes", 1_048_576).unwrap();
+  logger.log(&mut block, None).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  bytes: 1_048_576\nmessage: '0.30'"
+  );
+}
+
+#[test]
+/// Durations and sizes are written as readable scalars, on their own and through the logger
+fn durations_and_sizes_are_readable() {
+  #[derive(Serialize)]
+  struct Upload {
+    took: Duration,
+    size: u64,
+    parts: u64,
+  }
+  let upload = Upload {
+    took: Duration::from_millis(1200),
+    size: 4_718_592,
+    parts: 3,
+  };
+  let format = ValueFormat::new().durations().bytes("size");
+  let value = serde_yaml::to_value(&upload).unwrap();
+  assert_eq!(
+    format.apply(&value),
+    serde_yaml::from_str::<YmlValue>("{took: 1.2 s, size: 4.5 MiB, parts: 3}").unwrap()
+  );
+  // Without asking, serde's shapes are kept
+  assert_eq!(ValueFormat::new().apply(&value), value);
+
+  let mut formatter = YamlFormatter::default();
+  formatter.set_value_format(format.clone());
+  assert_eq!(
+    formatter.stringify(value, None).unwrap(),
+    "took: 1.2 s\nsize: 4.5 MiB\nparts: 3\n"
+  );
+
+  let (logger, buffer) = common::buffered();
+  logger.set_value_format(format);
+  let mut block = Block::new();
+  block.set_key_value("size", 2048).unwrap();
+  block.add_field("wait", Duration::from_micros(350)).unwrap();
+  block.set_field_duration("total", Duration::from_secs(90));
+  block.set_field_bytes("cache", 512);
+  logger.log(&mut block, None).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  wait: 350.0 µs\n  total: 1.5 min\n  cache: 512 B\nmessage:\n  size: 2.0 KiB"
+  );
+}
+
+#[test]
+/// Small nested mappings and sequences are written on one line, and larger ones stay as blocks
+fn small_containers_are_flowed() {
+  let value = serde_yaml::from_str::<YmlValue>(
+    "{user: {id: 7, name: ann}, tags: [a, 'b, c'], notes: [first note to keep, second note]}",
+  )
+  .unwrap();
+  let mut formatter = YamlFormatter::default();
+  formatter.set_flow_width(Some(24));
+  let written = formatter.stringify(value.clone(), None).unwrap();
+  assert_eq!(
+    written,
+    "user: {id: 7, name: ann}\ntags: [a, \"b, c\"]\nnotes:\n  - first note to keep\n  - second note\n"
+  );
+  assert_eq!(serde_yaml::from_str::<YmlValue>(&written).unwrap(), value);
+
+  let (logger, buffer) = common::buffered();
+  logger.set_flow_width(Some(40));
+  let mut block = Block::new();
+  block.set_message("Retrying").unwrap();
+  block.add_field("attempt", vec![2, 3]).unwrap();
+  logger.log(&mut block, None).unwrap();
+  let mut block = Block::new();
+  block.set_key_value("limits", &value).unwrap();
+  logger.log(&mut block, Some("+_")).unwrap();
+  let written = common::contents(&buffer);
+  assert_eq!(
+    written,
+    "---\nfields: {attempt: [2, 3]}\nmessage: Retrying\nchildren:\n  - limits:\n      user: {id: 7, name: ann}\n      tags: [a, \"b, c\"]\n      notes: [first note to keep, second note]"
+  );
+  serde_yaml::from_str::<YmlValue>(&written).unwrap();
+
+  // Text that looks like the marks standing in for flowed values is written as it is
+  let (logger, buffer) = common::buffered();
+  logger.set_flow_width(Some(40));
+  let mut block = Block::new();
+  block.set_message("__ymlog_flow_0__").unwrap();
+  block.add_field("attempt", vec![2, 3]).unwrap();
+  logger.log(&mut block, None).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields: {attempt: [2, 3]}\nmessage: __ymlog_flow_0__"
+  );
+}
+
+/// Strings that read as something else, or not at all, if they are written as they are
+const SPECIAL: &[&str] = &[
+  "",
+  " ",
+  "yes",
+  "No",
+  "ON",
+  "off",
+  "y",
+  "N",
+  "true",
+  "False",
+  "null",
+  "Null",
+  "NULL",
+  "~",
+  "3.14",
+  "-2",
+  "+7",
+  "1e3",
+  ".5",
+  "0x1F",
+  "0o17",
+  "0b101",
+  ".inf",
+  "-.Inf",
+  ".NaN",
+  "1_000",
+  "1:30",
+  "2001-12-14",
+  "2001-12-14T21:59:43Z",
+  "<<",
+  "=",
+  "- item",
+  "-",
+  "--",
+  "---",
+  "...",
+  "? key",
+  ":",
+  ":colon",
+  "key:",
+  "a: b",
+  "a #b",
+  "#comment",
+  "[list]",
+  "{map}",
+  "a, b",
+  "*alias",
+  "&anchor",
+  "!tag",
+  "|",
+  ">",
+  "'single'",
+  "\"double\"",
+  "%directive",
+  "@at",
+  "`tick",
+  " lead",
+  "trail ",
+  "tab\there",
+  "line\nbreak",
+  "bell\u{7}",
+  "\u{feff}bom",
+  "nel\u{85}",
+  "sep\u{2028}",
+];
+
+#[test]
+/// Every plain scalar the formatter writes reads back as the same string
+fn special_scalars_are_quoted() {
+  for style in [Style::Plain, Style::Single, Style::Double, Style::Guess] {
+    for special in SPECIAL {
+      let value = YmlValue::String(special.to_string());
+      let mut mapping = serde_yaml::Mapping::new();
+      mapping.insert(value.clone(), value.clone());
+      mapping.insert(
+        "list".into(),
+        YmlValue::Sequence(vec![value.clone(), 1.into()]),
+      );
+      let mapping = YmlValue::Mapping(mapping);
+
+      let mut formatter = YamlFormatter::default();
+      formatter.set_style(style.clone());
+      let written = formatter.stringify(mapping.clone(), None).unwrap();
+      let parsed = serde_yaml::from_str::<YmlValue>(&written);
+      assert_eq!(
+        parsed.ok().as_ref(),
+        Some(&mapping),
+        "{:?}:\n{}",
+        style,
+        written
+      );
+
+      formatter.set_flow_width(Some(200));
+      let written = formatter.stringify(mapping.clone(), None).unwrap();
+      let parsed = serde_yaml::from_str::<YmlValue>(&written);
+      assert_eq!(
+        parsed.ok().as_ref(),
+        Some(&mapping),
+        "{:?}:\n{}",
+        style,
+        written
+      );
+    }
+  }
+
+  // Anything else stays plain
+  let mut formatter = YamlFormatter::default();
+  formatter.set_style(Style::Plain);
+  for plain in [
+    "word",
+    "two words",
+    "a-b",
+    "a:b",
+    "a#b",
+    "1.2.3",
+    "yes please",
+    "C:\\temp",
+  ] {
+    let written = formatter.stringify(plain.into(), None).unwrap();
+    assert_eq!(written, format!("{}\n", plain));
+  }
+
+  // The logger's records read back the same too
+  let (logger, buffer) = common::buffered();
+  for special in SPECIAL {
+    let mut block = Block::new();
+    block.set_message(special).unwrap();
+    block.add_field(special, special).unwrap();
+    logger.log(&mut block, None).unwrap();
+  }
+  let written = common::contents(&buffer);
+  let documents = written
+    .split("\n---\n")
+    .map(|doc| doc.trim_start_matches("---\n"));
+  for (special, document) in SPECIAL.iter().zip(documents) {
+    let parsed = serde_yaml::from_str::<YmlValue>(document).unwrap();
+    assert_eq!(parsed["message"].as_str(), Some(*special), "{}", document);
+    assert_eq!(
+      parsed["fields"][*special].as_str(),
+      Some(*special),
+      "{}",
+      document
+    );
+  }
+}
+
+#[test]
+/// The guessed style is the most readable one that reads back as the same string
+fn guessed_styles() {
+  let prose = "A single line of prose that goes on for long enough to be worth folding";
+  let guess = |value: &str, fold_length| format!("{:?}", Style::guess_style(value, fold_length));
+  for (value, expected) in [
+    ("word", "Plain"),
+    ("two words", "Plain"),
+    ("C:/temp", "Plain"),
+    ("yes", "Double"),
+    ("- item", "Double"),
+    ("it's", "Double"),
+    ("say \"hi\"", "Double"),
+    ("back\\slash", "Double"),
+    ("bell\u{7}", "Double"),
+    ("crlf\r\nline", "Double"),
+    ("fn main() {\n  run();\n}", "Literal(Strip)"),
+    ("  indented\ncode\n", "Literal(Clip)"),
+    ("tab\tand\nlines\n\n", "Literal(Keep)"),
+    (prose, "Folded(Strip)"),
+    // Without a space to wrap at, or starting with one, it all stays on one line
+    (&prose.replace(' ', "_"), "Plain"),
+    (&format!(" {}", prose), "Double"),
+  ] {
+    assert_eq!(guess(value, 40), expected, "{:?}", value);
+  }
+  assert_eq!(guess(prose, 80), "Plain");
+  assert_eq!(guess("日本語の文章 です", 16), "Folded(Strip)");
+
+  // The length defaults to the wrap width
+  let value = YmlValue::Sequence(vec![prose.into(), "it's".into()]);
+  let mut formatter = YamlFormatter::default();
+  assert_eq!(
+    formatter.stringify(value.clone(), None).unwrap(),
+    format!("- {}\n- \"it's\"\n", prose)
+  );
+  formatter.set_fold_length(Some(40));
+  let written = formatter.stringify(value.clone(), None).unwrap();
+  assert!(written.starts_with("- >-\n"), "{}", written);
+  assert_eq!(serde_yaml::from_str::<YmlValue>(&written).unwrap(), value);
+}
+
+#[test]
+/// Strict mode quotes the plain scalars a YAML 1.1 loader would read as booleans, numbers or dates
+fn strict_yaml_quotes_ambiguous_scalars() {
+  const AMBIGUOUS: [&str; 8] = [
+    "yes",
+    "Off",
+    "y",
+    "1:30",
+    "190:20:30.15",
+    "1_000",
+    "2001-12-14",
+    "<<",
+  ];
+  let (logger, buffer) = common::buffered();
+  logger.set_strict_yaml(true);
+
+  let mut parent = Block::new();
+  parent.set_message("on").unwrap();
+  logger.log(&mut parent, Some("_+")).unwrap();
+  for value in AMBIGUOUS.iter() {
+    let mut block = Block::new();
+    block.set_message(value).unwrap();
+    logger.log(&mut block, None).unwrap();
+  }
+  let mut block = Block::new();
+  block.set_message("plain").unwrap();
+  block.add_field("no", "12:30").unwrap();
+  block.add_field("text", "yes please").unwrap();
+  logger.log(&mut block, None).unwrap();
+
+  let output = common::contents(&buffer);
+  assert!(
+    output.starts_with("---\n'on':\n  - 'yes'\n  - 'Off'\n  - 'y'\n  - '1:30'"),
+    "{}",
+    output
+  );
+  assert!(output.contains("'no': '12:30'"), "{}", output);
+  assert!(output.contains("text: yes please"), "{}", output);
+
+  // Everything reads back as the strings that were logged
+  let parsed: YmlValue = serde_yaml::from_str(output.trim_start_matches("---\n")).unwrap();
+  let children = parsed["on"].as_sequence().unwrap();
+  for (value, child) in AMBIGUOUS.iter().zip(children) {
+    assert_eq!(child.as_str(), Some(*value));
+  }
+  let fields = &children[AMBIGUOUS.len()]["fields"];
+  assert_eq!(fields["no"].as_str(), Some("12:30"));
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+/// Numbers, timestamps and line endings are written byte for byte the same on every platform
+fn records_are_the_same_on_every_platform() {
+  use chrono::TimeZone;
+  use ymlog::TimestampFormat;
+
+  let stamp = chrono::Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 5).unwrap();
+  let (logger, buffer) = common::buffered();
+  logger.set_number_format(NumberFormat::new().thousands(','));
+  logger.normalize_line_endings(true);
+  let mut block = Block::new();
+  block.set_message("Read the upload").unwrap();
+  block.set_timestamp(stamp);
+  block.add_field("ratio", 1.0 / 3.0).unwrap();
+  block.add_field("small", -2.5e-8).unwrap();
+  block.add_field("bytes", u64::MAX).unwrap();
+  block
+    .add_field("body", "line one\r\nline two\rline three")
+    .unwrap();
+  logger.log(&mut block, None).unwrap();
+
+  logger.set_timestamp_format(TimestampFormat::Custom("%A %d %B %Y %H:%M".into()));
+  let mut named = Block::new();
+  named.set_message("Named").unwrap();
+  named.set_timestamp(stamp);
+  logger.log(&mut named, None).unwrap();
+
+  let expected = concat!(
+    "---\ntimestamp: 2024-03-01T12:30:05+00:00\nfields:\n",
+    "  ratio: '0.3333333333333333'\n  small: '-0.000000025'\n",
+    "  bytes: 18,446,744,073,709,551,615\n",
+    "  body: |-\n    line one\n    line two\n    line three\n",
+    "message: Read the upload\n",
+    "---\ntimestamp: Friday 01 March 2024 12:30\nmessage: Named",
+  );
+  assert_eq!(common::contents(&buffer), expected);
+}
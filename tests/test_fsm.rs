@@ -0,0 +1,58 @@
+//! Test logging state machine transitions
+
+use ymlog::fsm::{self, Counters, Transition};
+use ymlog::prelude::*;
+use ymlog::reader;
+
+mod common;
+
+#[test]
+/// The counted transitions of each entity can be rebuilt from the parsed log
+fn histories_are_rebuilt() {
+  let (logger, buffer) = common::buffered();
+  let counters = Counters::new();
+
+  let mut root = Block::new();
+  root.set_message("Processing orders").unwrap();
+  logger.log(&mut root, None).unwrap();
+  for (actions, (entity, from, to, reason)) in [
+    ("+_", ("order-1", "pending", "paid", "card accepted")),
+    ("_", ("order-2", "pending", "cancelled", "timed out")),
+    ("_", ("order-1", "paid", "shipped", "label printed")),
+  ] {
+    let transition = Transition::new(entity, from, to, reason);
+    counters.log(&logger, transition, Some(actions)).unwrap();
+  }
+  assert_eq!(counters.count("order-1"), 2);
+  assert_eq!(counters.count("order-3"), 0);
+
+  let records = reader::parse(common::contents(&buffer).as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  let histories = fsm::histories(&records);
+  assert_eq!(histories.len(), 2);
+
+  let states = histories["order-1"]
+    .iter()
+    .map(|transition| (transition.to.as_str(), transition.count))
+    .collect::<Vec<_>>();
+  assert_eq!(states, vec![("paid", Some(1)), ("shipped", Some(2))]);
+  assert_eq!(histories["order-2"][0].reason, "timed out");
+}
+
+#[test]
+/// Uncounted transitions from the global helper read back the same
+fn global_transitions_are_written() {
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+
+  fsm::log_transition("job-9", "queued", "running", "worker free");
+  let records = reader::parse(common::contents(&buffer).as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  assert_eq!(records[0].message().unwrap(), "job-9: queued -> running");
+  assert_eq!(
+    Transition::from_block(&records[0]).unwrap(),
+    Transition::new("job-9", "queued", "running", "worker free")
+  );
+}
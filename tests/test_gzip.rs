@@ -0,0 +1,130 @@
+//! Test the gzip codec
+#![cfg(feature = "gzip")]
+
+use std::io::{ErrorKind, Read};
+
+use ymlog::gzip::Gzip;
+use ymlog::prelude::*;
+use ymlog::reader::{LogSet, Opener};
+use ymlog::{Codec, Compression};
+
+mod common;
+
+const LOG: &str = "---\nStarting the deploy:\n  - Checking out main\n  - Building:\n      - cargo build --release\n      - status: ok\n  - Uploading the artifact\n---\nDeploy finished\n";
+
+/// The log compressed by `gzip -9 -n`, which writes a block with its own Huffman codes
+const GZIPPED: [u8; 131] = [
+  0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x3d, 0x8e, 0xc1, 0x11, 0x02, 0x21,
+  0x0c, 0x45, 0xef, 0x54, 0xf1, 0x1b, 0x48, 0x03, 0x7b, 0x54, 0x3b, 0x70, 0x2c, 0x20, 0x42, 0x76,
+  0xc9, 0x2c, 0x82, 0x03, 0xe1, 0x60, 0xf7, 0x02, 0x33, 0x9a, 0xe3, 0xfb, 0x3f, 0x79, 0x21, 0x22,
+  0x77, 0x37, 0xae, 0xa6, 0xf9, 0x80, 0x45, 0x41, 0x90, 0x77, 0x2a, 0x9f, 0xcd, 0x01, 0x84, 0x6b,
+  0x14, 0x7f, 0xce, 0xa0, 0x74, 0xc3, 0x8b, 0x35, 0x2f, 0x7a, 0xe9, 0x9a, 0xc2, 0xa0, 0xb3, 0x33,
+  0x87, 0xe0, 0xb9, 0x1e, 0x05, 0xcf, 0xc9, 0x41, 0x54, 0x25, 0x09, 0x37, 0xf9, 0xa7, 0xcd, 0xd8,
+  0x7a, 0xdb, 0x50, 0xce, 0xb5, 0xfe, 0x18, 0xf7, 0x39, 0xfc, 0x74, 0xd3, 0xbc, 0xb3, 0x37, 0x47,
+  0xe3, 0x91, 0xdb, 0x72, 0x63, 0xd7, 0xac, 0x2d, 0x4a, 0x70, 0x5f, 0x0c, 0xc3, 0x6c, 0xad, 0x9d,
+  0x00, 0x00, 0x00,
+];
+
+#[test]
+/// Files written by the gzip tool are read, including several members one after another
+fn gzip_files_are_decompressed() {
+  assert_eq!(Gzip.decompress(&GZIPPED).unwrap(), LOG.as_bytes());
+
+  let twice = [GZIPPED, GZIPPED].concat();
+  assert_eq!(Gzip.decompress(&twice).unwrap(), LOG.repeat(2).as_bytes());
+}
+
+#[test]
+/// What we compress comes back the same, and repetitive logs shrink
+fn compressed_data_round_trips() {
+  // Bytes that don't repeat, from a small linear congruential generator
+  let mut seed = 7u32;
+  let noise = (0..50_000)
+    .map(|_| {
+      seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+      (seed >> 16) as u8
+    })
+    .collect::<Vec<_>>();
+  let repeated = LOG.repeat(2000).into_bytes();
+
+  for data in [
+    vec![],
+    b"a".to_vec(),
+    LOG.as_bytes().to_vec(),
+    noise,
+    repeated.clone(),
+  ] {
+    let packed = Gzip.compress(&data).unwrap();
+    assert_eq!(Gzip.decompress(&packed).unwrap(), data);
+  }
+  assert!(Gzip.compress(&repeated).unwrap().len() < repeated.len() / 20);
+}
+
+#[test]
+/// Corrupt and cut off data are errors, rather than garbage
+fn corrupt_data_is_rejected() {
+  let mut corrupt = GZIPPED;
+  corrupt[123] ^= 0xff;
+  let err = Gzip.decompress(&corrupt).err().unwrap();
+  assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+  for cut in [5, 40, 126] {
+    let err = Gzip.decompress(&GZIPPED[..cut]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+  }
+  let err = Gzip.decompress(b"not gzip at all").err().unwrap();
+  assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+/// Rotated gzip files are read without adding a codec
+fn readers_open_gzip_files() {
+  let dir = std::env::temp_dir().join(format!("ymlog-gzip-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(dir.join("app.yml"), "---\nCurrent").unwrap();
+  std::fs::write(dir.join("app.yml.1.gz"), GZIPPED).unwrap();
+
+  let mut text = String::new();
+  Opener::new()
+    .open(dir.join("app.yml.1.gz"))
+    .unwrap()
+    .read_to_string(&mut text)
+    .unwrap();
+  assert_eq!(text, LOG);
+
+  let set = LogSet::from_files(
+    vec![dir.join("app.yml.1.gz"), dir.join("app.yml")],
+    Opener::new(),
+  );
+  let records = ymlog::reader::parse(set)
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  let messages = records
+    .iter()
+    .map(|record| record.message().unwrap().as_str().unwrap().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    messages,
+    ["Starting the deploy", "Deploy finished", "Current"]
+  );
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+/// Messages the logger compresses with gzip are expanded by the reader
+fn gzip_messages_are_expanded() {
+  let (logger, buffer) = common::buffered();
+  logger.set_compression(Compression::new(Box::new(Gzip), 64));
+  let large = LOG.repeat(4);
+  let mut block = Block::new();
+  block.set_message(&large).unwrap();
+  logger.log(&mut block, Some("_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert!(output.contains("!ymlog/gzip "), "{}", output);
+  assert!(output.len() < large.len());
+  let opener = Opener::new();
+  let record = opener.parse(output.as_bytes()).next().unwrap().unwrap();
+  assert_eq!(record.message().unwrap(), large.as_str());
+}
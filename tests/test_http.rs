@@ -0,0 +1,62 @@
+//! Test logging HTTP requests and responses
+
+use std::time::Duration;
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::http::{self, Request};
+use ymlog::prelude::*;
+
+mod common;
+
+#[test]
+/// The response and anything logged while handling the request are nested under it
+fn responses_are_children_of_requests() {
+  let (logger, buffer) = common::buffered();
+
+  let exchange = Request::new("POST", "/users")
+    .header("Content-Type", "application/json")
+    .header("AUTHORIZATION", "Bearer secret")
+    .header("X-Session", "abc")
+    .redact("x-session")
+    .body_size(42)
+    .log(&logger)
+    .unwrap();
+  assert!(exchange.handle().is_some());
+
+  let mut block = Block::new();
+  block.set_message("Creating the user").unwrap();
+  logger.log(&mut block, None).unwrap();
+  exchange.respond(&logger, 201).unwrap();
+
+  let output = common::contents(&buffer);
+  assert!(!output.contains("secret"));
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(parsed["message"], "POST /users");
+  assert_eq!(parsed["fields"]["method"], "POST");
+  assert_eq!(parsed["fields"]["body_bytes"], 42);
+  assert_eq!(
+    parsed["fields"]["headers"]["Content-Type"],
+    "application/json"
+  );
+  assert_eq!(parsed["fields"]["headers"]["AUTHORIZATION"], http::REDACTED);
+  assert_eq!(parsed["fields"]["headers"]["X-Session"], http::REDACTED);
+  assert_eq!(parsed["children"][0], "Creating the user");
+  assert_eq!(parsed["children"][1]["fields"]["status"], 201);
+  assert!(parsed["children"][1]["fields"]["latency_ms"].is_f64());
+}
+
+#[test]
+/// Failed responses are raised to warnings and errors
+fn response_levels_follow_the_status() {
+  for (status, level) in [
+    (200, Level::Info),
+    (302, Level::Info),
+    (404, Level::Warn),
+    (503, Level::Error),
+  ] {
+    let block = http::response(status, Duration::from_millis(5));
+    assert_eq!(block.log_level(), &level);
+    assert_eq!(block.field("latency_ms").unwrap(), 5.0);
+  }
+}
@@ -0,0 +1,106 @@
+//! Test writing messages split into key/value pairs
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+
+mod common;
+use common::message;
+
+#[test]
+/// Pairs written on a new indent are attached to the record above, and close when a record follows
+fn pairs_dedent_automatically() {
+  let (logger, buffer) = common::buffered();
+
+  logger.log(&mut message("Handling"), Some("_")).unwrap();
+  logger.log(&mut message("Request"), Some("+_")).unwrap();
+  logger
+    .log(&mut message("status: 200"), Some("+k_"))
+    .unwrap();
+  logger.log(&mut message("length: 12"), Some("k_")).unwrap();
+  logger.log(&mut message("Response"), None).unwrap();
+  logger.log(&mut message("body: done"), Some("+k_")).unwrap();
+  logger
+    .log(&mut message("Indents after a pair are ignored"), Some("+_"))
+    .unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    concat!(
+      "---\n",
+      "Handling:\n",
+      "  - Request:\n",
+      "    - status: '200'\n",
+      "    - length: '12'\n",
+      "  - Response:\n",
+      "    - body: done\n",
+      "  - Indents after a pair are ignored",
+    )
+  );
+
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(parsed["Handling"][0]["Request"][1]["length"], "12");
+  assert_eq!(parsed["Handling"][2], "Indents after a pair are ignored");
+}
+
+#[test]
+/// A pair among other records stays at its depth, and can't have records indented under it
+fn sibling_pairs_stay_put() {
+  let (logger, buffer) = common::buffered();
+
+  logger.log(&mut message("Root"), Some("_")).unwrap();
+  logger.log(&mut message("First"), Some("+_")).unwrap();
+  logger.log(&mut message("id: 7"), Some("k_")).unwrap();
+  logger
+    .log(&mut message("Still at depth 1"), Some("+_"))
+    .unwrap();
+  logger
+    .log(&mut message("note: multiple\nlines"), Some("k_"))
+    .unwrap();
+
+  let output = common::contents(&buffer);
+  assert_eq!(
+    output,
+    concat!(
+      "---\n",
+      "Root:\n",
+      "  - First\n",
+      "  - id: '7'\n",
+      "  - Still at depth 1\n",
+      "  - note: |-\n",
+      "      multiple\n",
+      "      lines",
+    )
+  );
+  let parsed: YmlValue = serde_yaml::from_str(&output).unwrap();
+  assert_eq!(parsed["Root"][3]["note"], "multiple\nlines");
+}
+
+#[test]
+/// JSON lines get the same depths as the YAML would have
+fn pair_depths_match_in_json() {
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+
+  logger.log(&mut message("Handling"), Some("_")).unwrap();
+  logger.log(&mut message("Request"), Some("+_")).unwrap();
+  logger
+    .log(&mut message("status: 200"), Some("+k_"))
+    .unwrap();
+  logger.log(&mut message("Response"), Some("_")).unwrap();
+
+  let depths = common::contents(&buffer)
+    .lines()
+    .map(|line| line.split(',').next().unwrap().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    depths,
+    vec![
+      "{\"depth\":0",
+      "{\"depth\":1",
+      "{\"depth\":2",
+      "{\"depth\":1"
+    ]
+  );
+}
@@ -0,0 +1,65 @@
+//! Test writing values differently for people and machines
+
+use std::sync::{Arc, Mutex};
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+use ymlog::Pipeline;
+
+mod common;
+
+#[allow(dead_code)]
+#[derive(Loggable)]
+enum OrderState {
+  PendingPayment,
+  #[loggable(human = "Sent to the customer", machine = "shipped")]
+  InTransit {
+    carrier: String,
+  },
+  Cancelled(u32),
+}
+
+#[test]
+/// The names are turned into words and identifiers unless they're given
+fn names_are_derived() {
+  assert_eq!(OrderState::PendingPayment.human(), "Pending payment");
+  assert_eq!(
+    OrderState::PendingPayment.machine(),
+    YmlValue::from("pending_payment")
+  );
+  let shipped = OrderState::InTransit {
+    carrier: "post".into(),
+  };
+  assert_eq!(shipped.human(), "Sent to the customer");
+  assert_eq!(shipped.machine(), YmlValue::from("shipped"));
+  assert_eq!(
+    OrderState::Cancelled(3).machine(),
+    YmlValue::from("cancelled")
+  );
+}
+
+#[test]
+/// Outputs get the machine version unless the block is humanized on the way
+fn outputs_pick_a_version() {
+  let (logger, buffer) = common::buffered();
+  let readable = Arc::new(Mutex::new(vec![]));
+  logger.add_output_with(
+    common::TestWriter::new(&readable),
+    Pipeline::new().enrich(Block::humanize),
+  );
+
+  let mut block = Block::new();
+  block.set_loggable(&OrderState::PendingPayment);
+  block.add_loggable("next", &OrderState::Cancelled(1));
+  logger.log(&mut block, Some("_")).unwrap();
+
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nfields:\n  next: cancelled\nmessage: pending_payment"
+  );
+  assert_eq!(
+    common::contents(&readable),
+    "---\nfields:\n  next: Cancelled\nmessage: Pending payment"
+  );
+}
@@ -0,0 +1,52 @@
+//! Test the macros work when called by their full path, without importing anything
+//!
+//! Nothing is imported here on purpose, so a macro expanding to a bare call of another fails to
+//! compile.
+
+mod common;
+
+/// The tests share the global logger, so they take turns
+static GLOBAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[test]
+/// Every form of ymlog! expands through `$crate`
+fn ymlog_works_by_path() {
+  let _global = GLOBAL.lock().unwrap_or_else(|err| err.into_inner());
+  let buffer = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  common::init_global(&buffer);
+
+  let id = 7;
+  ymlog::ymlog!("Root");
+  ymlog::ymlog!("+_" => "Child {}", id);
+  ymlog::ymlog!("_" => "Fields"; id, text = %"shown", debug = ?"quoted");
+  ymlog::ymlog!("_" => { msg: "Block", level: Warn, fields: { id: id } });
+  ymlog::ymlog!("T_" => "Filtered out");
+  ymlog::ymlog!("-" => { msg: "Built and skipped", level: Trace });
+  ymlog::ymlog!("_" => "Back at the root");
+
+  let output = common::contents(&buffer);
+  assert!(
+    output.starts_with("---\nRoot:\n  - Child 7\n"),
+    "{}",
+    output
+  );
+  assert!(output.contains("id: 7"), "{}", output);
+  assert!(!output.contains("Filtered out"), "{}", output);
+  assert!(output.ends_with("\n---\nBack at the root"), "{}", output);
+}
+
+#[test]
+/// ymlog_err! goes through ymlog!'s internal arms by path too
+fn ymlog_err_works_by_path() {
+  let _global = GLOBAL.lock().unwrap_or_else(|err| err.into_inner());
+  let buffer = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  common::init_global(&buffer);
+
+  let err = std::io::Error::new(std::io::ErrorKind::NotFound, "missing.yml");
+  ymlog::ymlog_err!(&err);
+  ymlog::ymlog_err!("r" => &err);
+
+  let output = common::contents(&buffer);
+  assert_eq!(output.matches("missing.yml").count(), 2, "{}", output);
+  assert_eq!(output.matches("---").count(), 2, "{}", output);
+}
@@ -1,7 +1,6 @@
 //! Test the various macros
 //!
 //!
-#![feature(trace_macros)]
 
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -9,57 +8,13 @@ use std::sync::Mutex;
 use ymlog::prelude::*;
 
 // Make a buffer for test result inspection
-mod common {
-  use std::io::{IoSlice, Result, Write};
-  use std::sync::{Arc, Mutex};
-
-  /// A basic write buffer that we can keep a reference to to examine the contents later
-  #[derive(Clone)]
-  pub struct TestWriter(Arc<Mutex<Vec<u8>>>);
-
-  impl TestWriter {
-    pub fn new(buffer: &Arc<Mutex<Vec<u8>>>) -> TestWriter {
-      TestWriter(Arc::clone(buffer))
-    }
-  }
-
-  unsafe impl Send for TestWriter {}
-  unsafe impl Sync for TestWriter {}
-
-  impl Write for TestWriter {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-      self.0.lock().unwrap().write(buf)
-    }
-
-    fn flush(&mut self) -> Result<()> {
-      self.0.lock().unwrap().flush()
-    }
-
-    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
-      self.0.lock().unwrap().write_vectored(bufs)
-    }
-
-    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
-      self.0.lock().unwrap().write_all(buf)
-    }
-
-    fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> Result<()> {
-      self.0.lock().unwrap().write_fmt(fmt)
-    }
-  }
-}
-
-lazy_static::lazy_static! {
-  // TODO: It looks as if slog_scopes does this by magic. Look into it
-  pub(crate) static ref LOG: Mutex<YmLog<common::TestWriter>> = Mutex::new(YmLog::new());
-}
+mod common;
 
 #[test]
 /// Just making sure the basics work. All the functional edge cases will be tested elsewhere
 fn sanity_check() {
   let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
-  let writer = common::TestWriter::new(&Arc::clone(&buffer));
-  crate::LOG.lock().unwrap().set_output(writer);
+  common::init_global(&buffer);
 
   fn is_eq(expected: &str, buffer: &Arc<Mutex<Vec<u8>>>) {
     assert_eq!(
@@ -132,8 +87,105 @@ fn sanity_check() {
   expected.push_str("\n      - Add a simple item");
   is_eq(&expected, &buffer);
 
+  // Anything serializable can be the message
+  let mut order = std::collections::BTreeMap::new();
+  order.insert("id", 7);
+  ymlog!("r_" => &order);
+  expected.push_str("\n---\nmessage:\n  id: 7");
+  is_eq(&expected, &buffer);
+
+  // A whole block can be built in place
+  ymlog!({ msg: "Connecting", source: "web-1" });
+  expected.push_str("\n---\nsource: web-1\nmessage: Connecting");
+  is_eq(&expected, &buffer);
+
+  ymlog!("+_" => {
+    msg: format!("Query {}", "failed"),
+    level: Error,
+    tags: ["db", "retry"],
+    fields: { attempt: 3, table: "users" },
+    tag_type: "db",
+  });
+  expected.push_str(
+    "\nchildren:\n  - !db\n    log_level: Error\n    tags:\n    - db\n    - retry\n    fields:\n      attempt: 3\n      table: users\n    message: Query failed",
+  );
+  is_eq(&expected, &buffer);
+
+  // JSON lines write the level and tags as well
+  ymlog::global().set_format(OutputFormat::JsonLines);
+  ymlog!("r_" => { msg: "Retrying", level: Warn, tags: ["db"] });
+  let written = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+  let line = written.lines().last().unwrap();
+  assert!(line.contains("\"log_level\":\"Warn\""), "{}", line);
+  assert!(line.contains("\"tags\":[\"db\"]"), "{}", line);
+
+  // Messages below the level aren't evaluated, but their actions still move the depth
+  ymlog::global().set_format(OutputFormat::Yaml);
+  ymlog::global().set_level(Level::Warn);
+  let evaluated = std::cell::Cell::new(0);
+  let dump = || {
+    evaluated.set(evaluated.get() + 1);
+    "A large dump"
+  };
+  ymlog!("rW" => "Parent");
+  let before = buffer.lock().unwrap().len();
+  ymlog!("+D" => "{}", dump());
+  ymlog!("W" => "{}", dump());
+  assert_eq!(evaluated.get(), 1);
+  let written = String::from_utf8(buffer.lock().unwrap()[before..].to_vec()).unwrap();
+  assert_eq!(
+    written,
+    "\nchildren:\n  - log_level: Warn\n    message: A large dump"
+  );
+
+  // Errors are written with their sources nested under them
+  let error = std::io::Error::other("Disk full");
+  ymlog_err!("r" => &error);
+  let written = String::from_utf8(buffer.lock().unwrap()[before..].to_vec()).unwrap();
+  assert!(
+    written.ends_with("\n---\nlog_level: Error\nmessage: Disk full"),
+    "{}",
+    written
+  );
+
+  // Fields can be captured after the message
+  let id = std::net::Ipv4Addr::LOCALHOST;
+  let attempt = Some(2);
+  let retries = 3;
+  ymlog!("rW" => "User {}", "logged in"; user_id = %id, attempt = ?attempt, retries, tags = vec!["a"]);
+  let written = String::from_utf8(buffer.lock().unwrap()[before..].to_vec()).unwrap();
+  assert!(
+    written.ends_with(
+      "\n---\nlog_level: Warn\nfields:\n  user_id: 127.0.0.1\n  attempt: Some(2)\n  retries: 3\n  tags:\n  - a\nmessage: User logged in"
+    ),
+    "{}",
+    written
+  );
+
   // println!(
   //   "\n\nThe final buffer: '''{}'''\n",
   //   std::str::from_utf8(&buffer.lock().unwrap()).unwrap()
   // );
 }
+
+#[test]
+/// The max level features decide the lowest level the macros compile in
+fn static_max_level_follows_features() {
+  let expected = match () {
+    _ if cfg!(feature = "max_level_off") => None,
+    _ if cfg!(feature = "max_level_error") => Some(Level::Error),
+    _ if cfg!(feature = "max_level_warn") => Some(Level::Warn),
+    _ if cfg!(feature = "max_level_info") => Some(Level::Info),
+    _ if cfg!(feature = "max_level_debug") => Some(Level::Debug),
+    _ => Some(Level::Trace),
+  };
+  if cfg!(debug_assertions) {
+    assert_eq!(ymlog::STATIC_MAX_LEVEL, expected);
+  }
+  for level in Level::iter() {
+    assert_eq!(
+      level.compiled_in(),
+      ymlog::STATIC_MAX_LEVEL.is_some_and(|max| level >= max)
+    );
+  }
+}
@@ -0,0 +1,31 @@
+//! Test registering metrics and writing them as one record
+
+use ymlog::metrics::Metrics;
+
+mod common;
+use common::message;
+
+#[test]
+/// Every metric is written under one `metrics` key, in name order
+fn metrics_are_one_mapping() {
+  let (logger, buffer) = common::buffered();
+  let metrics = Metrics::new();
+
+  let files = metrics.counter("files_read");
+  files.inc();
+  files.add(2);
+  assert_eq!(metrics.counter("files_read").get(), 3);
+  metrics.gauge("queue_depth").set(4.0);
+  let rows = metrics.histogram("row_ms");
+  [2.0, 4.0, 12.0].iter().for_each(|ms| rows.record(*ms));
+  metrics.histogram("empty");
+
+  logger.log(&mut message("Run"), None).unwrap();
+  logger.log_metrics(&metrics, Some("+")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nRun:\n  - metrics:\n      empty:\n        count: 0\n        sum: 0.0\n      files_read: 3\n      \
+     queue_depth: 4.0\n      row_ms:\n        count: 3\n        sum: 18.0\n        min: 2.0\n        \
+     max: 12.0\n        mean: 6.0"
+  );
+}
@@ -0,0 +1,33 @@
+//! Test writing panics to the global log
+
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+#[test]
+/// A panic is written as an error record with where it happened, and flushed before it unwinds
+fn panics_are_logged() {
+  let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  common::init_global(&buffer);
+  ymlog::install_panic_hook();
+
+  let result = std::thread::Builder::new()
+    .name("worker".into())
+    .spawn(|| panic!("Lost the connection to {}", "db"))
+    .unwrap()
+    .join();
+  let _ = std::panic::take_hook();
+  assert!(result.is_err());
+
+  let output = common::contents(&buffer);
+  let location = format!("  location: {}:", file!());
+  assert!(
+    output.starts_with("---\n!ymlog/panic\nlog_level: Error\nfields:\n"),
+    "{}",
+    output
+  );
+  assert!(output.contains(&location), "{}", output);
+  assert!(output.contains("  thread: worker\n"));
+  assert!(output.contains("  backtrace: |"));
+  assert!(output.ends_with("\nmessage: Lost the connection to db"));
+}
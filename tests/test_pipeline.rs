@@ -0,0 +1,157 @@
+//! Test the per-output pipelines
+
+use std::sync::{Arc, Mutex};
+
+use ymlog::prelude::*;
+use ymlog::{Pipeline, RateKey, RateLimit};
+
+mod common;
+use common::message;
+
+#[test]
+/// Stages run in order, and only change the block for their own output
+fn stages_run_in_order() {
+  let (logger, plain) = common::buffered();
+  let piped = Arc::new(Mutex::new(Vec::<u8>::new()));
+  logger.add_output_with(
+    common::TestWriter::new(&piped),
+    Pipeline::new()
+      .filter(|block| block.tag_type() != Some("noisy"))
+      .redact(|block| {
+        let _ = block.set_message("<redacted>");
+      })
+      .sample(2)
+      .enrich(|block| block.set_tag_type("kept"))
+      .format(OutputFormat::JsonLines),
+  );
+
+  for (i, tag) in ["first", "noisy", "second", "third"].iter().enumerate() {
+    let mut block = message(&format!("Secret {}", i));
+    block.set_tag_type(tag);
+    logger.log(&mut block, Some("_")).unwrap();
+  }
+
+  // The first output has an empty pipeline, so it gets everything untouched
+  assert_eq!(
+    common::contents(&plain),
+    concat!(
+      "---\n!first Secret 0\n---\n!noisy Secret 1\n",
+      "---\n!second Secret 2\n---\n!third Secret 3"
+    )
+  );
+
+  // The noisy one is filtered before it reaches the sampler, so the third is sampled out
+  assert_eq!(
+    common::contents(&piped),
+    concat!(
+      "{\"depth\":0,\"tag_type\":\"kept\",\"message\":\"<redacted>\"}\n",
+      "{\"depth\":0,\"tag_type\":\"kept\",\"message\":\"<redacted>\"}\n",
+    )
+  );
+}
+
+#[test]
+/// A pipeline with its own level ignores the logger's threshold
+fn level_stage_replaces_threshold() {
+  let (logger, plain) = common::buffered();
+  let verbose = Arc::new(Mutex::new(Vec::<u8>::new()));
+  logger.add_output_with(
+    common::TestWriter::new(&verbose),
+    Pipeline::new().level(Level::Trace),
+  );
+
+  logger.log(&mut message("Tracing"), Some("T_")).unwrap();
+  assert_eq!(common::contents(&plain), "");
+  assert_eq!(
+    common::contents(&verbose),
+    "---\nlog_level: Trace\nmessage: Tracing"
+  );
+}
+
+#[test]
+/// Closing the log tells each output what was kept from it, but not what was below its level
+fn closing_summarizes_what_was_withheld() {
+  let (logger, plain) = common::buffered();
+  let piped = Arc::new(Mutex::new(Vec::<u8>::new()));
+  logger.add_output_with(
+    common::TestWriter::new(&piped),
+    Pipeline::new()
+      .level(Level::Info)
+      .filter(|block| block.tag_type() != Some("noisy"))
+      .sample(2),
+  );
+
+  for tag in ["first", "noisy", "second", "third", "fourth"] {
+    let mut block = message(tag);
+    block.set_tag_type(tag);
+    logger.log(&mut block, None).unwrap();
+  }
+  let mut quiet = message("quiet");
+  quiet.set_log_level(Level::Debug);
+  logger.log(&mut quiet, None).unwrap();
+  logger.close().unwrap();
+
+  assert_eq!(
+    common::contents(&piped),
+    "---\n!first first\n---\n!third third\n---\n!ymlog/summary\nfields:\n  filtered: 1\n  sampled: 2\nmessage: Withheld from this output\n...\n"
+  );
+  assert!(!common::contents(&plain).contains("ymlog/summary"));
+
+  // The counts start over once they are written
+  piped.lock().unwrap().clear();
+  logger.log(&mut message("fifth"), None).unwrap();
+  logger.log(&mut message("sixth"), None).unwrap();
+  logger.close().unwrap();
+  assert_eq!(
+    common::contents(&piped),
+    "\n---\nfifth\n---\n!ymlog/summary\nfields:\n  sampled: 1\nmessage: Withheld from this output\n...\n"
+  );
+}
+
+#[test]
+/// Records over the rate limit are dropped, and summarized once their key gets one through
+fn rate_limits_summarize_what_they_suppressed() {
+  let (logger, buffer) = common::buffered();
+  logger.set_rate_limit(Some(RateLimit::new(RateKey::Prefix(4), 2, 0.0)));
+
+  for i in 0..5 {
+    logger
+      .log(&mut message(&format!("Poll {}", i)), Some("r"))
+      .unwrap();
+  }
+  logger.log(&mut message("Done"), Some("r")).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "\n---\nPoll 0\n---\nPoll 1\n---\nDone"
+  );
+
+  // The bucket for "Poll" refills once the limit allows more
+  logger.set_rate_limit(Some(RateLimit::new(RateKey::Prefix(4), 2, 1000.0)));
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  logger.log(&mut message("Poll 5"), Some("r")).unwrap();
+  assert!(common::contents(&buffer).ends_with(concat!(
+    "\n---\n!ymlog/suppressed\nfields:\n  key: Poll\nmessage: Suppressed 3 similar messages",
+    "\n---\nPoll 5"
+  )));
+}
+
+#[test]
+/// Sampling keeps about the rate of the blocks, and counts the rest as withheld
+fn sampling_keeps_a_fraction() {
+  let (logger, buffer) = common::buffered();
+  logger.sample(0.0);
+  logger.log(&mut message("Never"), None).unwrap();
+  assert_eq!(common::contents(&buffer), "");
+
+  logger.sample(0.5);
+  for _ in 0..200 {
+    logger.log(&mut message("Maybe"), Some("r")).unwrap();
+  }
+  let kept = common::contents(&buffer).matches("Maybe").count();
+  assert!((50..150).contains(&kept), "kept {}", kept);
+
+  logger.sample(1.0);
+  logger.close().unwrap();
+  let summary = format!("sampled: {}\n", 201 - kept);
+  assert!(common::contents(&buffer).contains(&summary));
+}
@@ -0,0 +1,36 @@
+//! Test counting the steps of loops without logging each one
+
+mod common;
+
+#[test]
+/// Only the summary is written, once, when the progress is finished or dropped
+fn progress_is_summarized_once() {
+  let (logger, buffer) = common::buffered();
+
+  let files = logger.progress("Processing files", 40);
+  std::thread::scope(|scope| {
+    for _ in 0..4 {
+      scope.spawn(|| (0..10).for_each(|_| files.inc()));
+    }
+  });
+  assert_eq!(files.count(), 40);
+  assert!(common::contents(&buffer).is_empty());
+  drop(files);
+
+  let rows = logger.progress("Copying rows", None);
+  rows.add(5);
+  rows.finish().unwrap();
+
+  let output = common::contents(&buffer);
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(roots.len(), 2, "{}", output);
+  assert_eq!(roots[0].text(), Some("Processing files"));
+  assert_eq!(roots[0].field("count").unwrap().as_u64(), Some(40));
+  assert_eq!(roots[0].field("total").unwrap().as_u64(), Some(40));
+  assert!(roots[0].field("duration").unwrap().get("ms").is_some());
+  assert!(roots[0].field("per_second").unwrap().as_f64().unwrap() > 0.0);
+  assert_eq!(roots[1].field("count").unwrap().as_u64(), Some(5));
+  assert!(roots[1].field("total").is_none());
+}
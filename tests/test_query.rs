@@ -0,0 +1,165 @@
+//! Test merging and filtering records
+
+use ymlog::prelude::*;
+use ymlog::query::{merge, Query};
+use ymlog::{reader, Timestamp};
+
+mod common;
+
+/// A second into 2024
+fn at(second: u32) -> Timestamp {
+  format!("2024-01-01T00:00:{:02}Z", second).parse().unwrap()
+}
+
+fn record(msg: &str, second: Option<u32>) -> Block {
+  let mut block = Block::new();
+  block.set_message(msg).unwrap();
+  if let Some(second) = second {
+    block.set_timestamp(at(second));
+  }
+  block
+}
+
+#[test]
+/// Merged records are interleaved by time and keep track of where they came from
+fn merged_records_keep_their_source() {
+  let web = vec![
+    record("Request", Some(1)),
+    record("Untimed detail", None),
+    record("Response", Some(4)),
+  ];
+  let db = vec![record("Query", Some(2)), record("Commit", Some(3))];
+
+  let merged = merge(vec![("web", web), ("db", db)]);
+  let order = merged
+    .iter()
+    .map(|block| block.source().unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(order, vec!["web", "web", "db", "db", "web"]);
+
+  let db_only = Query::new().source("db").filter(merged.clone());
+  assert_eq!(db_only.len(), 2);
+
+  // The source is written with the record, so the combined log stays attributable
+  let (logger, buffer) = common::buffered();
+  for mut block in merged.into_iter().take(2) {
+    logger.log(&mut block, None).unwrap();
+  }
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "---\n",
+      "timestamp: 2024-01-01T00:00:01+00:00\n",
+      "source: web\n",
+      "message: Request\n",
+      "---\n",
+      "source: web\n",
+      "message: Untimed detail",
+    )
+  );
+}
+
+#[test]
+/// Levels, tags and times can be searched for anywhere in the trees
+fn subtrees_are_found_anywhere() {
+  let mut db = record("Connecting", Some(2));
+  db.set_tags(vec!["db"]);
+  db.set_children(vec![record("Timed out", Some(3))]);
+  let mut warning = record("Retrying", Some(4));
+  warning.set_log_level(Level::Warn);
+  warning.set_tag_type("db");
+  let mut root = record("Request", Some(1));
+  root.set_children(vec![db, warning]);
+  let records = vec![root, record("Untimed", None)];
+
+  let tagged = Query::new().tag("db").subtrees(&records);
+  assert_eq!(tagged.len(), 2);
+  assert_eq!(tagged[0].children()[0].message().unwrap(), "Timed out");
+  assert_eq!(tagged[1].message().unwrap(), "Retrying");
+
+  let warnings = Query::new().level(Level::Warn).flatten(&records);
+  assert_eq!(warnings.len(), 1);
+  assert_eq!(warnings[0].message().unwrap(), "Retrying");
+
+  let window = Query::new().since(at(2)).until(at(3)).flatten(&records);
+  let messages = window
+    .iter()
+    .map(|block| block.message().unwrap().as_str().unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(messages, vec!["Connecting", "Timed out"]);
+  assert!(window.iter().all(|block| block.children().is_empty()));
+}
+
+#[test]
+/// Queries written as text select the same records as the ones built in code
+fn text_queries_are_parsed() {
+  let mut timeout = record("Query timeout", None);
+  timeout.set_log_level(Level::Error);
+  timeout.set_tags(vec!["db"]);
+  let mut slow = record("Slow query", None);
+  slow.set_log_level(Level::Warn);
+  slow.set_tags(vec!["db"]);
+  let mut nested = record("Request", None);
+  nested.set_children(vec![timeout.clone()]);
+  let mut deep = record("Deep", None);
+  deep.set_children(vec![nested.clone()]);
+  let records = vec![timeout, slow, nested, deep];
+
+  let query: Query = r#"level>=warn AND tag:db AND msg~"timeout" AND depth<2"#
+    .parse()
+    .unwrap();
+  let found = query.flatten(&records);
+  let found = found
+    .iter()
+    .map(|block| block.message().unwrap().as_str().unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(found, vec!["Query timeout", "Query timeout"]);
+
+  let below = Query::parse("level < error and depth = 0").unwrap();
+  assert_eq!(below.filter(records.clone()).len(), 3);
+  let quoted = Query::parse(r#"msg~"Slow \"query""#).unwrap();
+  assert!(quoted.filter(records.clone()).is_empty());
+  assert_eq!(Query::parse("").unwrap().filter(records.clone()).len(), 4);
+
+  for bad in [
+    "level>=loud",
+    "depth<x",
+    "tag~db",
+    "color:red",
+    "level",
+    "tag:db AND",
+    "msg~\"open",
+  ] {
+    let err = Query::parse(bad).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", bad);
+  }
+}
+
+#[test]
+/// Level and tag predicates see the levels and tags read back from a YAML log
+fn text_queries_match_yaml_logs() {
+  let (logger, buffer) = common::buffered();
+  logger.log(&mut record("Request", None), Some("_")).unwrap();
+  let mut timeout = record("Query timeout", None);
+  timeout.set_log_level(Level::Error);
+  timeout.set_tags(vec!["db"]);
+  logger.log(&mut timeout, Some("+_")).unwrap();
+  let mut cached = record("Cache hit", None);
+  cached.set_tags(vec!["db"]);
+  logger.log(&mut cached, Some("_")).unwrap();
+  let mut slow = record("Slow render", None);
+  slow.set_log_level(Level::Warn);
+  logger.log(&mut slow, Some("_")).unwrap();
+
+  let records = reader::parse(common::contents(&buffer).as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  let found = Query::parse("level>=warn AND tag:db")
+    .unwrap()
+    .flatten(&records);
+  let found = found
+    .iter()
+    .map(|block| block.message().unwrap().as_str().unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(found, vec!["Query timeout"]);
+}
@@ -0,0 +1,276 @@
+//! Test reading logs back in
+
+use std::io::{ErrorKind, Read, Result as IoResult};
+use std::time::{Duration, SystemTime};
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+use ymlog::reader::{self, LogSet, Opener};
+use ymlog::{Codec, Compression, Timestamp};
+
+mod common;
+use common::message;
+
+/// A stand-in codec marking its files with a header and storing the bytes reversed
+struct Reverse;
+
+impl Codec for Reverse {
+  fn name(&self) -> &str {
+    "reverse"
+  }
+
+  fn compress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    let mut packed = b"REV!".to_vec();
+    packed.extend(data.iter().rev());
+    Ok(packed)
+  }
+
+  fn decompress(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+    Ok(data[4..].iter().rev().cloned().collect())
+  }
+
+  fn magic(&self) -> &[u8] {
+    b"REV!"
+  }
+}
+
+#[test]
+/// Plain and compressed files in a rotation set read the same, in name order
+fn rotated_files_are_decompressed() {
+  let dir = std::env::temp_dir().join(format!("ymlog-reader-{}", std::process::id()));
+  std::fs::create_dir_all(dir.join("archive")).unwrap();
+  std::fs::write(dir.join("app.yml"), "---\nCurrent").unwrap();
+  std::fs::write(
+    dir.join("app.yml.1.rev"),
+    Reverse.compress(b"---\nRotated").unwrap(),
+  )
+  .unwrap();
+
+  let logs = Opener::new()
+    .codec(Box::new(Reverse))
+    .open_dir(&dir)
+    .unwrap();
+  let contents = logs
+    .into_iter()
+    .map(|(path, mut log)| {
+      let mut text = String::new();
+      log.read_to_string(&mut text).unwrap();
+      (
+        path.file_name().unwrap().to_str().unwrap().to_string(),
+        text,
+      )
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(
+    contents,
+    vec![
+      ("app.yml".to_string(), "---\nCurrent".to_string()),
+      ("app.yml.1.rev".to_string(), "---\nRotated".to_string()),
+    ]
+  );
+
+  // Compressed files without a codec are an error, rather than a stream of garbage
+  std::fs::write(dir.join("app.yml.2.bz2"), b"BZh9").unwrap();
+  let err = Opener::new().open(dir.join("app.yml.2.bz2")).err().unwrap();
+  assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+/// A rotation set reads as one stream, oldest file first
+fn log_sets_read_in_time_order() {
+  let dir = std::env::temp_dir().join(format!("ymlog-set-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let now = SystemTime::now();
+  for (name, msg, age) in [
+    ("app.yml", "Current", 0),
+    ("app.yml.1.rev", "Older", 60),
+    ("app.yml.2", "Oldest", 120),
+    ("other.yml", "Not in the set", 180),
+  ] {
+    let path = dir.join(name);
+    let contents = format!("---\n{}", msg).into_bytes();
+    match name.ends_with(".rev") {
+      true => std::fs::write(&path, Reverse.compress(&contents).unwrap()).unwrap(),
+      false => std::fs::write(&path, contents).unwrap(),
+    }
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(now - Duration::from_secs(age)).unwrap();
+  }
+
+  let opener = Opener::new().codec(Box::new(Reverse));
+  let mut set = LogSet::with_opener(dir.join("app.yml*"), opener).unwrap();
+  assert_eq!(set.files().len(), 3);
+
+  let mut text = String::new();
+  set.read_to_string(&mut text).unwrap();
+  assert_eq!(text, "---\nOldest\n---\nOlder\n---\nCurrent");
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Write the records and read them back in
+fn round_trip(format: OutputFormat) -> Vec<Block> {
+  let (logger, buffer) = common::buffered();
+  logger.set_format(format);
+
+  logger.log(&mut message("Root"), Some("_")).unwrap();
+  logger.log(&mut message("Child"), Some("+_")).unwrap();
+  logger.log(&mut message("Grandchild"), Some("+_")).unwrap();
+  logger.log(&mut message("status: ok"), Some("+k_")).unwrap();
+  logger.log(&mut message("Sibling"), Some("-_")).unwrap();
+
+  let mut record = message("Deployed");
+  record.set_timestamp(Timestamp::from_millis(1_700_000_000_000));
+  record.set_tag_type("deploy");
+  record.add_field("version", "1.2").unwrap();
+  record.set_log_level(Level::Warn);
+  record.set_tags(vec!["ops"]);
+  logger.log(&mut record, Some("r_")).unwrap();
+  logger
+    .log(&mut message("Under the record"), Some("+_"))
+    .unwrap();
+
+  logger.log(&mut message("Blocks"), Some("r_")).unwrap();
+  logger
+    .log(&mut message("Line one\nLine two"), Some("+_"))
+    .unwrap();
+  logger
+    .log(&mut message("Under the block"), Some("+_"))
+    .unwrap();
+
+  let output = common::contents(&buffer);
+  reader::parse(output.as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap()
+}
+
+#[test]
+/// The tree, metadata and message shapes written to YAML come back the same
+fn yaml_logs_are_parsed() {
+  let records = round_trip(OutputFormat::Yaml);
+  assert_eq!(records.len(), 3);
+
+  let root = &records[0];
+  assert_eq!(root.message().unwrap(), "Root");
+  assert_eq!(root.children()[0].message().unwrap(), "Child");
+  let children = root.children()[0].children();
+  assert_eq!(children.len(), 2);
+  assert_eq!(children[0].message().unwrap(), "Grandchild");
+  let pair = children[0].children()[0].key_value().unwrap();
+  assert_eq!(pair, (&YmlValue::from("status"), &YmlValue::from("ok")));
+  assert_eq!(children[1].message().unwrap(), "Sibling");
+
+  let deployed = &records[1];
+  assert_eq!(deployed.message().unwrap(), "Deployed");
+  assert_eq!(deployed.log_level(), &Level::Warn);
+  assert_eq!(deployed.tags(), ["ops"]);
+  assert_eq!(deployed.tag_type(), Some("deploy"));
+  assert_eq!(deployed.field("version").unwrap(), "1.2");
+  assert_eq!(deployed.timestamp().unwrap().millis(), 1_700_000_000_000);
+  assert_eq!(
+    deployed.children()[0].message().unwrap(),
+    "Under the record"
+  );
+
+  let block = &records[2].children()[0];
+  assert_eq!(block.message().unwrap(), "Line one\nLine two");
+  assert_eq!(block.children()[0].message().unwrap(), "Under the block");
+}
+
+#[test]
+/// YAML records keep their levels and tags, with Info ones written and read without a level
+fn yaml_levels_and_tags_round_trip() {
+  let (logger, buffer) = common::buffered();
+  let mut query = message("Querying");
+  query.set_log_level(Level::Warn);
+  query.set_tags(vec!["db"]);
+  logger.log(&mut query, Some("_")).unwrap();
+  let mut failed = message("Timed out");
+  failed.set_log_level(Level::Error);
+  logger.log(&mut failed, Some("+_")).unwrap();
+  let mut info = message("Listening");
+  info.set_log_level(Level::Info);
+  logger.log(&mut info, Some("r_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert!(output.ends_with("\n---\nListening"), "{}", output);
+  let records = reader::parse(output.as_bytes())
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  assert_eq!(records.len(), 2);
+  assert_eq!(records[0].message().unwrap(), "Querying");
+  assert_eq!(records[0].log_level(), &Level::Warn);
+  assert_eq!(records[0].tags(), ["db"]);
+  let child = &records[0].children()[0];
+  assert_eq!(child.message().unwrap(), "Timed out");
+  assert_eq!(child.log_level(), &Level::Error);
+  assert!(child.tags().is_empty());
+  assert_eq!(records[1].log_level(), &Level::Info);
+}
+
+#[test]
+/// JSON lines are nested by their depth, and keep their levels and tags too
+fn json_lines_are_parsed() {
+  let records = round_trip(OutputFormat::JsonLines);
+  assert_eq!(records.len(), 3);
+  let children = records[0].children()[0].children();
+  assert_eq!(children.len(), 2);
+  assert!(children[0].children()[0].key_value().is_some());
+  assert_eq!(children[1].message().unwrap(), "Sibling");
+
+  let deployed = &records[1];
+  assert_eq!(deployed.log_level(), &Level::Warn);
+  assert_eq!(deployed.tags(), ["ops"]);
+  assert_eq!(deployed.tag_type(), Some("deploy"));
+  assert_eq!(
+    deployed.children()[0].message().unwrap(),
+    "Under the record"
+  );
+  let block = &records[2].children()[0];
+  assert_eq!(block.children()[0].message().unwrap(), "Under the block");
+
+  let err = reader::parse(&b"{\"message\":\"No depth\"}"[..])
+    .next()
+    .unwrap()
+    .err()
+    .unwrap();
+  assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+/// Messages compressed when written are expanded by an opener with the codec
+fn compressed_messages_are_expanded() {
+  let large = "A large literal\nthat is well over the threshold";
+  for format in [OutputFormat::Yaml, OutputFormat::JsonLines] {
+    let (logger, buffer) = common::buffered();
+    logger.set_format(format);
+    logger.set_compression(Compression::new(Box::new(Reverse), 16));
+
+    logger.log(&mut message(large), Some("_")).unwrap();
+    logger.log(&mut message(large), Some("+_")).unwrap();
+    let mut record = message(large);
+    record.add_field("size", 46).unwrap();
+    logger.log(&mut record, Some("r_")).unwrap();
+
+    let output = common::contents(&buffer);
+    assert!(output.contains("ymlog/reverse"), "{}", output);
+    let opener = Opener::new().codec(Box::new(Reverse));
+    let records = opener
+      .parse(output.as_bytes())
+      .collect::<std::io::Result<Vec<_>>>()
+      .unwrap();
+    assert_eq!(records.len(), 2, "{}", output);
+    assert_eq!(records[0].message().unwrap(), large);
+    assert_eq!(records[0].tag_type(), None);
+    assert_eq!(records[0].children()[0].message().unwrap(), large);
+    assert_eq!(records[1].message().unwrap(), large);
+    assert_eq!(records[1].field("size").unwrap(), 46);
+
+    // Without the codec they are left as written
+    let packed = reader::parse(output.as_bytes()).next().unwrap().unwrap();
+    assert_ne!(packed.message().unwrap(), large);
+  }
+}
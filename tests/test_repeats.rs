@@ -0,0 +1,105 @@
+//! Test large payloads repeated within a document are only written once, and runs of identical
+//! records are collapsed
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+
+mod common;
+use common::message;
+
+fn attempt(number: u64, body: &str) -> Block {
+  let mut block = Block::new();
+  block.set_message("Attempt").unwrap();
+  block.add_field("number", number).unwrap();
+  block.add_field("body", body).unwrap();
+  block
+}
+
+#[test]
+/// Copies in the same document refer back to the first record, and new documents start over
+fn repeated_payloads_refer_back() {
+  let (logger, buffer) = common::buffered();
+  logger.set_repeat_threshold(Some(16));
+  let body = "{\"order\": 1234, \"items\": [1, 2, 3]}";
+
+  let mut retrying = Block::new();
+  retrying.set_message("Retrying").unwrap();
+  logger.log(&mut retrying, Some("_+")).unwrap();
+  for number in 1..=3 {
+    logger.log(&mut attempt(number, body), None).unwrap();
+  }
+
+  // Small values are always written
+  let mut short = Block::new();
+  short.set_key_value("status", "failed").unwrap();
+  logger.log(&mut short, None).unwrap();
+  logger.log(&mut short, None).unwrap();
+
+  logger.log(&mut attempt(4, body), Some("r")).unwrap();
+
+  let output = common::contents(&buffer);
+  let documents = output
+    .split("---\n")
+    .filter(|doc| !doc.is_empty())
+    .map(|doc| serde_yaml::from_str::<YmlValue>(doc).unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(documents.len(), 2, "{}", output);
+
+  let attempts = &documents[0]["Retrying"];
+  assert_eq!(attempts[0]["fields"]["body"], YmlValue::from(body));
+  for repeat in [&attempts[1], &attempts[2]] {
+    assert_eq!(
+      serde_yaml::to_string(&repeat["fields"]["body"]).unwrap(),
+      "same_as: 1\n"
+    );
+  }
+  assert_eq!(attempts[2]["fields"]["number"], YmlValue::from(3));
+  assert_eq!(attempts[3]["status"], YmlValue::from("failed"));
+  assert_eq!(attempts[4]["status"], YmlValue::from("failed"));
+  assert_eq!(documents[1]["fields"]["body"], YmlValue::from(body));
+}
+
+#[test]
+/// Without a threshold every copy is written
+fn repeats_are_kept_by_default() {
+  let (logger, buffer) = common::buffered();
+  logger
+    .log(&mut attempt(1, "a long enough body"), None)
+    .unwrap();
+  logger
+    .log(&mut attempt(2, "a long enough body"), Some("+_"))
+    .unwrap();
+  assert!(!common::contents(&buffer).contains("same_as"));
+}
+
+#[test]
+/// A run of identical records is written as the first and one copy counting the rest
+fn duplicate_records_collapse() {
+  let (logger, buffer) = common::buffered();
+  logger.collapse_duplicates(true);
+
+  logger.log(&mut message("Connecting"), Some("_+")).unwrap();
+  for _ in 0..1000 {
+    logger.log(&mut message("Retrying"), None).unwrap();
+  }
+  logger.log(&mut message("Connected"), None).unwrap();
+
+  // A single record isn't a run, and a dedent ends one
+  logger.log(&mut message("Waiting"), None).unwrap();
+  logger.log(&mut message("Waiting"), None).unwrap();
+  logger.log(&mut message("Waiting"), Some("-")).unwrap();
+  logger.log(&mut message("Done"), None).unwrap();
+
+  // Repeats still held back are written when the log is flushed
+  logger.log(&mut message("Done"), None).unwrap();
+  logger.flush().unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    concat!(
+      "---\nConnecting:\n  - Retrying\n  - fields:\n      count: 999\n    message: Retrying",
+      "\n  - Connected\n  - Waiting\n  - fields:\n      count: 1\n    message: Waiting",
+      "\n---\nWaiting\n---\nDone\n---\nfields:\n  count: 1\nmessage: Done",
+    )
+  );
+}
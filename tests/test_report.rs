@@ -0,0 +1,43 @@
+//! Test writing logs as HTML reports
+
+use ymlog::prelude::*;
+use ymlog::report;
+
+mod common;
+use common::message;
+
+#[test]
+/// Children are collapsible, levels get a class, and everything is escaped
+fn records_become_a_tree() {
+  let mut failure = message("Test <parse> failed");
+  failure.set_log_level(Level::Error);
+  failure.add_field("file", "lib.rs").unwrap();
+  let mut step = message("Running tests");
+  step.set_children(vec![failure, message("Output:\nline one")]);
+  let mut root = message("Build & test");
+  root.set_tag_type("build");
+  root.set_children(vec![step]);
+
+  let page = report::html("Nightly <build>", &[root, message("Done")]);
+  assert!(page.starts_with("<!DOCTYPE html>"));
+  assert!(page.contains("<title>Nightly &lt;build&gt;</title>"));
+  assert!(page.contains(concat!(
+    "<details class=\"info\" open><summary><span class=\"level\">Info</span>",
+    "<span class=\"tag\">!build</span><span class=\"message\">Build &amp; test</span></summary>",
+  )));
+  assert!(page.contains("<details class=\"info\"><summary><span class=\"level\">Info</span><span class=\"message\">Running tests</span></summary>"));
+  assert!(page.contains(concat!(
+    "<div class=\"record leaf error\"><span class=\"level\">Error</span>",
+    "<span class=\"message\">Test &lt;parse&gt; failed</span> ",
+    "<span class=\"field\"><span class=\"key\">file</span>=lib.rs</span></div>",
+  )));
+  assert!(page.contains("<pre>Output:\nline one</pre>"));
+  assert!(page.contains("<div class=\"record info\"><span class=\"level\">Info</span><span class=\"message\">Done</span></div>"));
+  assert!(page.ends_with("</body>\n</html>\n"));
+
+  let mut out = vec![];
+  report::write_html("Nightly <build>", &[], &mut out).unwrap();
+  assert!(String::from_utf8(out)
+    .unwrap()
+    .contains("<h1>Nightly &lt;build&gt;</h1>"));
+}
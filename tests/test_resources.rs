@@ -0,0 +1,28 @@
+//! Test the resource snapshots
+#![cfg(all(feature = "resources", target_os = "linux"))]
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::resources::Resources;
+
+mod common;
+
+#[test]
+/// A snapshot has believable numbers, and is attached to the record above it
+fn snapshots_are_logged_as_key_values() {
+  let snapshot = Resources::snapshot().unwrap();
+  assert!(snapshot.rss_bytes > 0);
+  assert!(snapshot.open_fds >= 3);
+  assert!(snapshot.threads >= 1);
+
+  let (logger, buffer) = common::buffered();
+  let mut block = ymlog::Block::new();
+  block.set_message("Loaded the cache").unwrap();
+  logger.log(&mut block, None).unwrap();
+  logger.log_resources(Some("+")).unwrap();
+
+  let parsed: YmlValue = serde_yaml::from_str(&common::contents(&buffer)).unwrap();
+  let resources = &parsed["Loaded the cache"][0]["resources"];
+  assert!(resources["rss_bytes"].as_u64().unwrap() > 0);
+  assert!(resources["cpu_millis"].as_u64().is_some());
+}
@@ -0,0 +1,63 @@
+//! Test logging retried operations
+
+use std::time::Duration;
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+use ymlog::retry::{self, Retry};
+
+mod common;
+
+#[test]
+/// Failed attempts are nested under the operation, ending with the outcome
+fn attempts_are_children() {
+  let (logger, buffer) = common::buffered();
+
+  let result = Retry::new(3, Duration::ZERO).run(&logger, "fetch", |attempt| match attempt {
+    1 => Err("connection refused"),
+    _ => Ok(attempt * 10),
+  });
+  assert_eq!(result, Ok(20));
+
+  let parsed: YmlValue = serde_yaml::from_str(&common::contents(&buffer)).unwrap();
+  let children = parsed["Retrying fetch"].as_sequence().unwrap();
+  assert_eq!(children.len(), 2);
+  assert_eq!(children[0]["fields"]["attempt"], 1);
+  assert_eq!(children[0]["fields"]["error"], "connection refused");
+  assert_eq!(children[0]["fields"]["backoff_ms"], 0);
+  assert_eq!(children[1]["outcome"], "succeeded on attempt 2");
+}
+
+#[test]
+/// The last error is returned once the attempts run out, and the log carries on where it was
+fn exhausted_retries_fail() {
+  let (_, buffer) = common::buffered();
+  common::init_global(&buffer);
+
+  let mut tries = 0;
+  let result: Result<(), String> = Retry::new(2, Duration::from_millis(1))
+    .factor(3)
+    .log_attempts("upload", |attempt| {
+      tries += 1;
+      Err(format!("timeout {}", attempt))
+    });
+  assert_eq!(result, Err("timeout 2".to_string()));
+  assert_eq!(tries, 2);
+  ymlog!("After");
+
+  let output = common::contents(&buffer);
+  let (retries, after) = output.rsplit_once("\n---\n").unwrap();
+  assert_eq!(after, "After");
+  let parsed: YmlValue = serde_yaml::from_str(retries).unwrap();
+  let children = parsed["Retrying upload"].as_sequence().unwrap();
+  assert_eq!(children[0]["fields"]["backoff_ms"], 1);
+  assert!(children[1]["fields"].get("backoff_ms").is_none());
+  assert_eq!(children[2]["log_level"], "Error");
+  assert_eq!(children[2]["message"]["outcome"], "failed after 2 attempts");
+
+  assert_eq!(
+    retry::log_attempts("nothing", |_| Ok::<_, String>(())),
+    Ok(())
+  );
+}
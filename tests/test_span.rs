@@ -0,0 +1,51 @@
+//! Test timing sections of the log with spans
+
+use serde_yaml::Value as YmlValue;
+
+use ymlog::prelude::*;
+use ymlog::DURATION_KEY;
+
+mod common;
+use common::message;
+
+/// The duration a record closing a span took, checking it was the last child
+fn duration(record: &Block) -> f64 {
+  let (key, value) = record.children().last().unwrap().key_value().unwrap();
+  assert_eq!(key, &YmlValue::from(DURATION_KEY));
+  value.as_f64().unwrap()
+}
+
+#[test]
+/// Spans close with their duration as the last child, whether ended or dropped
+fn spans_record_their_duration() {
+  let (logger, buffer) = common::buffered();
+
+  let pipeline = logger.span(&mut message("Pipeline")).unwrap();
+  let extract = logger.span(&mut message("Extract")).unwrap();
+  assert!(extract.handle().is_some());
+  logger.log(&mut message("Read 3 rows"), None).unwrap();
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  extract.end().unwrap();
+  {
+    let _load = logger.span(&mut message("Load")).unwrap();
+  }
+  drop(pipeline);
+  assert_eq!(logger.current_depth(), 0);
+  logger.log(&mut message("Done"), None).unwrap();
+
+  let output = common::contents(&buffer);
+  let roots = ymlog::reader::parse(output.as_bytes())
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(roots.len(), 2, "{}", output);
+  let pipeline = &roots[0];
+  let stages = pipeline.children();
+  assert_eq!(stages.len(), 3, "{}", output);
+  assert_eq!(stages[0].text(), Some("Extract"));
+  assert_eq!(stages[0].children()[0].text(), Some("Read 3 rows"));
+  assert!(duration(&stages[0]) >= 5.0);
+  assert_eq!(stages[1].text(), Some("Load"));
+  assert!(duration(&stages[1]) < duration(&stages[0]));
+  assert!(duration(pipeline) >= duration(&stages[0]));
+  assert_eq!(roots[1].text(), Some("Done"));
+}
@@ -0,0 +1,84 @@
+//! Test the timestamps records are stamped with
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ymlog::Timestamp;
+
+#[test]
+/// Times are written in RFC 3339 in UTC, with the digits of the second they need in threes
+fn timestamps_are_written_in_rfc3339() {
+  for (millis, expected) in [
+    (0, "1970-01-01T00:00:00+00:00"),
+    (1_709_296_205_000, "2024-03-01T12:30:05+00:00"),
+    (1_709_296_205_250, "2024-03-01T12:30:05.250+00:00"),
+    (951_825_599_999, "2000-02-29T11:59:59.999+00:00"),
+    (-1, "1969-12-31T23:59:59.999+00:00"),
+  ] {
+    let timestamp = Timestamp::from_millis(millis);
+    assert_eq!(timestamp.to_rfc3339(), expected);
+    assert_eq!(timestamp.millis(), millis);
+    assert_eq!(Timestamp::parse_rfc3339(expected), Some(timestamp));
+  }
+
+  let micros = Timestamp::from(UNIX_EPOCH + Duration::from_micros(1_500));
+  assert_eq!(micros.to_rfc3339(), "1970-01-01T00:00:00.001500+00:00");
+  let nanos = Timestamp::from(UNIX_EPOCH + Duration::from_nanos(7));
+  assert_eq!(nanos.to_rfc3339(), "1970-01-01T00:00:00.000000007+00:00");
+  assert_eq!(
+    SystemTime::from(nanos),
+    UNIX_EPOCH + Duration::from_nanos(7)
+  );
+}
+
+#[test]
+/// Any offset is read, and anything that isn't RFC 3339 is rejected
+fn timestamps_are_read_with_offsets() {
+  let noon = Timestamp::parse_rfc3339("2024-03-01T12:00:00Z").unwrap();
+  for text in [
+    "2024-03-01t12:00:00z",
+    "2024-03-01 13:30:00+01:30",
+    "2024-03-01T07:00:00.000-05:00",
+    "2024-02-29T23:00:00-13:00",
+  ] {
+    assert_eq!(Timestamp::parse_rfc3339(text), Some(noon), "{}", text);
+  }
+  assert_eq!(
+    "2024-03-01T12:00:00.1234567891Z"
+      .parse::<Timestamp>()
+      .unwrap()
+      .to_rfc3339(),
+    "2024-03-01T12:00:00.123456789+00:00"
+  );
+
+  for text in [
+    "",
+    "2024-03-01",
+    "2024-03-01T12:00:00",
+    "2024-02-30T12:00:00Z",
+    "2023-02-29T12:00:00Z",
+    "2024-03-01T24:00:00Z",
+    "2024-03-01T12:00:00.Z",
+    "2024-03-01T12:00:00+0100",
+    "2024-03-01T12:00:00+24:00",
+    "2024-03-01T12:00:00Z trailing",
+  ] {
+    assert_eq!(Timestamp::parse_rfc3339(text), None, "{}", text);
+  }
+  let err = "yesterday".parse::<Timestamp>().unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+/// Timestamps convert to and from chrono's, and are written the way chrono writes them
+fn timestamps_match_chrono() {
+  use chrono::{DateTime, Utc};
+
+  for nanos in [0, 1_000_000, 1_500, 7, 1_709_296_205_250_000_000] {
+    let time = DateTime::<Utc>::from_timestamp_nanos(nanos);
+    let timestamp = Timestamp::from(time);
+    assert_eq!(timestamp.to_rfc3339(), time.to_rfc3339());
+    assert_eq!(DateTime::<Utc>::from(timestamp), time);
+  }
+  assert!(Timestamp::now().millis() > 1_700_000_000_000);
+}
@@ -0,0 +1,471 @@
+//! Test each transition of the tracker that keeps the YAML stream valid
+
+use ymlog::prelude::*;
+use ymlog::LastBlockType as Last;
+use ymlog::{TimestampFormat, Tracker};
+
+mod common;
+
+/// A step in a script run against a tracker
+enum Step<'a> {
+  Record(&'a str),
+  Pair(&'a str, &'a str),
+  Fields(&'a str),
+  Indent,
+  Dedent,
+  DedentTo(usize),
+  Reset,
+}
+
+use Step::*;
+
+/// Run the steps against a new tracker, returning what was written and the levels left
+fn run(steps: &[Step]) -> (String, Vec<Last>) {
+  run_with(Tracker::new(), steps)
+}
+
+/// Run the steps against the tracker, returning what was written and the levels left
+fn run_with(mut tracker: Tracker, steps: &[Step]) -> (String, Vec<Last>) {
+  let mut written = String::new();
+  for step in steps {
+    let mut block = Block::new();
+    match step {
+      Record(msg) => block.set_message(msg).unwrap(),
+      Pair(key, value) => block.set_key_value(key, value).unwrap(),
+      Fields(msg) => {
+        block.set_message(msg).unwrap();
+        block.add_field("id", 7).unwrap();
+      }
+      Indent => {
+        tracker.indent();
+        continue;
+      }
+      Dedent => {
+        tracker.dedent();
+        continue;
+      }
+      DedentTo(levels) => {
+        tracker.dedent_to(*levels);
+        continue;
+      }
+      Reset => {
+        tracker.reset();
+        continue;
+      }
+    }
+    written.push_str(&tracker.serialize(&block, &TimestampFormat::default()));
+  }
+  (written, tracker.levels().to_vec())
+}
+
+/// Check every document written parses as YAML
+fn assert_valid(written: &str) {
+  for document in written.split("---\n").filter(|doc| !doc.is_empty()) {
+    let parsed = serde_yaml::from_str::<serde_yaml::Value>(document);
+    assert!(parsed.is_ok(), "Invalid YAML:\n{}", written);
+  }
+}
+
+#[test]
+/// The first record starts the stream, and later root records start new documents
+fn root_records_start_documents() {
+  let (written, levels) = run(&[]);
+  assert_eq!((written.as_str(), levels), ("", vec![]));
+
+  let (written, levels) = run(&[Record("A"), Record("B")]);
+  assert_eq!(written, "---\nA\n---\nB");
+  assert_eq!(levels, vec![Last::Message]);
+}
+
+#[test]
+/// Indenting after a plain record turns it into a key for the records that follow
+fn indents_nest_records() {
+  let (written, levels) = run(&[Record("A"), Indent]);
+  assert_eq!(written, "---\nA");
+  assert_eq!(levels, vec![Last::Message, Last::Indent]);
+
+  let (written, levels) = run(&[Record("A"), Indent, Record("B"), Record("C")]);
+  assert_eq!(written, "---\nA:\n  - B\n  - C");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  // A second indent before the next record is ignored
+  let (written, levels) = run(&[Record("A"), Indent, Indent, Record("B")]);
+  assert_eq!(written, "---\nA:\n  - B");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  // Nested records are written as they are, whatever they hold
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Indent,
+    Record("__Cut Here__:\n"),
+  ];
+  let (written, _) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B:\n    - |\n      __Cut Here__:");
+  assert_valid(&written);
+}
+
+#[test]
+/// Multiline records can't be keys, so their children go under a phony key
+fn blocks_indent_under_a_phony_key() {
+  let steps = [Record("A"), Indent, Record("B\nC")];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - |-\n    B\n    C");
+  assert_eq!(levels, vec![Last::Message, Last::BlockMessage]);
+
+  let (written, levels) = run(&[Record("A"), Indent, Record("B\nC"), Indent]);
+  assert_eq!(
+    levels,
+    vec![Last::Message, Last::BlockMessage, Last::BlockIndent]
+  );
+  assert_eq!(written, "---\nA:\n  - |-\n    B\n    C");
+
+  let (written, levels) = run(&[Record("A"), Indent, Record("B\nC"), Indent, Record("D")]);
+  assert_eq!(
+    written,
+    "---\nA:\n  - |-\n    B\n    C\n  - \"\" :\n    - D"
+  );
+  // The level stays a block, so indenting under D uses the phony key again
+  assert_eq!(
+    levels,
+    vec![Last::Message, Last::BlockMessage, Last::BlockMessage]
+  );
+  assert_valid(&written);
+}
+
+#[test]
+/// Records with metadata are mappings, so children are added as another field
+fn records_indent_under_children() {
+  let (written, levels) = run(&[Fields("A")]);
+  assert_eq!(written, "---\nfields:\n  id: 7\nmessage: A");
+  assert_eq!(levels, vec![Last::Record]);
+
+  let (_, levels) = run(&[Fields("A"), Indent]);
+  assert_eq!(levels, vec![Last::Record, Last::RecordIndent]);
+
+  let (written, levels) = run(&[Fields("A"), Indent, Record("B")]);
+  assert_eq!(
+    written,
+    "---\nfields:\n  id: 7\nmessage: A\nchildren:\n  - B"
+  );
+  assert_eq!(levels, vec![Last::Record, Last::Message]);
+  assert_valid(&written);
+}
+
+#[test]
+/// Pairs on a new indent attach to the record above, and are closed by the next plain record
+fn pairs_close_themselves() {
+  let (written, levels) = run(&[Record("A"), Indent, Pair("k", "v")]);
+  assert_eq!(written, "---\nA:\n  - k: v");
+  assert_eq!(levels, vec![Last::Message, Last::KeyValue]);
+
+  let (written, levels) = run(&[Record("A"), Indent, Pair("k", "v"), Record("B")]);
+  assert_eq!(written, "---\nA:\n  - k: v\n---\nB");
+  assert_eq!(levels, vec![Last::Message]);
+
+  // Pairs after another record at the same depth stay there
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Pair("k", "v"),
+    Record("C"),
+  ];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B\n  - k: v\n  - C");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  // The record after the pair can still be indented under
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Pair("k", "v"),
+    Record("C"),
+    Indent,
+    Record("D"),
+  ];
+  let (written, _) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B\n  - k: v\n  - C:\n    - D");
+  assert_valid(&written);
+
+  // A pair already has a value, so it can't be indented under
+  let (written, levels) = run(&[Record("A"), Indent, Pair("k", "v"), Indent]);
+  assert_eq!(levels, vec![Last::Message, Last::KeyValue]);
+  assert_eq!(written, "---\nA:\n  - k: v");
+}
+
+#[test]
+/// Dedents step back out one level, but never past the root
+fn dedents_stop_at_the_root() {
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Indent,
+    Record("C"),
+    Dedent,
+    Record("D"),
+  ];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B:\n    - C\n  - D");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  let (written, levels) = run(&[Dedent, Record("A"), Dedent, Dedent, Record("B")]);
+  assert_eq!(written, "---\nA\n---\nB");
+  assert_eq!(levels, vec![Last::Message]);
+
+  // Dedenting a pending indent cancels it
+  let (written, levels) = run(&[Record("A"), Indent, Dedent, Record("B")]);
+  assert_eq!(written, "---\nA\n---\nB");
+  assert_eq!(levels, vec![Last::Message]);
+}
+
+#[test]
+/// Dedenting to a depth drops as many levels as it takes, but still not the root
+fn dedents_to_a_depth() {
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Indent,
+    Record("C"),
+    Indent,
+    Record("D"),
+    DedentTo(1),
+    Record("E"),
+  ];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B:\n    - C:\n      - D\n  - E");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  let (written, _) = run(&[Record("A"), Indent, Record("B"), DedentTo(0), Record("C")]);
+  assert_eq!(written, "---\nA:\n  - B\n---\nC");
+
+  // Going deeper than the tracker already is does nothing
+  let (_, levels) = run(&[Record("A"), Indent, Record("B"), DedentTo(5)]);
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  // A pending indent counts as the depth the next record goes to
+  let (written, _) = run(&[Record("A"), Indent, DedentTo(1), Record("B")]);
+  assert_eq!(written, "---\nA:\n  - B");
+}
+
+#[test]
+/// The next depth is where a plain record would go, without moving the tracker
+fn next_depth_looks_ahead() {
+  let mut tracker = Tracker::new();
+  assert_eq!(tracker.next_depth(), 0);
+  let mut block = Block::new();
+  block.set_message("A").unwrap();
+  tracker.advance(&block);
+  assert_eq!(tracker.next_depth(), 0);
+  tracker.indent();
+  assert_eq!(tracker.next_depth(), 1);
+  assert_eq!(tracker.levels(), &[Last::Message, Last::Indent]);
+
+  // Plain records close key/value pairs on a new indent
+  block.set_key_value("k", "v").unwrap();
+  tracker.advance(&block);
+  assert_eq!(tracker.next_depth(), 0);
+  tracker.reset();
+  assert_eq!(tracker.next_depth(), 0);
+}
+
+#[test]
+/// Marks let a caller return to a depth without counting the indents made since
+fn marks_unwind_to_their_depth() {
+  let (logger, buffer) = common::buffered();
+  let mut block = Block::new();
+  block.set_message("Request").unwrap();
+  logger.log(&mut block, Some("_+")).unwrap();
+  logger.mark("request");
+  for step in ["Parse", "Validate", "Query"] {
+    block.set_message(step).unwrap();
+    logger.log(&mut block, Some("_+")).unwrap();
+  }
+  assert_eq!(logger.current_depth(), 4);
+  logger.unwind_to("request").unwrap();
+  assert_eq!(logger.current_depth(), 1);
+  block.set_message("Respond").unwrap();
+  logger.log(&mut block, None).unwrap();
+  assert_eq!(
+    common::contents(&buffer),
+    "---\nRequest:\n  - Parse:\n    - Validate:\n      - Query\n  - Respond"
+  );
+
+  let err = logger.unwind_to("missing").unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+  // Dedenting to a depth never goes deeper or past the root
+  logger.dedent_to(4);
+  assert_eq!(logger.current_depth(), 1);
+  logger.dedent_to(0);
+  assert_eq!(logger.current_depth(), 0);
+}
+
+#[test]
+/// Resets start a new document, dropping whatever was open
+fn resets_start_new_documents() {
+  let (_, levels) = run(&[Record("A"), Indent, Record("B"), Reset]);
+  assert_eq!(levels, vec![Last::Reset]);
+
+  let (written, levels) = run(&[Record("A"), Indent, Record("B"), Reset, Record("C")]);
+  assert_eq!(written, "---\nA:\n  - B\n---\nC");
+  assert_eq!(levels, vec![Last::Message]);
+
+  // A reset after an indent on a multiline record leaves it without children
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B\nC"),
+    Indent,
+    Reset,
+    Record("D"),
+  ];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - |-\n    B\n    C\n---\nD");
+  assert_eq!(levels, vec![Last::Message]);
+  assert_valid(&written);
+
+  // Nothing can be indented under a reset until a record is written
+  let (written, levels) = run(&[Record("A"), Reset, Indent, Record("B"), Indent, Record("C")]);
+  assert_eq!(written, "---\nA\n---\nB:\n  - C");
+  assert_eq!(levels, vec![Last::Message, Last::Message]);
+
+  // Before anything is written, there is nothing to indent under
+  let (written, levels) = run(&[Reset, Indent, Record("A")]);
+  assert_eq!(written, "\n---\nA");
+  assert_eq!(levels, vec![Last::Message]);
+}
+
+#[test]
+/// Records written in other formats move the tracker the same way as the YAML
+fn advancing_matches_serializing() {
+  let mut tracker = Tracker::new();
+  let mut block = Block::new();
+  block.set_message("A").unwrap();
+  assert_eq!(tracker.advance(&block), 0);
+  tracker.indent();
+  assert_eq!(tracker.advance(&block), 1);
+  block.set_message("B\nC").unwrap();
+  assert_eq!(tracker.advance(&block), 1);
+  assert_eq!(tracker.levels(), &[Last::Message, Last::BlockMessage]);
+  tracker.indent();
+  assert_eq!(
+    tracker.levels(),
+    &[Last::Message, Last::BlockMessage, Last::BlockIndent]
+  );
+  tracker.reset();
+  assert_eq!(tracker.advance(&block), 0);
+}
+
+#[test]
+/// Each streamed level and the lines within each record are indented with the tracker's indent,
+/// leaving the same YAML
+fn levels_use_the_indent() {
+  let steps = [
+    Record("A"),
+    Indent,
+    Fields("B"),
+    Indent,
+    Record("C\nD"),
+    Indent,
+    Record("E"),
+    DedentTo(1),
+    Pair("F", "G"),
+  ];
+  let mut tracker = Tracker::new();
+  tracker.set_indent(ymlog::Indent::Space(4));
+  let (written, _) = run_with(tracker, &steps);
+  assert_eq!(
+    written,
+    "---\nA:\n    -   fields:\n            id: 7\n        message: B\n        children:\n        - |-\n            C\n            D\n        - \"\" :\n            - E\n    -   F: G"
+  );
+  let (two_spaces, _) = run(&steps);
+  assert_eq!(
+    serde_yaml::from_str::<serde_yaml::Value>(&written).unwrap(),
+    serde_yaml::from_str::<serde_yaml::Value>(&two_spaces).unwrap()
+  );
+
+  // YAML can't be indented with tabs, so they fall back to two spaces
+  let mut tracker = Tracker::new();
+  tracker.set_indent(ymlog::Indent::Tab);
+  let (written, _) = run_with(tracker, &steps);
+  assert_eq!(written, two_spaces);
+
+  // The logger passes its indent on to the trackers of its outputs
+  let (logger, buffer) = common::buffered();
+  logger.set_indent(ymlog::Indent::Space(4));
+  for (msg, actions) in [("Root", "_+"), ("Child", "_")] {
+    let mut block = Block::new();
+    block.set_message(msg).unwrap();
+    logger.log(&mut block, Some(actions)).unwrap();
+  }
+  assert_eq!(common::contents(&buffer), "---\nRoot:\n    - Child");
+}
+
+#[test]
+/// Indenting again after dedenting out of a record's children carries them on
+fn indents_after_dedents_continue_the_children() {
+  let steps = [
+    Record("A"),
+    Indent,
+    Record("B"),
+    Dedent,
+    Indent,
+    Record("C"),
+  ];
+  let (written, levels) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - B\n  - C");
+  assert_eq!(
+    levels,
+    vec![Last::Parent(Box::new(Last::Message)), Last::Message]
+  );
+  assert_valid(&written);
+
+  // Pairs carry on as siblings, so a plain record after them stays at their depth
+  let steps = [
+    Record("A"),
+    Indent,
+    Pair("k", "v"),
+    Dedent,
+    Indent,
+    Record("B"),
+    Indent,
+    Record("C"),
+  ];
+  let (written, _) = run(&steps);
+  assert_eq!(written, "---\nA:\n  - k: v\n  - B:\n    - C");
+  assert_valid(&written);
+
+  // A record mapping's children carry on under the same key
+  let (written, _) = run(&[
+    Fields("A"),
+    Indent,
+    Record("B"),
+    Dedent,
+    Indent,
+    Record("C"),
+  ]);
+  assert_eq!(
+    written,
+    "---\nfields:\n  id: 7\nmessage: A\nchildren:\n  - B\n  - C"
+  );
+  assert_valid(&written);
+
+  // Without an indent, the next record is the sibling of the one with children
+  let (written, levels) = run(&[Record("A"), Indent, Record("B"), Dedent]);
+  assert_eq!(levels, vec![Last::Parent(Box::new(Last::Message))]);
+  assert_eq!(written, "---\nA:\n  - B");
+}
+
+#[test]
+/// A multiline document can't hold children, so the records after it start new documents
+fn multiline_documents_take_no_children() {
+  let (written, levels) = run(&[Record("A\nB"), Indent, Record("C")]);
+  assert_eq!(written, "---\n|-\n  A\n  B\n---\nC");
+  assert_eq!(levels, vec![Last::Message]);
+  assert_valid(&written);
+}
@@ -0,0 +1,198 @@
+//! Check that whatever is logged, in whatever order, the output parses as YAML
+//!
+//! The runs are generated from fixed seeds, so a failure can be reproduced by running its seed.
+
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value as YmlValue};
+
+use ymlog::prelude::*;
+use ymlog::Indent;
+
+mod common;
+
+/// Strings that are easy to get wrong: YAML syntax, typed words, whitespace and line breaks
+const PIECES: &[&str] = &[
+  "plain",
+  "two words",
+  "",
+  " ",
+  "yes",
+  "null",
+  "~",
+  "3.14",
+  "-1",
+  "0x1f",
+  "1:30",
+  "2001-12-14",
+  "- item",
+  "-",
+  "---",
+  "...",
+  "? key",
+  ":",
+  "key:",
+  "a: b",
+  "a #b",
+  "#comment",
+  "[list]",
+  "{map}",
+  "*alias",
+  "&anchor",
+  "!tag",
+  "|",
+  ">",
+  "'",
+  "\"",
+  "%",
+  "@",
+  "`",
+  "\\",
+  " lead",
+  "trail ",
+  "\t",
+  "\n",
+  "\n\n",
+  "\r\n",
+  "\r",
+  "  indented",
+  "日本語",
+  "emoji 🎉",
+  "e\u{301}",
+  "\u{2028}",
+  "\u{feff}",
+  "\u{7}",
+];
+
+/// The actions a record can be logged with
+const ACTIONS: &[&str] = &[
+  "_", "_", "_", "+_", "-_", "_+", "+_+", "--_", "r_", "_-", "-+_", "b_", "f_+", "+q_", "b_+",
+];
+
+/// A small xorshift generator, so the runs don't need a dependency
+struct Rng(u64);
+
+impl Rng {
+  fn new(seed: u64) -> Rng {
+    Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+  }
+
+  fn next(&mut self) -> u64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    self.0
+  }
+
+  fn below(&mut self, max: usize) -> usize {
+    (self.next() % max as u64) as usize
+  }
+
+  fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+    items[self.below(items.len())]
+  }
+
+  /// A string of a few pieces run together
+  fn text(&mut self) -> String {
+    (0..1 + self.below(3))
+      .map(|_| self.pick(PIECES))
+      .collect::<String>()
+  }
+
+  /// A scalar, or a small mapping or sequence of them
+  fn value(&mut self, depth: usize) -> YmlValue {
+    match self.below(if depth > 1 { 4 } else { 7 }) {
+      0 => YmlValue::Null,
+      1 => (self.next() % 1000).into(),
+      2 => (self.below(2) == 0).into(),
+      3 => self.text().into(),
+      4 | 5 => {
+        let mut mapping = Mapping::new();
+        for _ in 0..self.below(4) {
+          mapping.insert(self.text().into(), self.value(depth + 1));
+        }
+        YmlValue::Mapping(mapping)
+      }
+      _ => YmlValue::Sequence((0..self.below(4)).map(|_| self.value(depth + 1)).collect()),
+    }
+  }
+
+  /// A record of any shape, with fields sometimes
+  fn block(&mut self) -> Block {
+    let mut block = Block::new();
+    match self.below(4) {
+      0 => block.set_key_value(self.text(), self.value(0)).unwrap(),
+      1 => block.set_message(self.value(0)).unwrap(),
+      _ => block.set_message(self.text()).unwrap(),
+    }
+    if self.below(4) == 0 {
+      for _ in 0..1 + self.below(3) {
+        block.add_field(&self.text(), self.value(1)).unwrap();
+      }
+    }
+    if self.below(8) == 0 {
+      block.set_tag_type(self.pick(&["note", "ymlog/test", "a-b"]));
+    }
+    block
+  }
+}
+
+/// Parse every document in the output, returning what didn't parse
+fn invalid_documents(output: &str) -> Option<String> {
+  for document in serde_yaml::Deserializer::from_str(output) {
+    if let Err(err) = YmlValue::deserialize(document) {
+      return Some(err.to_string());
+    }
+  }
+  None
+}
+
+/// Log a random run into a logger set up by the seed, returning what was written
+fn run(seed: u64) -> String {
+  let mut rng = Rng::new(seed);
+  let (logger, buffer) = common::buffered();
+  match seed % 4 {
+    1 => logger.set_indent(Indent::Space(4)),
+    2 => logger.set_flow_width(Some(40)),
+    3 => logger.set_strict_yaml(true),
+    _ => (),
+  }
+  for _ in 0..40 {
+    match rng.below(12) {
+      0 => logger.skip(Some(rng.pick(&["+", "-", "r"]))).unwrap(),
+      1 if rng.below(4) == 0 => logger.end_document().unwrap(),
+      _ => {
+        let actions = rng.pick(ACTIONS);
+        logger.log(&mut rng.block(), Some(actions)).unwrap();
+      }
+    }
+  }
+  logger.close().unwrap();
+  common::contents(&buffer)
+}
+
+#[test]
+/// Every document of every run parses, whatever was logged
+fn output_is_always_valid_yaml() {
+  for seed in 0..400 {
+    let output = run(seed);
+    if let Some(err) = invalid_documents(&output) {
+      panic!("Seed {} wrote invalid YAML ({}):\n{}", seed, err, output);
+    }
+  }
+}
+
+#[test]
+/// Strings that look like the marks standing in for escaped ones are written as they are
+fn mark_like_strings_are_kept() {
+  let (logger, buffer) = common::buffered();
+  let mut block = Block::new();
+  block.set_message("__ymlog_escape_0__").unwrap();
+  block.add_field("line", "one\u{2028}two").unwrap();
+  logger.log(&mut block, None).unwrap();
+  logger.close().unwrap();
+
+  let output = common::contents(&buffer);
+  let parsed: YmlValue = serde_yaml::from_str(output.trim_start_matches("---\n")).unwrap();
+  assert_eq!(parsed["message"], "__ymlog_escape_0__", "{}", output);
+  assert_eq!(parsed["fields"]["line"], "one\u{2028}two", "{}", output);
+}
@@ -0,0 +1,848 @@
+//! Test the ways blocks get to the output
+
+use std::io::{ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ymlog::prelude::*;
+use ymlog::{
+  ErrorHandler, FlushPolicy, Pipeline, SinkHealth, SinkStatus, TimeoutPolicy, WriteTimeout,
+};
+
+mod common;
+use common::{contents, message};
+
+#[test]
+/// The background thread should write the same thing as the direct writer, once it is drained
+fn async_writer_matches_direct() {
+  let direct_buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let direct = common::at_info(YmLog::new());
+  direct.set_output(common::TestWriter::new(&direct_buffer));
+
+  let async_buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let queued = common::at_info(YmLog::with_async_writer(common::TestWriter::new(
+    &async_buffer,
+  )));
+
+  for (actions, msg) in [
+    ("_", "Root"),
+    ("+_", "Child"),
+    ("_", "Sibling"),
+    ("-_", "Back"),
+  ] {
+    direct.log(&mut message(msg), Some(actions)).unwrap();
+    queued.log(&mut message(msg), Some(actions)).unwrap();
+  }
+
+  queued.flush().unwrap();
+  assert_eq!(contents(&direct_buffer), contents(&async_buffer));
+
+  // Anything logged after the flush still gets out on shutdown
+  queued.log(&mut message("Last"), Some("_")).unwrap();
+  queued.shutdown().unwrap();
+  assert!(contents(&async_buffer).ends_with("\n---\nLast"));
+}
+
+#[test]
+/// Each handle should point at exactly the bytes written for its record
+fn handles_index_the_output() {
+  let buffer = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(common::TestWriter::new(&buffer));
+
+  let first = logger
+    .log(&mut message("Root"), Some("_"))
+    .unwrap()
+    .unwrap();
+  let second = logger
+    .log(&mut message("Child"), Some("+_"))
+    .unwrap()
+    .unwrap();
+  let filtered = logger.log(&mut message("Quiet"), Some("D_")).unwrap();
+
+  assert_eq!((first.sequence, second.sequence), (0, 1));
+  assert_eq!(first.range.end, second.range.start);
+  assert_eq!(filtered, None);
+
+  let output = contents(&buffer);
+  let slice =
+    |handle: &RecordHandle| &output[handle.range.start as usize..handle.range.end as usize];
+  assert_eq!(slice(&first), "---\nRoot");
+  assert_eq!(slice(&second), ":\n  - Child");
+}
+
+#[test]
+/// Every output gets the same YAML, but only for the levels it accepts
+fn outputs_fan_out_by_level() {
+  let (logger, everything) = common::buffered();
+  let warnings = Arc::new(Mutex::new(Vec::<u8>::new()));
+  logger.add_output(common::TestWriter::new(&warnings), Level::Warn);
+
+  logger.log(&mut message("Starting"), Some("_")).unwrap();
+  logger
+    .log(&mut message("Disk is filling up"), Some("W_"))
+    .unwrap();
+  logger.log(&mut message("Details"), Some("+_")).unwrap();
+  logger
+    .log(&mut message("Out of space"), Some("E_"))
+    .unwrap();
+
+  assert_eq!(
+    contents(&everything),
+    "---\nStarting\n---\nlog_level: Warn\nmessage: Disk is filling up\nchildren:\n  - Details\n  - \
+     log_level: Error\n    message: Out of space"
+  );
+  assert_eq!(
+    contents(&warnings),
+    "---\nlog_level: Warn\nmessage: Disk is filling up\nchildren:\n  - log_level: Error\n    message: Out \
+     of space"
+  );
+}
+
+#[test]
+/// The same actions produce one JSON object per record, with the depth the YAML would have had
+fn json_lines_record_depth() {
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+
+  logger.log(&mut message("Root"), Some("_")).unwrap();
+  logger
+    .log(&mut message("Child \"quoted\""), Some("+_"))
+    .unwrap();
+  logger
+    .log(&mut message("Grandchild\nwith lines"), Some("+W_"))
+    .unwrap();
+  logger.log(&mut message("Next root"), Some("r_")).unwrap();
+
+  assert_eq!(
+    contents(&buffer),
+    concat!(
+      "{\"depth\":0,\"message\":\"Root\"}\n",
+      "{\"depth\":1,\"message\":\"Child \\\"quoted\\\"\"}\n",
+      "{\"depth\":2,\"log_level\":\"Warn\",\"message\":\"Grandchild\\nwith lines\"}\n",
+      "{\"depth\":0,\"message\":\"Next root\"}\n",
+    )
+  );
+}
+
+#[test]
+/// The logger locks itself, so threads can share it without wrapping it in a mutex
+fn loggers_are_shared_between_threads() {
+  fn shareable<T: Send + Sync>(_: &T) {}
+
+  let (logger, buffer) = common::buffered();
+  shareable(&logger);
+  let logger = Arc::new(logger);
+  logger.log(&mut message("Root"), None).unwrap();
+  logger.log(&mut message("First"), Some("+_")).unwrap();
+
+  let threads = (0..4)
+    .map(|thread| {
+      let logger = Arc::clone(&logger);
+      std::thread::spawn(move || {
+        for i in 0..25 {
+          let msg = format!("{}-{}", thread, i);
+          logger.log(&mut message(&msg), None).unwrap();
+        }
+      })
+    })
+    .collect::<Vec<_>>();
+  threads
+    .into_iter()
+    .for_each(|thread| thread.join().unwrap());
+
+  let parsed: serde_yaml::Value = serde_yaml::from_str(&contents(&buffer)).unwrap();
+  let children = parsed["Root"].as_sequence().unwrap();
+  assert_eq!(children.len(), 101);
+  for thread in 0..4 {
+    for i in 0..25 {
+      let expected = serde_yaml::Value::from(format!("{}-{}", thread, i));
+      assert!(children.contains(&expected));
+    }
+  }
+}
+
+#[test]
+/// The standard stream writers can be used without wrapping them
+fn standard_streams_are_writable() {
+  let logger = common::at_info(YmLog::to_stderr());
+  logger.log(&mut message("To stderr"), Some("_")).unwrap();
+  logger.flush().unwrap();
+
+  let logger = YmLog::to_stdout();
+  logger.log(&mut message("To stdout"), Some("_")).unwrap();
+  logger.add_output(ymlog::sinks::Stdout::new(), Level::Error);
+  logger.flush().unwrap();
+}
+
+#[test]
+/// Buffered records are only written when the policy says so, or on a flush
+fn flush_policies_hold_records_back() {
+  let (logger, buffer) = common::buffered();
+  logger.set_flush_policy(FlushPolicy::Bytes(24)).unwrap();
+  logger.log(&mut message("First"), Some("_")).unwrap();
+  assert_eq!(contents(&buffer), "");
+  logger
+    .log(&mut message("Second record"), Some("_"))
+    .unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst\n---\nSecond record");
+
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.log(&mut message("Third"), Some("_")).unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst\n---\nSecond record");
+  logger.flush().unwrap();
+  assert!(contents(&buffer).ends_with("---\nThird"));
+
+  logger.log(&mut message("Fourth"), Some("_")).unwrap();
+  logger.set_flush_policy(FlushPolicy::EveryRecord).unwrap();
+  assert!(contents(&buffer).ends_with("---\nFourth"));
+
+  let (logger, buffer) = common::buffered();
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.log(&mut message("Dropped"), Some("_")).unwrap();
+  assert_eq!(contents(&buffer), "");
+  drop(logger);
+  assert_eq!(contents(&buffer), "---\nDropped\n...\n");
+
+  let async_buffer = Arc::new(Mutex::new(vec![]));
+  let queued = common::at_info(YmLog::with_async_writer(common::TestWriter::new(
+    &async_buffer,
+  )));
+  queued
+    .set_flush_policy(FlushPolicy::Interval(std::time::Duration::from_secs(3600)))
+    .unwrap();
+  queued.log(&mut message("Queued"), Some("_")).unwrap();
+  queued.flush().unwrap();
+  assert_eq!(contents(&async_buffer), "---\nQueued");
+}
+
+#[test]
+/// A resumed logger carries on as if the process had never restarted
+fn state_is_resumed() {
+  let steps = [
+    ("_+", "Batch"),
+    ("_", "Step one"),
+    ("_+", "Step two"),
+    ("_", "Detail"),
+    ("-_", "Step three"),
+  ];
+
+  let (uninterrupted, expected) = common::buffered();
+  for (actions, msg) in steps {
+    uninterrupted.log(&mut message(msg), Some(actions)).unwrap();
+  }
+
+  let (before, buffer) = common::buffered();
+  for (actions, msg) in &steps[..3] {
+    before.log(&mut message(msg), Some(actions)).unwrap();
+  }
+  let saved = serde_yaml::to_string(&before.snapshot_state().unwrap()).unwrap();
+  // The process is killed, so the logger never gets to close the document
+  std::mem::forget(before);
+
+  let state: ymlog::StateBlob = serde_yaml::from_str(&saved).unwrap();
+  let after = common::at_info(YmLog::resume(state, common::TestWriter::new(&buffer)).unwrap());
+  let mut handle = None;
+  for (actions, msg) in &steps[3..] {
+    handle = after.log(&mut message(msg), Some(actions)).unwrap();
+  }
+  assert_eq!(contents(&buffer), contents(&expected));
+  let handle = handle.unwrap();
+  assert_eq!(handle.sequence, 4);
+  assert_eq!(handle.range.end, contents(&buffer).len() as u64);
+
+  // A logger closed before the snapshot carries on with a new document
+  let (before, buffer) = common::buffered();
+  before.log(&mut message("First run"), Some("_+")).unwrap();
+  before.close().unwrap();
+  let state = before.snapshot_state().unwrap();
+  drop(before);
+  let after = common::at_info(YmLog::resume(state, common::TestWriter::new(&buffer)).unwrap());
+  after.log(&mut message("Second run"), Some("_")).unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst run\n...\n\n---\nSecond run");
+
+  let empty: ymlog::StateBlob = serde_yaml::from_str("outputs: []").unwrap();
+  assert!(YmLog::resume(empty, common::TestWriter::new(&buffer)).is_err());
+}
+
+#[test]
+/// Closing ends the open document with the footer, once, and dropping the logger closes it
+fn closing_ends_the_document() {
+  let (logger, buffer) = common::buffered();
+  let mut footer = message("Log closed");
+  footer.add_field("records", 2).unwrap();
+  logger.set_footer(footer);
+  logger.log(&mut message("Root"), Some("_+")).unwrap();
+  logger.log(&mut message("Child"), Some("_")).unwrap();
+  logger.close().unwrap();
+  logger.close().unwrap();
+  assert_eq!(
+    contents(&buffer),
+    concat!(
+      "---\nRoot:\n  - Child\n",
+      "---\nfields:\n  records: 2\nmessage: Log closed\n",
+      "...\n",
+    )
+  );
+
+  let (logger, buffer) = common::buffered();
+  logger.set_format(OutputFormat::JsonLines);
+  logger.log(&mut message("Root"), Some("_")).unwrap();
+  drop(logger);
+  assert_eq!(contents(&buffer), "{\"depth\":0,\"message\":\"Root\"}\n");
+}
+
+/// A writer that hangs while it is told to stall, like a collector that stopped reading
+struct Stalling {
+  stalled: Arc<AtomicBool>,
+  inner: common::TestWriter,
+}
+
+impl Write for Stalling {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    while self.stalled.load(Ordering::Acquire) {
+      std::thread::sleep(Duration::from_millis(1));
+    }
+    self.inner.write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+/// Log four records, with the second stalling the output and the third logged while it is
+fn stall_on_second(policy: TimeoutPolicy) -> (String, std::io::Result<()>) {
+  let buffer = Arc::new(Mutex::new(vec![]));
+  let stalled = Arc::new(AtomicBool::new(false));
+  let logger = common::at_info(YmLog::new());
+  let output = Stalling {
+    stalled: Arc::clone(&stalled),
+    inner: common::TestWriter::new(&buffer),
+  };
+  let timeout = WriteTimeout::new(Duration::from_millis(50), policy);
+  logger.add_timed_output(output, Pipeline::new(), timeout);
+
+  logger.log(&mut message("First"), None).unwrap();
+  stalled.store(true, Ordering::Release);
+  let start = Instant::now();
+  logger.log(&mut message("Second"), None).unwrap();
+  let third = logger.log(&mut message("Third"), None).map(|_| ());
+  assert!(start.elapsed() < Duration::from_secs(1));
+
+  stalled.store(false, Ordering::Release);
+  logger.flush().unwrap();
+  logger.log(&mut message("Fourth"), None).unwrap();
+  logger.flush().unwrap();
+  (contents(&buffer), third)
+}
+
+#[test]
+/// A stalled output doesn't hold up logging, and the policy decides where records go meanwhile
+fn stalled_writes_time_out() {
+  let (written, third) = stall_on_second(TimeoutPolicy::Drop);
+  assert_eq!(written, "---\nFirst\n---\nSecond\n---\nFourth");
+  assert_eq!(third.unwrap_err().kind(), ErrorKind::TimedOut);
+
+  let (written, third) = stall_on_second(TimeoutPolicy::Buffer);
+  assert_eq!(written, "---\nFirst\n---\nSecond\n---\nThird\n---\nFourth");
+  assert!(third.is_ok());
+
+  let fallback = Arc::new(Mutex::new(vec![]));
+  let policy = TimeoutPolicy::Fallback(Box::new(common::TestWriter::new(&fallback)));
+  let (written, third) = stall_on_second(policy);
+  assert_eq!(written, "---\nFirst\n---\nSecond\n---\nFourth");
+  assert!(third.is_ok());
+  assert_eq!(contents(&fallback), "\n---\nThird");
+
+  // An output that never recovers is left behind when the logger is dropped
+  let stalled = Arc::new(AtomicBool::new(true));
+  let logger = common::at_info(YmLog::new());
+  let output = Stalling {
+    stalled: Arc::clone(&stalled),
+    inner: common::TestWriter::new(&fallback),
+  };
+  let timeout = WriteTimeout::new(Duration::from_millis(20), TimeoutPolicy::Drop);
+  logger.add_timed_output(output, Pipeline::new(), timeout);
+  logger.log(&mut message("Lost"), None).unwrap();
+  assert_eq!(logger.flush().unwrap_err().kind(), ErrorKind::TimedOut);
+  let start = Instant::now();
+  drop(logger);
+  assert!(start.elapsed() < Duration::from_secs(1));
+  stalled.store(false, Ordering::Release);
+}
+
+/// A writer for a collector that is down
+struct Failing;
+
+impl Write for Failing {
+  fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+    Err(std::io::Error::other("the collector is down"))
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+/// A writer for a collector that can go down and come back
+struct Collector {
+  down: Arc<AtomicBool>,
+  inner: common::TestWriter,
+}
+
+impl Write for Collector {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    match self.down.load(Ordering::Acquire) {
+      true => Err(std::io::Error::other("the collector is down")),
+      false => self.inner.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[test]
+/// Only one member of a failover group takes each record, and the changes are noted in the log
+fn failover_groups_take_turns() {
+  let remote = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let local = Arc::new(Mutex::new(Vec::<u8>::new()));
+  let down = Arc::new(AtomicBool::new(false));
+  let logger: YmLog<Box<dyn Write + Send + Sync>> = common::at_info(YmLog::new());
+  let collector = Collector {
+    down: Arc::clone(&down),
+    inner: common::TestWriter::new(&remote),
+  };
+  logger.add_output_with(Box::new(collector), Pipeline::new().failover("ship"));
+  logger.add_output_with(
+    Box::new(common::TestWriter::new(&local)),
+    Pipeline::new().failover("ship"),
+  );
+  logger.set_failback_interval(Duration::ZERO);
+
+  logger.log(&mut message("Shipped"), None).unwrap();
+  down.store(true, Ordering::Release);
+  logger.log(&mut message("Kept"), None).unwrap();
+  logger.log(&mut message("Kept again"), None).unwrap();
+  down.store(false, Ordering::Release);
+  logger.log(&mut message("Shipped again"), None).unwrap();
+
+  let remote = contents(&remote);
+  let local = contents(&local);
+  assert!(remote.starts_with("---\nShipped\n---\nShipped again\n---\n!ymlog/failover\n"));
+  assert!(remote.contains("  to: 0\nmessage: Failed back to output 0 of ship"));
+  assert!(local.starts_with("---\nKept\n---\n!ymlog/failover\n"));
+  assert!(
+    local.contains("  reason: the collector is down\nmessage: Failed over to output 1 of ship")
+  );
+  assert!(local.ends_with("\n---\nKept again"), "{}", local);
+}
+
+#[test]
+/// Each output reports whether it is taking records and how much it is holding back
+fn outputs_report_their_health() {
+  let (logger, _buffer) = common::buffered();
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.log(&mut message("Held"), None).unwrap();
+  let healthy = SinkHealth {
+    status: SinkStatus::Ok,
+    queued_bytes: 8,
+  };
+  assert_eq!(logger.health(), vec![healthy]);
+  logger.flush().unwrap();
+  assert_eq!(logger.health()[0].queued_bytes, 0);
+
+  // Failures are timed from the first in a row
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(Failing);
+  assert!(logger.log(&mut message("Lost"), None).is_err());
+  let first = match &logger.health()[0].status {
+    SinkStatus::Failed { since, error } => {
+      assert_eq!(error, "the collector is down");
+      *since
+    }
+    other => panic!("Expected a failure, found {:?}", other),
+  };
+  assert!(logger.log(&mut message("Lost again"), None).is_err());
+  assert!(matches!(
+    &logger.health()[0].status,
+    SinkStatus::Failed { since, .. } if *since == first
+  ));
+
+  // Failures on a background thread are reported too
+  let logger = common::at_info(YmLog::with_async_writer(Failing));
+  logger.log(&mut message("Lost"), None).unwrap();
+  logger.flush().unwrap();
+  assert!(matches!(
+    logger.health()[0].status,
+    SinkStatus::Failed { .. }
+  ));
+
+  // A stalled output is degraded, with what it has queued waiting for it
+  let buffer = Arc::new(Mutex::new(vec![]));
+  let stalled = Arc::new(AtomicBool::new(true));
+  let logger = common::at_info(YmLog::new());
+  let output = Stalling {
+    stalled: Arc::clone(&stalled),
+    inner: common::TestWriter::new(&buffer),
+  };
+  let timeout = WriteTimeout::new(Duration::from_millis(20), TimeoutPolicy::Buffer);
+  logger.add_timed_output(output, Pipeline::new(), timeout);
+  logger.log(&mut message("Waiting"), None).unwrap();
+  logger.log(&mut message("Queued"), None).unwrap();
+  let health = logger.health();
+  assert_eq!(
+    health[0].status,
+    SinkStatus::Degraded("The output is stalled, so records are queued".to_string())
+  );
+  assert_eq!(health[0].queued_bytes, 22);
+
+  stalled.store(false, Ordering::Release);
+  logger.flush().unwrap();
+  let recovered = SinkHealth {
+    status: SinkStatus::Ok,
+    queued_bytes: 0,
+  };
+  assert_eq!(logger.health(), vec![recovered]);
+}
+
+#[test]
+/// A background flusher writes out held back records without being asked
+fn flusher_bounds_buffering() {
+  let (logger, buffer) = common::buffered();
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.start_flusher(Duration::from_millis(10));
+  logger.log(&mut message("Buffered"), None).unwrap();
+
+  let start = Instant::now();
+  while contents(&buffer).is_empty() {
+    assert!(start.elapsed() < Duration::from_secs(5), "Never flushed");
+    std::thread::sleep(Duration::from_millis(1));
+  }
+  assert_eq!(contents(&buffer), "---\nBuffered");
+
+  // Once stopped, records stay buffered until the logger flushes
+  logger.stop_flusher();
+  logger.log(&mut message("Held"), None).unwrap();
+  std::thread::sleep(Duration::from_millis(50));
+  assert_eq!(contents(&buffer), "---\nBuffered");
+
+  logger.start_flusher(Duration::from_secs(3600));
+  drop(logger);
+  assert_eq!(contents(&buffer), "---\nBuffered\n---\nHeld\n...\n");
+}
+
+#[test]
+/// A scope left without records for too long is warned about once, nested where it stalled
+fn watchdog_warns_about_stalled_scopes() {
+  let (logger, buffer) = common::buffered();
+  logger.start_watchdog(Duration::from_millis(20));
+  logger.log(&mut message("Deploying"), Some("_+")).unwrap();
+
+  let start = Instant::now();
+  while !contents(&buffer).contains("!ymlog/stalled") {
+    assert!(start.elapsed() < Duration::from_secs(5), "Never warned");
+    std::thread::sleep(Duration::from_millis(1));
+  }
+  std::thread::sleep(Duration::from_millis(60));
+  logger.stop_watchdog();
+  logger.log(&mut message("Pulled"), None).unwrap();
+
+  let output = contents(&buffer);
+  assert_eq!(output.matches("!ymlog/stalled").count(), 1);
+  assert!(
+    output.starts_with("---\nDeploying:\n  - !ymlog/stalled\n    log_level: Warn\n    fields:\n")
+  );
+  assert!(output.contains("      scope: Deploying\n      started: "));
+  assert!(output.ends_with("\n  - Pulled"), "{}", output);
+}
+
+/// A writer that keeps each call it gets, writing at most `limit` bytes of each vectored call
+struct Calls {
+  calls: Arc<Mutex<Vec<String>>>,
+  limit: usize,
+}
+
+impl Write for Calls {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let buf = &buf[..buf.len().min(self.limit)];
+    self
+      .calls
+      .lock()
+      .unwrap()
+      .push(String::from_utf8_lossy(buf).into_owned());
+    Ok(buf.len())
+  }
+
+  fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
+    let joined = bufs
+      .iter()
+      .flat_map(|buf| buf.iter())
+      .copied()
+      .collect::<Vec<_>>();
+    self.write(&joined)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+/// Records are written in one vectored call, and partial writes carry on where they stopped
+fn records_are_written_vectored() {
+  for (limit, expected) in [
+    (usize::MAX, vec!["---\nRoot", ":\n  - Child"]),
+    (4, vec!["---\n", "Root", ":\n  ", "- Ch", "ild"]),
+  ]
+  .iter()
+  {
+    let calls = Arc::new(Mutex::new(vec![]));
+    let logger = common::at_info(YmLog::new());
+    logger.set_output(Calls {
+      calls: Arc::clone(&calls),
+      limit: *limit,
+    });
+    logger.log(&mut message("Root"), Some("_+")).unwrap();
+    logger.log(&mut message("Child"), None).unwrap();
+    assert_eq!(&*calls.lock().unwrap(), expected);
+  }
+}
+
+#[test]
+/// Characters JSON has to escape are found wherever they fall in a long message
+fn json_escapes_are_found_at_any_offset() {
+  for offset in 0..20 {
+    for special in ["\"", "\\", "\n", "\t", "\u{1}", "é"].iter() {
+      let (logger, buffer) = common::buffered();
+      logger.set_format(OutputFormat::JsonLines);
+      let text = format!("{}{}{}", "a".repeat(offset), special, "b".repeat(offset));
+      logger.log(&mut message(&text), None).unwrap();
+
+      let record: serde_yaml::Value = serde_yaml::from_str(&contents(&buffer)).unwrap();
+      assert_eq!(record["message"].as_str(), Some(text.as_str()));
+    }
+  }
+}
+
+#[test]
+/// Characters that stop a YAML string being plain are found wherever they fall, and ones that
+/// don't leave it plain
+fn yaml_specials_are_found_at_any_offset() {
+  for offset in 1..20 {
+    for (special, plain) in [
+      (": ", false),
+      (" #", false),
+      ("\u{feff}", false),
+      ("\u{85}", false),
+      (":", true),
+      ("#", true),
+      ("é", true),
+    ]
+    .iter()
+    {
+      let (logger, buffer) = common::buffered();
+      let text = format!("{}{}{}", "a".repeat(offset), special, "b".repeat(offset));
+      logger.log(&mut message(&text), None).unwrap();
+
+      let output = contents(&buffer);
+      let record: serde_yaml::Value = serde_yaml::from_str(&output).unwrap();
+      assert_eq!(record.as_str(), Some(text.as_str()));
+      assert_eq!(output == format!("---\n{}", text), *plain, "{}", output);
+    }
+  }
+}
+
+#[test]
+/// The adaptive policy writes slow records one at a time, and batches bursts until they slow down
+fn adaptive_flushing_follows_the_record_rate() {
+  let calls = Arc::new(Mutex::new(vec![]));
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(Calls {
+    calls: Arc::clone(&calls),
+    limit: usize::MAX,
+  });
+  logger.set_flush_policy(FlushPolicy::Adaptive).unwrap();
+
+  logger.log(&mut message("Starting"), Some("_")).unwrap();
+  assert_eq!(calls.lock().unwrap().len(), 1);
+
+  for i in 0..1000 {
+    logger
+      .log(&mut message(&format!("Row {}", i)), None)
+      .unwrap();
+  }
+  let burst = calls.lock().unwrap().len();
+  assert!(burst < 100, "{} writes for the burst", burst);
+
+  std::thread::sleep(Duration::from_millis(20));
+  logger.log(&mut message("Done"), None).unwrap();
+  let written = calls.lock().unwrap().concat();
+  assert_eq!(written.matches("---\n").count(), 1002);
+  assert!(written.ends_with("---\nDone"));
+}
+
+#[test]
+/// Writes that fail where the caller can't see them are passed to the error handler
+fn background_failures_reach_the_error_handler() {
+  let seen = Arc::new(Mutex::new(vec![]));
+  let handler = || {
+    let store = Arc::clone(&seen);
+    ErrorHandler::Custom(Box::new(move |err| {
+      store.lock().unwrap().push(err.to_string())
+    }))
+  };
+
+  // Only the first failure in a row is reported
+  let logger = common::at_info(YmLog::with_async_writer(Failing));
+  logger.set_error_handler(handler());
+  logger.log(&mut message("Lost"), None).unwrap();
+  logger.flush().unwrap();
+  logger.log(&mut message("Also lost"), None).unwrap();
+  logger.flush().unwrap();
+  logger.log(&mut message("Still lost"), None).unwrap();
+  assert_eq!(*seen.lock().unwrap(), ["the collector is down"]);
+  drop(logger);
+
+  // Closing the log when it is dropped has no caller to return its error to
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(Failing);
+  logger.set_error_handler(handler());
+  assert!(logger.log(&mut message("Lost"), None).is_err());
+  seen.lock().unwrap().clear();
+  drop(logger);
+  assert_eq!(*seen.lock().unwrap(), ["the collector is down"]);
+
+  let logger = common::at_info(YmLog::with_async_writer(Failing));
+  logger.set_error_handler(ErrorHandler::Ignore);
+  logger.log(&mut message("Lost"), None).unwrap();
+  logger.flush().unwrap();
+  logger.log(&mut message("Quietly"), None).unwrap();
+}
+
+#[test]
+/// Syncing writes out everything held back, and waits for files to have it on disk
+fn sync_all_reaches_the_file() {
+  let path = std::env::temp_dir().join(format!("ymlog_sync_{}.yml", std::process::id()));
+  let read = || std::fs::read_to_string(&path).unwrap();
+
+  let logger = common::at_info(YmLog::new());
+  logger.set_output(std::fs::File::create(&path).unwrap());
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.log(&mut message("Checkpoint"), None).unwrap();
+  assert_eq!(read(), "");
+  logger.sync_all().unwrap();
+  assert_eq!(read(), "---\nCheckpoint");
+  drop(logger);
+
+  let file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+  let logger = common::at_info(YmLog::with_async_writer(file));
+  logger.log(&mut message("Queued"), None).unwrap();
+  logger.sync_all().unwrap();
+  assert_eq!(read(), "---\nQueued");
+  drop(logger);
+  std::fs::remove_file(&path).unwrap();
+
+  // Outputs that aren't files are only flushed
+  let (logger, buffer) = common::buffered();
+  logger.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+  logger.log(&mut message("Buffered"), None).unwrap();
+  logger.sync_all().unwrap();
+  assert_eq!(contents(&buffer), "---\nBuffered");
+}
+
+#[test]
+/// Log files are opened with the mode's options, making their directory first
+fn output_paths_follow_the_file_mode() {
+  use ymlog::sinks::FileMode;
+
+  let dir = std::env::temp_dir().join(format!("ymlog_paths_{}", std::process::id()));
+  let path = dir.join("nested").join("app.yml");
+  let read = || std::fs::read_to_string(&path).unwrap();
+  let write = |mode: FileMode, msg: &str| {
+    let logger = common::at_info(YmLog::to_file(&path, mode)?);
+    logger.log(&mut message(msg), None).unwrap();
+    logger.flush()
+  };
+
+  write(FileMode::CreateNew, "First").unwrap();
+  assert_eq!(read(), "---\nFirst\n...\n");
+  let err = write(FileMode::CreateNew, "Never").unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+  write(FileMode::Append, "Appended").unwrap();
+  assert_eq!(read(), "---\nFirst\n...\n---\nAppended\n...\n");
+  write(FileMode::Truncate, "Fresh").unwrap();
+  assert_eq!(read(), "---\nFresh\n...\n");
+
+  // A log that was never closed gets a new line before the next document
+  std::fs::write(&path, "---\nCrashed").unwrap();
+  write(FileMode::Append, "Restarted").unwrap();
+  assert_eq!(read(), "---\nCrashed\n---\nRestarted\n...\n");
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resumed_files_carry_on_nesting() {
+  let path = std::env::temp_dir().join(format!("ymlog_resume_{}.yml", std::process::id()));
+  let crashed = "---\nEarlier\n...\n---\nServer:\n  - Listening\n  - Request:\n    - Parsed";
+  std::fs::write(&path, crashed).unwrap();
+
+  let logger = common::at_info(YmLog::resume_file(&path).unwrap());
+  assert_eq!(logger.current_depth(), 2);
+  logger.log(&mut message("Handled"), None).unwrap();
+  logger.log(&mut message("Stopping"), Some("-")).unwrap();
+  logger.close().unwrap();
+  drop(logger);
+
+  let written = std::fs::read_to_string(&path).unwrap();
+  let expected = "\n    - Handled\n  - Stopping\n...\n";
+  assert_eq!(written, format!("{}{}", crashed, expected));
+  let roots = ymlog::reader::parse(written.as_bytes()).collect::<Result<Vec<_>, _>>();
+  assert_eq!(roots.unwrap().len(), 2);
+
+  // Closed logs and ones cut off mid-record start a new document
+  let logger = common::at_info(YmLog::resume_file(&path).unwrap());
+  logger.log(&mut message("Closed"), None).unwrap();
+  drop(logger);
+  std::fs::write(&path, "---\nTorn: [\"half").unwrap();
+  let logger = common::at_info(YmLog::resume_file(&path).unwrap());
+  logger.log(&mut message("Torn"), None).unwrap();
+  drop(logger);
+  assert_eq!(
+    std::fs::read_to_string(&path).unwrap(),
+    "---\nTorn: [\"half\n---\nTorn\n...\n"
+  );
+
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+/// Documents can be started and ended without closing the log
+fn documents_are_started_and_ended_explicitly() {
+  let (logger, buffer) = common::buffered();
+  logger.log(&mut message("First"), None).unwrap();
+
+  // The marker is written by the reset itself, and only once
+  logger.new_document().unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst\n---\n");
+  logger.skip(Some("r")).unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst\n---\n");
+
+  logger.log(&mut message("Second"), None).unwrap();
+  assert_eq!(contents(&buffer), "---\nFirst\n---\nSecond");
+
+  // Ending a document doesn't write the footer, and an ended document isn't ended again
+  logger.set_footer(message("Closed"));
+  logger.end_document().unwrap();
+  logger.end_document().unwrap();
+  logger.log(&mut message("Third"), None).unwrap();
+  assert_eq!(
+    contents(&buffer),
+    "---\nFirst\n---\nSecond\n...\n\n---\nThird"
+  );
+}
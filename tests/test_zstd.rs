@@ -0,0 +1,95 @@
+//! Test the zstd codec
+#![cfg(feature = "zstd")]
+
+use std::io::{ErrorKind, Read};
+
+use ymlog::prelude::*;
+use ymlog::reader::{LogSet, Opener};
+use ymlog::zstd::Zstd;
+use ymlog::{Codec, Compression};
+
+mod common;
+
+const LOG: &str = "---\nStarting the deploy:\n  - Checking out main\n  - Building:\n      - cargo build --release\n      - status: ok\n  - Uploading the artifact\n---\nDeploy finished\n";
+
+#[test]
+/// What we compress comes back the same, including several frames one after another
+fn compressed_data_round_trips() {
+  for data in [vec![], b"a".to_vec(), LOG.repeat(2000).into_bytes()] {
+    let packed = Zstd.compress(&data).unwrap();
+    assert!(packed.starts_with(Zstd.magic()));
+    assert_eq!(Zstd.decompress(&packed).unwrap(), data);
+  }
+
+  let frame = Zstd.compress(LOG.as_bytes()).unwrap();
+  let twice = [frame.clone(), frame].concat();
+  assert_eq!(Zstd.decompress(&twice).unwrap(), LOG.repeat(2).as_bytes());
+}
+
+#[test]
+/// Corrupt and cut off data are errors, rather than garbage
+fn corrupt_data_is_rejected() {
+  let packed = Zstd.compress(LOG.as_bytes()).unwrap();
+  for cut in [3, 8, packed.len() - 1] {
+    let err = Zstd.decompress(&packed[..cut]).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData, "{}", err);
+  }
+  let err = Zstd.decompress(b"not zstd at all").err().unwrap();
+  assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+/// Rotated zstd files are read without adding a codec
+fn readers_open_zstd_files() {
+  let dir = std::env::temp_dir().join(format!("ymlog-zstd-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(dir.join("app.yml"), "---\nCurrent").unwrap();
+  std::fs::write(
+    dir.join("app.yml.1.zst"),
+    Zstd.compress(LOG.as_bytes()).unwrap(),
+  )
+  .unwrap();
+
+  let mut text = String::new();
+  Opener::new()
+    .open(dir.join("app.yml.1.zst"))
+    .unwrap()
+    .read_to_string(&mut text)
+    .unwrap();
+  assert_eq!(text, LOG);
+
+  let set = LogSet::from_files(
+    vec![dir.join("app.yml.1.zst"), dir.join("app.yml")],
+    Opener::new(),
+  );
+  let records = ymlog::reader::parse(set)
+    .collect::<std::io::Result<Vec<_>>>()
+    .unwrap();
+  let messages = records
+    .iter()
+    .map(|record| record.message().unwrap().as_str().unwrap().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    messages,
+    ["Starting the deploy", "Deploy finished", "Current"]
+  );
+
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+/// Messages the logger compresses with zstd are tagged with it and expanded by the reader
+fn zstd_messages_are_expanded() {
+  let (logger, buffer) = common::buffered();
+  logger.set_compression(Compression::new(Box::new(Zstd), 64));
+  let large = LOG.repeat(4);
+  let mut block = Block::new();
+  block.set_message(&large).unwrap();
+  logger.log(&mut block, Some("_")).unwrap();
+
+  let output = common::contents(&buffer);
+  assert!(output.contains("!ymlog/zstd "), "{}", output);
+  let opener = Opener::new();
+  let record = opener.parse(output.as_bytes()).next().unwrap().unwrap();
+  assert_eq!(record.message().unwrap(), large.as_str());
+}
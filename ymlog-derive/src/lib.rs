@@ -0,0 +1,108 @@
+//! Derive macros for ymlog
+//!
+//! These are re-exported by ymlog, so there is no need to depend on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, LitStr};
+
+/// Implement `ymlog::Loggable` for an enum
+///
+/// By default a variant is written to people as its name in words (`PendingPayment` becomes
+/// "Pending payment") and to machines as its name in snake case (`pending_payment`). Either can be
+/// replaced with `#[loggable(human = "Waiting for payment", machine = "pending")]`. Any data the
+/// variants hold is ignored.
+#[proc_macro_derive(Loggable, attributes(loggable))]
+pub fn derive_loggable(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  loggable(input)
+    .unwrap_or_else(|err| err.to_compile_error())
+    .into()
+}
+
+fn loggable(input: DeriveInput) -> syn::Result<TokenStream2> {
+  let variants = match &input.data {
+    Data::Enum(data) => &data.variants,
+    _ => {
+      return Err(Error::new_spanned(
+        &input.ident,
+        "Loggable can only be derived for enums",
+      ))
+    }
+  };
+
+  let mut human_arms = vec![];
+  let mut machine_arms = vec![];
+  for variant in variants {
+    let name = variant.ident.to_string();
+    let mut human = words(&name);
+    let mut machine = snake_case(&name);
+    for attr in variant
+      .attrs
+      .iter()
+      .filter(|attr| attr.path().is_ident("loggable"))
+    {
+      attr.parse_nested_meta(|meta| {
+        let target = if meta.path.is_ident("human") {
+          &mut human
+        } else if meta.path.is_ident("machine") {
+          &mut machine
+        } else {
+          return Err(meta.error("expected `human` or `machine`"));
+        };
+        *target = meta.value()?.parse::<LitStr>()?.value();
+        Ok(())
+      })?;
+    }
+
+    let ident = &variant.ident;
+    human_arms.push(quote! { Self::#ident { .. } => #human });
+    machine_arms.push(quote! { Self::#ident { .. } => #machine });
+  }
+
+  let name = &input.ident;
+  let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+  Ok(quote! {
+    impl #impl_generics ::ymlog::Loggable for #name #type_generics #where_clause {
+      fn human(&self) -> ::std::string::String {
+        ::std::string::String::from(match self {
+          #(#human_arms,)*
+        })
+      }
+
+      fn machine(&self) -> ::ymlog::__private::YmlValue {
+        ::ymlog::__private::YmlValue::from(match self {
+          #(#machine_arms,)*
+        })
+      }
+    }
+  })
+}
+
+/// Split a camel case name into lowercase words, capitalizing the first
+fn words(name: &str) -> String {
+  let mut words = String::new();
+  for (i, c) in name.chars().enumerate() {
+    match (i, c.is_uppercase()) {
+      (0, _) => words.extend(c.to_uppercase()),
+      (_, true) => {
+        words.push(' ');
+        words.extend(c.to_lowercase());
+      }
+      (_, false) => words.push(c),
+    }
+  }
+  words
+}
+
+fn snake_case(name: &str) -> String {
+  let mut snake = String::new();
+  for (i, c) in name.chars().enumerate() {
+    if c.is_uppercase() && i > 0 {
+      snake.push('_');
+    }
+    snake.extend(c.to_lowercase());
+  }
+  snake
+}